@@ -63,6 +63,24 @@ pub fn parse_line(line: String, config: &mut Config, file: &str, line_number: i3
             });
             return;
         }
+        if head == "markdown" {
+            method_markdown(MethodArgs {
+                config,
+                line_splitted: &mut line_splitted,
+                file,
+                line_number,
+            });
+            return;
+        }
+        if head == "markdown-compile" {
+            method_markdown_compile(MethodArgs {
+                config,
+                line_splitted: &mut line_splitted,
+                file,
+                line_number,
+            });
+            return;
+        }
         if head == "inject" {
             method_inject(MethodArgs {
                 config,
@@ -101,6 +119,26 @@ pub fn parse_line(line: String, config: &mut Config, file: &str, line_number: i3
             });
             return;
         }
+        #[cfg(not(feature = "no-glisp"))]
+        if head == "@handler" {
+            method_import_handler(MethodArgs {
+                config,
+                line_splitted: &mut line_splitted,
+                file,
+                line_number,
+            });
+            return;
+        }
+        #[cfg(not(feature = "no-glisp"))]
+        if head == "template" {
+            method_template(MethodArgs {
+                config,
+                line_splitted: &mut line_splitted,
+                file,
+                line_number,
+            });
+            return;
+        }
         if head == ">" {
             method_log(MethodArgs {
                 config,
@@ -243,7 +281,7 @@ fn compile(args: MethodArgs, head2: &str) {
             return;
         }
 
-        if let Some(pos) = l.unwrap().find("$_gcflag") {
+        for (pos, _) in l.unwrap().match_indices("$_gcflag") {
             flags.push((linenumber, pos));
         }
         linenumber += 1;
@@ -274,6 +312,72 @@ fn compile(args: MethodArgs, head2: &str) {
         }
     }
 }
+/// 挂载的 URL 默认是把文件的后缀名 `.md` 换为 `.html` ，与 `+` 相同，`/` 代表根路径
+fn markdown_url(head2: &str, head3: Option<&str>) -> String {
+    match head3 {
+        Some("/") => "/".to_owned(),
+        Some(a) => "/".to_owned() + a,
+        None => "/".to_owned() + head2.strip_suffix(".md").unwrap_or(head2) + ".html",
+    }
+}
+/// 在每次请求时渲染，所以修改 Markdown 文件之后不需要重启
+fn method_markdown(args: MethodArgs) {
+    let Some(head2) = args.line_splitted.next() else {
+        syntax_error(args.file, args.line_number, LOG[18]);
+        return;
+    };
+    if !Path::new(&("export/".to_owned() + head2)).is_file() {
+        syntax_error(args.file, args.line_number, LOG[20]);
+        return;
+    }
+    let url = markdown_url(head2, args.line_splitted.next());
+    let mut data = ServeFileData::from_with_content_type(
+        "/".to_owned() + head2,
+        "text/html; charset=utf-8".to_owned(),
+    );
+    data.markdown = true;
+    args.config.router_config.serve_files_info.insert(url, data);
+}
+/// 只在加载配置时渲染一次，结果被写入 temp/<文件名>.html
+fn method_markdown_compile(args: MethodArgs) {
+    let Some(head2) = args.line_splitted.next() else {
+        syntax_error(args.file, args.line_number, LOG[18]);
+        return;
+    };
+    let target = match compile_markdown(std::path::Path::new(""), head2) {
+        Ok(a) => a,
+        Err(e) => {
+            syntax_error(args.file, args.line_number, &e);
+            return;
+        }
+    };
+    log!(Debug, format!("{}{}", LOG[24], target));
+    args.config.router_config.serve_files_info.insert(
+        markdown_url(head2, args.line_splitted.next()),
+        ServeFileData::from_with_content_type(
+            "/../".to_owned() + &target,
+            "text/html; charset=utf-8".to_owned(),
+        ),
+    );
+}
+/// 把 root 下的 export/<name> 渲染为 temp/<name>.html ，name 在子目录中时，temp 下对应的目录会被创建
+/// 返回相对于 root 的目标文件路径，失败时返回要报告的错误信息
+fn compile_markdown(root: &std::path::Path, name: &str) -> Result<String, String> {
+    let Ok(markdown) = std::fs::read_to_string(root.join("export/".to_owned() + name)) else {
+        return Err(LOG[20].to_owned());
+    };
+    let target = "temp/".to_owned() + name + ".html";
+    let path = root.join(&target);
+    let written = match path.parent() {
+        Some(a) => std::fs::create_dir_all(a),
+        None => Ok(()),
+    }
+    .and_then(|_| std::fs::write(&path, crate::drop::markdown::to_html(&markdown)));
+    if written.is_err() {
+        return Err(format!("{}{}", LOG[23], target));
+    }
+    Ok(target)
+}
 fn method_inject(mut args: MethodArgs) {
     if method_inject_haserr(&mut args) == Err(()) {
         syntax_error(args.file, args.line_number, LOG[25]);
@@ -282,29 +386,126 @@ fn method_inject(mut args: MethodArgs) {
 #[cfg(not(feature = "no-glisp"))]
 fn method_import_gl(args: MethodArgs) {
     if let Some(head2) = args.line_splitted.next() {
-        let env = &mut crate::glisp::core::default_env();
-        match crate::glisp::core::parse_eval(
-            read_to_string("config/".to_owned() + head2)
+        let env = &crate::glisp::core::default_env();
+        let path = "config/".to_owned() + head2;
+        match crate::glisp::core::parse_eval_file(
+            read_to_string(&path)
                 .result_shldfatal(-1, || log!(Fatal, format!("{}{}", LOG[22], head2))),
+            &path,
             env,
             Some(std::cell::RefCell::new(args.config).into()),
         ) {
             Ok(res) => log!(Info, format!("[{}] {} {}", LOG[32], LOG[33], res)),
-            Err(e) => match e {
-                crate::glisp::core::GError::Reason(msg) => {
-                    log!(Info, format!("[{}] {} {}", LOG[32], LOG[34], msg))
-                }
-            },
+            Err(e) => log!(Info, format!("[{}] {} {}", LOG[32], LOG[34], e.diagnostic())),
         }
     }
 }
 #[cfg(not(feature = "no-glisp"))]
 fn method_import_pipe(args: MethodArgs) {
     if let Some(head2) = args.line_splitted.next() {
-        args.config.router_config.pipe.push(
-            read_to_string("config/".to_owned() + head2)
-                .result_shldfatal(-1, || log!(Fatal, format!("{}{}", LOG[22], head2))),
+        let path = "config/".to_owned() + head2;
+        let source = read_to_string(&path)
+            .result_shldfatal(-1, || log!(Fatal, format!("{}{}", LOG[22], head2)));
+        match crate::glisp::pipe::Pipe::compile_file(&source, &path) {
+            Ok(pipe) => args.config.router_config.pipe.push(pipe),
+            Err(e) => syntax_error(
+                args.file,
+                args.line_number,
+                &format!("{} {}", LOG[34], e.diagnostic()),
+            ),
+        }
+    }
+}
+/// `@handler <file> <url-pattern>` ，file 的求值结果必须是一个 Lambda
+#[cfg(not(feature = "no-glisp"))]
+fn method_import_handler(args: MethodArgs) {
+    let (Some(head2), Some(head3)) = (args.line_splitted.next(), args.line_splitted.next()) else {
+        syntax_error(args.file, args.line_number, LOG[44]);
+        return;
+    };
+    let env = &crate::glisp::core::default_env();
+    let path = "config/".to_owned() + head2;
+    let code = read_to_string(&path)
+        .result_shldfatal(-1, || log!(Fatal, format!("{}{}", LOG[22], head2)));
+    match crate::glisp::core::parse_eval_file(
+        code,
+        &path,
+        env,
+        Some(std::cell::RefCell::new(&mut *args.config).into()),
+    ) {
+        Ok(crate::glisp::core::Expression::Lambda(lambda)) => args
+            .config
+            .router_config
+            .handlers
+            .push(crate::glisp::handler::Handler {
+                pattern: "/".to_owned() + head3,
+                lambda,
+            }),
+        Ok(_) => syntax_error(args.file, args.line_number, LOG[44]),
+        Err(e) => log!(Info, format!("[{}] {} {}", LOG[32], LOG[34], e.diagnostic())),
+    }
+}
+/// `template <file> [url] [vars.gl]` ，加载并编译一个模板，有 url 时把它挂载到 url ，`/` 代表根路径
+/// vars.gl 的求值结果必须是一个 Map ，它的键值对是渲染被挂载的模板时使用的变量
+#[cfg(not(feature = "no-glisp"))]
+fn method_template(args: MethodArgs) {
+    use crate::glisp::core::{default_env, parse_eval_file, Expression};
+
+    let Some(head2) = args.line_splitted.next() else {
+        syntax_error(args.file, args.line_number, LOG[18]);
+        return;
+    };
+    let template = match crate::glisp::template::Template::load(head2) {
+        Ok(a) => a,
+        Err(e) => {
+            syntax_error(
+                args.file,
+                args.line_number,
+                &format!("{} {}", LOG[34], e.diagnostic()),
+            );
+            return;
+        }
+    };
+    if let Some(head3) = args.line_splitted.next() {
+        let vars = match args.line_splitted.next() {
+            Some(head4) => {
+                let path = "config/".to_owned() + head4;
+                let code = read_to_string(&path)
+                    .result_shldfatal(-1, || log!(Fatal, format!("{}{}", LOG[22], head4)));
+                match parse_eval_file(code, &path, &default_env(), None) {
+                    Ok(Expression::Map(a)) => a,
+                    Ok(a) => {
+                        syntax_error(
+                            args.file,
+                            args.line_number,
+                            &format!(
+                                "{} template: {} must evaluate to a map, got {}",
+                                LOG[34], head4, a
+                            ),
+                        );
+                        return;
+                    }
+                    Err(e) => {
+                        syntax_error(
+                            args.file,
+                            args.line_number,
+                            &format!("{} {}", LOG[34], e.diagnostic()),
+                        );
+                        return;
+                    }
+                }
+            }
+            None => Default::default(),
+        };
+        let mut data = ServeFileData::from_with_content_type(
+            "/".to_owned() + head2,
+            "text/html; charset=utf-8".to_owned(),
         );
+        data.template = Some(crate::glisp::template::Mounted { template, vars });
+        args.config
+            .router_config
+            .serve_files_info
+            .insert("/".to_owned() + if head3 == "/" { "" } else { head3 }, data);
     }
 }
 fn method_log(args: MethodArgs) {
@@ -370,8 +571,8 @@ fn method_inject_haserr(args: &mut MethodArgs) -> Result<(), ()> {
                     } else {
                         return Err(());
                     },
-                    column: f.0,
-                    line: f.1,
+                    line: f.0,
+                    column: f.1,
                 });
             } else {
                 return Err(());
@@ -386,8 +587,8 @@ fn method_inject_haserr(args: &mut MethodArgs) -> Result<(), ()> {
                 } else {
                     return Err(());
                 },
-                column: f.0,
-                line: f.1,
+                line: f.0,
+                column: f.1,
             }]);
         } else {
             return Err(());
@@ -400,3 +601,24 @@ fn method_inject_haserr(args: &mut MethodArgs) -> Result<(), ()> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile_markdown() {
+        let root = std::env::temp_dir().join(format!("ttweb-markdown-{}", std::process::id()));
+        std::fs::create_dir_all(root.join("export/docs/guide")).unwrap();
+        std::fs::write(root.join("export/docs/guide/intro.md"), "# Hello").unwrap();
+
+        // temp/docs/guide 不存在，它会被创建
+        let target = compile_markdown(&root, "docs/guide/intro.md").unwrap();
+        assert_eq!(target, "temp/docs/guide/intro.md.html");
+        let html = std::fs::read_to_string(root.join(&target)).unwrap();
+        assert!(html.contains("Hello"));
+
+        assert!(compile_markdown(&root, "docs/missing.md").is_err());
+        std::fs::remove_dir_all(&root).unwrap();
+    }
+}
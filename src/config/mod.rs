@@ -43,6 +43,7 @@ mod vars;
 use crate::config::base::*;
 use crate::drop::http::HttpResponse;
 use crate::drop::log::LogLevel::*;
+use crate::https::server::ClientAuth;
 use crate::i18n::LOG;
 use crate::macros::*;
 use core::sync::atomic::Ordering;
@@ -66,15 +67,27 @@ pub static ENABLE_RETURN_IF_PIPE_ERR: AtomicBool = AtomicBool::new(true); // 参
 pub static ENABLE_CODE_BAD_REQUEST: AtomicBool = AtomicBool::new(false); // 参见引用之处
 pub static ENABLE_CODE_NOT_FOUND: AtomicBool = AtomicBool::new(false); // 参见引用之处
 pub static mut GLOBAL_ROUTER_CONFIG: Option<Arc<Mutex<RouterConfig>>> = None; //每一个请求都会收到一个对其的引用
-pub static mut SSL_CERTIFICATE: Option<Arc<RwLock<Vec<u8>>>> = None; //CA证书，这是 nightly 版本的一部分，仍在开发
-pub static mut SSL_PRAVITE_KEY: Option<Arc<RwLock<Vec<u8>>>> = None; //公钥，这是nightly版本的一部分，以后会被移除
+pub static SSL_IDENTITY: RwLock<SslIdentity> = RwLock::new(SslIdentity {
+    certificate: None,
+    pravite_key: None,
+}); //证书和私钥，这是 nightly 版本的一部分，仍在开发
+
+/// 服务端的证书和私钥，它们被放在同一个锁中，这样 ACME 续期时可以同时替换它们，
+/// 处理连接的线程不会读到新的证书和旧的私钥
+/// certificate: PEM 格式的证书链，或 DER 格式的单个证书
+/// pravite_key: 证书的私钥，参见 `https::key::PrivateKey::load`
+#[cfg_attr(not(feature = "nightly"), allow(dead_code))]
+pub struct SslIdentity {
+    pub certificate: Option<Vec<u8>>,
+    pub pravite_key: Option<Vec<u8>>,
+}
 
 /// 该结构体用以存储一个 `$_grflags` 及其对应的元数据
 /// 一个 OriginResponse 可能需要多个 ReplaceData ，因为这与 `$_grflags` 是一一对应的
 /// 通常来说，OriginResponse = 文件
 /// content: 要被替换的内容
-/// column: `$_grflags` 在文件中的列号
-/// line: `$_grflags` 在文件中的行号
+/// column: `$_grflags` 在这一行中的位置，是从 0 开始的字节偏移
+/// line: `$_grflags` 在文件中的行号，从 1 开始
 #[derive(Clone)]
 pub struct ReplaceData {
     pub content: String,
@@ -86,18 +99,75 @@ pub struct ReplaceData {
 /// 如果可能，应该尽量作为引用而非拷贝
 /// serve_file_info: 要挂载的文件，其中键是最终的 URL
 /// response_404: 404 NOT FOUND 响应，如果没有开启 404 状态码则不需要设置
-/// pipe: pipe 字符串（而非文件）的列表，会被从前往后的读取
+/// pipe: 在加载配置时就被解析的 pipe 的列表，会被从前往后的执行
+/// hsts: 可选的，通过 TLS 发送的响应会附带值为它的 `Strict-Transport-Security` 响应头
+/// client_auth: 每个监听地址在 TLS 握手时是否请求客户端证书，没有列出的监听地址不请求
+/// client_ca: 用于验证客户端证书的 CA 证书（DER 格式）
+/// client_cert_routes: 这些路径及其子路径下的请求必须带有经过验证的客户端证书，否则以 403 响应
+/// tls_session_lifetime: TLS 会话票据的有效期（秒），为 0 时不进行会话恢复
+/// tls_session_cache: 为 true 时 TLS 会话被保存在内存中，否则使用加密的无状态票据
 #[derive(Clone, Default)]
 pub struct RouterConfig {
     pub serve_files_info: HashMap<String, ServeFileData>,
     pub response_404: Option<HttpResponse>,
-    pub pipe: Vec<String>,
+    pub pipe: Vec<crate::glisp::pipe::Pipe>,
+    pub hsts: Option<String>,
+    pub client_auth: Vec<(std::net::SocketAddr, ClientAuth)>,
+    pub client_ca: Vec<Vec<u8>>,
+    pub client_cert_routes: Vec<String>,
+    pub tls_session_lifetime: u32,
+    pub tls_session_cache: bool,
+    pub handlers: Vec<crate::glisp::handler::Handler>,
+}
+
+/// 该结构体用以存储一个只用来重定向到 HTTPS 的监听地址
+/// addr: 监听地址，例如 `0.0.0.0:80`
+/// origin: 可选的，重定向的目标源，例如 `https://example.com` ，如果不设置，则根据请求的 Host 头推断
+/// code: 重定向使用的状态码，只能是 301 或 308
+///
+/// 关于 308 状态码，参见：https://datatracker.ietf.org/doc/html/rfc7538
+#[derive(Clone)]
+pub struct RedirectBind {
+    pub addr: String,
+    pub origin: Option<String>,
+    pub code: u16,
+}
+
+/// 该结构体用以存储 ACME 客户端的配置，如果 domains 为空，则不会自动申请证书
+/// directory: ACME 服务的目录 URL ，默认是 Let's Encrypt 的生产环境
+/// domains: 要申请证书的域名，第一个域名同时用作证书和密钥的文件名
+/// contact: 可选的，账户的联系方式，例如 `mailto:admin@example.com`
+/// renew_days: 证书的剩余有效期少于多少天时续期
+/// ca_certificates: 可选的，验证 ACME 服务器证书所用的 CA 证书文件，不设置时使用系统的 CA 证书
+///
+/// 关于 ACME ，参见：https://datatracker.ietf.org/doc/html/rfc8555
+#[derive(Clone)]
+pub struct AcmeConfig {
+    pub directory: String,
+    pub domains: Vec<String>,
+    pub contact: Option<String>,
+    pub renew_days: u32,
+    pub ca_certificates: Option<String>,
+}
+
+impl Default for AcmeConfig {
+    fn default() -> Self {
+        AcmeConfig {
+            directory: "https://acme-v02.api.letsencrypt.org/directory".to_owned(),
+            domains: vec![],
+            contact: None,
+            renew_days: 30,
+            ca_certificates: None,
+        }
+    }
 }
 
 /// 该结构体用以存储一个被托管的文件对应的元数据
 /// file_path: 被托管的文件的路径
 /// content_type: 被托管的文件的 MIME 类型，例如 `application/octet-stream`
 /// replace: 可选的，如果该文件里包含 `$_grflags` ，则存储它们及其对应的元数据
+/// markdown: 是否在每次请求时把该文件从 Markdown 渲染为 HTML
+/// template: 可选的，如果该文件是一个模板，则存储编译之后的模板及其变量，它在每次请求时被渲染
 ///
 /// 关于 MIME 类型的标准名，参见：https://datatracker.ietf.org/doc/html/rfc6838
 #[derive(Clone)]
@@ -105,12 +175,16 @@ pub struct ServeFileData {
     pub file_path: String,
     pub content_type: String,
     pub replace: Option<Vec<ReplaceData>>,
+    pub markdown: bool,
+    pub template: Option<crate::glisp::template::Mounted>,
 }
 
 /// 这是总的配置文件，应该尽量避免拷贝，应该尽量保证唯一性，因为拷贝的代价很大
 /// use_localtime: 是否使用本地时间而非 UTC 时间
 /// enable_debug: 是否使用 debug 模式运行本程序，这主要跟日志的输出有关，debug 模式会极大的拖慢性能
 /// addr_bind: 所有 IP 绑定的集合，例如 ["127.0.0.1:80", "127.0.0.1:22397", "[fe80::0]:80"]
+/// redirect_bind: 所有只用来重定向到 HTTPS 的 IP 绑定的集合，它们不会经过 Router
+/// acme: ACME 客户端的配置
 /// mime_bind: 所有额外的 MIME 类型绑定的集合，键是文件后缀名，值的类型的标准名
 /// status_codes: 启用的所有状态码，例如 [400, 404]
 ///
@@ -122,6 +196,8 @@ pub struct Config {
     pub use_localtime: bool,
     pub enable_debug: bool,
     pub addr_bind: Vec<String>,
+    pub redirect_bind: Vec<RedirectBind>,
+    pub acme: AcmeConfig,
     pub router_config: RouterConfig,
     pub mime_bind: HashMap<String, String>,
     pub status_codes: Vec<u16>,
@@ -135,6 +211,8 @@ impl ServeFileData {
                 _ => "application/octet-stream".to_owned(),
             },
             replace: None,
+            markdown: false,
+            template: None,
             file_path,
         }
     }
@@ -142,6 +220,8 @@ impl ServeFileData {
         ServeFileData {
             content_type,
             replace: None,
+            markdown: false,
+            template: None,
             file_path,
         }
     }
@@ -172,10 +252,19 @@ impl Config {
             use_localtime: true,
             enable_debug: false,
             addr_bind: vec![],
+            redirect_bind: vec![],
+            acme: AcmeConfig::default(),
             router_config: RouterConfig {
                 serve_files_info: HashMap::new(),
                 response_404: None,
                 pipe: vec![],
+                hsts: None,
+                client_auth: vec![],
+                client_ca: vec![],
+                client_cert_routes: vec![],
+                tls_session_lifetime: 7200,
+                tls_session_cache: false,
+                handlers: vec![],
             },
             mime_bind: HashMap::new(),
             status_codes: vec![],
@@ -8,7 +8,10 @@
 
 use std::sync::atomic::Ordering;
 
-use crate::{drop::http::HttpResponse, drop::log::LogLevel::*, i18n::LOG, macros::*};
+use crate::{
+    drop::http::HttpResponse, drop::log::LogLevel::*, https::server::ClientAuth, i18n::LOG,
+    macros::*,
+};
 
 use super::*;
 
@@ -47,7 +50,161 @@ pub fn method_set(args: MethodArgs) {
                 }
                 return;
             } else if head2 == "+addr" {
-                args.config.addr_bind.push(head3.to_owned());
+                addr_option(args, head3);
+                return;
+            } else if head2 == "hsts" {
+                hsts_option(args, head3);
+                return;
+            } else if head2 == "client-ca" {
+                client_ca_option(args, head3);
+                return;
+            } else if head2 == "+client-cert-route" {
+                args.config
+                    .router_config
+                    .client_cert_routes
+                    .push(head3.trim_end_matches('/').to_owned());
+                return;
+            } else if head2 == "tls-session-lifetime" {
+                // RFC 8446 规定票据的有效期不能超过 7 天
+                match head3.parse() {
+                    Ok(a) if a <= 604800 => args.config.router_config.tls_session_lifetime = a,
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
+                return;
+            } else if head2 == "tls-session-cache" {
+                pas_bool_option(
+                    &mut args.config.router_config.tls_session_cache,
+                    head3,
+                    args.file,
+                    args.line_number,
+                );
+                return;
+            } else if head2 == "acme-directory" {
+                args.config.acme.directory = head3.to_owned();
+                return;
+            } else if head2 == "+acme-domain" {
+                args.config.acme.domains.push(head3.to_owned());
+                return;
+            } else if head2 == "acme-ca-certificates" {
+                args.config.acme.ca_certificates = Some(head3.to_owned());
+                return;
+            } else if head2 == "acme-contact" {
+                args.config.acme.contact = Some(head3.to_owned());
+                return;
+            } else if head2 == "acme-renew-days" {
+                if let Ok(a) = head3.parse() {
+                    args.config.acme.renew_days = a;
+                } else {
+                    syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    );
+                }
+                return;
+            } else if head2 == "+glisp-path" {
+                #[cfg(not(feature = "no-glisp"))]
+                crate::glisp::module::SEARCH_PATH
+                    .write()
+                    .unwrap()
+                    .push("config/".to_owned() + head3.trim_end_matches('/') + "/");
+                return;
+            } else if head2 == "glisp-legacy-strings" {
+                let mut value = false;
+                pas_bool_option(&mut value, head3, args.file, args.line_number);
+                #[cfg(not(feature = "no-glisp"))]
+                crate::glisp::core::LEGACY_STRINGS.store(value, Ordering::Relaxed);
+                return;
+            } else if head2 == "+glisp-read-dir" {
+                #[cfg(not(feature = "no-glisp"))]
+                if let Some(a) = &mut crate::glisp::sandbox::request().write().unwrap().read {
+                    a.push(head3.to_owned());
+                }
+                return;
+            } else if head2 == "+glisp-write-dir" {
+                #[cfg(not(feature = "no-glisp"))]
+                if let Some(a) = &mut crate::glisp::sandbox::request().write().unwrap().write {
+                    a.push(head3.to_owned());
+                }
+                return;
+            } else if head2 == "+glisp-run" {
+                #[cfg(not(feature = "no-glisp"))]
+                if let Some(a) = &mut crate::glisp::sandbox::request().write().unwrap().run {
+                    a.push(head3.to_owned());
+                }
+                return;
+            } else if head2 == "glisp-max-steps" {
+                match head3.parse::<u64>() {
+                    #[cfg(not(feature = "no-glisp"))]
+                    Ok(a) if a > 0 => crate::glisp::budget::REQUEST.write().unwrap().steps = a,
+                    #[cfg(feature = "no-glisp")]
+                    Ok(a) if a > 0 => {}
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
+                return;
+            } else if head2 == "glisp-timeout" {
+                // 以毫秒为单位
+                match head3.parse::<u64>() {
+                    #[cfg(not(feature = "no-glisp"))]
+                    Ok(a) if a > 0 => {
+                        crate::glisp::budget::REQUEST.write().unwrap().time =
+                            std::time::Duration::from_millis(a)
+                    }
+                    #[cfg(feature = "no-glisp")]
+                    Ok(a) if a > 0 => {}
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
+                return;
+            } else if head2 == "glisp-max-size" {
+                match head3.parse::<usize>() {
+                    #[cfg(not(feature = "no-glisp"))]
+                    Ok(a) if a > 0 => crate::glisp::budget::REQUEST.write().unwrap().size = a,
+                    #[cfg(feature = "no-glisp")]
+                    Ok(a) if a > 0 => {}
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
+                return;
+            } else if head2 == "glisp-max-memory" {
+                match head3.parse::<usize>() {
+                    #[cfg(not(feature = "no-glisp"))]
+                    Ok(a) if a > 0 => crate::glisp::budget::REQUEST.write().unwrap().memory = a,
+                    #[cfg(feature = "no-glisp")]
+                    Ok(a) if a > 0 => {}
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
+                return;
+            } else if head2 == "glisp-max-depth" {
+                match head3.parse::<usize>() {
+                    #[cfg(not(feature = "no-glisp"))]
+                    Ok(a) if a > 0 => crate::glisp::core::MAX_DEPTH.store(a, Ordering::Relaxed),
+                    #[cfg(feature = "no-glisp")]
+                    Ok(a) if a > 0 => {}
+                    _ => syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], head3),
+                    ),
+                }
                 return;
             } else if head2 == "+mime" {
                 if let Some(head4) = args.line_splitted.next() {
@@ -86,19 +243,17 @@ pub fn method_set(args: MethodArgs) {
                 return;
             } else if head2 == "ssl-certificate" {
                 #[cfg(feature = "nightly")]
-                unsafe {
-                    SSL_CERTIFICATE = Some(std::sync::Arc::new(std::sync::RwLock::new(
-                        std::fs::read(head3.to_owned()).unwrap(),
-                    ))) // TODO: Error log
-                };
+                match std::fs::read(head3.to_owned()) {
+                    Ok(a) => SSL_IDENTITY.write().unwrap().certificate = Some(a),
+                    Err(_) => log!(Error, format!("{}{}", LOG[22], head3)),
+                }
                 return;
             } else if head2 == "ssl-pravite-key" {
                 #[cfg(feature = "nightly")]
-                unsafe {
-                    SSL_PRAVITE_KEY = Some(std::sync::Arc::new(std::sync::RwLock::new(
-                        std::fs::read(head3.to_owned()).unwrap(),
-                    ))) // TODO: Error log
-                };
+                match std::fs::read(head3.to_owned()) {
+                    Ok(a) => SSL_IDENTITY.write().unwrap().pravite_key = Some(a),
+                    Err(_) => log!(Error, format!("{}{}", LOG[22], head3)),
+                }
                 return;
             } else if head2 == "xrps-counter-cache-size" {
                 XRPS_COUNTER_CACHE_SIZE.store(
@@ -207,3 +362,97 @@ fn page_404_option(args: MethodArgs, head3: &str) {
     );
     args.config.router_config.response_404 = Some(res);
 }
+
+/// `$ +addr <地址> [redirect [源] [301|308]]`
+/// 带有 redirect 标记的地址不会被挂载到 Router ，而是把所有请求重定向到 HTTPS 源
+fn addr_option(args: MethodArgs, head3: &str) {
+    let addr = head3.strip_prefix("http://").unwrap_or(head3).to_owned();
+    match args.line_splitted.next() {
+        None => args.config.addr_bind.push(addr),
+        Some("redirect") => {
+            let mut bind = RedirectBind {
+                addr,
+                origin: None,
+                code: 301,
+            };
+            for e in args.line_splitted.by_ref() {
+                if e.starts_with("https://") {
+                    bind.origin = Some(e.trim_end_matches('/').to_owned());
+                } else if e == "301" || e == "308" {
+                    bind.code = e.parse().unwrap();
+                } else {
+                    syntax_error(args.file, args.line_number, &format!("{}{}", LOG[17], e));
+                    return;
+                }
+            }
+            args.config.redirect_bind.push(bind);
+        }
+        Some("client-cert") => {
+            let client_auth = match args.line_splitted.next() {
+                Some("no") => ClientAuth::No,
+                Some("optional") => ClientAuth::Optional,
+                Some("required") => ClientAuth::Required,
+                a => {
+                    syntax_error(
+                        args.file,
+                        args.line_number,
+                        &format!("{}{}", LOG[17], a.unwrap_or_default()),
+                    );
+                    return;
+                }
+            };
+            match std::net::ToSocketAddrs::to_socket_addrs(&addr)
+                .ok()
+                .and_then(|mut a| a.next())
+            {
+                Some(a) => args
+                    .config
+                    .router_config
+                    .client_auth
+                    .push((a, client_auth)),
+                None => {
+                    syntax_error(args.file, args.line_number, &format!("{}{}", LOG[28], addr));
+                    return;
+                }
+            }
+            args.config.addr_bind.push(addr);
+        }
+        Some(a) => syntax_error(args.file, args.line_number, &format!("{}{}", LOG[17], a)),
+    }
+}
+
+/// `$ client-ca <PEM 格式的 CA 证书文件>` ，可以多次使用以添加多个 CA
+fn client_ca_option(args: MethodArgs, head3: &str) {
+    let certificates = match std::fs::read(head3) {
+        Ok(a) => crate::https::server::load_certificates(&a),
+        Err(_) => vec![],
+    };
+    if certificates.is_empty() {
+        log!(Error, format!("{}{}", LOG[22], head3));
+        return;
+    }
+    args.config.router_config.client_ca.extend(certificates);
+}
+
+/// `$ hsts max-age=31536000; includeSubDomains` 或 `$ hsts no`
+/// 该行剩余的所有内容都会被原样作为 `Strict-Transport-Security` 响应头的值
+fn hsts_option(args: MethodArgs, head3: &str) {
+    if head3 == "no" {
+        args.config.router_config.hsts = None;
+        return;
+    }
+    if !head3.starts_with("max-age=") {
+        syntax_error(
+            args.file,
+            args.line_number,
+            &format!("{}{}", LOG[17], head3),
+        );
+        return;
+    }
+    let mut value = head3.to_owned();
+    for e in args.line_splitted.by_ref() {
+        value += " ";
+        value += e;
+    }
+    args.config.router_config.hsts = Some(value);
+}
@@ -0,0 +1,66 @@
+/* Tiny-Tiny-Web/Drop
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Base64 编码和解码
+//! encode 使用标准字母表并补齐 `=` ，用于 PEM 等场景
+//! encode_url 使用 URL 安全的字母表且不补齐，用于 JWS 等场景
+//! decode 同时接受两种字母表，忽略空白字符和 `=`
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc4648
+
+const STANDARD: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+const URL_SAFE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789-_";
+
+fn encode_with(data: &[u8], alphabet: &[u8; 64], pad: bool) -> String {
+    let mut str = String::with_capacity(data.len().div_ceil(3) * 4);
+    for chunk in data.chunks(3) {
+        let n = (chunk[0] as u32) << 16
+            | (*chunk.get(1).unwrap_or(&0) as u32) << 8
+            | *chunk.get(2).unwrap_or(&0) as u32;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                str.push(alphabet[(n >> (18 - i * 6)) as usize & 63] as char);
+            } else if pad {
+                str.push('=');
+            }
+        }
+    }
+    str
+}
+
+pub fn encode(data: &[u8]) -> String {
+    encode_with(data, STANDARD, true)
+}
+
+pub fn encode_url(data: &[u8]) -> String {
+    encode_with(data, URL_SAFE, false)
+}
+
+pub fn decode(str: &str) -> Option<Vec<u8>> {
+    let mut vec = Vec::with_capacity(str.len() / 4 * 3);
+    let mut n: u32 = 0;
+    let mut bits = 0;
+    for c in str.bytes() {
+        let v = match c {
+            b'A'..=b'Z' => c - b'A',
+            b'a'..=b'z' => c - b'a' + 26,
+            b'0'..=b'9' => c - b'0' + 52,
+            b'+' | b'-' => 62,
+            b'/' | b'_' => 63,
+            b'=' | b' ' | b'\t' | b'\r' | b'\n' => continue,
+            _ => return None,
+        };
+        n = n << 6 | v as u32;
+        bits += 6;
+        if bits >= 8 {
+            bits -= 8;
+            vec.push((n >> bits) as u8);
+        }
+    }
+    Some(vec)
+}
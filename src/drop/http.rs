@@ -17,9 +17,9 @@ pub struct HttpRequestError;
 /// url: 请求希望获取的页面的链接
 /// version: HTTP 协议的版本，例如 `1.1`
 /// headers: 该哈希表的键表示请求头的键，值表示请求头的值
-/// content: 可选的，请求的主体部分，以缓冲区的方式储存
+/// content: 可选的，请求的主体部分，它是一个只能读取 Content-Length 个字节的 Reader
 ///
-/// content 以缓冲区的方式储存的目的是避免过大的主体造成的一次性内存读取从而拖慢效率
+/// content 以 Reader 的方式储存的目的是避免过大的主体造成的一次性内存读取从而拖慢效率
 ///
 /// 一个请求头的例子: `Content-Length: 32`，`Content-Length` 是键，`32` 是值
 ///
@@ -29,7 +29,7 @@ pub struct HttpRequest<'a, T> {
     url: String,
     version: String,
     headers: HashMap<String, String>,
-    content: Option<std::iter::Take<std::io::Lines<std::io::BufReader<&'a mut T>>>>,
+    content: Option<std::io::Take<&'a mut T>>,
 }
 impl<'a, T> HttpRequest<'a, T> {
     pub fn new() -> Self {
@@ -79,14 +79,20 @@ impl<'a, T> HttpRequest<'a, T> {
                     }
                 },
             };
-            request.headers.insert(k.to_string(), v.to_string());
+            request.headers.insert(k.to_string(), v.trim().to_string());
         }
         Ok(request)
     }
+    /// 请求头的键不区分大小写
     pub fn get_header(&self, str: String) -> Option<&String> {
-        self.headers.get(&str)
+        self.headers
+            .iter()
+            .find(|(k, _)| k.eq_ignore_ascii_case(&str))
+            .map(|(_, v)| v)
+    }
+    pub fn headers(&self) -> &HashMap<String, String> {
+        &self.headers
     }
-    #[allow(dead_code)]
     pub fn request_method(&self) -> &String {
         &self.request_method
     }
@@ -98,13 +104,74 @@ impl<'a, T> HttpRequest<'a, T> {
         &self.version
     }
 
-    pub fn set_content(
-        &mut self,
-        content: Option<std::iter::Take<std::io::Lines<std::io::BufReader<&'a mut T>>>>,
-    ) {
+    pub fn set_content(&mut self, content: Option<std::io::Take<&'a mut T>>) {
         self.content = content;
     }
 }
+impl<T: std::io::Read> HttpRequest<'_, T> {
+    /// 读取全部的请求主体，没有主体时返回空的 Vec
+    pub fn read_content(&mut self) -> Vec<u8> {
+        let mut vec = vec![];
+        if let Some(content) = &mut self.content {
+            let _ = std::io::Read::read_to_end(content, &mut vec);
+        }
+        vec
+    }
+}
+
+/// 返回状态码对应的状态，例如 `404` 对应 `404 NOT FOUND` ，不认识的状态码返回 None
+///
+/// See: https://www.rfc-editor.org/rfc/rfc9110#section-15
+pub fn status_text(code: u16) -> Option<String> {
+    let reason = match code {
+        100 => "CONTINUE",
+        101 => "SWITCHING PROTOCOLS",
+        200 => "OK",
+        201 => "CREATED",
+        202 => "ACCEPTED",
+        203 => "NON-AUTHORITATIVE INFORMATION",
+        204 => "NO CONTENT",
+        205 => "RESET CONTENT",
+        206 => "PARTIAL CONTENT",
+        300 => "MULTIPLE CHOICES",
+        301 => "MOVED PERMANENTLY",
+        302 => "FOUND",
+        303 => "SEE OTHER",
+        304 => "NOT MODIFIED",
+        307 => "TEMPORARY REDIRECT",
+        308 => "PERMANENT REDIRECT",
+        400 => "BAD REQUEST",
+        401 => "UNAUTHORIZED",
+        402 => "PAYMENT REQUIRED",
+        403 => "FORBIDDEN",
+        404 => "NOT FOUND",
+        405 => "METHOD NOT ALLOWED",
+        406 => "NOT ACCEPTABLE",
+        408 => "REQUEST TIMEOUT",
+        409 => "CONFLICT",
+        410 => "GONE",
+        411 => "LENGTH REQUIRED",
+        412 => "PRECONDITION FAILED",
+        413 => "CONTENT TOO LARGE",
+        414 => "URI TOO LONG",
+        415 => "UNSUPPORTED MEDIA TYPE",
+        416 => "RANGE NOT SATISFIABLE",
+        417 => "EXPECTATION FAILED",
+        422 => "UNPROCESSABLE CONTENT",
+        426 => "UPGRADE REQUIRED",
+        428 => "PRECONDITION REQUIRED",
+        429 => "TOO MANY REQUESTS",
+        431 => "REQUEST HEADER FIELDS TOO LARGE",
+        500 => "INTERNAL SERVER ERROR",
+        501 => "NOT IMPLEMENTED",
+        502 => "BAD GATEWAY",
+        503 => "SERVICE UNAVAILABLE",
+        504 => "GATEWAY TIMEOUT",
+        505 => "HTTP VERSION NOT SUPPORTED",
+        _ => return None,
+    };
+    Some(format!("{} {}", code, reason))
+}
 
 /// 可以构造一个标准的 HTTP 响应字符串
 ///
@@ -0,0 +1,520 @@
+/* Tiny-Tiny-Web/Drop
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! 一个严格的 JSON 解析器和序列化器
+//! 对象以 Vec 而非 HashMap 储存，以保留键的顺序，这样序列化的结果是确定的
+//! 解析失败时，JsonError 会给出出错的位置（行号和列号都从 1 开始，列号以字符计）
+//!
+//! 例如：
+//! ```
+//! let value = json::parse(r#"{"a": [1, 2.5, "x"]}"#)?;
+//! value.get("a"); // Some(Array([Number(1.0), Number(2.5), String("x")]))
+//! value.to_string(); // {"a":[1,2.5,"x"]}
+//! ```
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8259
+
+use std::fmt;
+
+/// 嵌套的最大深度，防止恶意输入导致栈溢出
+const MAX_DEPTH: usize = 512;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum JsonValue {
+    Null,
+    Bool(bool),
+    Number(f64),
+    String(String),
+    Array(Vec<JsonValue>),
+    Object(Vec<(String, JsonValue)>),
+}
+
+/// offset: 出错位置的字节偏移量
+#[derive(Debug, Clone, PartialEq)]
+pub struct JsonError {
+    pub message: &'static str,
+    pub offset: usize,
+    pub line: usize,
+    pub column: usize,
+}
+impl fmt::Display for JsonError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} at line {}, column {}",
+            self.message, self.line, self.column
+        )
+    }
+}
+
+impl JsonValue {
+    /// 如果本值是对象，返回键对应的值，如果有重复的键，返回最后一个
+    pub fn get(&self, key: &str) -> Option<&JsonValue> {
+        match self {
+            JsonValue::Object(a) => a.iter().rev().find(|(k, _)| k == key).map(|(_, v)| v),
+            _ => None,
+        }
+    }
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            JsonValue::String(a) => Some(a),
+            _ => None,
+        }
+    }
+    pub fn as_array(&self) -> Option<&Vec<JsonValue>> {
+        match self {
+            JsonValue::Array(a) => Some(a),
+            _ => None,
+        }
+    }
+    #[allow(dead_code)]
+    pub fn as_f64(&self) -> Option<f64> {
+        match self {
+            JsonValue::Number(a) => Some(*a),
+            _ => None,
+        }
+    }
+    #[allow(dead_code)]
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            JsonValue::Bool(a) => Some(*a),
+            _ => None,
+        }
+    }
+
+    /// 以 indent 个空格缩进的、多行的形式序列化
+    pub fn to_string_pretty(&self, indent: usize) -> String {
+        let mut str = String::new();
+        self.write(&mut str, Some(indent), 0);
+        str
+    }
+
+    fn write(&self, out: &mut String, indent: Option<usize>, level: usize) {
+        let newline = |out: &mut String, level: usize| {
+            if let Some(indent) = indent {
+                out.push('\n');
+                out.extend(std::iter::repeat_n(' ', indent * level));
+            }
+        };
+        match self {
+            JsonValue::Null => out.push_str("null"),
+            JsonValue::Bool(a) => out.push_str(if *a { "true" } else { "false" }),
+            JsonValue::Number(a) => write_number(out, *a),
+            JsonValue::String(a) => write_string(out, a),
+            JsonValue::Array(a) => {
+                out.push('[');
+                for (i, e) in a.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    e.write(out, indent, level + 1);
+                }
+                if !a.is_empty() {
+                    newline(out, level);
+                }
+                out.push(']');
+            }
+            JsonValue::Object(a) => {
+                out.push('{');
+                for (i, (k, v)) in a.iter().enumerate() {
+                    if i != 0 {
+                        out.push(',');
+                    }
+                    newline(out, level + 1);
+                    write_string(out, k);
+                    out.push(':');
+                    if indent.is_some() {
+                        out.push(' ');
+                    }
+                    v.write(out, indent, level + 1);
+                }
+                if !a.is_empty() {
+                    newline(out, level);
+                }
+                out.push('}');
+            }
+        }
+    }
+}
+impl fmt::Display for JsonValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut str = String::new();
+        self.write(&mut str, None, 0);
+        f.write_str(&str)
+    }
+}
+
+/// JSON 不能表示 NaN 和无穷大，它们被序列化为 null
+fn write_number(out: &mut String, n: f64) {
+    if !n.is_finite() {
+        out.push_str("null");
+    } else if n.fract() == 0.0 && n.abs() < 1e15 {
+        out.push_str(&(n as i64).to_string());
+    } else {
+        out.push_str(&n.to_string());
+    }
+}
+
+fn write_string(out: &mut String, str: &str) {
+    out.push('"');
+    for c in str.chars() {
+        match c {
+            '"' => out.push_str("\\\""),
+            '\\' => out.push_str("\\\\"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            '\t' => out.push_str("\\t"),
+            '\u{08}' => out.push_str("\\b"),
+            '\u{0c}' => out.push_str("\\f"),
+            c if (c as u32) < 0x20 => out.push_str(&format!("\\u{:04x}", c as u32)),
+            c => out.push(c),
+        }
+    }
+    out.push('"');
+}
+
+pub fn parse(str: &str) -> Result<JsonValue, JsonError> {
+    let mut parser = Parser {
+        src: str,
+        bytes: str.as_bytes(),
+        pos: 0,
+    };
+    parser.skip_whitespace();
+    let value = parser.parse_value(0)?;
+    parser.skip_whitespace();
+    if parser.pos != parser.bytes.len() {
+        return Err(parser.error("Unexpected trailing characters"));
+    }
+    Ok(value)
+}
+
+struct Parser<'a> {
+    src: &'a str,
+    bytes: &'a [u8],
+    pos: usize,
+}
+impl Parser<'_> {
+    fn error(&self, message: &'static str) -> JsonError {
+        let offset = self.pos.min(self.bytes.len());
+        let before = &self.src[..floor_char_boundary(self.src, offset)];
+        let line = before.matches('\n').count() + 1;
+        let column = match before.rfind('\n') {
+            Some(a) => before[a + 1..].chars().count() + 1,
+            None => before.chars().count() + 1,
+        };
+        JsonError {
+            message,
+            offset,
+            line,
+            column,
+        }
+    }
+
+    fn peek(&self) -> Option<u8> {
+        self.bytes.get(self.pos).copied()
+    }
+
+    fn skip_whitespace(&mut self) {
+        while let Some(b' ' | b'\t' | b'\r' | b'\n') = self.peek() {
+            self.pos += 1;
+        }
+    }
+
+    fn expect_literal(&mut self, literal: &str, value: JsonValue) -> Result<JsonValue, JsonError> {
+        if self.bytes[self.pos..].starts_with(literal.as_bytes()) {
+            self.pos += literal.len();
+            Ok(value)
+        } else {
+            Err(self.error("Invalid literal"))
+        }
+    }
+
+    fn parse_value(&mut self, depth: usize) -> Result<JsonValue, JsonError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Nesting too deep"));
+        }
+        match self.peek() {
+            None => Err(self.error("Unexpected end of input")),
+            Some(b'n') => self.expect_literal("null", JsonValue::Null),
+            Some(b't') => self.expect_literal("true", JsonValue::Bool(true)),
+            Some(b'f') => self.expect_literal("false", JsonValue::Bool(false)),
+            Some(b'"') => Ok(JsonValue::String(self.parse_string()?)),
+            Some(b'-' | b'0'..=b'9') => self.parse_number(),
+            Some(b'[') => {
+                self.pos += 1;
+                let mut vec = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b']') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Array(vec));
+                }
+                loop {
+                    self.skip_whitespace();
+                    vec.push(self.parse_value(depth + 1)?);
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b']') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Array(vec));
+                        }
+                        None => return Err(self.error("Unexpected end of input")),
+                        _ => return Err(self.error("Expected ',' or ']'")),
+                    }
+                }
+            }
+            Some(b'{') => {
+                self.pos += 1;
+                let mut vec = vec![];
+                self.skip_whitespace();
+                if self.peek() == Some(b'}') {
+                    self.pos += 1;
+                    return Ok(JsonValue::Object(vec));
+                }
+                loop {
+                    self.skip_whitespace();
+                    if self.peek() != Some(b'"') {
+                        return Err(self.error("Expected string key"));
+                    }
+                    let key = self.parse_string()?;
+                    self.skip_whitespace();
+                    if self.peek() != Some(b':') {
+                        return Err(self.error("Expected ':'"));
+                    }
+                    self.pos += 1;
+                    self.skip_whitespace();
+                    vec.push((key, self.parse_value(depth + 1)?));
+                    self.skip_whitespace();
+                    match self.peek() {
+                        Some(b',') => self.pos += 1,
+                        Some(b'}') => {
+                            self.pos += 1;
+                            return Ok(JsonValue::Object(vec));
+                        }
+                        None => return Err(self.error("Unexpected end of input")),
+                        _ => return Err(self.error("Expected ',' or '}'")),
+                    }
+                }
+            }
+            _ => Err(self.error("Unexpected character")),
+        }
+    }
+
+    fn parse_number(&mut self) -> Result<JsonValue, JsonError> {
+        let start = self.pos;
+        let digits = |p: &mut Self| {
+            let start = p.pos;
+            while let Some(b'0'..=b'9') = p.peek() {
+                p.pos += 1;
+            }
+            p.pos - start
+        };
+        if self.peek() == Some(b'-') {
+            self.pos += 1;
+        }
+        if self.peek() == Some(b'0') {
+            self.pos += 1;
+        } else if digits(self) == 0 {
+            return Err(self.error("Invalid number"));
+        }
+        if self.peek() == Some(b'.') {
+            self.pos += 1;
+            if digits(self) == 0 {
+                return Err(self.error("Invalid number"));
+            }
+        }
+        if let Some(b'e' | b'E') = self.peek() {
+            self.pos += 1;
+            if let Some(b'+' | b'-') = self.peek() {
+                self.pos += 1;
+            }
+            if digits(self) == 0 {
+                return Err(self.error("Invalid number"));
+            }
+        }
+        match self.src[start..self.pos].parse() {
+            Ok(a) => Ok(JsonValue::Number(a)),
+            Err(_) => {
+                self.pos = start;
+                Err(self.error("Invalid number"))
+            }
+        }
+    }
+
+    fn parse_hex4(&mut self) -> Result<u32, JsonError> {
+        let hex = match self.src.get(self.pos..self.pos + 4) {
+            Some(a) => a,
+            None => return Err(self.error("Invalid unicode escape")),
+        };
+        match u32::from_str_radix(hex, 16) {
+            Ok(a) if hex.bytes().all(|c| c.is_ascii_hexdigit()) => {
+                self.pos += 4;
+                Ok(a)
+            }
+            _ => Err(self.error("Invalid unicode escape")),
+        }
+    }
+
+    fn parse_string(&mut self) -> Result<String, JsonError> {
+        self.pos += 1; // '"'
+        let mut str = String::new();
+        loop {
+            let start = self.pos;
+            while let Some(c) = self.peek() {
+                if c == b'"' || c == b'\\' || c < 0x20 {
+                    break;
+                }
+                self.pos += 1;
+            }
+            str.push_str(&self.src[start..self.pos]);
+            match self.peek() {
+                None => return Err(self.error("Unterminated string")),
+                Some(b'"') => {
+                    self.pos += 1;
+                    return Ok(str);
+                }
+                Some(b'\\') => {
+                    self.pos += 1;
+                    let c = match self.peek() {
+                        Some(b'"') => '"',
+                        Some(b'\\') => '\\',
+                        Some(b'/') => '/',
+                        Some(b'b') => '\u{08}',
+                        Some(b'f') => '\u{0c}',
+                        Some(b'n') => '\n',
+                        Some(b'r') => '\r',
+                        Some(b't') => '\t',
+                        Some(b'u') => {
+                            let escape_start = self.pos - 1;
+                            self.pos += 1;
+                            let mut code = self.parse_hex4()?;
+                            if (0xd800..0xdc00).contains(&code) {
+                                // UTF-16 代理对
+                                if !self.bytes[self.pos..].starts_with(b"\\u") {
+                                    self.pos = escape_start;
+                                    return Err(self.error("Unpaired surrogate"));
+                                }
+                                self.pos += 2;
+                                let low = self.parse_hex4()?;
+                                if !(0xdc00..0xe000).contains(&low) {
+                                    self.pos = escape_start;
+                                    return Err(self.error("Unpaired surrogate"));
+                                }
+                                code = 0x10000 + ((code - 0xd800) << 10) + (low - 0xdc00);
+                            }
+                            match char::from_u32(code) {
+                                Some(c) => str.push(c),
+                                None => {
+                                    self.pos = escape_start;
+                                    return Err(self.error("Unpaired surrogate"));
+                                }
+                            }
+                            continue;
+                        }
+                        _ => return Err(self.error("Invalid escape")),
+                    };
+                    self.pos += 1;
+                    str.push(c);
+                }
+                Some(_) => return Err(self.error("Control character in string")),
+            }
+        }
+    }
+}
+
+fn floor_char_boundary(str: &str, mut index: usize) -> usize {
+    while !str.is_char_boundary(index) {
+        index -= 1;
+    }
+    index
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse() {
+        let value =
+            parse(r#" {"a": [1, -2.5e1, "x"], "b": {"c": null}, "d": true, "a": false} "#).unwrap();
+        assert_eq!(value.get("a"), Some(&JsonValue::Bool(false)));
+        assert_eq!(value.get("b").unwrap().get("c"), Some(&JsonValue::Null));
+        assert_eq!(value.get("d").unwrap().as_bool(), Some(true));
+        assert_eq!(value.get("e"), None);
+        let JsonValue::Object(entries) = &value else {
+            panic!("expected an object");
+        };
+        assert_eq!(
+            entries[0].1,
+            JsonValue::Array(vec![
+                JsonValue::Number(1.0),
+                JsonValue::Number(-25.0),
+                JsonValue::String("x".to_owned())
+            ])
+        );
+        assert_eq!(parse("0").unwrap().as_f64(), Some(0.0));
+        assert_eq!(parse("[]").unwrap().as_array().unwrap().len(), 0);
+    }
+
+    #[test]
+    fn test_parse_string() {
+        assert_eq!(
+            parse(r#""a\"\\\/\b\f\n\r\té""#).unwrap().as_str(),
+            Some("a\"\\/\u{08}\u{0c}\n\r\té")
+        );
+        // UTF-16 代理对
+        assert_eq!(
+            parse(r#""\ud83d\ude00""#).unwrap().as_str(),
+            Some("\u{1f600}")
+        );
+        assert_eq!(parse("\"中文\"").unwrap().as_str(), Some("中文"));
+    }
+
+    #[test]
+    fn test_parse_error() {
+        let error = |str: &str| {
+            let e = parse(str).unwrap_err();
+            (e.message, e.line, e.column)
+        };
+        assert_eq!(error(""), ("Unexpected end of input", 1, 1));
+        assert_eq!(error("[1,\n  2 3]"), ("Expected ',' or ']'", 2, 5));
+        assert_eq!(error(r#"{"中": tru}"#), ("Invalid literal", 1, 7));
+        assert_eq!(error("01"), ("Unexpected trailing characters", 1, 2));
+        assert_eq!(error("1."), ("Invalid number", 1, 3));
+        assert_eq!(error("{1: 2}"), ("Expected string key", 1, 2));
+        assert_eq!(error(r#""\ud83d""#), ("Unpaired surrogate", 1, 2));
+        assert_eq!(error(r#""\x""#), ("Invalid escape", 1, 3));
+        assert_eq!(error("\"a\nb\""), ("Control character in string", 1, 3));
+        assert_eq!(error("\"abc"), ("Unterminated string", 1, 5));
+        assert_eq!(error(&"[".repeat(1000)), ("Nesting too deep", 1, 514));
+    }
+
+    #[test]
+    fn test_stringify() {
+        let value = JsonValue::Object(vec![
+            ("a".to_owned(), JsonValue::Number(1.0)),
+            ("b".to_owned(), JsonValue::Number(0.5)),
+            ("c".to_owned(), JsonValue::Number(f64::NAN)),
+            ("d".to_owned(), JsonValue::String("\"\n\u{1}".to_owned())),
+            ("e".to_owned(), JsonValue::Array(vec![])),
+        ]);
+        assert_eq!(
+            value.to_string(),
+            r#"{"a":1,"b":0.5,"c":null,"d":"\"\n\u0001","e":[]}"#
+        );
+        assert_eq!(
+            JsonValue::Array(vec![JsonValue::Null, JsonValue::Object(vec![])]).to_string_pretty(2),
+            "[\n  null,\n  {}\n]"
+        );
+        assert_eq!(
+            parse(&value.to_string()).unwrap().to_string(),
+            value.to_string()
+        );
+    }
+}
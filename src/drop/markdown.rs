@@ -0,0 +1,1367 @@
+/* Tiny-Tiny-Web/Drop
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Markdown 到 HTML 的渲染器，遵循 CommonMark 的规则，另外支持 GFM 的表格和删除线
+//!
+//! 渲染分为两步：
+//! 先把文本按行解析为块（段落、标题、列表、引用、代码块、表格等），引用和列表项的内容被递归地解析，
+//! 同时收集链接的引用定义（ `[label]: url "title"` ）
+//! 再渲染每个块，块中的文本在这时被解析为行内元素（强调、链接、图片、代码等），所以引用定义可以出现在使用它的地方之后
+//!
+//! 普通文本中的 `&` 、 `<` 、 `>` 和 `"` 会被转义，HTML 块和行内的 HTML 标签原样输出
+//! 与 CommonMark 不同的地方：引用定义只能写在一行中，列表和引用的懒惰延续行只按行判断
+//!
+//! 例如：
+//! ```
+//! markdown::to_html("# 标题\n\n*强调* 和 [链接](https://example.com)");
+//! // <h1>标题</h1>
+//! // <p><em>强调</em> 和 <a href="https://example.com">链接</a></p>
+//! ```
+//!
+//! See: https://spec.commonmark.org/
+//! See: https://github.github.com/gfm/
+
+use std::collections::HashMap;
+
+/// 引用和列表，以及链接文本的嵌套的最大深度，防止恶意输入导致栈溢出
+const MAX_DEPTH: usize = 32;
+
+/// 链接的引用定义，键是被规范化的标签
+type Refs = HashMap<String, (String, Option<String>)>;
+
+enum Block {
+    /// 未被解析的行内文本
+    Paragraph(String),
+    Heading(usize, String),
+    /// 第一个值是代码的语言
+    Code(Option<String>, String),
+    Html(String),
+    Rule,
+    Quote(Vec<Block>),
+    List {
+        start: Option<u64>,
+        tight: bool,
+        items: Vec<Vec<Block>>,
+    },
+    Table {
+        aligns: Vec<Option<&'static str>>,
+        head: Vec<String>,
+        rows: Vec<Vec<String>>,
+    },
+}
+
+pub fn to_html(markdown: &str) -> String {
+    let lines = markdown.lines().map(expand_tabs).collect::<Vec<_>>();
+    let mut refs = Refs::new();
+    let (blocks, _) = parse_blocks(&lines, &mut refs, 0);
+    let mut out = String::new();
+    for block in &blocks {
+        render_block(&mut out, block, &refs, false);
+    }
+    out
+}
+
+/// 把行首的制表符展开为空格，制表位的宽度为 4
+fn expand_tabs(line: &str) -> String {
+    let mut out = String::new();
+    for (i, c) in line.char_indices() {
+        match c {
+            ' ' => out.push(' '),
+            '\t' => out.extend(std::iter::repeat_n(' ', 4 - out.len() % 4)),
+            _ => {
+                out.push_str(&line[i..]);
+                break;
+            }
+        }
+    }
+    out
+}
+
+fn indent(line: &str) -> usize {
+    line.len() - line.trim_start_matches(' ').len()
+}
+
+fn is_blank(line: &str) -> bool {
+    line.trim().is_empty()
+}
+
+/// 缩进少于 4 个空格时，返回去掉缩进之后的部分
+fn unindent(line: &str) -> Option<&str> {
+    (indent(line) < 4).then(|| line.trim_start_matches(' '))
+}
+
+fn atx_heading(t: &str) -> Option<(usize, &str)> {
+    let level = t.len() - t.trim_start_matches('#').len();
+    let rest = &t[level..];
+    if !(1..=6).contains(&level) || !(rest.is_empty() || rest.starts_with(' ')) {
+        return None;
+    }
+    let rest = rest.trim();
+    // 结尾的 `#` 序列之前必须是空格
+    let content = match rest.trim_end_matches('#') {
+        "" => "",
+        a if a.ends_with(' ') => a.trim_end(),
+        _ => rest,
+    };
+    Some((level, content))
+}
+
+fn is_rule(t: &str) -> bool {
+    let chars = t.chars().filter(|c| *c != ' ').collect::<Vec<_>>();
+    chars.len() >= 3 && matches!(chars[0], '*' | '-' | '_') && chars.iter().all(|c| *c == chars[0])
+}
+
+/// 返回代码块的围栏字符、围栏的长度和信息字符串
+fn fence(t: &str) -> Option<(char, usize, &str)> {
+    let c = t.chars().next().filter(|c| *c == '`' || *c == '~')?;
+    let len = t.len() - t.trim_start_matches(c).len();
+    let info = t[len..].trim();
+    (len >= 3 && !(c == '`' && info.contains('`'))).then_some((c, len, info))
+}
+
+fn setext_underline(t: &str) -> Option<usize> {
+    let t = t.trim_end();
+    if t.is_empty() {
+        None
+    } else if t.chars().all(|c| c == '=') {
+        Some(1)
+    } else if t.chars().all(|c| c == '-') {
+        Some(2)
+    } else {
+        None
+    }
+}
+
+/// marker: 无序列表的符号，或有序列表的数字之后的 `.` 或 `)`
+/// width: 列表项的内容的缩进
+struct ItemStart {
+    ordered: bool,
+    marker: char,
+    start: u64,
+    width: usize,
+    content: String,
+}
+
+fn list_item(line: &str) -> Option<ItemStart> {
+    let t = unindent(line)?;
+    let indent = indent(line);
+    let (ordered, marker, start, marker_width) = match t.chars().next()? {
+        c @ ('-' | '+' | '*') => (false, c, 0, 1),
+        _ => {
+            let digits = t.len() - t.trim_start_matches(|c: char| c.is_ascii_digit()).len();
+            let marker = t[digits..]
+                .chars()
+                .next()
+                .filter(|c| *c == '.' || *c == ')')?;
+            if !(1..=9).contains(&digits) {
+                return None;
+            }
+            (true, marker, t[..digits].parse().ok()?, digits + 1)
+        }
+    };
+    let rest = &t[marker_width..];
+    let spaces = match indent_of_rest(rest)? {
+        // 空的列表项，或者内容是缩进的代码块
+        a if a > 4 || is_blank(rest) => 1,
+        a => a,
+    };
+    let width = indent + marker_width + spaces;
+    Some(ItemStart {
+        ordered,
+        marker,
+        start,
+        width,
+        content: line.get(width..).unwrap_or("").to_owned(),
+    })
+}
+
+/// 列表的符号之后必须是空格或行尾
+fn indent_of_rest(rest: &str) -> Option<usize> {
+    match indent(rest) {
+        0 if !rest.is_empty() => None,
+        a => Some(a),
+    }
+}
+
+/// HTML 块的结束条件
+enum HtmlEnd {
+    /// 在包含这个字符串的行结束
+    Contains(&'static str),
+    /// 在空行之前结束
+    Blank,
+}
+
+const BLOCK_TAGS: &[&str] = &[
+    "address",
+    "article",
+    "aside",
+    "base",
+    "basefont",
+    "blockquote",
+    "body",
+    "caption",
+    "center",
+    "col",
+    "colgroup",
+    "dd",
+    "details",
+    "dialog",
+    "dir",
+    "div",
+    "dl",
+    "dt",
+    "fieldset",
+    "figcaption",
+    "figure",
+    "footer",
+    "form",
+    "frame",
+    "frameset",
+    "h1",
+    "h2",
+    "h3",
+    "h4",
+    "h5",
+    "h6",
+    "head",
+    "header",
+    "hr",
+    "html",
+    "iframe",
+    "legend",
+    "li",
+    "link",
+    "main",
+    "menu",
+    "menuitem",
+    "nav",
+    "noframes",
+    "ol",
+    "optgroup",
+    "option",
+    "p",
+    "param",
+    "search",
+    "section",
+    "summary",
+    "table",
+    "tbody",
+    "td",
+    "tfoot",
+    "th",
+    "thead",
+    "title",
+    "tr",
+    "track",
+    "ul",
+];
+
+/// in_paragraph 为 true 时，只返回可以打断段落的 HTML 块
+fn html_block(t: &str, in_paragraph: bool) -> Option<HtmlEnd> {
+    if !t.starts_with('<') {
+        return None;
+    }
+    let lower = t.to_ascii_lowercase();
+    if lower.starts_with("<!--") {
+        return Some(HtmlEnd::Contains("-->"));
+    }
+    let name_start = if lower.starts_with("</") { 2 } else { 1 };
+    let name = lower[name_start..]
+        .chars()
+        .take_while(|c| c.is_ascii_alphanumeric() || *c == '-')
+        .collect::<String>();
+    // 标签名必须以字母开头，之后是空白、`/` 、`>` 或行尾，所以 `<http://a.com>` 这样的自动链接不是 HTML 块
+    let after = lower[name_start + name.len()..].chars().next();
+    let ends_name = name.starts_with(|c: char| c.is_ascii_alphabetic())
+        && after.is_none_or(|c| c.is_ascii_whitespace() || c == '/' || c == '>');
+    if !ends_name {
+        return None;
+    }
+    if name_start == 1 {
+        match name.as_str() {
+            "script" => return Some(HtmlEnd::Contains("</script>")),
+            "pre" => return Some(HtmlEnd::Contains("</pre>")),
+            "style" => return Some(HtmlEnd::Contains("</style>")),
+            "textarea" => return Some(HtmlEnd::Contains("</textarea>")),
+            _ => {}
+        }
+    }
+    if BLOCK_TAGS.contains(&name.as_str()) {
+        return Some(HtmlEnd::Blank);
+    }
+    // 单独占一行的完整的标签
+    let t = t.trim_end();
+    let complete = t.ends_with('>') && !t[1..t.len() - 1].contains(['<', '>']);
+    (!in_paragraph && complete).then_some(HtmlEnd::Blank)
+}
+
+/// 这一行是否会打断段落
+fn interrupts(line: &str) -> bool {
+    let Some(t) = unindent(line) else {
+        return false;
+    };
+    atx_heading(t).is_some()
+        || is_rule(t)
+        || fence(t).is_some()
+        || t.starts_with('>')
+        || html_block(t, true).is_some()
+        // 空的列表项和不从 1 开始的有序列表不能打断段落
+        || list_item(line).is_some_and(|a| !is_blank(&a.content) && (!a.ordered || a.start == 1))
+}
+
+/// 把表格的一行分割为单元格， `\|` 代表单元格中的 `|`
+fn split_cells(t: &str) -> Vec<String> {
+    let t = t.trim();
+    let t = t.strip_prefix('|').unwrap_or(t);
+    let t = match t.strip_suffix('|') {
+        Some(a) if !a.ends_with('\\') => a,
+        _ => t,
+    };
+    let mut cells = vec![String::new()];
+    let mut chars = t.chars();
+    while let Some(c) = chars.next() {
+        let cell = cells.last_mut().unwrap();
+        match c {
+            '\\' => match chars.next() {
+                Some('|') => cell.push('|'),
+                Some(a) => {
+                    cell.push('\\');
+                    cell.push(a);
+                }
+                None => cell.push('\\'),
+            },
+            '|' => cells.push(String::new()),
+            c => cell.push(c),
+        }
+    }
+    cells.into_iter().map(|a| a.trim().to_owned()).collect()
+}
+
+/// 表格的分隔行，返回每一列的对齐方式
+fn table_aligns(line: &str) -> Option<Vec<Option<&'static str>>> {
+    let t = unindent(line)?;
+    if !t.contains('|') {
+        return None;
+    }
+    split_cells(t)
+        .iter()
+        .map(|cell| {
+            let dashes = cell.trim_matches(':');
+            if dashes.is_empty() || !dashes.chars().all(|c| c == '-') {
+                return None;
+            }
+            Some(match (cell.starts_with(':'), cell.ends_with(':')) {
+                (true, true) => Some("center"),
+                (true, false) => Some("left"),
+                (false, true) => Some("right"),
+                _ => None,
+            })
+        })
+        .collect()
+}
+
+/// 规范化链接的标签：忽略大小写，合并空白
+fn normalize_label(label: &str) -> String {
+    label
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .to_lowercase()
+}
+
+/// 解析 `[label]: url "title"` 形式的引用定义
+fn ref_definition(line: &str) -> Option<(String, String, Option<String>)> {
+    let t = unindent(line)?.strip_prefix('[')?;
+    let end = t.find("]:")?;
+    let label = &t[..end];
+    if label.trim().is_empty() || label.contains(['[', ']']) {
+        return None;
+    }
+    let rest = t[end + 2..].chars().collect::<Vec<_>>();
+    let (url, title, i) = link_destination(&rest, 0)?;
+    (!url.is_empty() && i == rest.len()).then(|| (normalize_label(label), url, title))
+}
+
+/// 解析块，返回的 bool 表示顶层的块之间是否有空行，它决定了列表是否是松散的
+fn parse_blocks(lines: &[String], refs: &mut Refs, depth: usize) -> (Vec<Block>, bool) {
+    let mut blocks = vec![];
+    let mut gap = false;
+    let mut blank_before = false;
+    let mut i = 0;
+    while i < lines.len() {
+        let line = &lines[i];
+        if is_blank(line) {
+            blank_before = true;
+            i += 1;
+            continue;
+        }
+        gap |= blank_before && !blocks.is_empty();
+        blank_before = false;
+
+        let Some(t) = unindent(line) else {
+            // 缩进的代码块
+            let mut end = i;
+            let mut j = i;
+            while j < lines.len() && (is_blank(&lines[j]) || indent(&lines[j]) >= 4) {
+                if !is_blank(&lines[j]) {
+                    end = j + 1;
+                }
+                j += 1;
+            }
+            let code = lines[i..end]
+                .iter()
+                .map(|a| a.get(4..).unwrap_or("").to_owned() + "\n")
+                .collect();
+            blocks.push(Block::Code(None, code));
+            i = end;
+            continue;
+        };
+
+        if let Some((c, len, info)) = fence(t) {
+            let fence_indent = indent(line);
+            let mut code = String::new();
+            i += 1;
+            while i < lines.len() {
+                let line = &lines[i];
+                i += 1;
+                let closing = unindent(line)
+                    .and_then(fence)
+                    .is_some_and(|(c2, len2, info2)| c2 == c && len2 >= len && info2.is_empty());
+                if closing {
+                    break;
+                }
+                // 去掉和开头的围栏相同的缩进
+                code.push_str(&line[indent(line).min(fence_indent)..]);
+                code.push('\n');
+            }
+            let lang = info.split_whitespace().next().map(str::to_owned);
+            blocks.push(Block::Code(lang, code));
+            continue;
+        }
+        if let Some((level, content)) = atx_heading(t) {
+            blocks.push(Block::Heading(level, content.to_owned()));
+            i += 1;
+            continue;
+        }
+        if is_rule(t) {
+            blocks.push(Block::Rule);
+            i += 1;
+            continue;
+        }
+        if depth < MAX_DEPTH && t.starts_with('>') {
+            let mut inner: Vec<String> = vec![];
+            while i < lines.len() {
+                let line = &lines[i];
+                match unindent(line).and_then(|a| a.strip_prefix('>')) {
+                    Some(rest) => inner.push(rest.strip_prefix(' ').unwrap_or(rest).to_owned()),
+                    // 懒惰的延续行
+                    None if !is_blank(line)
+                        && inner.last().is_some_and(|a| !is_blank(a))
+                        && !interrupts(line) =>
+                    {
+                        inner.push(line.trim_start().to_owned())
+                    }
+                    None => break,
+                }
+                i += 1;
+            }
+            blocks.push(Block::Quote(parse_blocks(&inner, refs, depth + 1).0));
+            continue;
+        }
+        if depth < MAX_DEPTH {
+            if let Some(first) = list_item(line) {
+                let (list, next) = parse_list(lines, i, first, refs, depth);
+                blocks.push(list);
+                i = next;
+                continue;
+            }
+        }
+        if let Some(aligns) = lines.get(i + 1).and_then(|a| table_aligns(a)) {
+            let head = split_cells(t);
+            if t.contains('|') && head.len() == aligns.len() {
+                let mut rows = vec![];
+                i += 2;
+                while i < lines.len() && !is_blank(&lines[i]) && !interrupts(&lines[i]) {
+                    let mut row = split_cells(&lines[i]);
+                    row.resize(head.len(), String::new());
+                    rows.push(row);
+                    i += 1;
+                }
+                blocks.push(Block::Table { aligns, head, rows });
+                continue;
+            }
+        }
+        if let Some(end) = html_block(t, false) {
+            let mut html = vec![];
+            while i < lines.len() {
+                let line = &lines[i];
+                match end {
+                    HtmlEnd::Blank if is_blank(line) => break,
+                    HtmlEnd::Contains(a) if line.to_ascii_lowercase().contains(a) => {
+                        html.push(line.as_str());
+                        i += 1;
+                        break;
+                    }
+                    _ => html.push(line),
+                }
+                i += 1;
+            }
+            blocks.push(Block::Html(html.join("\n")));
+            continue;
+        }
+
+        // 段落，它开头的引用定义被收集起来
+        let mut paragraph = vec![t];
+        let mut setext = None;
+        i += 1;
+        while i < lines.len() && !is_blank(&lines[i]) {
+            if let Some(level) = unindent(&lines[i]).and_then(setext_underline) {
+                setext = Some(level);
+                i += 1;
+                break;
+            }
+            if interrupts(&lines[i]) {
+                break;
+            }
+            paragraph.push(lines[i].trim_start());
+            i += 1;
+        }
+        while let Some((label, url, title)) = paragraph.first().and_then(|a| ref_definition(a)) {
+            refs.entry(label).or_insert((url, title));
+            paragraph.remove(0);
+        }
+        if paragraph.is_empty() {
+            continue;
+        }
+        let text = paragraph.join("\n").trim_end().to_owned();
+        blocks.push(match setext {
+            Some(level) => Block::Heading(level, text),
+            None => Block::Paragraph(text),
+        });
+    }
+    (blocks, gap)
+}
+
+/// 解析从第 i 行开始的列表，返回列表和它之后的第一行
+fn parse_list(
+    lines: &[String],
+    mut i: usize,
+    first: ItemStart,
+    refs: &mut Refs,
+    depth: usize,
+) -> (Block, usize) {
+    let (ordered, marker, start) = (first.ordered, first.marker, first.start);
+    let mut items = vec![];
+    let mut loose = false;
+    let mut item = Some(first);
+    while let Some(current) = item.take() {
+        let mut inner = vec![current.content];
+        i += 1;
+        while i < lines.len() {
+            let line = &lines[i];
+            if is_blank(line) {
+                // 列表项最多只能以一个空行开始
+                if inner.len() == 1 && is_blank(&inner[0]) {
+                    break;
+                }
+                inner.push(String::new());
+            } else if indent(line) >= current.width {
+                inner.push(line[current.width..].to_owned());
+            } else if inner.last().is_some_and(|a| !is_blank(a))
+                && !interrupts(line)
+                && list_item(line).is_none()
+            {
+                // 懒惰的延续行
+                inner.push(line.trim_start().to_owned());
+            } else {
+                break;
+            }
+            i += 1;
+        }
+        // 结尾的空行不属于列表项
+        let mut trailing = 0;
+        while inner.len() > 1 && inner.last().is_some_and(|a| is_blank(a)) {
+            inner.pop();
+            trailing += 1;
+        }
+        let (blocks, gap) = parse_blocks(&inner, refs, depth + 1);
+        loose |= gap;
+        items.push(blocks);
+
+        item = lines
+            .get(i)
+            .filter(|a| !unindent(a).is_some_and(is_rule))
+            .and_then(|a| list_item(a))
+            .filter(|a| a.ordered == ordered && a.marker == marker);
+        if item.is_some() {
+            loose |= trailing > 0;
+        } else {
+            // 列表之后的空行留给外层判断
+            i -= trailing;
+        }
+    }
+    let list = Block::List {
+        start: ordered.then_some(start),
+        tight: !loose,
+        items,
+    };
+    (list, i)
+}
+
+fn escape_char(out: &mut String, c: char) {
+    match c {
+        '&' => out.push_str("&amp;"),
+        '<' => out.push_str("&lt;"),
+        '>' => out.push_str("&gt;"),
+        '"' => out.push_str("&quot;"),
+        c => out.push(c),
+    }
+}
+
+fn escape(str: &str) -> String {
+    let mut out = String::new();
+    str.chars().for_each(|c| escape_char(&mut out, c));
+    out
+}
+
+/// 转义链接的地址，空格和非 ASCII 字符会被百分号编码
+fn escape_url(url: &str) -> String {
+    let mut out = String::new();
+    for b in url.bytes() {
+        match b {
+            b'&' => out.push_str("&amp;"),
+            b'a'..=b'z' | b'A'..=b'Z' | b'0'..=b'9' => out.push(b as char),
+            b if b"-._~:/?#[]@!$'()*+,;=%".contains(&b) => out.push(b as char),
+            b => out.push_str(&format!("%{:02X}", b)),
+        }
+    }
+    out
+}
+
+fn render_block(out: &mut String, block: &Block, refs: &Refs, tight: bool) {
+    match block {
+        Block::Paragraph(text) if tight => out.push_str(&render_inline(text, refs, 0)),
+        Block::Paragraph(text) => {
+            out.push_str(&format!("<p>{}</p>\n", render_inline(text, refs, 0)));
+        }
+        Block::Heading(level, text) => {
+            let text = render_inline(text, refs, 0);
+            out.push_str(&format!("<h{}>{}</h{}>\n", level, text, level));
+        }
+        Block::Code(lang, code) => {
+            match lang {
+                Some(a) => out.push_str(&format!("<pre><code class=\"language-{}\">", escape(a))),
+                None => out.push_str("<pre><code>"),
+            }
+            out.push_str(&escape(code));
+            out.push_str("</code></pre>\n");
+        }
+        Block::Html(html) => {
+            out.push_str(html);
+            out.push('\n');
+        }
+        Block::Rule => out.push_str("<hr />\n"),
+        Block::Quote(blocks) => {
+            out.push_str("<blockquote>\n");
+            blocks
+                .iter()
+                .for_each(|a| render_block(out, a, refs, false));
+            out.push_str("</blockquote>\n");
+        }
+        Block::List {
+            start,
+            tight,
+            items,
+        } => {
+            let tag = match start {
+                Some(1) => {
+                    out.push_str("<ol>\n");
+                    "ol"
+                }
+                Some(a) => {
+                    out.push_str(&format!("<ol start=\"{}\">\n", a));
+                    "ol"
+                }
+                None => {
+                    out.push_str("<ul>\n");
+                    "ul"
+                }
+            };
+            for item in items {
+                out.push_str("<li>");
+                for (i, block) in item.iter().enumerate() {
+                    // 紧凑的列表中，段落不被 `<p>` 包围
+                    let bare = *tight && matches!(block, Block::Paragraph(_));
+                    if i == 0 && !bare {
+                        out.push('\n');
+                    }
+                    render_block(out, block, refs, *tight);
+                    if bare && i + 1 < item.len() {
+                        out.push('\n');
+                    }
+                }
+                out.push_str("</li>\n");
+            }
+            out.push_str(&format!("</{}>\n", tag));
+        }
+        Block::Table { aligns, head, rows } => {
+            let row = |out: &mut String, cells: &[String], tag: &str| {
+                out.push_str("<tr>\n");
+                for (cell, align) in cells.iter().zip(aligns) {
+                    match align {
+                        Some(a) => out.push_str(&format!("<{} align=\"{}\">", tag, a)),
+                        None => out.push_str(&format!("<{}>", tag)),
+                    }
+                    out.push_str(&render_inline(cell, refs, 0));
+                    out.push_str(&format!("</{}>\n", tag));
+                }
+                out.push_str("</tr>\n");
+            };
+            out.push_str("<table>\n<thead>\n");
+            row(out, head, "th");
+            out.push_str("</thead>\n");
+            if !rows.is_empty() {
+                out.push_str("<tbody>\n");
+                rows.iter().for_each(|a| row(out, a, "td"));
+                out.push_str("</tbody>\n");
+            }
+            out.push_str("</table>\n");
+        }
+    }
+}
+
+/// 强调的分隔符（ `*` 、 `_` 或 `~` 的连续序列）
+/// count: 还没有被匹配的分隔符的个数
+/// length: 原本的个数
+/// opens, closes: 匹配之后，在这个分隔符处开始和结束的标签
+struct Delimiter {
+    c: char,
+    count: usize,
+    length: usize,
+    can_open: bool,
+    can_close: bool,
+    opens: Vec<&'static str>,
+    closes: Vec<&'static str>,
+}
+
+enum Piece {
+    Html(String),
+    Delimiter(Delimiter),
+}
+
+fn is_escapable(c: char) -> bool {
+    c.is_ascii_punctuation()
+}
+
+fn is_punctuation(c: char) -> bool {
+    !c.is_alphanumeric() && !c.is_whitespace()
+}
+
+/// 找到所有的代码段和互相匹配的方括号
+/// 返回值的第一项的键是代码段开头的位置，值是它结尾的位置，第二项的键是 `[` 的位置，值是与它匹配的 `]` 的位置
+fn scan(chars: &[char]) -> (HashMap<usize, usize>, HashMap<usize, usize>) {
+    // 每一段连续的反引号的位置和长度，以及之后第一段相同长度的反引号
+    let mut runs = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        if chars[i] == '`' {
+            let len = chars[i..].iter().take_while(|c| **c == '`').count();
+            runs.push((i, len));
+            i += len;
+        } else {
+            i += 1;
+        }
+    }
+    let mut next_same = vec![None; runs.len()];
+    let mut last = HashMap::new();
+    for (k, (_, len)) in runs.iter().enumerate().rev() {
+        next_same[k] = last.insert(*len, k);
+    }
+
+    let mut code = HashMap::new();
+    let mut brackets = HashMap::new();
+    let mut stack = vec![];
+    let mut i = 0;
+    while i < chars.len() {
+        match chars[i] {
+            '\\' => i += 2,
+            '`' => match runs.binary_search_by_key(&i, |a| a.0) {
+                Ok(k) => {
+                    let (_, len) = runs[k];
+                    match next_same[k] {
+                        Some(end) => {
+                            code.insert(i, runs[end].0 + len);
+                            i = runs[end].0 + len;
+                        }
+                        None => i += len,
+                    }
+                }
+                Err(_) => i += 1,
+            },
+            '[' => {
+                stack.push(i);
+                i += 1;
+            }
+            ']' => {
+                if let Some(a) = stack.pop() {
+                    brackets.insert(a, i);
+                }
+                i += 1;
+            }
+            _ => i += 1,
+        }
+    }
+    (code, brackets)
+}
+
+fn skip_whitespace(chars: &[char], mut i: usize) -> usize {
+    while chars.get(i).is_some_and(|c| c.is_whitespace()) {
+        i += 1;
+    }
+    i
+}
+
+/// 解析链接的地址和可选的标题，返回它们和之后的第一个非空白字符的位置
+fn link_destination(chars: &[char], i: usize) -> Option<(String, Option<String>, usize)> {
+    let mut i = skip_whitespace(chars, i);
+    let mut url = String::new();
+    if chars.get(i) == Some(&'<') {
+        i += 1;
+        loop {
+            match *chars.get(i)? {
+                '>' => break,
+                '<' | '\n' => return None,
+                '\\' if chars.get(i + 1).is_some_and(|c| is_escapable(*c)) => {
+                    url.push(chars[i + 1]);
+                    i += 1;
+                }
+                c => url.push(c),
+            }
+            i += 1;
+        }
+        i += 1;
+    } else {
+        let mut depth = 0;
+        while let Some(&c) = chars.get(i) {
+            match c {
+                '\\' if chars.get(i + 1).is_some_and(|c| is_escapable(*c)) => {
+                    url.push(chars[i + 1]);
+                    i += 2;
+                    continue;
+                }
+                // 和 cmark 一样限制括号的嵌套，否则未闭合的括号会使每个链接都扫描到文本的结尾
+                '(' if depth == MAX_DEPTH => return None,
+                '(' => depth += 1,
+                ')' if depth == 0 => break,
+                ')' => depth -= 1,
+                c if c.is_whitespace() || c.is_control() => break,
+                _ => {}
+            }
+            url.push(c);
+            i += 1;
+        }
+        if depth != 0 {
+            return None;
+        }
+    }
+    let before_title = i;
+    i = skip_whitespace(chars, i);
+    let title = match chars.get(i) {
+        Some(&open @ ('"' | '\'' | '(')) if i > before_title => {
+            let close = if open == '(' { ')' } else { open };
+            let mut title = String::new();
+            i += 1;
+            loop {
+                match *chars.get(i)? {
+                    c if c == close => break,
+                    '\\' if chars.get(i + 1).is_some_and(|c| is_escapable(*c)) => {
+                        title.push(chars[i + 1]);
+                        i += 1;
+                    }
+                    c => title.push(c),
+                }
+                i += 1;
+            }
+            i += 1;
+            Some(title)
+        }
+        _ => None,
+    };
+    Some((url, title, skip_whitespace(chars, i)))
+}
+
+/// 解析 `[` 或 `![` 之后的链接，open 是 `[` 的位置，close 是与它匹配的 `]` 的位置
+/// 返回链接的地址、标题和链接之后的位置
+fn link(
+    chars: &[char],
+    open: usize,
+    close: usize,
+    refs: &Refs,
+) -> Option<(String, Option<String>, usize)> {
+    match chars.get(close + 1) {
+        Some('(') => {
+            let (url, title, i) = link_destination(chars, close + 2)?;
+            (chars.get(i) == Some(&')')).then_some((url, title, i + 1))
+        }
+        Some('[') => {
+            let end = (close + 2..chars.len()).find(|a| chars[*a] == ']')?;
+            let label = chars[close + 2..end].iter().collect::<String>();
+            let label = if label.trim().is_empty() {
+                chars[open + 1..close].iter().collect()
+            } else {
+                label
+            };
+            let (url, title) = refs.get(&normalize_label(&label))?;
+            Some((url.clone(), title.clone(), end + 1))
+        }
+        _ => {
+            let label = chars[open + 1..close].iter().collect::<String>();
+            let (url, title) = refs.get(&normalize_label(&label))?;
+            Some((url.clone(), title.clone(), close + 1))
+        }
+    }
+}
+
+/// `<http://example.com>` 或 `<a@example.com>` 形式的自动链接，返回地址、文本和之后的位置
+fn autolink(chars: &[char], i: usize) -> Option<(String, String, usize)> {
+    let end = (i + 1..chars.len().min(i + 2048))
+        .take_while(|a| !matches!(chars[*a], ' ' | '<' | '\n'))
+        .find(|a| chars[*a] == '>')?;
+    let text = chars[i + 1..end].iter().collect::<String>();
+    let scheme = text.split(':').next().unwrap_or("");
+    let is_scheme = text.contains(':')
+        && (2..=32).contains(&scheme.len())
+        && scheme.starts_with(|c: char| c.is_ascii_alphabetic())
+        && scheme
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || matches!(c, '+' | '.' | '-'));
+    if is_scheme {
+        return Some((text.clone(), text, end + 1));
+    }
+    let (local, domain) = text.split_once('@')?;
+    let is_email = !local.is_empty()
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".!#$%&'*+/=?^_`{|}~-".contains(c))
+        && domain
+            .split('.')
+            .all(|a| !a.is_empty() && a.chars().all(|c| c.is_ascii_alphanumeric() || c == '-'));
+    is_email.then(|| ("mailto:".to_owned() + &text, text, end + 1))
+}
+
+/// 行内的 HTML 标签或注释，返回它之后的位置
+fn inline_html(chars: &[char], i: usize) -> Option<usize> {
+    let rest = &chars[i..chars.len().min(i + 4096)];
+    if rest.starts_with(&['<', '!', '-', '-']) {
+        let end = (4..rest.len()).find(|a| rest[*a - 2..=*a] == ['-', '-', '>'])?;
+        return Some(i + end + 1);
+    }
+    let mut j = if rest.get(1) == Some(&'/') { 2 } else { 1 };
+    if !rest.get(j)?.is_ascii_alphabetic() {
+        return None;
+    }
+    while rest
+        .get(j)
+        .is_some_and(|c| c.is_ascii_alphanumeric() || *c == '-')
+    {
+        j += 1;
+    }
+    if !matches!(rest.get(j), Some(' ' | '\n' | '/' | '>')) {
+        return None;
+    }
+    let mut quote = None;
+    loop {
+        let c = *rest.get(j)?;
+        match (quote, c) {
+            (Some(q), c) if c == q => quote = None,
+            (Some(_), _) => {}
+            (None, '"' | '\'') => quote = Some(c),
+            (None, '>') => return Some(i + j + 1),
+            (None, '<') => return None,
+            _ => {}
+        }
+        j += 1;
+    }
+}
+
+/// `&amp;` 、 `&#123;` 或 `&#x7B;` 形式的实体引用，返回它之后的位置
+fn entity(chars: &[char], i: usize) -> Option<usize> {
+    let end = (i + 1..chars.len().min(i + 34)).find(|a| chars[*a] == ';')?;
+    let body = chars[i + 1..end].iter().collect::<String>();
+    let valid = match body.strip_prefix('#') {
+        Some(a) => match a.strip_prefix(['x', 'X']) {
+            Some(hex) => (1..=6).contains(&hex.len()) && hex.chars().all(|c| c.is_ascii_hexdigit()),
+            None => (1..=7).contains(&a.len()) && a.chars().all(|c| c.is_ascii_digit()),
+        },
+        None => {
+            body.starts_with(|c: char| c.is_ascii_alphabetic())
+                && body.chars().all(|c| c.is_ascii_alphanumeric())
+        }
+    };
+    valid.then_some(end + 1)
+}
+
+fn strip_tags(html: &str) -> String {
+    let mut out = String::new();
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+fn render_inline(text: &str, refs: &Refs, depth: usize) -> String {
+    let chars = text.chars().collect::<Vec<_>>();
+    let (code, brackets) = scan(&chars);
+    let mut pieces = vec![];
+    let mut html = String::new();
+    let mut i = 0;
+    while i < chars.len() {
+        let c = chars[i];
+        match c {
+            '\\' if chars.get(i + 1) == Some(&'\n') => {
+                html.push_str("<br />\n");
+                i += 2;
+            }
+            '\\' if chars.get(i + 1).is_some_and(|c| is_escapable(*c)) => {
+                escape_char(&mut html, chars[i + 1]);
+                i += 2;
+            }
+            '`' => {
+                let len = chars[i..].iter().take_while(|c| **c == '`').count();
+                match code.get(&i) {
+                    Some(&end) => {
+                        let content = chars[i + len..end - len]
+                            .iter()
+                            .map(|c| if *c == '\n' { ' ' } else { *c })
+                            .collect::<String>();
+                        let content =
+                            match content.strip_prefix(' ').and_then(|a| a.strip_suffix(' ')) {
+                                Some(a) if !content.trim().is_empty() => a,
+                                _ => &content,
+                            };
+                        html.push_str(&format!("<code>{}</code>", escape(content)));
+                        i = end;
+                    }
+                    None => {
+                        html.extend(std::iter::repeat_n('`', len));
+                        i += len;
+                    }
+                }
+            }
+            '*' | '_' | '~' => {
+                let len = chars[i..].iter().take_while(|a| **a == c).count();
+                // 开头和结尾被视为空白
+                let before = if i == 0 { ' ' } else { chars[i - 1] };
+                let after = chars.get(i + len).copied().unwrap_or(' ');
+                let left = !after.is_whitespace()
+                    && (!is_punctuation(after) || before.is_whitespace() || is_punctuation(before));
+                let right = !before.is_whitespace()
+                    && (!is_punctuation(before) || after.is_whitespace() || is_punctuation(after));
+                let (can_open, can_close) = match c {
+                    '_' => (
+                        left && (!right || is_punctuation(before)),
+                        right && (!left || is_punctuation(after)),
+                    ),
+                    '~' if len > 2 => (false, false),
+                    _ => (left, right),
+                };
+                pieces.push(Piece::Html(std::mem::take(&mut html)));
+                pieces.push(Piece::Delimiter(Delimiter {
+                    c,
+                    count: len,
+                    length: len,
+                    can_open,
+                    can_close,
+                    opens: vec![],
+                    closes: vec![],
+                }));
+                i += len;
+            }
+            '!' | '[' => {
+                let open = if c == '!' { i + 1 } else { i };
+                let found = (c == '[' || chars.get(open) == Some(&'['))
+                    .then(|| brackets.get(&open))
+                    .flatten()
+                    .filter(|_| depth < MAX_DEPTH)
+                    .and_then(|close| Some((*close, link(&chars, open, *close, refs)?)));
+                let Some((close, (url, title, end))) = found else {
+                    escape_char(&mut html, c);
+                    i += 1;
+                    continue;
+                };
+                let inner = chars[open + 1..close].iter().collect::<String>();
+                let inner = render_inline(&inner, refs, depth + 1);
+                let title = title
+                    .map(|a| format!(" title=\"{}\"", escape(&a)))
+                    .unwrap_or_default();
+                if c == '!' {
+                    html.push_str(&format!(
+                        "<img src=\"{}\" alt=\"{}\"{} />",
+                        escape_url(&url),
+                        strip_tags(&inner),
+                        title
+                    ));
+                } else {
+                    html.push_str(&format!(
+                        "<a href=\"{}\"{}>{}</a>",
+                        escape_url(&url),
+                        title,
+                        inner
+                    ));
+                }
+                i = end;
+            }
+            '<' => {
+                if let Some((url, text, end)) = autolink(&chars, i) {
+                    html.push_str(&format!(
+                        "<a href=\"{}\">{}</a>",
+                        escape_url(&url),
+                        escape(&text)
+                    ));
+                    i = end;
+                } else if let Some(end) = inline_html(&chars, i) {
+                    html.extend(&chars[i..end]);
+                    i = end;
+                } else {
+                    html.push_str("&lt;");
+                    i += 1;
+                }
+            }
+            '&' => match entity(&chars, i) {
+                Some(end) => {
+                    html.extend(&chars[i..end]);
+                    i = end;
+                }
+                None => {
+                    html.push_str("&amp;");
+                    i += 1;
+                }
+            },
+            ' ' => {
+                let len = chars[i..].iter().take_while(|c| **c == ' ').count();
+                match chars.get(i + len) {
+                    // 行尾的两个以上的空格是硬换行
+                    Some('\n') if len >= 2 => html.push_str("<br />\n"),
+                    Some('\n') => html.push('\n'),
+                    _ => html.extend(std::iter::repeat_n(' ', len)),
+                }
+                i += len + usize::from(chars.get(i + len) == Some(&'\n'));
+            }
+            c => {
+                escape_char(&mut html, c);
+                i += 1;
+            }
+        }
+    }
+    pieces.push(Piece::Html(html));
+    process_emphasis(&mut pieces);
+
+    let mut out = String::new();
+    for piece in pieces {
+        match piece {
+            Piece::Html(a) => out.push_str(&a),
+            Piece::Delimiter(a) => {
+                a.closes
+                    .iter()
+                    .for_each(|t| out.push_str(&format!("</{}>", t)));
+                out.extend(std::iter::repeat_n(a.c, a.count));
+                a.opens
+                    .iter()
+                    .for_each(|t| out.push_str(&format!("<{}>", t)));
+            }
+        }
+    }
+    out
+}
+
+/// CommonMark 的 process emphasis 算法：从左到右为每个可以关闭的分隔符寻找最近的可以打开的分隔符
+fn process_emphasis(pieces: &mut [Piece]) {
+    fn delimiter(piece: &mut Piece) -> Option<&mut Delimiter> {
+        match piece {
+            Piece::Delimiter(a) => Some(a),
+            _ => None,
+        }
+    }
+
+    // 对于同一种分隔符，上一次没有找到匹配的位置，之后不需要再向前查找
+    let mut bottoms = HashMap::new();
+    let mut c = 0;
+    while c < pieces.len() {
+        let Some(closer) = delimiter(&mut pieces[c]).filter(|a| a.can_close && a.count > 0) else {
+            c += 1;
+            continue;
+        };
+        let (ch, count, length, closer_can_open) =
+            (closer.c, closer.count, closer.length, closer.can_open);
+        let key = (ch, closer_can_open, length % 3);
+        let bottom = bottoms.get(&key).copied().unwrap_or(0);
+        let opener = (bottom..c).rev().find(|o| match &pieces[*o] {
+            Piece::Delimiter(a) if a.c == ch && a.can_open && a.count > 0 => {
+                if ch == '~' {
+                    return a.count == count;
+                }
+                // 两边都可以打开和关闭时，长度之和不能是 3 的倍数，除非两边都是 3 的倍数
+                let both = a.can_close || closer_can_open;
+                !(both && (a.length + length) % 3 == 0 && !(a.length % 3 == 0 && length % 3 == 0))
+            }
+            _ => false,
+        });
+        let Some(o) = opener else {
+            bottoms.insert(key, c);
+            c += 1;
+            continue;
+        };
+        let opener = delimiter(&mut pieces[o]).unwrap();
+        let used = if ch == '~' || (opener.count >= 2 && count >= 2) {
+            2.min(count)
+        } else {
+            1
+        };
+        let tag = match (ch, used) {
+            ('~', _) => "del",
+            (_, 2) => "strong",
+            _ => "em",
+        };
+        opener.count -= used;
+        opener.opens.insert(0, tag);
+        // 之间的分隔符不能再被匹配
+        for piece in &mut pieces[o + 1..c] {
+            if let Some(a) = delimiter(piece) {
+                a.can_open = false;
+                a.can_close = false;
+            }
+        }
+        let closer = delimiter(&mut pieces[c]).unwrap();
+        closer.count -= used;
+        closer.closes.push(tag);
+        if closer.count == 0 {
+            c += 1;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_headings() {
+        assert_eq!(
+            to_html("# a\n## b ##\n###### c\n####### d\n#e"),
+            "<h1>a</h1>\n<h2>b</h2>\n<h6>c</h6>\n<p>####### d\n#e</p>\n"
+        );
+        assert_eq!(to_html("a\n===\nb\n---"), "<h1>a</h1>\n<h2>b</h2>\n");
+    }
+
+    #[test]
+    fn test_lists() {
+        // 列表项之间有空行时，列表是松散的，每一项的内容都被放进段落
+        assert_eq!(
+            to_html("- a\n- b\n\n- c"),
+            "<ul>\n<li>\n<p>a</p>\n</li>\n<li>\n<p>b</p>\n</li>\n<li>\n<p>c</p>\n</li>\n</ul>\n"
+        );
+        // 分隔符改变时开始一个新的列表
+        assert_eq!(
+            to_html("1. a\n2. b\n\n3) c"),
+            "<ol>\n<li>a</li>\n<li>b</li>\n</ol>\n<ol start=\"3\">\n<li>c</li>\n</ol>\n"
+        );
+        assert_eq!(
+            to_html("- a\n  - b\n- c"),
+            "<ul>\n<li>a\n<ul>\n<li>b</li>\n</ul>\n</li>\n<li>c</li>\n</ul>\n"
+        );
+        assert_eq!(
+            to_html("* a\n\n  b"),
+            "<ul>\n<li>\n<p>a</p>\n<p>b</p>\n</li>\n</ul>\n"
+        );
+    }
+
+    #[test]
+    fn test_code() {
+        assert_eq!(
+            to_html("```rust\nfn a() {}\n<b>\n```"),
+            "<pre><code class=\"language-rust\">fn a() {}\n&lt;b&gt;\n</code></pre>\n"
+        );
+        // 结束的围栏可以更长
+        assert_eq!(
+            to_html("~~~\na\n~~~~\nb"),
+            "<pre><code>a\n</code></pre>\n<p>b</p>\n"
+        );
+        assert_eq!(
+            to_html("    code\n    more"),
+            "<pre><code>code\nmore\n</code></pre>\n"
+        );
+    }
+
+    #[test]
+    fn test_emphasis() {
+        assert_eq!(
+            to_html("*a* _b_ **c** __d__ ***e*** ~~f~~"),
+            "<p><em>a</em> <em>b</em> <strong>c</strong> <strong>d</strong> \
+             <em><strong>e</strong></em> <del>f</del></p>\n"
+        );
+        // 单词内的 `_` 和两侧都是空白的 `*` 不是强调
+        assert_eq!(
+            to_html("a*b*c a_b_c * a *"),
+            "<p>a<em>b</em>c a_b_c * a *</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_autolinks() {
+        // 自动链接不是 HTML 块，它在段落中被渲染为链接
+        assert_eq!(
+            to_html("<http://a.com>"),
+            "<p><a href=\"http://a.com\">http://a.com</a></p>\n"
+        );
+        assert_eq!(
+            to_html("<http://a.com?b=1&c=2>\n\n<a@b.com>"),
+            "<p><a href=\"http://a.com?b=1&amp;c=2\">http://a.com?b=1&amp;c=2</a></p>\n\
+             <p><a href=\"mailto:a@b.com\">a@b.com</a></p>\n"
+        );
+        assert_eq!(
+            to_html("a <http://a.com> b"),
+            "<p>a <a href=\"http://a.com\">http://a.com</a> b</p>\n"
+        );
+    }
+
+    #[test]
+    fn test_html() {
+        // HTML 块原样输出，直到空行
+        assert_eq!(
+            to_html("<div>\n*a*\n</div>\n\n*b*"),
+            "<div>\n*a*\n</div>\n<p><em>b</em></p>\n"
+        );
+        assert_eq!(
+            to_html("<div class=\"x\">\na\n\nb"),
+            "<div class=\"x\">\na\n<p>b</p>\n"
+        );
+        assert_eq!(to_html("<custom-tag>\n*a*"), "<custom-tag>\n*a*\n");
+        assert_eq!(to_html("<br/>"), "<br/>\n");
+        // 注释和 script 到它们的结束标记为止，中间可以有空行
+        assert_eq!(to_html("<!-- a\n\nb -->\nc"), "<!-- a\n\nb -->\n<p>c</p>\n");
+        assert_eq!(
+            to_html("<script>\na\n\nb\n</script>\nc"),
+            "<script>\na\n\nb\n</script>\n<p>c</p>\n"
+        );
+        // 行内的标签，以及不能打断段落的标签
+        assert_eq!(
+            to_html("<span>b</span>\n\nc"),
+            "<p><span>b</span></p>\n<p>c</p>\n"
+        );
+        assert_eq!(to_html("a\n<span>b</span>"), "<p>a\n<span>b</span></p>\n");
+        // 不是合法的标签名
+        assert_eq!(to_html("<1a>"), "<p>&lt;1a&gt;</p>\n");
+        assert_eq!(to_html("<a"), "<p>&lt;a</p>\n");
+    }
+}
@@ -17,10 +17,26 @@
 //!
 //! pub mod random
 //! 生成随机数，谨慎使用
+//!
+//! pub mod base64
+//! Base64 编码和解码，支持标准和 URL 安全两种字母表
+//!
+//! pub mod json
+//! 解析和序列化 JSON ，解析错误会给出精确的位置
+//!
+//! pub mod regex
+//! 正则表达式，匹配的时间和文本的长度成正比
+//!
+//! pub mod markdown
+//! 把 Markdown 渲染为 HTML ，支持 CommonMark 和 GFM 的表格
 
+pub mod base64;
 pub mod http;
+pub mod json;
 pub mod log;
+pub mod markdown;
 pub mod random;
+pub mod regex;
 pub mod thread;
 pub mod time;
 pub mod tool;
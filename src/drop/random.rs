@@ -13,6 +13,7 @@ use super::time::Time;
 /// 生成一个 256 位的随机数，用两段 128 位字节表示
 /// 这个随机数生成器仅用作开发，未来必须重写
 /// FIXME: 重新选择随机数，或推翻重写，以修复随机数不够随机的问题
+#[allow(dead_code)]
 pub fn get_random_256() -> Result<(u128, u128), SystemTimeError> {
     let seed = Time::nsec()? as u128;
     let seed2 = Time::msec()? as u128;
@@ -22,3 +23,12 @@ pub fn get_random_256() -> Result<(u128, u128), SystemTimeError> {
     let high128 = (23479875723479903917252112421248757 * seed2) & ((1 << 127) - 1) | 0;
     Ok((low128, high128))
 }
+
+/// 从操作系统的随机数源读取 len 个字节，适用于生成密钥等需要密码学安全的场景
+/// 目前只支持提供 `/dev/urandom` 的操作系统
+pub fn get_random_bytes(len: usize) -> std::io::Result<Vec<u8>> {
+    use std::io::Read;
+    let mut buf = vec![0; len];
+    std::fs::File::open("/dev/urandom")?.read_exact(&mut buf)?;
+    Ok(buf)
+}
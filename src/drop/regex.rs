@@ -0,0 +1,826 @@
+/* Tiny-Tiny-Web/Drop
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! 一个小型的正则表达式引擎
+//! 模式先被解析为语法树，再被编译为指令，最后由 Pike VM （带有捕获组的 Thompson NFA ）执行
+//! 它同时模拟所有可能的匹配，所以匹配的时间和文本的长度成正比，不会因为回溯而变得极慢，
+//! 代价是不支持反向引用和环视
+//!
+//! 支持的语法：
+//! 字符和转义： `a` 、 `.` （除了换行以外的任意字符） 、 `\n` 、 `\t` 、 `\r` 、 `\.` 等
+//! 字符类： `[abc]` 、 `[^a-z]` 、 `\d` 、 `\w` 、 `\s` 以及它们的大写形式（取反）
+//! 锚点： `^` 和 `$` 只匹配文本的开头和结尾， `\b` 和 `\B` 匹配单词的边界和非边界
+//! 分组： `(...)` 是捕获组， `(?:...)` 是非捕获组， `a|b` 是选择
+//! 重复： `*` 、 `+` 、 `?` 、 `{n}` 、 `{n,}` 、 `{n,m}` ，在其后加上 `?` 时尽可能少地匹配
+//!
+//! 例如：
+//! ```
+//! let re = Regex::new(r"(\w+)@(\w+)\.com")?;
+//! re.captures_at("mail: a@b.com", 0); // Some([Some(6..13), Some(6..7), Some(8..9)])
+//! re.replace_all("a@b.com", "$2"); // b
+//! ```
+
+use std::{fmt, ops::Range};
+
+/// 分组嵌套的最大深度，防止恶意的模式导致栈溢出
+const MAX_DEPTH: usize = 256;
+/// 编译后的最大指令数，重复会复制被重复的部分，这个限制避免 `(a{1000}){1000}` 这样的模式耗尽内存
+const MAX_INSTS: usize = 100_000;
+
+/// position: 出错的位置，以字符计，从 1 开始
+#[derive(Debug, Clone, PartialEq)]
+pub struct RegexError {
+    pub message: &'static str,
+    pub position: usize,
+}
+impl fmt::Display for RegexError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} at position {}", self.message, self.position)
+    }
+}
+
+/// 每个捕获组匹配的范围（字节偏移量），第 0 个是整个匹配，没有参与匹配的组为 None
+pub type Captures = Vec<Option<Range<usize>>>;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Perl {
+    Digit,
+    Word,
+    Space,
+}
+impl Perl {
+    fn matches(&self, c: char) -> bool {
+        match self {
+            Perl::Digit => c.is_ascii_digit(),
+            Perl::Word => is_word(c),
+            Perl::Space => c.is_whitespace(),
+        }
+    }
+}
+
+fn is_word(c: char) -> bool {
+    c.is_alphanumeric() || c == '_'
+}
+
+#[derive(Debug, Clone)]
+enum ClassItem {
+    Range(char, char),
+    /// 第二个值为 true 时取反，例如 `\D`
+    Perl(Perl, bool),
+}
+
+#[derive(Debug, Clone)]
+struct Class {
+    negated: bool,
+    items: Vec<ClassItem>,
+}
+impl Class {
+    fn matches(&self, c: char) -> bool {
+        let found = self.items.iter().any(|a| match a {
+            ClassItem::Range(start, end) => (*start..=*end).contains(&c),
+            ClassItem::Perl(perl, negated) => perl.matches(c) != *negated,
+        });
+        found != self.negated
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+enum Assertion {
+    Start,
+    End,
+    WordBoundary,
+    NotWordBoundary,
+}
+
+#[derive(Debug)]
+enum Node {
+    Empty,
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// 第二个值是捕获组的序号，非捕获组为 None
+    Group(Box<Node>, Option<usize>),
+    Concat(Vec<Node>),
+    Alt(Vec<Node>),
+    Repeat {
+        node: Box<Node>,
+        min: usize,
+        max: Option<usize>,
+        greedy: bool,
+    },
+}
+impl Node {
+    /// 编译后的指令数
+    fn size(&self) -> usize {
+        match self {
+            Node::Empty => 0,
+            Node::Char(_) | Node::Any | Node::Class(_) | Node::Assert(_) => 1,
+            Node::Group(a, index) => a.size() + if index.is_some() { 2 } else { 0 },
+            Node::Concat(a) => a.iter().map(Node::size).sum(),
+            Node::Alt(a) => a.iter().map(|a| a.size() + 2).sum(),
+            Node::Repeat { node, min, max, .. } => {
+                let copies = max.unwrap_or(*min + 1).max(*min);
+                node.size().saturating_add(2).saturating_mul(copies)
+            }
+        }
+    }
+}
+
+struct Parser {
+    chars: Vec<char>,
+    pos: usize,
+    groups: usize,
+}
+impl Parser {
+    fn error(&self, message: &'static str) -> RegexError {
+        RegexError {
+            message,
+            position: self.pos + 1,
+        }
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.chars.get(self.pos).copied()
+    }
+
+    fn eat(&mut self, c: char) -> bool {
+        if self.peek() == Some(c) {
+            self.pos += 1;
+            true
+        } else {
+            false
+        }
+    }
+
+    fn parse_alt(&mut self, depth: usize) -> Result<Node, RegexError> {
+        if depth > MAX_DEPTH {
+            return Err(self.error("Groups are nested too deeply"));
+        }
+        let mut alts = vec![self.parse_concat(depth)?];
+        while self.eat('|') {
+            alts.push(self.parse_concat(depth)?);
+        }
+        Ok(if alts.len() == 1 {
+            alts.pop().unwrap()
+        } else {
+            Node::Alt(alts)
+        })
+    }
+
+    fn parse_concat(&mut self, depth: usize) -> Result<Node, RegexError> {
+        let mut nodes = vec![];
+        while let Some(c) = self.peek() {
+            if c == '|' || c == ')' {
+                break;
+            }
+            let start = self.pos;
+            let atom = self.parse_atom(depth)?;
+            nodes.push(self.parse_repeat(atom, start)?);
+        }
+        Ok(match nodes.len() {
+            0 => Node::Empty,
+            1 => nodes.pop().unwrap(),
+            _ => Node::Concat(nodes),
+        })
+    }
+
+    fn parse_atom(&mut self, depth: usize) -> Result<Node, RegexError> {
+        let c = self.peek().unwrap();
+        self.pos += 1;
+        Ok(match c {
+            '.' => Node::Any,
+            '^' => Node::Assert(Assertion::Start),
+            '$' => Node::Assert(Assertion::End),
+            '(' => {
+                let index = if self.eat('?') {
+                    if !self.eat(':') {
+                        return Err(self.error("Unsupported group syntax"));
+                    }
+                    None
+                } else {
+                    self.groups += 1;
+                    Some(self.groups)
+                };
+                let node = self.parse_alt(depth + 1)?;
+                if !self.eat(')') {
+                    return Err(self.error("Unclosed group"));
+                }
+                Node::Group(Box::new(node), index)
+            }
+            '[' => Node::Class(self.parse_class()?),
+            '\\' => match self.parse_escape()? {
+                Escape::Char(c) => Node::Char(c),
+                Escape::Perl(perl, negated) => Node::Class(Class {
+                    negated: false,
+                    items: vec![ClassItem::Perl(perl, negated)],
+                }),
+                Escape::Assert(a) => Node::Assert(a),
+            },
+            '*' | '+' | '?' => {
+                self.pos -= 1;
+                return Err(self.error("Nothing to repeat"));
+            }
+            c => Node::Char(c),
+        })
+    }
+
+    fn parse_escape(&mut self) -> Result<Escape, RegexError> {
+        let Some(c) = self.peek() else {
+            return Err(self.error("Unexpected end of pattern"));
+        };
+        self.pos += 1;
+        Ok(match c {
+            'n' => Escape::Char('\n'),
+            't' => Escape::Char('\t'),
+            'r' => Escape::Char('\r'),
+            'd' => Escape::Perl(Perl::Digit, false),
+            'D' => Escape::Perl(Perl::Digit, true),
+            'w' => Escape::Perl(Perl::Word, false),
+            'W' => Escape::Perl(Perl::Word, true),
+            's' => Escape::Perl(Perl::Space, false),
+            'S' => Escape::Perl(Perl::Space, true),
+            'b' => Escape::Assert(Assertion::WordBoundary),
+            'B' => Escape::Assert(Assertion::NotWordBoundary),
+            c if c.is_ascii_alphanumeric() => {
+                self.pos -= 1;
+                return Err(self.error("Unknown escape"));
+            }
+            c => Escape::Char(c),
+        })
+    }
+
+    fn parse_class(&mut self) -> Result<Class, RegexError> {
+        let negated = self.eat('^');
+        let mut items = vec![];
+        // 紧跟在 `[` 或 `[^` 之后的 `]` 是普通字符
+        let mut first = true;
+        loop {
+            let Some(c) = self.peek() else {
+                return Err(self.error("Unclosed character class"));
+            };
+            self.pos += 1;
+            if c == ']' && !first {
+                return Ok(Class { negated, items });
+            }
+            first = false;
+            let start = match c {
+                '\\' => match self.parse_escape()? {
+                    Escape::Char(c) => c,
+                    Escape::Perl(perl, negated) => {
+                        items.push(ClassItem::Perl(perl, negated));
+                        continue;
+                    }
+                    Escape::Assert(_) => {
+                        self.pos -= 1;
+                        return Err(self.error("Unknown escape"));
+                    }
+                },
+                c => c,
+            };
+            // `-` 在开头或结尾时是普通字符
+            if self.peek() == Some('-') && !matches!(self.chars.get(self.pos + 1), Some(']') | None)
+            {
+                self.pos += 1;
+                let end = match self.peek() {
+                    Some('\\') => {
+                        self.pos += 1;
+                        match self.parse_escape()? {
+                            Escape::Char(c) => c,
+                            _ => return Err(self.error("Invalid range")),
+                        }
+                    }
+                    Some(c) => {
+                        self.pos += 1;
+                        c
+                    }
+                    None => return Err(self.error("Unclosed character class")),
+                };
+                if end < start {
+                    return Err(self.error("Invalid range"));
+                }
+                items.push(ClassItem::Range(start, end));
+            } else {
+                items.push(ClassItem::Range(start, start));
+            }
+        }
+    }
+
+    fn parse_number(&mut self) -> Option<usize> {
+        let start = self.pos;
+        while self.peek().is_some_and(|c| c.is_ascii_digit()) {
+            self.pos += 1;
+        }
+        self.chars[start..self.pos]
+            .iter()
+            .collect::<String>()
+            .parse()
+            .ok()
+    }
+
+    /// 解析 `{n}` 、 `{n,}` 或 `{n,m}` ，不是这些形式时 `{` 是普通字符
+    fn parse_counts(&mut self) -> Option<(usize, Option<usize>)> {
+        let start = self.pos;
+        let result = (|| {
+            self.pos += 1;
+            let min = self.parse_number()?;
+            let max = if self.eat(',') {
+                if self.peek() == Some('}') {
+                    None
+                } else {
+                    Some(self.parse_number()?)
+                }
+            } else {
+                Some(min)
+            };
+            self.eat('}').then_some((min, max))
+        })();
+        if result.is_none() {
+            self.pos = start;
+        }
+        result
+    }
+
+    fn parse_repeat(&mut self, mut node: Node, start: usize) -> Result<Node, RegexError> {
+        loop {
+            let (min, max) = match self.peek() {
+                Some('*') => {
+                    self.pos += 1;
+                    (0, None)
+                }
+                Some('+') => {
+                    self.pos += 1;
+                    (1, None)
+                }
+                Some('?') => {
+                    self.pos += 1;
+                    (0, Some(1))
+                }
+                Some('{') => match self.parse_counts() {
+                    Some(a) => a,
+                    None => return Ok(node),
+                },
+                _ => return Ok(node),
+            };
+            if max.is_some_and(|max| max < min) {
+                return Err(self.error("Invalid repetition count"));
+            }
+            if let Node::Assert(_) = node {
+                self.pos = start;
+                return Err(self.error("Nothing to repeat"));
+            }
+            let greedy = !self.eat('?');
+            node = Node::Repeat {
+                node: Box::new(node),
+                min,
+                max,
+                greedy,
+            };
+            if node.size() > MAX_INSTS {
+                return Err(self.error("Pattern is too large"));
+            }
+        }
+    }
+}
+
+enum Escape {
+    Char(char),
+    Perl(Perl, bool),
+    Assert(Assertion),
+}
+
+#[derive(Debug)]
+enum Inst {
+    Char(char),
+    Any,
+    Class(Class),
+    Assert(Assertion),
+    /// 两个分支都会被尝试，第一个分支的优先级更高
+    Split(usize, usize),
+    Jmp(usize),
+    /// 把当前位置保存到第 n 个捕获位置
+    Save(usize),
+    Match,
+}
+
+struct Compiler {
+    prog: Vec<Inst>,
+}
+impl Compiler {
+    fn push(&mut self, inst: Inst) -> usize {
+        self.prog.push(inst);
+        self.prog.len() - 1
+    }
+
+    fn emit(&mut self, node: &Node) {
+        match node {
+            Node::Empty => {}
+            Node::Char(c) => {
+                self.push(Inst::Char(*c));
+            }
+            Node::Any => {
+                self.push(Inst::Any);
+            }
+            Node::Class(a) => {
+                self.push(Inst::Class(a.clone()));
+            }
+            Node::Assert(a) => {
+                self.push(Inst::Assert(*a));
+            }
+            Node::Group(node, index) => {
+                if let Some(index) = index {
+                    self.push(Inst::Save(index * 2));
+                    self.emit(node);
+                    self.push(Inst::Save(index * 2 + 1));
+                } else {
+                    self.emit(node);
+                }
+            }
+            Node::Concat(nodes) => nodes.iter().for_each(|a| self.emit(a)),
+            Node::Alt(nodes) => {
+                let mut jumps = vec![];
+                for (i, node) in nodes.iter().enumerate() {
+                    if i == nodes.len() - 1 {
+                        self.emit(node);
+                        break;
+                    }
+                    let split = self.push(Inst::Split(0, 0));
+                    self.emit(node);
+                    jumps.push(self.push(Inst::Jmp(0)));
+                    self.prog[split] = Inst::Split(split + 1, self.prog.len());
+                }
+                let end = self.prog.len();
+                for a in jumps {
+                    self.prog[a] = Inst::Jmp(end);
+                }
+            }
+            Node::Repeat {
+                node,
+                min,
+                max,
+                greedy,
+            } => {
+                for _ in 0..*min {
+                    self.emit(node);
+                }
+                let split = |a, b| {
+                    if *greedy {
+                        Inst::Split(a, b)
+                    } else {
+                        Inst::Split(b, a)
+                    }
+                };
+                match max {
+                    None => {
+                        let start = self.push(Inst::Split(0, 0));
+                        self.emit(node);
+                        self.push(Inst::Jmp(start));
+                        self.prog[start] = split(start + 1, self.prog.len());
+                    }
+                    Some(max) => {
+                        let mut splits = vec![];
+                        for _ in *min..*max {
+                            splits.push(self.push(Inst::Split(0, 0)));
+                            self.emit(node);
+                        }
+                        let end = self.prog.len();
+                        for a in splits {
+                            self.prog[a] = split(a + 1, end);
+                        }
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// 一个位置上所有存活的线程，按优先级排序
+struct Threads {
+    list: Vec<(usize, Vec<Option<usize>>)>,
+    /// 每条指令是否已经有线程，优先级低的重复线程会被丢弃
+    seen: Vec<bool>,
+}
+impl Threads {
+    fn new(len: usize) -> Self {
+        Threads {
+            list: vec![],
+            seen: vec![false; len],
+        }
+    }
+
+    fn clear(&mut self) {
+        self.list.clear();
+        self.seen.iter_mut().for_each(|a| *a = false);
+    }
+}
+
+pub struct Regex {
+    prog: Vec<Inst>,
+    /// 捕获组的个数，包括第 0 组
+    groups: usize,
+}
+
+impl Regex {
+    pub fn new(pattern: &str) -> Result<Regex, RegexError> {
+        let mut parser = Parser {
+            chars: pattern.chars().collect(),
+            pos: 0,
+            groups: 0,
+        };
+        let node = parser.parse_alt(0)?;
+        if parser.pos != parser.chars.len() {
+            return Err(parser.error("Unmatched ')'"));
+        }
+        let mut compiler = Compiler {
+            prog: vec![Inst::Save(0)],
+        };
+        compiler.emit(&node);
+        compiler.push(Inst::Save(1));
+        compiler.push(Inst::Match);
+        Ok(Regex {
+            prog: compiler.prog,
+            groups: parser.groups + 1,
+        })
+    }
+
+    fn check(assertion: Assertion, text: &str, pos: usize) -> bool {
+        let word_before = text[..pos].chars().next_back().is_some_and(is_word);
+        let word_after = text[pos..].chars().next().is_some_and(is_word);
+        match assertion {
+            Assertion::Start => pos == 0,
+            Assertion::End => pos == text.len(),
+            Assertion::WordBoundary => word_before != word_after,
+            Assertion::NotWordBoundary => word_before == word_after,
+        }
+    }
+
+    /// 从 pc 开始沿着不消耗字符的指令前进，把到达的线程按优先级加入 threads
+    fn add(
+        &self,
+        threads: &mut Threads,
+        pc: usize,
+        slots: Vec<Option<usize>>,
+        text: &str,
+        pos: usize,
+    ) {
+        // 用栈代替递归，后压入的先处理
+        let mut stack = vec![(pc, slots)];
+        while let Some((pc, mut slots)) = stack.pop() {
+            if threads.seen[pc] {
+                continue;
+            }
+            threads.seen[pc] = true;
+            match &self.prog[pc] {
+                Inst::Jmp(a) => stack.push((*a, slots)),
+                Inst::Split(a, b) => {
+                    stack.push((*b, slots.clone()));
+                    stack.push((*a, slots));
+                }
+                Inst::Save(a) => {
+                    slots[*a] = Some(pos);
+                    stack.push((pc + 1, slots));
+                }
+                Inst::Assert(a) => {
+                    if Self::check(*a, text, pos) {
+                        stack.push((pc + 1, slots));
+                    }
+                }
+                _ => threads.list.push((pc, slots)),
+            }
+        }
+    }
+
+    /// 从字节偏移量 start 开始查找第一个匹配
+    /// 锚点和单词边界仍然以整个 text 为准，所以 `^` 在 start 不为 0 时不会匹配
+    pub fn captures_at(&self, text: &str, start: usize) -> Option<Captures> {
+        let mut current = Threads::new(self.prog.len());
+        let mut next = Threads::new(self.prog.len());
+        let mut matched = None;
+        let mut pos = start;
+        loop {
+            // 新的起点的优先级低于已经开始的匹配，找到匹配之后不再尝试新的起点
+            if matched.is_none() {
+                self.add(&mut current, 0, vec![None; self.groups * 2], text, pos);
+            }
+            // 起点上的断言可能失败，这时还没有线程，但后面的起点仍然可能匹配
+            if current.list.is_empty() && matched.is_some() {
+                break;
+            }
+            let c = text[pos..].chars().next();
+            let next_pos = pos + c.map_or(0, char::len_utf8);
+            for (pc, slots) in current.list.drain(..) {
+                let accepted = match (&self.prog[pc], c) {
+                    (Inst::Match, _) => {
+                        // 优先级更低的线程被丢弃
+                        matched = Some(slots);
+                        break;
+                    }
+                    (Inst::Char(a), Some(c)) => *a == c,
+                    (Inst::Any, Some(c)) => c != '\n',
+                    (Inst::Class(a), Some(c)) => a.matches(c),
+                    _ => false,
+                };
+                if accepted {
+                    self.add(&mut next, pc + 1, slots, text, next_pos);
+                }
+            }
+            if c.is_none() {
+                break;
+            }
+            std::mem::swap(&mut current, &mut next);
+            next.clear();
+            pos = next_pos;
+        }
+        matched.map(|slots| {
+            slots
+                .chunks(2)
+                .map(|a| match a {
+                    [Some(start), Some(end)] => Some(*start..*end),
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    /// 所有不重叠的匹配
+    /// 空的匹配之后从下一个字符开始查找，所以 `a*` 在 `"ba"` 中的匹配是 `""` 、 `"a"` 和 `""`
+    pub fn captures_all(&self, text: &str) -> Vec<Captures> {
+        let mut result = vec![];
+        let mut pos = 0;
+        while pos <= text.len() {
+            let Some(captures) = self.captures_at(text, pos) else {
+                break;
+            };
+            let range = captures[0].clone().unwrap();
+            pos = match text[range.end..].chars().next() {
+                Some(c) if range.is_empty() => range.end + c.len_utf8(),
+                None if range.is_empty() => range.end + 1,
+                _ => range.end,
+            };
+            result.push(captures);
+        }
+        result
+    }
+
+    /// 替换所有的匹配
+    /// replacement 中的 `$n` 或 `${n}` 代表第 n 个捕获组， `$0` 代表整个匹配， `$$` 代表 `$`
+    /// 不存在或没有参与匹配的组被替换为空字符串
+    pub fn replace_all(&self, text: &str, replacement: &str) -> String {
+        let mut result = String::new();
+        let mut last = 0;
+        for captures in self.captures_all(text) {
+            let range = captures[0].clone().unwrap();
+            result.push_str(&text[last..range.start]);
+            expand(&mut result, replacement, text, &captures);
+            last = range.end;
+        }
+        result.push_str(&text[last..]);
+        result
+    }
+
+    /// 以匹配为分隔符分割 text ，空的匹配会被忽略
+    pub fn split<'a>(&self, text: &'a str) -> Vec<&'a str> {
+        let mut result = vec![];
+        let mut last = 0;
+        for captures in self.captures_all(text) {
+            let range = captures[0].clone().unwrap();
+            if range.is_empty() {
+                continue;
+            }
+            result.push(&text[last..range.start]);
+            last = range.end;
+        }
+        result.push(&text[last..]);
+        result
+    }
+}
+
+fn expand(out: &mut String, replacement: &str, text: &str, captures: &Captures) {
+    let mut chars = replacement.chars().peekable();
+    while let Some(c) = chars.next() {
+        if c != '$' {
+            out.push(c);
+            continue;
+        }
+        let braced = chars.next_if_eq(&'{').is_some();
+        let mut digits = String::new();
+        while let Some(c) = chars.next_if(|c| c.is_ascii_digit()) {
+            digits.push(c);
+        }
+        if braced && (digits.is_empty() || chars.next_if_eq(&'}').is_none()) {
+            // 不是合法的引用，原样输出
+            out.push('$');
+            out.push('{');
+            out.push_str(&digits);
+            continue;
+        }
+        if digits.is_empty() {
+            out.push('$');
+            chars.next_if_eq(&'$');
+            continue;
+        }
+        let group = digits
+            .parse::<usize>()
+            .ok()
+            .and_then(|a| captures.get(a).cloned().flatten());
+        if let Some(range) = group {
+            out.push_str(&text[range]);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 第一个匹配中每个组匹配到的文本
+    fn find<'a>(pattern: &str, text: &'a str) -> Option<Vec<Option<&'a str>>> {
+        let captures = Regex::new(pattern).unwrap().captures_at(text, 0)?;
+        Some(captures.into_iter().map(|a| a.map(|a| &text[a])).collect())
+    }
+
+    fn error(pattern: &str) -> (&'static str, usize) {
+        let e = Regex::new(pattern).err().unwrap();
+        (e.message, e.position)
+    }
+
+    #[test]
+    fn test_classes() {
+        assert_eq!(find("[a-c]+", "xbcad"), Some(vec![Some("bca")]));
+        assert_eq!(find("[^a-z]+", "abC1d"), Some(vec![Some("C1")]));
+        assert_eq!(find(r"\d+\W", "x12!"), Some(vec![Some("12!")]));
+        assert_eq!(find(r"\s\S", "a  b"), Some(vec![Some(" b")]));
+        assert_eq!(find("a.c", "a\nc"), None);
+        assert_eq!(find("[.]", "a.b"), Some(vec![Some(".")]));
+    }
+
+    #[test]
+    fn test_anchors() {
+        assert_eq!(find("^a", "ba"), None);
+        assert_eq!(find("a$", "a\nb"), None);
+        assert_eq!(find("^ab$", "ab"), Some(vec![Some("ab")]));
+        // 起点上的断言失败时，仍然要尝试后面的起点
+        assert_eq!(find(r"\bcat\b", "concat cat"), Some(vec![Some("cat")]));
+        let regex = Regex::new(r"\Bcat").unwrap();
+        assert_eq!(regex.captures_at("cat concat", 0), Some(vec![Some(7..10)]));
+        // 锚点以整个 text 为准
+        assert_eq!(Regex::new("^a").unwrap().captures_at("aa", 1), None);
+    }
+
+    #[test]
+    fn test_alternation() {
+        // 左边的分支优先
+        assert_eq!(find("a|ab", "ab"), Some(vec![Some("a")]));
+        assert_eq!(find("ab|a", "ab"), Some(vec![Some("ab")]));
+        assert_eq!(find("x(?:a|b)+y", "xabay"), Some(vec![Some("xabay")]));
+    }
+
+    #[test]
+    fn test_captures() {
+        let regex = Regex::new(r"(\w+)@(\w+)\.com").unwrap();
+        assert_eq!(
+            regex.captures_at("mail: a@b.com", 0),
+            Some(vec![Some(6..13), Some(6..7), Some(8..9)])
+        );
+        // 没有参与匹配的组是 None
+        assert_eq!(find("(a)|(b)", "b"), Some(vec![Some("b"), None, Some("b")]));
+        assert_eq!(
+            Regex::new(r"(\d+)").unwrap().replace_all("a1b22", "<$1>$$"),
+            "a<1>$b<22>$"
+        );
+        assert_eq!(
+            Regex::new(",").unwrap().split("a,b,,c"),
+            ["a", "b", "", "c"]
+        );
+        let all = Regex::new("a*").unwrap().captures_all("ba");
+        assert_eq!(all, [vec![Some(0..0)], vec![Some(1..2)], vec![Some(2..2)]]);
+    }
+
+    #[test]
+    fn test_lazy() {
+        assert_eq!(find("<.+>", "<a><b>"), Some(vec![Some("<a><b>")]));
+        assert_eq!(find("<.+?>", "<a><b>"), Some(vec![Some("<a>")]));
+        assert_eq!(find("a+?", "aaa"), Some(vec![Some("a")]));
+        assert_eq!(find("a{2,3}?", "aaaa"), Some(vec![Some("aa")]));
+        assert_eq!(find("a{2,}", "aaaa"), Some(vec![Some("aaaa")]));
+    }
+
+    #[test]
+    fn test_errors() {
+        assert_eq!(error("(a"), ("Unclosed group", 3));
+        assert_eq!(error("a)"), ("Unmatched ')'", 2));
+        assert_eq!(error("(?x)"), ("Unsupported group syntax", 3));
+        assert_eq!(error("*"), ("Nothing to repeat", 1));
+        assert_eq!(error("a|*"), ("Nothing to repeat", 3));
+        assert_eq!(error("[a"), ("Unclosed character class", 3));
+        assert_eq!(error("[z-a]"), ("Invalid range", 5));
+        assert_eq!(error(r"\q"), ("Unknown escape", 2));
+        assert_eq!(error(r"a\"), ("Unexpected end of pattern", 3));
+        assert_eq!(error("a{2,1}"), ("Invalid repetition count", 7));
+    }
+}
@@ -0,0 +1,283 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 限制处理请求的代码（ Pipe 和请求处理器）可以使用的指令数、时间和内存，避免一个没有 `return` 的 `loop` 永远占用工作线程
+//! 加载配置时执行的代码和 REPL 没有这些限制
+//!
+//! 虚拟机每执行一条指令就调用一次 step ，每 1024 条指令检查一次是否超时
+//! 可能产生很大的字符串或列表的内置函数（例如 `str.+` ）通过 check_size 检查结果的大小
+//! 耗时和输入的大小成正比的内置函数（例如 `re.replace` 、 `markdown->html` ）在开始工作之前通过 charge 按输入的大小消耗预算
+//! 分配的内存和输入的大小成正比的内置函数（例如 `range` 、 `map` 、 `str.+` ）通过 allocate 记录分配的内存，
+//! 一次运行中分配的内存总量也有限制，所以即使每个字符串和列表都不超过 check_size 的限制，也不能用很多个它们耗尽内存
+//! 预算耗尽之后，之后的每一条指令都会报错，所以 `try` 不能用来绕过它
+//!
+//! 可以用 `$ glisp-max-steps` 、 `$ glisp-timeout` 、 `$ glisp-max-size` 和 `$ glisp-max-memory` 设置这些限制
+
+use std::{
+    cell::Cell,
+    mem::size_of,
+    sync::RwLock,
+    time::{Duration, Instant},
+};
+
+use super::core::*;
+
+/// steps: 最多执行的指令数
+/// time: 最长的执行时间
+/// size: 字符串的最大字节数，以及列表的最大长度
+/// memory: 一次运行中最多分配的内存的字节数，被释放的内存不会被减去
+#[derive(Clone, Copy)]
+pub struct Limits {
+    pub steps: u64,
+    pub time: Duration,
+    pub size: usize,
+    pub memory: usize,
+}
+
+/// 处理请求的代码的限制
+pub static REQUEST: RwLock<Limits> = RwLock::new(Limits {
+    steps: 10_000_000,
+    time: Duration::from_secs(5),
+    size: 16 << 20,
+    memory: 256 << 20,
+});
+
+/// 一次求值剩余的预算
+/// allocated: 已经分配的内存的字节数
+#[derive(Clone, Copy)]
+struct Budget {
+    limits: Limits,
+    steps: u64,
+    deadline: Instant,
+    allocated: usize,
+}
+
+thread_local! {
+    /// 当前线程正在执行的代码的预算，为 None 时没有限制
+    static CURRENT: Cell<Option<Budget>> = const { Cell::new(None) };
+}
+
+/// 在 f 执行期间使用处理请求的代码的限制，即使 f 出现 panic 也会恢复原来的预算
+pub fn limit<T>(f: impl FnOnce() -> T) -> T {
+    let limits = *REQUEST.read().unwrap();
+    limit_with(limits, f)
+}
+
+fn limit_with<T>(limits: Limits, f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Budget>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.set(self.0);
+        }
+    }
+
+    let _restore = Restore(CURRENT.replace(Some(Budget {
+        limits,
+        steps: limits.steps,
+        deadline: Instant::now() + limits.time,
+        allocated: 0,
+    })));
+    f()
+}
+
+/// 消耗一条指令的预算
+pub fn step() -> Result<(), GError> {
+    let Some(mut budget) = CURRENT.get() else {
+        return Ok(());
+    };
+    if budget.steps == 0 {
+        return Err(exceeded(&budget));
+    }
+    budget.steps -= 1;
+    let timeout = budget.steps % 1024 == 0 && Instant::now() >= budget.deadline;
+    if timeout {
+        // 超时之后的指令也会报错
+        budget.steps = 0;
+    }
+    CURRENT.set(Some(budget));
+    if timeout {
+        return Err(exceeded(&budget));
+    }
+    Ok(())
+}
+
+/// 一条指令的预算可以处理的输入的字节数
+const BYTES_PER_STEP: usize = 64;
+
+/// 消耗处理 size 字节的输入所需的预算，预算不够时不消耗，而是和 step 一样使之后的指令都报错
+pub fn charge(size: usize) -> Result<(), GError> {
+    let Some(mut budget) = CURRENT.get() else {
+        return Ok(());
+    };
+    let steps = (size / BYTES_PER_STEP) as u64;
+    let exhausted = budget.steps < steps || Instant::now() >= budget.deadline;
+    budget.steps = if exhausted { 0 } else { budget.steps - steps };
+    CURRENT.set(Some(budget));
+    if exhausted {
+        return Err(exceeded(&budget));
+    }
+    Ok(())
+}
+
+/// 记录即将分配的 bytes 字节的内存，总量超过限制时不分配，而是和 step 一样使之后的指令都报错
+pub fn allocate(bytes: usize) -> Result<(), GError> {
+    let Some(mut budget) = CURRENT.get() else {
+        return Ok(());
+    };
+    budget.allocated = budget.allocated.saturating_add(bytes);
+    if budget.allocated > budget.limits.memory {
+        budget.steps = 0;
+    }
+    CURRENT.set(Some(budget));
+    if budget.steps == 0 {
+        return Err(exceeded(&budget));
+    }
+    Ok(())
+}
+
+/// 记录即将分配的有 len 个元素的列表，元素自身占用的内存（例如字符串的内容）需要另外记录
+pub fn allocate_list(len: usize) -> Result<(), GError> {
+    allocate(len.saturating_mul(size_of::<Expression>()))
+}
+
+fn exceeded(budget: &Budget) -> GError {
+    if Instant::now() >= budget.deadline {
+        GError::new(format!(
+            "exceeded the time budget of {} ms",
+            budget.limits.time.as_millis()
+        ))
+    } else if budget.allocated > budget.limits.memory {
+        GError::new(format!(
+            "exceeded the memory budget of {} bytes",
+            budget.limits.memory
+        ))
+    } else {
+        GError::new(format!(
+            "exceeded the budget of {} steps",
+            budget.limits.steps
+        ))
+    }
+}
+
+/// 检查 fnname 产生的字符串或列表的大小
+pub fn check_size(fnname: &str, size: usize) -> Result<(), GError> {
+    match CURRENT.get() {
+        Some(budget) if size > budget.limits.size => Err(GError::new(format!(
+            "{}: size {} exceeds the limit of {}",
+            fnname, size, budget.limits.size
+        ))),
+        _ => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn eval_with(limits: Limits, source: &str) -> Result<Expression, GError> {
+        limit_with(limits, || {
+            parse_eval(source.to_owned(), &default_env(), None)
+        })
+    }
+
+    #[test]
+    fn test_limit() {
+        let limits = Limits {
+            steps: 10000,
+            time: Duration::from_secs(60),
+            size: 1024,
+            memory: usize::MAX,
+        };
+        // 没有 return 的 loop
+        let Err(e) = eval_with(limits, "(loop (pass) (pass))") else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the budget of 10000 steps");
+        // try 不能绕过预算
+        let Err(e) = eval_with(
+            limits,
+            "(loop (try (loop (pass) (pass)) (catch e (pass))) (pass))",
+        ) else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the budget of 10000 steps");
+        let Err(e) = eval_with(
+            limits,
+            "(do (set s \"ab\") (loop (set s (str.+ s s)) (pass)))",
+        ) else {
+            panic!()
+        };
+        assert_eq!(e.message, "str.+: size 2048 exceeds the limit of 1024");
+        let Err(e) = eval_with(
+            limits,
+            "(do (set l (list 1)) (loop (set l `(,@l ,@l)) (pass)))",
+        ) else {
+            panic!()
+        };
+        assert_eq!(e.message, "quasiquote: size 2048 exceeds the limit of 1024");
+        assert!(eval_with(limits, "(str.+ \"a\" \"b\")").is_ok());
+        // 内置函数按输入的大小消耗预算
+        let pattern = "a".repeat(1000);
+        let source = format!("(re.match \"{}\" \"{}\")", pattern, pattern);
+        let Err(e) = eval_with(limits, &source) else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the budget of 10000 steps");
+        assert!(eval_with(limits, &format!("(re.match \"a\" \"{}\")", pattern)).is_ok());
+        let Err(e) = eval_with(limits, "(json.stringify (range 0 1000))") else {
+            panic!()
+        };
+        assert_eq!(
+            e.message,
+            "json.stringify: size 3891 exceeds the limit of 1024"
+        );
+
+        let limits = Limits {
+            steps: u64::MAX,
+            time: Duration::from_millis(10),
+            size: 1024,
+            memory: usize::MAX,
+        };
+        let Err(e) = eval_with(limits, "(loop (pass) (pass))") else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the time budget of 10 ms");
+        // 每个列表都不超过大小的限制，但分配的内存总量超过了限制
+        let limits = Limits {
+            steps: u64::MAX,
+            time: Duration::from_secs(60),
+            size: 1024,
+            memory: 64 << 10,
+        };
+        let Err(e) = eval_with(limits, "(map (lambda (i) (range 1000)) (range 100))") else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the memory budget of 65536 bytes");
+        let Err(e) = eval_with(
+            limits,
+            "(loop (set s (str.+ \"abcdefgh\" \"abcdefgh\")) (pass))",
+        ) else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the memory budget of 65536 bytes");
+        // 默认的限制在分配之前就拒绝了 16000000 个元素的列表
+        let Err(e) = limit(|| {
+            parse_eval(
+                "(map (lambda (i) (range 16000000)) (range 100))".to_owned(),
+                &default_env(),
+                None,
+            )
+        }) else {
+            panic!()
+        };
+        assert_eq!(e.message, "exceeded the memory budget of 268435456 bytes");
+        // 限制只在 limit 之中有效
+        assert!(check_size("str.+", usize::MAX).is_ok());
+    }
+}
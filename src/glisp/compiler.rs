@@ -0,0 +1,921 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 把语法树编译为紧凑的字节码，由 vm 模块执行
+//!
+//! ## 变量
+//! Lambda 的参数和在 Lambda 中被 `set` 的变量在编译时就被分配一个槽位，运行时通过下标而不是名字访问
+//! 其它的变量（外部的变量和全局变量）按名字在 Lambda 捕获的环境中查找
+//! 顶层代码没有槽位，它的变量直接保存在 Environment 中，这样 REPL 的多行之间可以共享变量
+//! `set!` 修改的变量可能属于任何一个外部环境，所以它总是按名字访问
+//!
+//! ## 错误
+//! 编译本身不会失败，所有的错误都被编译为 `Op::Fail` ，只有在它真正被执行时才会报告
+//! 这保证了字节码和原先直接对语法树求值的行为相同，例如 `(if true 1 (nope))` 不会报错
+//!
+//! ## 内置函数
+//! 内置函数在编译时就被解析为 BUILT_INS 中的下标，但同名的变量优先于内置函数
+//! 以下的名字被认为是变量：当前和外层 Lambda 的槽位、顶层代码中被 `set` 的变量、编译时已经能在 Environment 中找到的变量
+//! 对它们的调用被编译为普通的调用，在运行时按名字查找
+//! 类型不为 Any 的参数被包裹在 `Op::Mask` 和 `Op::Unmask` 之间，在其中发生的任何错误都会被报告为 `<函数名>: Unsupported type`
+//!
+//! ## 宏
+//! `defmacro` 在编译时就定义宏，所以同一段代码中在它之后的表达式就可以使用它，宏被保存在编译时传入的 Environment 中
+//! 调用宏时，它的 Lambda 以未被求值的参数在编译时被执行，返回的列表被作为代码继续编译
+//! 宏的名字优先于内置函数，但特殊形式不能被宏覆盖
+//! `quasiquote` 生成的列表没有 `quote` 前缀，它就是代码本身
+//!
+//! ## 异常
+//! `(try expr (catch e handler))` 中， expr 被包裹在 `Op::Try` 和 `Op::EndTry` 之间
+//! 其中发生的错误被虚拟机转换为错误对象并保存到 e ，然后执行 handler
+//!
+//! ## 位置
+//! 语法树本身不记录位置，编译时传入的 SpanTree 被转换为以语法树节点的地址为键的表
+//! 每条指令都记录生成它的表达式的位置，虚拟机在出错时用它报告位置和调用栈
+//! 宏展开生成的代码不在表中，它使用调用宏的表达式的位置
+
+use std::{collections::HashMap, rc::Rc, sync::Arc};
+
+use super::core::*;
+use super::module::require;
+use super::std::{built_in, check_args_len, func_cons, func_quote, ArgType, BUILT_INS};
+
+/// 字节码指令，跳转的目标都是 ops 中的下标，其它的 u32 都是 constants 、 names 或 slots 中的下标
+#[derive(Clone, Copy, Debug)]
+pub enum Op {
+    Const(u32),
+    /// 复制 constants[_] 中的 Lambda 模板，并让它捕获当前的环境
+    Closure(u32),
+    GetSlot(u32),
+    GetName(u32),
+    /// 弹出栈顶的值并保存到槽位
+    SetSlot(u32),
+    /// 弹出栈顶的值并按名字保存到当前的作用域
+    SetName(u32),
+    /// 弹出栈顶的值并修改当前环境或外部环境中已经存在的变量
+    Assign(u32),
+    Pop,
+    Jump(u32),
+    /// 弹出测试的结果，为 false 时跳转到 otherwise ，不是 Bool 时报告 constants[message]
+    If {
+        otherwise: u32,
+        message: u32,
+    },
+    /// 栈顶为 false 时保留它并跳转，否则弹出它，用于 `and` 的短路求值
+    JumpIfFalse(u32),
+    /// 弹出测试的结果，不为 true 时跳转，用于 `cond`
+    CondTest(u32),
+    /// 弹出循环体中一个表达式的结果，`return` 跳转到 end ，`continue` 跳转到 start
+    LoopCheck {
+        start: u32,
+        end: u32,
+    },
+    /// 栈顶是 `[列表, 下标]` ，取出下一个元素压栈，没有时弹出它们并跳转到 end
+    ForEachNext {
+        end: u32,
+    },
+    Mask(Mask),
+    /// 检查栈顶的类型并移除最近的 Mask
+    Unmask(ArgType),
+    BuiltIn {
+        index: u16,
+        argc: u16,
+    },
+    /// 在参数被求值之前检查被调用的是不是函数，以及参数的个数
+    PrepareCall(u32),
+    Call(u32),
+    /// 尾调用，复用当前的调用帧，所以尾递归不会增加调用栈的深度
+    TailCall(u32),
+    /// 弹出一个字符串，把它作为代码在当前的作用域中执行
+    EvalCode,
+    /// 弹出一个字符串，把它作为原子在当前的作用域中求值
+    EvalAtom,
+    /// 把栈顶的值包装为只有它一个元素的列表，用于 `quasiquote`
+    Segment,
+    /// 检查栈顶的值是一个列表，并去掉它的 `quote` 前缀，用于 `unquote-splicing`
+    Splice,
+    /// 弹出 _ 个列表并把它们连接为一个列表
+    Concat(u32),
+    /// 开始一个 `try` ，其中发生错误时跳转到 handler ，错误对象被压栈
+    Try(u32),
+    /// 结束最近的 `try` 并跳转到 end
+    EndTry(u32),
+    /// 报告 constants[message] 中的错误信息
+    Fail {
+        kind: ErrorKind,
+        message: u32,
+    },
+    Return,
+}
+
+/// 在 Mask 和 Unmask 之间发生的错误被替换为的信息，嵌套时最外层的生效
+#[derive(Clone, Copy, Debug)]
+pub enum Mask {
+    Type(&'static str),
+    Cond,
+}
+
+impl Mask {
+    pub fn message(&self) -> String {
+        match self {
+            Mask::Type(name) => format!("{}: Unsupported type", name),
+            Mask::Cond => "cond: Error3".to_owned(),
+        }
+    }
+}
+
+/// 一段编译后的代码，顶层代码和每个 Lambda 都有自己的 Chunk
+/// slots: 槽位对应的变量名， slot_map 是它的反向索引
+/// params: Lambda 的每个参数的槽位，参数列表不合法时是在调用时报告的错误信息
+/// spans: 每条指令对应的源代码位置，没有位置信息时为 None
+pub struct Chunk {
+    pub ops: Vec<Op>,
+    pub spans: Vec<Option<Span>>,
+    pub constants: Vec<Expression>,
+    pub names: Vec<String>,
+    pub slots: Vec<String>,
+    pub slot_map: HashMap<String, u32>,
+    pub params: Result<Vec<u32>, String>,
+}
+
+/// 宏，它的 Lambda 捕获了定义它时的环境
+/// rest: 为 true 时， Lambda 的最后一个参数是 `&rest` 参数，它接收剩余的所有参数组成的列表
+#[derive(Clone)]
+pub struct Macro {
+    pub lambda: Lambda,
+    pub rest: bool,
+}
+
+/// 宏展开的最大深度，超过它时认为宏在无限地展开自身
+const MAX_EXPANSION_DEPTH: usize = 256;
+
+/// 语法树节点的地址到它的位置的表，编译期间语法树不会被移动，所以地址是稳定的
+type SpanMap = Rc<HashMap<usize, Span>>;
+
+/// env: 查找和定义宏的环境
+/// depth: 当前宏展开的深度，嵌套的 Lambda 继承它
+/// span: 正在被编译的表达式的位置
+/// bound: 外层 Lambda 的槽位和顶层代码中被 `set` 的变量，它们会覆盖同名的内置函数
+struct Compiler {
+    chunk: Chunk,
+    bound: Rc<Vec<String>>,
+    name_map: HashMap<String, u32>,
+    /// 为 true 时，处于尾部位置的调用被编译为 TailCall ，只有 Lambda 的 Chunk 可以使用它
+    tail_calls: bool,
+    env: Environment,
+    depth: usize,
+    spans: SpanMap,
+    span: Option<Span>,
+}
+
+/// 编译顶层代码， spans 是 exp 的位置树
+pub fn compile(exp: &Expression, spans: Option<&SpanTree>, env: &Environment) -> Arc<Chunk> {
+    let mut map = HashMap::new();
+    if let Some(tree) = spans {
+        collect_spans(exp, tree, &mut map);
+    }
+    let mut bound = vec![];
+    collect_slots(exp, &mut bound);
+    let mut compiler = Compiler::new(vec![], false, env, 0);
+    compiler.spans = Rc::new(map);
+    compiler.bound = Rc::new(bound);
+    compiler.expression(exp, false);
+    compiler.finish()
+}
+
+fn collect_spans(exp: &Expression, tree: &SpanTree, map: &mut HashMap<usize, Span>) {
+    map.insert(exp as *const Expression as usize, tree.span.clone());
+    if let Expression::List(list) = exp {
+        for (exp, tree) in list.iter().zip(&tree.children) {
+            collect_spans(exp, tree, map);
+        }
+    }
+}
+
+/// 编译 Lambda ，参数和在其中被 `set` 的变量拥有槽位
+/// bound: 外层的变量，参见 Compiler
+fn compile_lambda(
+    params: &Expression,
+    body: &Expression,
+    env: &Environment,
+    depth: usize,
+    spans: &SpanMap,
+    bound: &Rc<Vec<String>>,
+) -> Arc<Chunk> {
+    let params = match params {
+        Expression::List(list) => list
+            .iter()
+            .map(|x| match x {
+                Expression::Symbol(s) => Ok(s.clone()),
+                _ => Err("expected symbol in the argument list".to_owned()),
+            })
+            .collect::<Result<Vec<_>, _>>(),
+        _ => Err("expected params to be a list".to_owned()),
+    };
+    let mut slots = params.clone().unwrap_or_default();
+    collect_slots(body, &mut slots);
+    let mut compiler = Compiler::new(slots, true, env, depth);
+    compiler.spans = spans.clone();
+    compiler.bound = bound.clone();
+    compiler.chunk.params = params.map(|a| a.iter().map(|a| compiler.chunk.slot_map[a]).collect());
+    compiler.expression(body, true);
+    compiler.finish()
+}
+
+/// 编译 `eval` 的代码，它和 scope 共享槽位
+pub fn compile_in_scope(exp: &Expression, scope: &Chunk, env: &Environment) -> Arc<Chunk> {
+    let mut compiler = Compiler::new(scope.slots.clone(), false, env, 0);
+    compiler.expression(exp, false);
+    compiler.finish()
+}
+
+/// 找出所有在 body 中被 `set` 的变量，不进入 quote 、 cons 、嵌套的 Lambda 和宏的定义
+fn collect_slots(exp: &Expression, slots: &mut Vec<String>) {
+    let Expression::List(list) = exp else {
+        return;
+    };
+    let mut add = |name: &str| {
+        if !slots.iter().any(|a| a == name) {
+            slots.push(name.to_owned())
+        }
+    };
+    match list.first() {
+        Some(Expression::Symbol(a))
+            if a == "quote" || a == "cons" || a == "lambda" || a == "defmacro" =>
+        {
+            return
+        }
+        Some(Expression::Symbol(a)) if a == "set" => {
+            if let Some(Expression::Symbol(name)) = list.get(1) {
+                add(name);
+            }
+        }
+        Some(Expression::Symbol(a)) if a == "for-each-eval" => add("$$"),
+        Some(Expression::Symbol(a)) if a == "catch" => {
+            if let Some(Expression::Symbol(name)) = list.get(1) {
+                add(name);
+            }
+        }
+        _ => {}
+    }
+    for e in list {
+        collect_slots(e, slots);
+    }
+}
+
+/// 如果 exp 是 `(unquote x)` 或 `(unquote-splicing x)` ，返回它的名字和 x
+fn unquote(exp: &Expression) -> Option<(&str, &Expression)> {
+    match exp {
+        Expression::List(list) => match list.as_slice() {
+            [Expression::Symbol(a), x] if a == "unquote" || a == "unquote-splicing" => Some((a, x)),
+            _ => None,
+        },
+        _ => None,
+    }
+}
+
+fn has_unquote(exp: &Expression) -> bool {
+    match exp {
+        Expression::List(list) => unquote(exp).is_some() || list.iter().any(has_unquote),
+        _ => false,
+    }
+}
+
+impl Compiler {
+    fn new(slots: Vec<String>, tail_calls: bool, env: &Environment, depth: usize) -> Self {
+        Compiler {
+            chunk: Chunk {
+                ops: vec![],
+                spans: vec![],
+                constants: vec![],
+                names: vec![],
+                slot_map: slots
+                    .iter()
+                    .enumerate()
+                    .map(|(i, a)| (a.clone(), i as u32))
+                    .collect(),
+                slots,
+                params: Ok(vec![]),
+            },
+            bound: Rc::new(vec![]),
+            name_map: HashMap::new(),
+            tail_calls,
+            env: env.clone(),
+            depth,
+            spans: Default::default(),
+            span: None,
+        }
+    }
+
+    fn finish(mut self) -> Arc<Chunk> {
+        self.emit(Op::Return);
+        Arc::new(self.chunk)
+    }
+
+    /// 名字是否是一个变量，它会覆盖同名的内置函数
+    fn is_bound(&self, name: &str) -> bool {
+        self.chunk.slot_map.contains_key(name)
+            || self.bound.iter().any(|a| a == name)
+            || self.env.get(name).is_some()
+    }
+
+    /// 嵌套的 Lambda 的 bound ，它包括自身的槽位
+    fn inner_bound(&self) -> Rc<Vec<String>> {
+        if self.chunk.slots.is_empty() {
+            return self.bound.clone();
+        }
+        let mut bound = self.bound.to_vec();
+        bound.extend(self.chunk.slots.iter().cloned());
+        Rc::new(bound)
+    }
+
+    fn emit(&mut self, op: Op) -> usize {
+        self.chunk.ops.push(op);
+        self.chunk.spans.push(self.span.clone());
+        self.chunk.ops.len() - 1
+    }
+
+    fn here(&self) -> u32 {
+        self.chunk.ops.len() as u32
+    }
+
+    /// 把 at 处的跳转指令的目标设置为当前位置
+    fn patch(&mut self, at: usize) {
+        let here = self.here();
+        match &mut self.chunk.ops[at] {
+            Op::Jump(a) | Op::JumpIfFalse(a) | Op::CondTest(a) | Op::Try(a) | Op::EndTry(a) => {
+                *a = here
+            }
+            Op::If { otherwise, .. } => *otherwise = here,
+            Op::ForEachNext { end } => *end = here,
+            _ => {}
+        }
+    }
+
+    fn constant(&mut self, exp: Expression) -> u32 {
+        self.chunk.constants.push(exp);
+        self.chunk.constants.len() as u32 - 1
+    }
+
+    fn name(&mut self, name: &str) -> u32 {
+        if let Some(a) = self.name_map.get(name) {
+            return *a;
+        }
+        self.chunk.names.push(name.to_owned());
+        let index = self.chunk.names.len() as u32 - 1;
+        self.name_map.insert(name.to_owned(), index);
+        index
+    }
+
+    fn fail(&mut self, message: &str) {
+        self.fail_with(ErrorKind::Runtime, message);
+    }
+
+    fn fail_with(&mut self, kind: ErrorKind, message: &str) {
+        let message = self.constant(Expression::String(message.to_owned()));
+        self.emit(Op::Fail { kind, message });
+    }
+
+    fn result(&mut self, result: Result<Expression, GError>) {
+        match result {
+            Ok(a) => {
+                let index = self.constant(a);
+                self.emit(Op::Const(index));
+            }
+            Err(e) => self.fail_with(e.kind, &e.message),
+        }
+    }
+
+    fn args_len(
+        &mut self,
+        name: &str,
+        args: &[Expression],
+        min: usize,
+        max: Option<usize>,
+    ) -> bool {
+        match check_args_len(name, args.len(), min, max) {
+            Ok(_) => true,
+            Err(e) => {
+                self.fail_with(e.kind, &e.message);
+                false
+            }
+        }
+    }
+
+    fn get(&mut self, name: &str) {
+        match self.chunk.slot_map.get(name) {
+            Some(a) => self.emit(Op::GetSlot(*a)),
+            None => {
+                let index = self.name(name);
+                self.emit(Op::GetName(index))
+            }
+        };
+    }
+
+    /// 弹出栈顶的值并保存到变量
+    fn set(&mut self, name: &str) {
+        match self.chunk.slot_map.get(name) {
+            Some(a) => self.emit(Op::SetSlot(*a)),
+            None => {
+                let index = self.name(name);
+                self.emit(Op::SetName(index))
+            }
+        };
+    }
+
+    /// 编译一个被 Mask 包裹的表达式，其中的错误都会被报告为 mask 的信息
+    fn masked(&mut self, exp: &Expression, mask: Mask, arg_type: ArgType) {
+        self.emit(Op::Mask(mask));
+        self.expression(exp, false);
+        self.emit(Op::Unmask(arg_type));
+    }
+
+    /// tail: 表达式是否处于 Lambda 的尾部位置
+    fn expression(&mut self, exp: &Expression, tail: bool) {
+        let span = self
+            .spans
+            .get(&(exp as *const Expression as usize))
+            .cloned();
+        let outer = match span {
+            Some(a) => self.span.replace(a),
+            None => self.span.clone(),
+        };
+        self.node(exp, tail);
+        self.span = outer;
+    }
+
+    fn node(&mut self, exp: &Expression, tail: bool) {
+        match exp {
+            Expression::Symbol(k) => self.get(k),
+            Expression::Bool(_)
+            | Expression::Int(_)
+            | Expression::Float(_)
+            | Expression::String(_) => self.result(Ok(exp.clone())),
+            Expression::List(list) => match list.split_first() {
+                Some((first, args)) => self.list(first, args, tail),
+                None => self.fail("expected a non-empty list"),
+            },
+            Expression::Func(_) => self.fail("unexpected form"),
+            _ => self.fail("not supported type."),
+        }
+    }
+
+    fn list(&mut self, first: &Expression, args: &[Expression], tail: bool) {
+        if let Expression::Symbol(symbol) = first {
+            match symbol.as_ref() {
+                "if" => return self.func_if(args, tail),
+                "set" => return self.func_set(args),
+                "set!" => return self.func_assign(args),
+                "lambda" => return self.func_lambda(args),
+                "quote" => return self.result(func_quote(args)),
+                "cons" => return self.result(func_cons(args)),
+                "cond" => return self.func_cond(args, tail),
+                "loop" => return self.func_loop(args),
+                "do" => return self.func_do(args, tail),
+                "and" => return self.func_and(args),
+                "for-each-eval" => return self.func_for_each_eval(args),
+                "eval" => return self.func_eval(args, "eval", Op::EvalCode),
+                "eval-atom" => return self.func_eval(args, "eval-atom", Op::EvalAtom),
+                "return" | "continue" | "pass" => return self.result(Ok(first.clone())),
+                "defmacro" => return self.func_defmacro(args),
+                "require" => return self.func_require(args),
+                "provide" => return self.func_provide(args),
+                "quasiquote" => return self.func_quasiquote(args),
+                "try" => return self.func_try(args, tail),
+                "catch" => return self.fail("catch: not in try"),
+                "unquote" | "unquote-splicing" => {
+                    return self.fail(&format!("{}: not in quasiquote", symbol))
+                }
+                _ => {}
+            }
+            if let Some(mac) = self.env.get_macro(symbol) {
+                return self.expand(symbol, mac, args, tail);
+            }
+            if let Some(index) = built_in(symbol) {
+                if !self.is_bound(symbol) {
+                    return self.built_in(index, args);
+                }
+            }
+        }
+
+        self.expression(first, false);
+        let argc = args.len() as u32;
+        self.emit(Op::PrepareCall(argc));
+        for arg in args {
+            self.expression(arg, false);
+        }
+        if tail && self.tail_calls {
+            self.emit(Op::TailCall(argc));
+        } else {
+            self.emit(Op::Call(argc));
+        }
+    }
+
+    fn built_in(&mut self, index: usize, args: &[Expression]) {
+        let func = &BUILT_INS[index];
+        if !self.args_len(func.name, args, func.min, func.max) {
+            return;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            match func.arg_type(i) {
+                ArgType::Any => self.expression(arg, false),
+                a => self.masked(arg, Mask::Type(func.name), a),
+            }
+        }
+        self.emit(Op::BuiltIn {
+            index: index as u16,
+            argc: args.len() as u16,
+        });
+    }
+
+    fn func_if(&mut self, args: &[Expression], tail: bool) {
+        let Some(test_form) = args.first() else {
+            return self.fail("expected test form");
+        };
+        self.expression(test_form, false);
+        let message = self.constant(Expression::String(format!(
+            "unexpected test form='{}'",
+            test_form
+        )));
+        let test = self.emit(Op::If {
+            otherwise: 0,
+            message,
+        });
+        match args.get(1) {
+            Some(a) => self.expression(a, tail),
+            None => self.fail("expected form idx=1"),
+        }
+        let end = self.emit(Op::Jump(0));
+        self.patch(test);
+        match args.get(2) {
+            Some(a) => self.expression(a, tail),
+            None => self.fail("expected form idx=2"),
+        }
+        self.patch(end);
+    }
+
+    /// 和原先的实现相同，值在变量名之前被求值
+    fn func_set(&mut self, args: &[Expression]) {
+        if !self.args_len("set", args, 2, Some(2)) {
+            return;
+        }
+        self.expression(&args[1], false);
+        match &args[0] {
+            Expression::Symbol(name) => {
+                self.set(name);
+                self.result(Ok(args[0].clone()));
+            }
+            _ => self.fail("unexpected var name"),
+        }
+    }
+
+    /// 和 set 不同， set! 不会定义新的变量，而是修改最近的已经存在的变量，包括 Lambda 捕获的变量
+    fn func_assign(&mut self, args: &[Expression]) {
+        if !self.args_len("set!", args, 2, Some(2)) {
+            return;
+        }
+        self.expression(&args[1], false);
+        match &args[0] {
+            Expression::Symbol(name) => {
+                let index = self.name(name);
+                self.emit(Op::Assign(index));
+                self.result(Ok(args[0].clone()));
+            }
+            _ => self.fail("unexpected var name"),
+        }
+    }
+
+    fn func_lambda(&mut self, args: &[Expression]) {
+        let Some(params) = args.first() else {
+            return self.fail("unexpected args form");
+        };
+        let Some(body) = args.get(1) else {
+            return self.fail("unexpected second form");
+        };
+        if args.len() != 2 {
+            return self.fail("lambda can only have two forms");
+        }
+        let code = compile_lambda(
+            params,
+            body,
+            &self.env,
+            self.depth,
+            &self.spans,
+            &self.inner_bound(),
+        );
+        let index = self.constant(Expression::Lambda(Lambda::new(params, body, code)));
+        self.emit(Op::Closure(index));
+    }
+
+    /// `(defmacro name (params) body)` ，参数列表的最后可以是 `&rest name`
+    fn func_defmacro(&mut self, args: &[Expression]) {
+        if !self.args_len("defmacro", args, 3, Some(3)) {
+            return;
+        }
+        let Expression::Symbol(name) = &args[0] else {
+            return self.fail("unexpected macro name");
+        };
+        let Expression::List(list) = &args[1] else {
+            return self.fail("expected params to be a list");
+        };
+        let rest_symbol = Expression::Symbol("&rest".to_owned());
+        let (params, rest) = match list.iter().position(|a| *a == rest_symbol) {
+            Some(i) if i + 2 == list.len() => {
+                let mut params = list.clone();
+                params.remove(i);
+                (params, true)
+            }
+            Some(_) => return self.fail("expected exactly one param after &rest"),
+            None => (list.clone(), false),
+        };
+        let params = Expression::List(params);
+        let code = compile_lambda(
+            &params,
+            &args[2],
+            &self.env,
+            self.depth,
+            &self.spans,
+            &self.inner_bound(),
+        );
+        if let Err(msg) = &code.params {
+            return self.fail(&msg.clone());
+        }
+        let mut lambda = Lambda::new(&params, &args[2], code);
+        lambda.env = Some(self.env.clone());
+        self.env.define_macro(name.clone(), Macro { lambda, rest });
+        self.result(Ok(args[0].clone()));
+    }
+
+    /// 在编译时以未被求值的参数调用宏，并编译它返回的代码
+    fn expand(&mut self, name: &str, mac: Macro, args: &[Expression], tail: bool) {
+        if self.depth >= MAX_EXPANSION_DEPTH {
+            return self.fail(&format!("{}: macro expansion is too deep", name));
+        }
+        let fixed = match &mac.lambda.code.params {
+            Ok(a) => a.len() - mac.rest as usize,
+            Err(msg) => return self.fail(&msg.clone()),
+        };
+        let mut args = args.to_vec();
+        if mac.rest {
+            if args.len() < fixed {
+                return self.fail_with(
+                    ErrorKind::Arity,
+                    &format!(
+                        "{}: expected at least {} args, got {}",
+                        name,
+                        fixed,
+                        args.len()
+                    ),
+                );
+            }
+            let rest = args.split_off(fixed);
+            args.push(Expression::List(rest));
+        } else if args.len() != fixed {
+            return self.fail_with(
+                ErrorKind::Arity,
+                &format!("{}: expected {} args, got {}", name, fixed, args.len()),
+            );
+        }
+        match call_lambda(&mac.lambda, args, None) {
+            Ok(code) => {
+                self.depth += 1;
+                self.expression(&code, tail);
+                self.depth -= 1;
+            }
+            Err(e) => self.fail_with(e.kind, &format!("{}: {}", name, e.message)),
+        }
+    }
+
+    /// 在编译时加载模块，它导出的宏被定义在编译时的环境中，变量在运行时被定义在当前的作用域中
+    fn func_require(&mut self, args: &[Expression]) {
+        if !self.args_len("require", args, 1, Some(1)) {
+            return;
+        }
+        let Expression::String(path) = &args[0] else {
+            return self.fail("require: expected a string literal");
+        };
+        let module = match require(path) {
+            Ok(a) => a,
+            Err(e) => return self.fail_with(e.kind, &e.message),
+        };
+        for (name, mac) in &module.macros {
+            self.env.define_macro(name.clone(), mac.clone());
+        }
+        for (name, value) in &module.values {
+            self.result(Ok(value.clone()));
+            self.set(name);
+        }
+        // 返回导出的名字组成的列表，没有导出任何名字时是 `(quote)`
+        let names = std::iter::once("quote")
+            .chain(module.values.iter().map(|a| a.0.as_str()))
+            .chain(module.macros.iter().map(|a| a.0.as_str()))
+            .map(|a| Expression::Symbol(a.to_owned()));
+        self.result(Ok(Expression::List(names.collect())));
+    }
+
+    /// 导出的名字由 module 模块在加载时从语法树中找出，所以它在运行时只返回这些名字
+    fn func_provide(&mut self, args: &[Expression]) {
+        if !self.args_len("provide", args, 1, None) {
+            return;
+        }
+        if args.iter().any(|a| !matches!(a, Expression::Symbol(_))) {
+            return self.fail("provide: expected symbols");
+        }
+        self.result(func_quote(args));
+    }
+
+    fn func_quasiquote(&mut self, args: &[Expression]) {
+        if !self.args_len("quasiquote", args, 1, Some(1)) {
+            return;
+        }
+        self.quasi(&args[0]);
+    }
+
+    /// 没有 `unquote` 的部分被编译为常量，列表在运行时由 Segment 、 Splice 和 Concat 拼接而成
+    fn quasi(&mut self, exp: &Expression) {
+        let Expression::List(list) = exp else {
+            return self.result(Ok(exp.clone()));
+        };
+        match unquote(exp) {
+            Some(("unquote", x)) => return self.expression(x, false),
+            Some(_) => return self.fail("unquote-splicing: not in list"),
+            None => {}
+        }
+        if !has_unquote(exp) {
+            return self.result(Ok(exp.clone()));
+        }
+        for e in list {
+            match unquote(e) {
+                Some(("unquote-splicing", x)) => {
+                    self.expression(x, false);
+                    self.emit(Op::Splice);
+                }
+                _ => {
+                    self.quasi(e);
+                    self.emit(Op::Segment);
+                }
+            }
+        }
+        self.emit(Op::Concat(list.len() as u32));
+    }
+
+    /// expr 不处于尾部位置，因为尾调用会替换 `try` 所在的调用帧
+    fn func_try(&mut self, args: &[Expression], tail: bool) {
+        if !self.args_len("try", args, 2, Some(2)) {
+            return;
+        }
+        let (name, handler) = match &args[1] {
+            Expression::List(list) => match list.as_slice() {
+                [Expression::Symbol(a), Expression::Symbol(name), handler] if a == "catch" => {
+                    (name, handler)
+                }
+                _ => return self.fail("try: expected (catch name handler)"),
+            },
+            _ => return self.fail("try: expected (catch name handler)"),
+        };
+        let start = self.emit(Op::Try(0));
+        self.expression(&args[0], false);
+        let end = self.emit(Op::EndTry(0));
+        self.patch(start);
+        self.set(name);
+        self.expression(handler, tail);
+        self.patch(end);
+    }
+
+    /// 每一对测试和分支依次被检查，没有分支被选中时报错
+    fn func_cond(&mut self, args: &[Expression], tail: bool) {
+        if !self.args_len("coud", args, 2, None) {
+            return;
+        }
+        let mut ends = vec![];
+        for pair in args.chunks(2) {
+            self.masked(&pair[0], Mask::Cond, ArgType::Any);
+            let test = self.emit(Op::CondTest(0));
+            match pair.get(1) {
+                Some(a) => self.expression(a, tail),
+                None => self.fail("cond: Error2"),
+            }
+            ends.push(self.emit(Op::Jump(0)));
+            self.patch(test);
+        }
+        self.fail("cond: Error2");
+        for end in ends {
+            self.patch(end);
+        }
+    }
+
+    /// 循环地依次执行每一个表达式，直到某个表达式返回 `return`
+    fn func_loop(&mut self, args: &[Expression]) {
+        if !self.args_len("loop", args, 2, None) {
+            return;
+        }
+        let start = self.here();
+        let mut checks = vec![];
+        for arg in args {
+            self.expression(arg, false);
+            checks.push(self.emit(Op::LoopCheck { start, end: 0 }));
+        }
+        self.emit(Op::Jump(start));
+        let end = self.here();
+        for check in checks {
+            self.chunk.ops[check] = Op::LoopCheck { start, end };
+        }
+        self.result(Ok(Expression::Bool(true)));
+    }
+
+    fn func_do(&mut self, args: &[Expression], tail: bool) {
+        if !self.args_len("do", args, 1, None) {
+            return;
+        }
+        for (i, arg) in args.iter().enumerate() {
+            if i + 1 == args.len() {
+                self.expression(arg, tail);
+            } else {
+                self.expression(arg, false);
+                self.emit(Op::Pop);
+            }
+        }
+    }
+
+    fn func_and(&mut self, args: &[Expression]) {
+        if !self.args_len("and", args, 2, Some(2)) {
+            return;
+        }
+        self.masked(&args[0], Mask::Type("and"), ArgType::Bool);
+        let jump = self.emit(Op::JumpIfFalse(0));
+        self.masked(&args[1], Mask::Type("and"), ArgType::Bool);
+        self.patch(jump);
+    }
+
+    /// 对列表中的每一个元素，把它的字符串形式保存到 `$$` 并执行一次表达式
+    fn func_for_each_eval(&mut self, args: &[Expression]) {
+        if !self.args_len("for-each-eval", args, 2, Some(2)) {
+            return;
+        }
+        self.masked(&args[0], Mask::Type("for-each-eval"), ArgType::List);
+        self.result(Ok(Expression::Int(0)));
+        let start = self.here();
+        let next = self.emit(Op::ForEachNext { end: 0 });
+        self.set("$$");
+        self.expression(&args[1], false);
+        self.emit(Op::Pop);
+        self.emit(Op::Jump(start));
+        self.patch(next);
+        self.result(Ok(Expression::Bool(true)));
+    }
+
+    fn func_eval(&mut self, args: &[Expression], name: &'static str, op: Op) {
+        if !self.args_len(name, args, 1, Some(1)) {
+            return;
+        }
+        self.masked(&args[0], Mask::Type(name), ArgType::String);
+        self.emit(op);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_compile() {
+        let tokens =
+            tokenize("(lambda (n acc) (if (= n 0) acc (do (set m 1) (f (- n m) acc))))".to_owned())
+                .ok()
+                .unwrap();
+        let (exp, _) = parse(&tokens).ok().unwrap();
+        let Expression::List(list) = exp else {
+            panic!()
+        };
+        let env = &default_env();
+        let chunk = compile_lambda(
+            &list[1],
+            &list[2],
+            env,
+            0,
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(chunk.slots, ["n", "acc", "m"]);
+        assert_eq!(chunk.params, Ok(vec![0, 1]));
+        // 外部的变量按名字访问，尾部位置的调用是尾调用
+        assert_eq!(chunk.names, ["=", "f", "-"]);
+        assert!(matches!(chunk.ops[chunk.ops.len() - 2], Op::TailCall(2)));
+
+        let chunk = compile_lambda(
+            &Expression::Symbol("x".to_owned()),
+            &list[2],
+            env,
+            0,
+            &Default::default(),
+            &Default::default(),
+        );
+        assert_eq!(chunk.params, Err("expected params to be a list".to_owned()));
+    }
+}
@@ -0,0 +1,594 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! Glisp 的一致性测试，每一个用例都会分别通过 REPL 、 `@gl` 和 Pipe 三种方式运行，它们的结果必须相同
+//! 用例的期望值是 REPL 打印的内容（去掉 `; => ` 前缀），也就是返回值的显示或者错误信息
+//! 修改求值器时，这些用例保证了语义没有改变；为语言增加功能时，应该同时在这里增加用例
+
+use super::core::*;
+use super::pipe::Pipe;
+use super::repl::eval_line;
+
+const CASES: &[(&str, &str)] = &[
+    // 数字和比较
+    ("(+ 1 2)", "3"),
+    ("(- 10 1 2)", "7"),
+    ("(+ 1 true)", "2"),
+    ("(+ 0.5 1)", "1.5"),
+    ("(= 1 1)", "true"),
+    ("(< 1 2 3)", "true"),
+    ("(> 3 1 2)", "false"),
+    ("(+ 1 \"a\")", "expect a number"),
+    // 整数和浮点数
+    ("(+ 0.5 0.5)", "1.0"),
+    ("(+ 9223372036854775807 1)", "integer overflow"),
+    ("(- -9223372036854775807 2)", "integer overflow"),
+    ("(= 9007199254740993 9007199254740992)", "false"),
+    ("(= 1 1.0)", "true"),
+    ("(* 2 3 4)", "24"),
+    ("(* 2 0.5)", "1.0"),
+    ("(*)", "1"),
+    ("(* 4611686018427387904 2)", "integer overflow"),
+    ("(/ 6 3)", "2"),
+    ("(/ 7 2)", "3.5"),
+    ("(/ 4)", "0.25"),
+    ("(/ 1 0)", "/: division by zero"),
+    ("(/ 1.0 0)", "inf"),
+    ("(mod 7 3)", "1"),
+    ("(mod -1 3)", "2"),
+    ("(mod 1 -3)", "-2"),
+    ("(mod 5.5 2)", "1.5"),
+    ("(mod 1 0)", "mod: division by zero"),
+    ("(mod -9223372036854775808 -1)", "0"),
+    ("(floor 2.7)", "2"),
+    ("(floor -2.5)", "-3"),
+    ("(round 2.5)", "3"),
+    ("(round 1e20)", "round: 100000000000000000000 is out of range"),
+    ("(abs -3)", "3"),
+    ("(abs -2.5)", "2.5"),
+    ("(abs -9223372036854775808)", "integer overflow"),
+    ("(min 3 1 2)", "1"),
+    ("(max 3 1.5)", "3.0"),
+    ("(number->string 42)", "\"42\""),
+    ("(number->string 0.1)", "\"0.1\""),
+    ("(number->string \"a\")", "number->string: Unsupported type"),
+    // if 和 set
+    ("(if (> 2 1) \"a\" \"b\")", "\"a\""),
+    ("(if 1 2 3)", "unexpected test form='1'"),
+    ("(if false 1)", "expected form idx=2"),
+    ("(if true 1 (nope))", "1"),
+    ("(if)", "expected test form"),
+    ("(set a 1)", "a"),
+    ("(set 1 2)", "unexpected var name"),
+    ("(do (set a 1) (set b (+ a 1)) b)", "2"),
+    ("(do)", "\"do\": There are more parameters than the minimum 1 allowed"),
+    // loop
+    (
+        "(do (set i 0) (loop (if (> i 10) (return) (pass)) (set i (+ i 1))) i)",
+        "11",
+    ),
+    (
+        "(do (set i 0) (loop (set i (+ i 1)) (if (> i 3) (return) (pass))))",
+        "true",
+    ),
+    (
+        "(do (set i 0) (set n 0)
+            (loop (set i (+ i 1)) (if (> i 5) (return) (pass)) (if (= i 3) (continue) (pass)) (set n (+ n i)))
+            n)",
+        "12",
+    ),
+    (
+        "(loop (return))",
+        "\"loop\": There are more parameters than the minimum 2 allowed",
+    ),
+    ("(return)", "return"),
+    // cond, and, or
+    ("(cond (eq 1 2) 1 (eq 1 1) 2)", "2"),
+    ("(cond (nope) 1 true 2)", "cond: Error3"),
+    ("(cond false 1 false 2)", "cond: Error2"),
+    ("(cond false 1 true)", "cond: Error2"),
+    ("(and false nope)", "false"),
+    ("(and true false)", "false"),
+    ("(or true false)", "true"),
+    ("(or true nope)", "or: Unsupported type"),
+    // 列表
+    ("(quote a b c)", "[\"quote\", \"a\", \"b\", \"c\"]"),
+    ("(quote)", "unexpected args form"),
+    ("(car (quote a b))", "quote"),
+    ("(cdr (quote a b))", "[\"a\", \"b\"]"),
+    ("(cons (quote a) (quote b c))", "[\"a\", \"b\", \"c\"]"),
+    ("(cons a (quote b))", "cons can only result a static list"),
+    ("(list (+ 1 1) \"a\")", "[\"quote\", \"2\", \"\\\"a\\\"\"]"),
+    ("(atom (quote a))", "false"),
+    ("(atom 1)", "true"),
+    ("(eq \"a\" \"a\")", "true"),
+    ("(eq 1 2)", "false"),
+    ("(length (quote a b))", "3"),
+    ("()", "expected a non-empty list"),
+    // lambda
+    ("(do (set f (lambda (x) (+ x 1))) (f 2))", "3"),
+    ("((lambda (x) (+ x 1)) 1)", "2"),
+    (
+        "(do (set f (lambda (x) x)) f)",
+        "lambda: { params: [\"x\"] , body: x }",
+    ),
+    ("(lambda (x))", "unexpected second form"),
+    ("(lambda (x) x x)", "lambda can only have two forms"),
+    (
+        "(do (set f (lambda (x) x)) (f 1 2))",
+        "expected 1 params, got 2",
+    ),
+    (
+        "(do (set f (lambda x x)) (f 1))",
+        "expected params to be a list",
+    ),
+    (
+        "(do (set f (lambda (1) 1)) (f 1))",
+        "expected symbol in the argument list",
+    ),
+    ("(1 2)", "first form must be a function"),
+    ("(+ nope 1)", "unexpected symbol k=nope"),
+    // 词法作用域：Lambda 看到的是它被创建时的环境，而不是调用者的环境
+    (
+        "(do (set y 10) (set f (lambda (x) (+ x y))) (set g (lambda (y) (f 1))) (g 5))",
+        "11",
+    ),
+    (
+        "(do (set f (lambda () n)) (set g (lambda (n) (f))) (g 7))",
+        "unexpected symbol k=n",
+    ),
+    (
+        "(do (set f (lambda () x)) (set x 2) (f))",
+        "2",
+    ),
+    // 闭包
+    (
+        "(do (set make-adder (lambda (n) (lambda (x) (+ x n)))) (set add2 (make-adder 2)) (add2 40))",
+        "42",
+    ),
+    (
+        "(do (set make-adder (lambda (n) (lambda (x) (+ x n)))) (set a (make-adder 1)) (set b (make-adder 10)) (+ (a 0) (b 0)))",
+        "11",
+    ),
+    (
+        "(do (set twice (lambda (f) (lambda (x) (f (f x))))) ((twice (lambda (x) (+ x 3))) 1))",
+        "7",
+    ),
+    (
+        "(do (set sum-to (lambda (n) (do (set go (lambda (i acc) (if (= i 0) acc (go (- i 1) (+ acc i))))) (go n 0)))) (sum-to 10))",
+        "55",
+    ),
+    // set! 修改被捕获的变量
+    (
+        "(do (set make-counter (lambda () (do (set n 0) (lambda () (do (set! n (+ n 1)) n)))))
+            (set c (make-counter)) (set d (make-counter))
+            (c) (c) (d)
+            (str.+ (meta (c)) (meta (d))))",
+        "\"32\"",
+    ),
+    ("(do (set x 1) (set! x 2) x)", "2"),
+    ("(set! nope 1)", "set!: unexpected symbol k=nope"),
+    ("(set! 1 1)", "unexpected var name"),
+    // 在 Lambda 中使用 set 不会修改外部的环境
+    (
+        "(do (set x 1) (set f (lambda () (do (set x 2) x))) (str.+ (meta (f)) (meta x)))",
+        "\"21\"",
+    ),
+    (
+        "(do (set w 1) (set f (lambda () (do (set r w) (set w 2) (str.+ (meta r) (meta w))))) (f))",
+        "\"12\"",
+    ),
+    // 递归
+    (
+        "(do (set sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1)))))) (sum 100))",
+        "5050",
+    ),
+    (
+        "(do (set count (lambda (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))) (count 100 0))",
+        "100",
+    ),
+    // 字符串
+    ("(str.+ \"a\" \"b\")", "\"ab\""),
+    ("(str.+ nope \"a\")", "str.+: Unsupported type"),
+    ("(slice (str.+ nope \"a\") 0 0)", "slice: Unsupported type"),
+    (
+        "(str.+ \"a\")",
+        "\"str.+\": There are more parameters than the minimum 2 allowed",
+    ),
+    ("(str \"a b()\")", "\"a b()\""),
+    // 字符串字面量
+    ("(length \"a b; (c)\")", "8"),
+    ("(str.+ \"a\" \"\\\"\\\\\")", "\"a\"\\\""),
+    ("(length \"\\n\\t\\r\\0\")", "4"),
+    ("(str.= \"\\u{48}\\u{1F600}\" \"H😀\")", "true"),
+    ("(length \"a\nb\")", "3"),
+    ("`,\"a b\"", "\"a b\""),
+    ("(str \"a\\bb\")", "unknown escape `\\b`"),
+    ("(str \"\\u{110000}\")", "invalid unicode escape"),
+    ("(str \"\\u48\")", "invalid unicode escape"),
+    ("(str \"a)", "unterminated string"),
+    ("(length \"abc\")", "3"),
+    ("(length 1)", "length: Unsupported type"),
+    ("(slice \"hello\" 1 3)", "\"ell\""),
+    ("(slice \"hello\" 1 5)", "slice: index 5 out of range for a string of length 5"),
+    ("(slice \"hello\" -1 3)", "slice: index -1 out of range for a string of length 5"),
+    ("(slice \"hello\" 3 1)", "slice: invalid range from 3 to 1"),
+    ("(slice \"hello\" 2 1)", "\"\""),
+    ("(slice \"你好，世界\" 3 4)", "\"世界\""),
+    ("(find \"你好，世界\" \"世\")", "3"),
+    ("(rfind \"世界，世界\" \"世\")", "3"),
+    ("(insert \"你好\" 2 \"！\")", "\"你好！\""),
+    ("(insert \"你好\" 3 \"！\")", "insert: index 3 out of range for a string of length 2"),
+    ("(remove \"你好，世界\" 2)", "\"你好世界\""),
+    ("(remove \"你好，世界\" 0 2)", "\"世界\""),
+    ("(remove \"你好\" 2)", "remove: index 2 out of range for a string of length 2"),
+    ("(last \"你好\")", "\"好\""),
+    ("(length \"你好\")", "2"),
+    ("(slice \"hello\" 1.0 3)", "slice: Unsupported type"),
+    ("(find \"hello\" \"l\")", "2"),
+    ("(rfind \"hello\" \"l\")", "3"),
+    ("(find \"hello\" \"z\")", "false"),
+    ("(contains \"hello\" \"ell\")", "true"),
+    ("(insert \"hllo\" 1 \"e\")", "\"hello\""),
+    ("(begin \"hello\")", "\"h\""),
+    ("(last \"hello\")", "\"o\""),
+    ("(begin \"\")", "false"),
+    ("(last \"\")", "false"),
+    ("(is-empty \"\")", "true"),
+    ("(remove \"hello\" 1)", "\"hllo\""),
+    ("(remove \"hello\" 1 3)", "\"ho\""),
+    ("(reverse \"abc\")", "\"cba\""),
+    ("(chars \"ab\")", "[\"quote\", \"\\\"a\\\"\", \"\\\"b\\\"\"]"),
+    ("(str.< \"a\" \"b\")", "true"),
+    ("(str.= \"a\" \"b\")", "false"),
+    ("(meta (+ 1 2))", "\"3\""),
+    // Map
+    ("(map.new)", "{}"),
+    (
+        "(map.new \"b\" 2 \"a\" (list 1))",
+        "{\"a\": [\"quote\", \"1\"], \"b\": 2}",
+    ),
+    ("(map.new \"a\")", "map.new: expected key-value pairs"),
+    ("(map.new 1 2)", "map.new: Unsupported type"),
+    ("(map.get (map.new \"a\" 1) \"a\")", "1"),
+    ("(map.get (map.new \"a\" 1) \"b\")", "false"),
+    ("(map.get (map.new \"a\" 1) \"b\" 0)", "0"),
+    ("(map.get (list 1) \"a\")", "map.get: Unsupported type"),
+    (
+        "(do (set m (map.new \"a\" 1)) (set n (map.set m \"b\" 2)) (str.+ (meta m) (meta n)))",
+        "\"{\"a\": 1}{\"a\": 1, \"b\": 2}\"",
+    ),
+    (
+        "(map.remove (map.new \"a\" 1 \"b\" 2) \"a\")",
+        "{\"b\": 2}",
+    ),
+    ("(map.has (map.new \"a\" 1) \"a\")", "true"),
+    ("(map.len (map.new \"a\" 1 \"b\" 2))", "2"),
+    (
+        "(map.keys (map.new \"b\" 1 \"a\" 2))",
+        "[\"quote\", \"\\\"a\\\"\", \"\\\"b\\\"\"]",
+    ),
+    ("(map.values (map.new \"b\" 1 \"a\" 2))", "[\"quote\", \"2\", \"1\"]"),
+    (
+        "(car (cdr (map.entries (map.new \"a\" 1))))",
+        "[\"\\\"a\\\"\", \"1\"]",
+    ),
+    (
+        "(do (set s \"\") (map.for-each (map.new \"a\" 1 \"b\" 2) (lambda (k v) (set! s (str.+ s k)))) s)",
+        "\"ab\"",
+    ),
+    ("(map.for-each (map.new) 1)", "map.for-each: Unsupported type"),
+    ("(eq (map.new \"a\" 1) (map.set (map.new) \"a\" 1))", "true"),
+    // JSON
+    (
+        "(json.parse \"[1,2.5,true,null,[]]\")",
+        "[\"quote\", \"1\", \"2.5\", \"true\", \"null\", \"[\\\"quote\\\"]\"]",
+    ),
+    ("(json.parse \"{}\")", "{}"),
+    (
+        "(json.parse \"[1,\")",
+        "json.parse: Unexpected end of input at line 1, column 4",
+    ),
+    (
+        "(json.stringify (map.new \"a\" (list 1 \"x\") \"b\" (map.new)))",
+        "\"{\"a\":[1,\"x\"],\"b\":{}}\"",
+    ),
+    (
+        "(json.stringify (list 1 (list)) 1)",
+        "\"[\n 1,\n []\n]\"",
+    ),
+    (
+        "(map.get (json.parse (json.stringify (map.new \"a\" 1))) \"a\")",
+        "1",
+    ),
+    (
+        "(json.stringify (lambda (x) x))",
+        "json.stringify: can not convert lambda: { params: [\"x\"] , body: x } to JSON",
+    ),
+    // 列表的高阶函数
+    ("(map (lambda (x) (+ x 1)) (list 1 2 3))", "[\"quote\", \"2\", \"3\", \"4\"]"),
+    ("(map (lambda (x) x) (list))", "[\"quote\"]"),
+    ("(map (lambda (x y) x) (list 1))", "expected 2 params, got 1"),
+    ("(map 1 (list 1))", "map: Unsupported type"),
+    (
+        "(filter (lambda (x) (> x 1)) (list 1 2 3))",
+        "[\"quote\", \"2\", \"3\"]",
+    ),
+    ("(filter (lambda (x) x) (list 1))", "filter: expected true or false, got 1"),
+    ("(reduce + 0 (list 1 2 3))", "6"),
+    ("(reduce (lambda (acc x) (str.+ x acc)) \"\" (list \"a\" \"b\"))", "\"ba\""),
+    ("(reduce + 7 (list))", "7"),
+    ("(sort (list 3 1.5 2))", "[\"quote\", \"1.5\", \"2\", \"3\"]"),
+    (
+        "(sort (list \"b\" \"a\"))",
+        "[\"quote\", \"\\\"a\\\"\", \"\\\"b\\\"\"]",
+    ),
+    ("(sort (list 1 \"a\"))", "sort: can not compare \"a\" and 1"),
+    (
+        "(sort-by (lambda (x) (nth x 1)) (list (list \"b\" 2) (list \"a\" 1) (list \"c\" 1)))",
+        "[\"quote\", \"[\\\"quote\\\", \\\"\\\\\\\"a\\\\\\\"\\\", \\\"1\\\"]\", \"[\\\"quote\\\", \\\"\\\\\\\"c\\\\\\\"\\\", \\\"1\\\"]\", \"[\\\"quote\\\", \\\"\\\\\\\"b\\\\\\\"\\\", \\\"2\\\"]\"]",
+    ),
+    ("(range 3)", "[\"quote\", \"0\", \"1\", \"2\"]"),
+    ("(range 1 3)", "[\"quote\", \"1\", \"2\"]"),
+    ("(range 5 0 -2)", "[\"quote\", \"5\", \"3\", \"1\"]"),
+    ("(range 3 1)", "[\"quote\"]"),
+    ("(range 0 1 0)", "range: step can not be 0"),
+    ("(range 1.5)", "range: Unsupported type"),
+    (
+        "(append (list 1) (quote 2 3) (list))",
+        "[\"quote\", \"1\", \"2\", \"3\"]",
+    ),
+    ("(nth (list 1 2) 1)", "2"),
+    ("(nth (list 1 2) 2)", "nth: index 2 out of range for a list of length 2"),
+    (
+        "(zip (list 1 2 3) (list \"a\" \"b\"))",
+        "[\"quote\", \"[\\\"quote\\\", \\\"1\\\", \\\"\\\\\\\"a\\\\\\\"\\\"]\", \"[\\\"quote\\\", \\\"2\\\", \\\"\\\\\\\"b\\\\\\\"\\\"]\"]",
+    ),
+    ("(apply + (list 1 2 3))", "6"),
+    ("(apply (lambda (a b) (str.+ a b)) (list \"x\" \"y\"))", "\"xy\""),
+    (
+        "(do (set f (lambda (n) (map f (list n)))) (f 0))",
+        "calls through built-in functions are nested more than 64 levels deep",
+    ),
+    // 正则表达式
+    ("(nth (re.match \"(\\\\w+)@(\\\\w+)\\\\.com\" \"mail: a@b.com\") 2)", "\"b\""),
+    ("(nth (re.match \"a(x)?b\" \"ab\") 1)", "false"),
+    ("(re.match \"^b\" \"ab\")", "false"),
+    ("(nth (re.match \"a.*?c\" \"abcbc\") 0)", "\"abc\""),
+    ("(nth (re.match \"a.*c\" \"abcbc\") 0)", "\"abcbc\""),
+    ("(nth (re.match \"[^a-c]{2,}\" \"abxyzc\") 0)", "\"xyz\""),
+    ("(nth (re.match \"\\\\bcat|dog\\\\b\" \"concat hotdog\") 0)", "\"dog\""),
+    ("(nth (re.match \"(a|ab)(c|bcd)\" \"abcd\") 0)", "\"abcd\""),
+    ("(nth (re.match \"(a*)*b\" \"aaab\") 1)", "\"aaa\""),
+    ("(nth (re.match \"[一-龥]+\" \"Glisp 正则\") 0)", "\"正则\""),
+    ("(re.find-all \"\\\\d+\" \"a1b22c333\")", "[\"quote\", \"\\\"1\\\"\", \"\\\"22\\\"\", \"\\\"333\\\"\"]"),
+    ("(re.find-all \"x*\" \"ab\")", "[\"quote\", \"\\\"\\\"\", \"\\\"\\\"\", \"\\\"\\\"\"]"),
+    ("(re.replace \"(\\\\w+) (\\\\w+)\" \"hello world\" \"$2 $1\")", "\"world hello\""),
+    ("(re.replace \"a\" \"banana\" \"${0}$$\")", "\"ba$na$na$\""),
+    ("(re.replace \"x*\" \"ab\" \"-\")", "\"-a-b-\""),
+    ("(re.split \",\\\\s*\" \"a, b,c\")", "[\"quote\", \"\\\"a\\\"\", \"\\\"b\\\"\", \"\\\"c\\\"\"]"),
+    ("(re.split \"x*\" \"ab\")", "[\"quote\", \"\\\"ab\\\"\"]"),
+    ("(re.match \"(a\" \"a\")", "re.match: Unclosed group at position 3"),
+    ("(re.match \"a)\" \"a\")", "re.match: Unmatched ')' at position 2"),
+    ("(re.match \"*a\" \"a\")", "re.match: Nothing to repeat at position 1"),
+    ("(re.match \"a{3,1}\" \"a\")", "re.match: Invalid repetition count at position 7"),
+    ("(re.match \"(a{1000}){1000}\" \"a\")", "re.match: Pattern is too large at position 16"),
+    ("(nth (re.match \"a{x\" \"a{x\") 0)", "\"a{x\""),
+    // Markdown
+    ("(markdown->html \"# 标题\")", "\"<h1>标题</h1>\n\""),
+    (
+        "(markdown->html \"*a* **b** `<c>` [d](/e \\\"f\\\")\")",
+        "\"<p><em>a</em> <strong>b</strong> <code>&lt;c&gt;</code> <a href=\"/e\" title=\"f\">d</a></p>\n\"",
+    ),
+    (
+        "(markdown->html \"- a\\n- b\\n\\n1. c\")",
+        "\"<ul>\n<li>a</li>\n<li>b</li>\n</ul>\n<ol>\n<li>c</li>\n</ol>\n\"",
+    ),
+    (
+        "(markdown->html \"| a | b |\\n|:--|--:|\\n| 1 | 2 |\")",
+        "\"<table>\n<thead>\n<tr>\n<th align=\"left\">a</th>\n<th align=\"right\">b</th>\n</tr>\n</thead>\n<tbody>\n<tr>\n<td align=\"left\">1</td>\n<td align=\"right\">2</td>\n</tr>\n</tbody>\n</table>\n\"",
+    ),
+    (
+        "(markdown->html \"```gl\\n(+ 1 2)\\n```\\n> 1 < 2 & ![x](a b.png)\")",
+        "\"<pre><code class=\"language-gl\">(+ 1 2)\n</code></pre>\n<blockquote>\n<p>1 &lt; 2 &amp; ![x](a b.png)</p>\n</blockquote>\n\"",
+    ),
+    ("(markdown->html \"[a]\\n\\n[a]: /b\")", "\"<p><a href=\"/b\">a</a></p>\n\""),
+    ("(markdown->html \"\")", "\"\""),
+    // for-each-eval, eval, eval-atom
+    (
+        "(do (set acc \"\") (for-each-eval (quote a b) (set acc (str.+ acc $$))) acc)",
+        "\"quoteab\"",
+    ),
+    (
+        "(do (set f (lambda (l) (do (set s \"\") (for-each-eval l (set s (str.+ s $$))) s))) (f (quote x y)))",
+        "\"quotexy\"",
+    ),
+    ("(do (set x 5) (eval \"(+ x 1)\"))", "6"),
+    ("(do (eval \"(set z 3)\") z)", "3"),
+    (
+        "(do (set f (lambda (a) (do (eval \"(set a 9)\") a))) (f 1))",
+        "9",
+    ),
+    ("(do (set v 4) (eval-atom \"v\"))", "4"),
+    ("(eval-atom \"12\")", "12"),
+    // quasiquote
+    ("`(a b)", "[\"a\", \"b\"]"),
+    ("`(a ,(+ 1 2) (b ,(+ 1 1)))", "[\"a\", \"3\", \"[\\\"b\\\", \\\"2\\\"]\"]"),
+    ("`(a ,@(list 1 2) c)", "[\"a\", \"1\", \"2\", \"c\"]"),
+    ("`a", "a"),
+    ("`,(+ 1 2)", "3"),
+    ("`(a ,@1)", "unquote-splicing: Unsupported type"),
+    ("`,@a", "unquote-splicing: not in list"),
+    (",a", "unquote: not in quasiquote"),
+    // defmacro
+    (
+        "(do (defmacro swap (a b) `(,b ,a)) (swap 1 str.+))",
+        "\"str.+\": There are more parameters than the minimum 2 allowed",
+    ),
+    (
+        "(do (defmacro my-if (c a b) `(cond ,c ,a true ,b)) (my-if (> 1 2) (nope) \"no\"))",
+        "\"no\"",
+    ),
+    (
+        "(do (defmacro inc! (x) `(set! ,x (+ ,x 1))) (set n 1) (inc! n) (inc! n) n)",
+        "3",
+    ),
+    (
+        "(do (defmacro first (a &rest b) a) (first 1 2 3))",
+        "1",
+    ),
+    (
+        "(do (defmacro rest (a &rest b) `(list ,@b)) (rest 1 2 3))",
+        "[\"quote\", \"2\", \"3\"]",
+    ),
+    (
+        "(do (defmacro two (a b) a) (two 1))",
+        "two: expected 2 args, got 1",
+    ),
+    (
+        "(do (defmacro m (a &rest b) a) (m))",
+        "m: expected at least 1 args, got 0",
+    ),
+    ("(defmacro m (a &rest) a)", "expected exactly one param after &rest"),
+    ("(defmacro 1 (a) a)", "unexpected macro name"),
+    (
+        "(do (defmacro m (x) (car x)) (m 1))",
+        "m: car: Unsupported type",
+    ),
+    (
+        "(do (defmacro loop-forever (x) `(loop-forever ,x)) (loop-forever 1))",
+        "loop-forever: macro expansion is too deep",
+    ),
+    // 宏在 Lambda 中展开，展开的代码使用 Lambda 的变量
+    (
+        "(do (defmacro twice (x) `(+ ,x ,x)) (set f (lambda (n) (twice n))) (f 4))",
+        "8",
+    ),
+    // 模块
+    ("(require 1)", "require: expected a string literal"),
+    ("(require \"nope.gl\")", "require: cannot find nope.gl"),
+    ("(provide a b)", "[\"quote\", \"a\", \"b\"]"),
+    ("(provide 1)", "provide: expected symbols"),
+    // prelude 中的宏
+    ("(when (> 2 1) 1 2)", "2"),
+    ("(when (> 1 2) 1)", "pass"),
+    ("(unless (> 1 2) 1)", "1"),
+    ("(unless (> 2 1) 1)", "pass"),
+    ("(let ((x 1) (y 2)) (+ x y))", "3"),
+    ("(do (set x 1) (let ((x 2)) (set x 3)) x)", "1"),
+    ("(let ((x 1)) (let ((y (+ x 1))) (+ x y)))", "3"),
+    (
+        "(case (str.+ \"g\" \"if\") (\"png\" 1) (\"gif\" 2) (else 3))",
+        "2",
+    ),
+    ("(case 5 (1 \"one\") (else \"many\"))", "\"many\""),
+    ("(case 5 (1 \"one\"))", "pass"),
+    (
+        "(do (set n 0) (case (do (set n (+ n 1)) n) (0 0) (1 1) (2 2)) n)",
+        "1",
+    ),
+    // 和内置函数同名的变量优先于内置函数
+    ("(do (set map (lambda (f l) \"mine\")) (map 1 2))", "\"mine\""),
+    ("((lambda (range) (range)) (lambda () 7))", "7"),
+    ("(let ((zip (lambda (a) a))) (zip 5))", "5"),
+    (
+        "((lambda (apply) ((lambda () (apply 1 2)))) (lambda (a b) (+ a b)))",
+        "3",
+    ),
+    ("(do (set sort 1) (sort (list 2 1)))", "first form must be a function"),
+    ("(do (set f (lambda (x) (range x))) (f 2))", "[\"quote\", \"0\", \"1\"]"),
+    // try 、 catch 和 raise
+    ("(try 5 (catch e 0))", "5"),
+    ("(try (raise \"boom\") (catch e (map.get e \"message\")))", "\"boom\""),
+    ("(try (+ 1 nope) (catch e (map.get e \"kind\")))", "\"name\""),
+    ("(try (str.+ 1 \"a\") (catch e (map.get e \"message\")))", "\"str.+: Unsupported type\""),
+    ("(str.+ (try (str.+ 1 \"a\") (catch e \"x\")) \"y\")", "\"xy\""),
+    (
+        "(do (set f (lambda (n) (if (= n 0) (raise \"deep\") (f (- n 1))))) (try (f 10) (catch e (map.get e \"message\"))))",
+        "\"deep\"",
+    ),
+    (
+        "(try (try (length) (catch e (raise e))) (catch e (map.get e \"kind\")))",
+        "\"arity\"",
+    ),
+    (
+        "(try (map.for-each (map.new \"a\" 1) (lambda (k v) (raise k))) (catch e (map.get e \"message\")))",
+        "\"a\"",
+    ),
+    ("(do (set x (try (raise \"a\") (catch e 1))) (+ x 1))", "2"),
+    ("(raise \"boom\")", "boom"),
+    ("(raise 1)", "raise: Unsupported type"),
+    ("(try 1 2)", "try: expected (catch name handler)"),
+    ("(catch e 1)", "catch: not in try"),
+];
+
+fn display(result: Result<Expression, GError>) -> String {
+    match result {
+        Ok(a) => a.to_string(),
+        Err(e) => e.message,
+    }
+}
+
+/// 分别通过 REPL 、 `@gl` 和 Pipe 运行 source ，返回三者的结果
+pub fn run_everywhere(source: &str) -> [String; 3] {
+    let repl = eval_line(source.to_owned(), &default_env());
+    let repl = repl.strip_prefix("; => ").unwrap().to_owned();
+
+    let mut config = crate::config::Config::new();
+    let gl = display(parse_eval(
+        source.to_owned(),
+        &default_env(),
+        Some(std::cell::RefCell::new(&mut config).into()),
+    ));
+
+    let pipe = display(Pipe::compile(source).and_then(|a| a.run("", None)));
+    [repl, gl, pipe]
+}
+
+#[test]
+fn test_conformance() {
+    let mut failures = vec![];
+    for (source, expected) in CASES {
+        for (mode, result) in ["repl", "@gl", "pipe"].iter().zip(run_everywhere(source)) {
+            if result != *expected {
+                failures.push(format!(
+                    "[{}] {}\n  expected: {}\n  got:      {}",
+                    mode, source, expected, result
+                ));
+            }
+        }
+    }
+    assert!(failures.is_empty(), "\n{}", failures.join("\n"));
+}
+
+#[test]
+fn test_conformance_repl_state() {
+    // REPL 的每一行共享同一个环境
+    let env = &default_env();
+    assert_eq!(eval_line("(set n 41)".to_owned(), env), "; => n");
+    assert_eq!(
+        eval_line("(set inc (lambda (x) (+ x 1)))".to_owned(), env),
+        "; => inc"
+    );
+    assert_eq!(eval_line("(inc n)".to_owned(), env), "; => 42");
+    // 之前的行定义的变量优先于内置函数
+    assert_eq!(
+        eval_line("(set range (lambda (x) (+ x 1)))".to_owned(), env),
+        "; => range"
+    );
+    assert_eq!(eval_line("(range n)".to_owned(), env), "; => 42");
+
+    // Pipe 的每一次运行都有一个干净的环境
+    let pipe = Pipe::compile("(do (set CONTENT (str.+ CONTENT \"!\")) CONTENT)")
+        .ok()
+        .unwrap();
+    for _ in 0..2 {
+        assert_eq!(display(pipe.run("a", None)), "\"a!\"");
+    }
+}
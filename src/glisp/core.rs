@@ -6,31 +6,73 @@
  * if not, see <https://www.gnu.org/licenses/>.
  */
 
-use super::std::eval_built_in_form;
-use std::{collections::HashMap, fmt::Display, rc::Rc};
+use super::compiler::{compile, Chunk, Macro};
+use std::{
+    cell::RefCell,
+    collections::{BTreeMap, HashMap},
+    fmt::Display,
+    rc::Rc,
+    sync::{
+        atomic::{AtomicBool, AtomicUsize, Ordering},
+        Arc, RwLock, RwLockReadGuard, RwLockWriteGuard, Weak,
+    },
+};
 
 #[derive(Clone, PartialEq)]
 pub enum Expression {
     Symbol(String),
-    Number(f64),
+    /// 整数，运算溢出时报错
+    Int(i64),
+    Float(f64),
     List(Vec<Expression>),
     Func(fn(&[Expression]) -> Result<Expression, GError>),
     Bool(bool),
     Lambda(Lambda),
     String(String),
+    /// 键是字符串的映射，按键的顺序保存，所以它的显示是确定的
+    Map(BTreeMap<String, Expression>),
 }
 
-#[derive(Clone, PartialEq)]
+/// 使用 Arc 是为了让 Lambda 可以被保存在 RouterConfig 中，并在多个线程之间共享
+/// code: 在 Lambda 被编译时生成的字节码
+/// env: Lambda 被创建时所在的环境，也就是它捕获的环境，它的变量在调用时仍然可见
+/// 只有编译器生成的 Lambda 模板的 env 是 None ，它在运行时被复制并捕获当前的环境
+#[derive(Clone)]
 pub struct Lambda {
-    pub params: Rc<Expression>,
-    pub body: Rc<Expression>,
+    pub params: Arc<Expression>,
+    pub body: Arc<Expression>,
+    pub code: Arc<Chunk>,
+    pub env: Option<Environment>,
+}
+
+impl Lambda {
+    pub fn new(params: &Expression, body: &Expression, code: Arc<Chunk>) -> Self {
+        Lambda {
+            params: Arc::new(params.clone()),
+            body: Arc::new(body.clone()),
+            code,
+            env: None,
+        }
+    }
+}
+
+/// 字节码由 params 和 body 决定，所以比较时忽略它，捕获的环境也被忽略
+impl PartialEq for Lambda {
+    fn eq(&self, other: &Self) -> bool {
+        self.params == other.params && self.body == other.body
+    }
 }
 
 impl Display for Expression {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
             Expression::Symbol(a) => write!(f, "{}", a),
-            Expression::Number(a) => write!(f, "{}", a),
+            Expression::Int(a) => write!(f, "{}", a),
+            // 整数值的浮点数显示为 `1.0` ，以和整数区分
+            Expression::Float(a) if a.is_finite() && a.fract() == 0.0 && a.abs() < 1e16 => {
+                write!(f, "{:.1}", a)
+            }
+            Expression::Float(a) => write!(f, "{}", a),
             Expression::List(a) => write!(
                 f,
                 "{:?}",
@@ -42,17 +84,450 @@ impl Display for Expression {
             }
             Expression::String(a) => write!(f, "\"{}\"", a),
             Expression::Func(_) => write!(f, "function()"),
+            Expression::Map(a) => write!(
+                f,
+                "{{{}}}",
+                a.iter()
+                    .map(|(k, v)| format!("\"{}\": {}", k, v))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
         }
     }
 }
 
-pub enum GError {
-    Reason(String),
+/// 源代码中的一个位置，行号和列号都从 1 开始，列号以字符计
+/// file: 源文件的路径，没有文件（例如 REPL 中的输入）时为空
+#[derive(Clone, PartialEq, Debug)]
+pub struct Span {
+    pub file: Arc<str>,
+    pub line: u32,
+    pub column: u32,
+}
+
+impl Display for Span {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.file.is_empty() {
+            write!(f, "line {}, column {}", self.line, self.column)
+        } else {
+            write!(f, "{}:{}:{}", self.file, self.line, self.column)
+        }
+    }
+}
+
+/// 错误的种类
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ErrorKind {
+    /// 解析源代码时的错误
+    Syntax,
+    /// 参数或值的类型不正确
+    Type,
+    /// 变量不存在
+    Name,
+    /// 参数的个数不正确
+    Arity,
+    /// 其它运行时的错误
+    Runtime,
+    /// 没有读写文件或执行程序的权限，参见 sandbox 模块
+    Permission,
+}
+
+impl ErrorKind {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ErrorKind::Syntax => "syntax",
+            ErrorKind::Type => "type",
+            ErrorKind::Name => "name",
+            ErrorKind::Arity => "arity",
+            ErrorKind::Runtime => "runtime",
+            ErrorKind::Permission => "permission",
+        }
+    }
+
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "syntax" => Some(ErrorKind::Syntax),
+            "type" => Some(ErrorKind::Type),
+            "name" => Some(ErrorKind::Name),
+            "arity" => Some(ErrorKind::Arity),
+            "runtime" => Some(ErrorKind::Runtime),
+            "permission" => Some(ErrorKind::Permission),
+            _ => None,
+        }
+    }
 }
 
-pub struct Environment<'a> {
+/// span: 出错的位置，由解析器或虚拟机填写
+/// trace: 出错时的调用栈，从内到外依次是每一层调用的位置
+#[derive(Clone, Debug)]
+pub struct GError {
+    pub kind: ErrorKind,
+    pub message: String,
+    pub span: Option<Span>,
+    pub trace: Vec<Span>,
+}
+
+impl GError {
+    pub fn new(message: String) -> Self {
+        GError::with_kind(ErrorKind::Runtime, message)
+    }
+
+    pub fn with_kind(kind: ErrorKind, message: String) -> Self {
+        GError {
+            kind,
+            message,
+            span: None,
+            trace: vec![],
+        }
+    }
+
+    fn syntax(message: &str, span: Option<&Span>) -> Self {
+        GError {
+            span: span.cloned(),
+            ..GError::with_kind(ErrorKind::Syntax, message.to_owned())
+        }
+    }
+
+    /// `catch` 得到的错误对象，它是一个 Map ，有 `kind` 和 `message` 两个键
+    /// 错误有位置时，还有 `line` 和 `column` ，它来自文件时还有 `file`
+    pub fn to_expression(&self) -> Expression {
+        let mut map = BTreeMap::new();
+        map.insert(
+            "kind".to_owned(),
+            Expression::String(self.kind.name().to_owned()),
+        );
+        map.insert(
+            "message".to_owned(),
+            Expression::String(self.message.clone()),
+        );
+        if let Some(span) = &self.span {
+            if !span.file.is_empty() {
+                map.insert("file".to_owned(), Expression::String(span.file.to_string()));
+            }
+            map.insert("line".to_owned(), Expression::Int(span.line as i64));
+            map.insert("column".to_owned(), Expression::Int(span.column as i64));
+        }
+        Expression::Map(map)
+    }
+
+    /// 多行的诊断信息，包含出错的位置、源代码的摘录和调用栈
+    /// 源代码从 span 中的文件读取，读取失败时省略摘录
+    pub fn diagnostic(&self) -> String {
+        let mut out = format!("{} error: {}", self.kind.name(), self.message);
+        let Some(span) = &self.span else {
+            return out;
+        };
+        out += &format!("\n  --> {}", span);
+        let line = std::fs::read_to_string(&*span.file)
+            .ok()
+            .and_then(|a| a.lines().nth(span.line as usize - 1).map(|a| a.to_owned()));
+        if let Some(line) = line {
+            let number = span.line.to_string();
+            let pad = " ".repeat(number.len());
+            // 保留制表符，使 ^ 和源代码对齐
+            let indent: String = line
+                .chars()
+                .take(span.column as usize - 1)
+                .map(|a| if a == '\t' { '\t' } else { ' ' })
+                .collect();
+            out += &format!("\n{} |\n{} | {}\n{} | {}^", pad, number, line, pad, indent);
+        }
+        for a in &self.trace {
+            out += &format!("\n  at {}", a);
+        }
+        out
+    }
+}
+
+/// 只显示错误信息，REPL 使用它
+impl Display for GError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+/// 一个作用域，顶层代码有一个，Lambda 的每次调用也会创建一个
+/// data: 按名字保存的变量，顶层代码的变量都保存在这里
+/// slots: Lambda 的参数和在其中被 `set` 的变量，它们的名字在 chunk.slots 中，没有被设置的槽位是 None
+/// macros: 由 `defmacro` 在编译时定义的宏，它们和变量不在同一个命名空间中
+/// outer: 外部环境，对于 Lambda 的作用域，它是 Lambda 捕获的环境（词法作用域）
+pub struct Scope {
     pub data: HashMap<String, Expression>,
-    pub outer: Option<&'a Environment<'a>>,
+    pub slots: Vec<Option<Expression>>,
+    pub macros: HashMap<String, Macro>,
+    pub chunk: Option<Arc<Chunk>>,
+    pub outer: Option<Environment>,
+}
+
+impl Scope {
+    /// 只在自身中查找变量，槽位优先
+    pub fn get_local(&self, key: &str) -> Option<&Expression> {
+        let slot = self
+            .chunk
+            .as_ref()
+            .and_then(|a| a.slot_map.get(key))
+            .and_then(|a| self.slots[*a as usize].as_ref());
+        slot.or_else(|| self.data.get(key))
+    }
+
+    /// 自身中被 lambda 捕获了自身的闭包和宏的数量
+    fn self_references(&self, env: &Environment) -> usize {
+        self.slots
+            .iter()
+            .flatten()
+            .chain(self.data.values())
+            .filter_map(|a| match a {
+                Expression::Lambda(a) => Some(a),
+                _ => None,
+            })
+            .chain(self.macros.values().map(|a| &a.lambda))
+            .filter(|a| matches!(&a.env, Some(a) if Arc::ptr_eq(&a.0, &env.0)))
+            .count()
+    }
+
+    /// 对自身持有的每一个环境的引用调用 f ，包括外部环境和闭包捕获的环境，列表和 Map 中的闭包也被包括在内
+    fn for_each_reference(&self, f: &mut impl FnMut(&Environment)) {
+        fn visit(exp: &Expression, f: &mut impl FnMut(&Environment)) {
+            match exp {
+                Expression::Lambda(Lambda { env: Some(env), .. }) => f(env),
+                Expression::List(list) => list.iter().for_each(|a| visit(a, f)),
+                Expression::Map(map) => map.values().for_each(|a| visit(a, f)),
+                _ => {}
+            }
+        }
+        if let Some(outer) = &self.outer {
+            f(outer);
+        }
+        for a in self.slots.iter().flatten().chain(self.data.values()) {
+            visit(a, f);
+        }
+        for a in self.macros.values() {
+            if let Some(env) = &a.lambda.env {
+                f(env);
+            }
+        }
+    }
+}
+
+thread_local! {
+    /// 当前的 collect 中创建的环境，不在 collect 中时为 None
+    static CREATED: RefCell<Option<Vec<Weak<RwLock<Scope>>>>> = const { RefCell::new(None) };
+}
+
+/// 执行 f ，在它结束后回收其中创建的、只被彼此引用的环境
+/// release 只能处理环境直接保存了捕获它的闭包的情况，保存在列表或 Map 中的闭包、
+/// 被子环境中的闭包捕获的环境等更复杂的循环引用由这里处理
+/// Pipe 、请求处理器和模板的每次运行都在 collect 中执行，所以请求不会留下不能被释放的环境
+pub fn collect<T>(f: impl FnOnce() -> T) -> T {
+    /// 在 f 返回之后才被 drop ，所以 f 的返回值引用的环境不会被回收
+    struct Collect(Option<Vec<Weak<RwLock<Scope>>>>);
+    impl Drop for Collect {
+        fn drop(&mut self) {
+            let created = CREATED.replace(self.0.take()).unwrap_or_default();
+            let survivors = collect_cycles(created);
+            // 仍然存活的环境之后由外层的 collect 检查
+            CREATED.with_borrow_mut(|a| {
+                if let Some(a) = a {
+                    a.extend(survivors);
+                }
+            });
+        }
+    }
+
+    let _collect = Collect(CREATED.replace(Some(vec![])));
+    f()
+}
+
+/// 找出 created 中除了彼此之间的引用以外没有其它引用的环境，清空它们以打破循环，返回其余仍然存活的环境
+fn collect_cycles(created: Vec<Weak<RwLock<Scope>>>) -> Vec<Weak<RwLock<Scope>>> {
+    let scopes: Vec<_> = created.iter().filter_map(Weak::upgrade).collect();
+    let index: HashMap<_, _> = scopes
+        .iter()
+        .enumerate()
+        .map(|(i, a)| (Arc::as_ptr(a), i))
+        .collect();
+    // 每个环境被其它候选环境引用的次数，以及它引用的候选环境
+    let mut internal = vec![0; scopes.len()];
+    let mut edges = vec![vec![]; scopes.len()];
+    for (i, scope) in scopes.iter().enumerate() {
+        scope.read().unwrap().for_each_reference(&mut |env| {
+            if let Some(j) = index.get(&Arc::as_ptr(&env.0)) {
+                internal[*j] += 1;
+                edges[i].push(*j);
+            }
+        });
+    }
+    // 除了 scopes 中的一个和候选环境之间的引用以外还有其它引用的环境是存活的，被存活的环境引用的环境也是存活的
+    let mut live: Vec<_> = scopes
+        .iter()
+        .zip(&internal)
+        .map(|(a, internal)| Arc::strong_count(a) > internal + 1)
+        .collect();
+    let mut stack: Vec<_> = (0..scopes.len()).filter(|i| live[*i]).collect();
+    while let Some(i) = stack.pop() {
+        for j in &edges[i] {
+            if !live[*j] {
+                live[*j] = true;
+                stack.push(*j);
+            }
+        }
+    }
+    let mut garbage = vec![];
+    for (scope, live) in scopes.iter().zip(&live) {
+        if !live {
+            let mut scope = scope.write().unwrap();
+            garbage.push((
+                std::mem::take(&mut scope.data),
+                std::mem::take(&mut scope.slots),
+                std::mem::take(&mut scope.macros),
+                scope.outer.take(),
+            ));
+        }
+    }
+    // 在释放锁之后再释放它们的内容
+    drop(garbage);
+    scopes
+        .iter()
+        .zip(live)
+        .filter(|(_, live)| *live)
+        .map(|(a, _)| Arc::downgrade(a))
+        .collect()
+}
+
+/// 使用 Arc 和 RwLock 而不是 Rc 和 RefCell ，是因为闭包和它捕获的环境会随着请求处理器被保存在 RouterConfig 中，并在多个线程之间共享
+#[derive(Clone)]
+pub struct Environment(Arc<RwLock<Scope>>);
+
+impl Environment {
+    /// 在 collect 中创建的环境会被记录下来
+    pub fn new(scope: Scope) -> Self {
+        let env = Environment(Arc::new(RwLock::new(scope)));
+        CREATED.with_borrow_mut(|created| {
+            if let Some(created) = created {
+                // 在需要扩容之前丢弃已经被释放的环境，使记录不会随着调用的次数无限增长
+                if created.len() == created.capacity() {
+                    created.retain(|a| a.strong_count() > 0);
+                }
+                created.push(Arc::downgrade(&env.0));
+            }
+        });
+        env
+    }
+
+    #[cfg(test)]
+    pub fn strong_count(&self) -> usize {
+        Arc::strong_count(&self.0)
+    }
+
+    pub fn read(&self) -> RwLockReadGuard<'_, Scope> {
+        self.0.read().unwrap()
+    }
+
+    pub fn write(&self) -> RwLockWriteGuard<'_, Scope> {
+        self.0.write().unwrap()
+    }
+
+    /// 创建一个以自身为外部环境的空环境，在子环境中使用 `set` 不会修改自身
+    pub fn child(&self) -> Environment {
+        Environment::new(Scope {
+            data: HashMap::new(),
+            slots: vec![],
+            macros: HashMap::new(),
+            chunk: None,
+            outer: Some(self.clone()),
+        })
+    }
+
+    /// 在自身及外部环境中按名字查找变量
+    pub fn get(&self, key: &str) -> Option<Expression> {
+        let mut env = self.clone();
+        loop {
+            let outer = {
+                let scope = env.read();
+                if let Some(exp) = scope.get_local(key) {
+                    return Some(exp.clone());
+                }
+                scope.outer.clone()?
+            };
+            env = outer;
+        }
+    }
+
+    /// 在自身及外部环境中按名字查找宏
+    pub fn get_macro(&self, key: &str) -> Option<Macro> {
+        let mut env = self.clone();
+        loop {
+            let outer = {
+                let scope = env.read();
+                if let Some(a) = scope.macros.get(key) {
+                    return Some(a.clone());
+                }
+                scope.outer.clone()?
+            };
+            env = outer;
+        }
+    }
+
+    /// 在自身中定义宏
+    pub fn define_macro(&self, key: String, value: Macro) {
+        self.write().macros.insert(key, value);
+    }
+
+    /// 在自身中定义变量
+    pub fn insert(&self, key: String, value: Expression) {
+        self.write().data.insert(key, value);
+    }
+
+    /// 修改自身或外部环境中已经存在的变量，全局环境中的变量不能被修改
+    pub fn assign(&self, key: &str, value: Expression) -> bool {
+        let mut env = self.clone();
+        loop {
+            if Arc::ptr_eq(&env.0, &global_env().0) {
+                return false;
+            }
+            let outer = {
+                let mut scope = env.write();
+                let scope = &mut *scope;
+                let slot = scope
+                    .chunk
+                    .as_ref()
+                    .and_then(|a| a.slot_map.get(key))
+                    .map(|a| &mut scope.slots[*a as usize]);
+                if let Some(a) = slot.filter(|a| a.is_some()) {
+                    *a = Some(value);
+                    return true;
+                }
+                if let Some(a) = scope.data.get_mut(key) {
+                    *a = value;
+                    return true;
+                }
+                match scope.outer.clone() {
+                    Some(a) => a,
+                    None => return false,
+                }
+            };
+            env = outer;
+        }
+    }
+
+    /// 在环境不再被使用时调用
+    /// 把自身保存在一个变量中的闭包或者在自身中定义的宏会形成循环引用，如果环境只被它们引用，清空它以使它能被释放
+    /// 它只检查这种最常见的情况，使环境尽早被释放，其它的循环引用在 collect 结束时被回收
+    pub fn release(self) {
+        let self_references = self.read().self_references(&self);
+        if self_references == 0 || Arc::strong_count(&self.0) != self_references + 1 {
+            return;
+        }
+        let (data, slots, macros) = {
+            let mut scope = self.write();
+            (
+                std::mem::take(&mut scope.data),
+                std::mem::take(&mut scope.slots),
+                std::mem::take(&mut scope.macros),
+            )
+        };
+        drop((data, slots, macros));
+    }
 }
 
 /// 使用 RefCell 包装是为了包装 `&'a mut crate::config::Config` 以使其可以被正确移动
@@ -63,63 +538,287 @@ pub struct Environment<'a> {
 /// 在未来的版本中，如果`&mut crate::config::Config` 不足以支撑 crate::config 包，会考虑全部换成 RefCell
 pub type Config<'a> = Option<Rc<std::cell::RefCell<&'a mut crate::config::Config>>>;
 
-pub fn func_lambda(args: &[Expression]) -> Result<Expression, GError> {
-    let params = args
-        .first()
-        .ok_or(GError::Reason("unexpected args form".to_string()))?;
-    let body = args
-        .get(1)
-        .ok_or(GError::Reason("unexpected second form".to_string()))?;
-    if args.len() != 2 {
-        return Err(GError::Reason("lambda can only have two forms".to_string()));
-    }
-    Ok(Expression::Lambda(Lambda {
-        params: Rc::new(params.clone()),
-        body: Rc::new(body.clone()),
-    }))
-}
-
-pub fn tokenize(expr: String) -> Vec<String> {
-    let lines = expr.lines();
-    let mut new_expr = String::new();
-    for line in lines {
-        if let Some(a) = line.find(';') {
-            new_expr += &line[..a]
+/// 一个记号和它在源代码中的位置
+#[derive(Clone, Debug)]
+pub struct Token {
+    pub text: String,
+    pub span: Span,
+}
+
+/// 和语法树的结构相同的位置树，每个节点是对应的表达式的位置，列表的位置是它的左括号的位置
+pub struct SpanTree {
+    pub span: Span,
+    pub children: Vec<SpanTree>,
+}
+
+/// 为 true 时，字符串字面量中不认识的转义（例如 `\b` 、 `\[` 和 `\]`）被原样保留，由 `str` 和 `log` 转换
+/// 用于兼容旧的配置，可以用 `$ glisp-legacy-strings yes` 打开
+pub static LEGACY_STRINGS: AtomicBool = AtomicBool::new(false);
+
+/// 求值时调用帧的最大个数，超过时报错而不是耗尽内存，尾调用不会增加调用帧的个数
+/// 可以用 `$ glisp-max-depth 10000` 设置
+pub static MAX_DEPTH: AtomicUsize = AtomicUsize::new(10000);
+
+/// 括号和引用的最大嵌套层数
+/// 解析、编译和显示表达式时都会在 Rust 的调用栈上递归，所以这个限制保证嵌套很深的代码只会产生语法错误
+pub const MAX_NESTING: usize = 200;
+
+pub fn tokenize(expr: String) -> Result<Vec<Token>, GError> {
+    tokenize_file(expr, "")
+}
+
+/// `;` 开始的注释持续到行尾，字符串字面量中可以包含空格、括号、分号和换行
+/// 字符串字面量的记号是带引号的、已经处理了转义的文本，由 parse_atom 去掉引号
+pub fn tokenize_file(expr: String, file: &str) -> Result<Vec<Token>, GError> {
+    lex(&expr, file, LEGACY_STRINGS.load(Ordering::Relaxed))
+}
+
+fn lex(expr: &str, file: &str, legacy: bool) -> Result<Vec<Token>, GError> {
+    Lexer {
+        chars: expr.chars().peekable(),
+        file: file.into(),
+        line: 1,
+        column: 1,
+        legacy,
+    }
+    .tokens()
+}
+
+/// line, column: 下一个字符的位置
+struct Lexer<'a> {
+    chars: std::iter::Peekable<std::str::Chars<'a>>,
+    file: Arc<str>,
+    line: u32,
+    column: u32,
+    legacy: bool,
+}
+
+impl Lexer<'_> {
+    fn span(&self) -> Span {
+        Span {
+            file: self.file.clone(),
+            line: self.line,
+            column: self.column,
+        }
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.chars.next()?;
+        if c == '\n' {
+            self.line += 1;
+            self.column = 1;
         } else {
-            new_expr += line
+            self.column += 1;
         }
+        Some(c)
     }
-    new_expr
-        .replace('(', " ( ")
-        .replace(')', " ) ")
-        .split_whitespace()
-        .map(|x| x.to_string())
-        .collect()
+
+    fn tokens(mut self) -> Result<Vec<Token>, GError> {
+        let mut tokens = vec![];
+        let mut current: Option<Token> = None;
+        loop {
+            let span = self.span();
+            let Some(c) = self.next() else {
+                break;
+            };
+            match c {
+                ';' => {
+                    tokens.extend(current.take());
+                    while self.chars.next_if(|a| *a != '\n').is_some() {}
+                }
+                '(' | ')' => {
+                    tokens.extend(current.take());
+                    tokens.push(Token {
+                        text: c.to_string(),
+                        span,
+                    });
+                }
+                // 字符串之前可以有读取器语法的前缀，例如 `,"a"`
+                '"' => {
+                    let text = self.string(&span)?;
+                    current
+                        .get_or_insert(Token {
+                            text: String::new(),
+                            span,
+                        })
+                        .text
+                        .push_str(&text);
+                    tokens.extend(current.take());
+                }
+                c if c.is_whitespace() => tokens.extend(current.take()),
+                c => current
+                    .get_or_insert(Token {
+                        text: String::new(),
+                        span,
+                    })
+                    .text
+                    .push(c),
+            }
+        }
+        tokens.extend(current);
+        Ok(tokens)
+    }
+
+    /// 读取开始的引号之后的部分，返回带引号的文本
+    /// start: 开始的引号的位置
+    fn string(&mut self, start: &Span) -> Result<String, GError> {
+        let mut text = "\"".to_owned();
+        loop {
+            let span = self.span();
+            let c = match self.next() {
+                Some('"') => break,
+                Some('\\') => match self.next() {
+                    Some('n') => '\n',
+                    Some('t') => '\t',
+                    Some('r') => '\r',
+                    Some('0') => '\0',
+                    Some('"') => '"',
+                    Some('\\') => '\\',
+                    Some('u') => self.unicode(&span)?,
+                    Some(c) if self.legacy => {
+                        text.push('\\');
+                        c
+                    }
+                    Some(c) => {
+                        return Err(GError::syntax(
+                            &format!("unknown escape `\\{}`", c),
+                            Some(&span),
+                        ))
+                    }
+                    None => return Err(GError::syntax("unterminated string", Some(start))),
+                },
+                Some(c) => c,
+                None => return Err(GError::syntax("unterminated string", Some(start))),
+            };
+            text.push(c);
+        }
+        text.push('"');
+        Ok(text)
+    }
+
+    /// `\u{...}` ，其中是一到六位十六进制数
+    fn unicode(&mut self, span: &Span) -> Result<char, GError> {
+        let error = || GError::syntax("invalid unicode escape", Some(span));
+        if self.next() != Some('{') {
+            return Err(error());
+        }
+        let mut hex = String::new();
+        loop {
+            match self.next() {
+                Some('}') if !hex.is_empty() => break,
+                Some(c) if c.is_ascii_hexdigit() && hex.len() < 6 => hex.push(c),
+                _ => return Err(error()),
+            }
+        }
+        u32::from_str_radix(&hex, 16)
+            .ok()
+            .and_then(char::from_u32)
+            .ok_or_else(error)
+    }
+}
+
+/// 读取器语法，`` `x `` 、 `,x` 和 `,@x` 分别是 `(quasiquote x)` 、 `(unquote x)` 和 `(unquote-splicing x)` 的简写
+const READER_MACROS: [(&str, &str); 3] = [
+    (",@", "unquote-splicing"),
+    (",", "unquote"),
+    ("`", "quasiquote"),
+];
+
+pub fn parse(tokens: &[Token]) -> Result<(Expression, &[Token]), GError> {
+    let (exp, _, rest) = parse_spanned(tokens)?;
+    Ok((exp, rest))
+}
+
+/// 和 parse 相同，同时返回表达式的位置树
+pub fn parse_spanned(tokens: &[Token]) -> Result<(Expression, SpanTree, &[Token]), GError> {
+    parse_nested(tokens, 0)
 }
 
-pub fn parse(tokens: &[String]) -> Result<(Expression, &[String]), GError> {
+/// depth: 正在解析的表达式所在的嵌套层数
+fn parse_nested(
+    tokens: &[Token],
+    depth: usize,
+) -> Result<(Expression, SpanTree, &[Token]), GError> {
     let (token, rest) = tokens
         .split_first()
-        .ok_or(GError::Reason("could not get token".to_string()))?;
-    match &token[..] {
-        "(" => read_seq(rest),
-        ")" => Err(GError::Reason("unexpected `)`".to_string())),
-        _ => Ok((parse_atom(token), rest)),
+        .ok_or(GError::syntax("could not get token", None))?;
+    parse_token(&token.text, &token.span, rest, depth)
+}
+
+/// 解析以 token 开始的表达式，rest 是 token 之后的记号
+fn parse_token<'a>(
+    token: &str,
+    span: &Span,
+    rest: &'a [Token],
+    depth: usize,
+) -> Result<(Expression, SpanTree, &'a [Token]), GError> {
+    if depth > MAX_NESTING {
+        return Err(GError::syntax(
+            &format!("expression is nested more than {} levels deep", MAX_NESTING),
+            Some(span),
+        ));
+    }
+    for (prefix, name) in READER_MACROS {
+        if let Some(a) = token.strip_prefix(prefix) {
+            let inner = Span {
+                column: span.column + prefix.len() as u32,
+                ..span.clone()
+            };
+            let (exp, tree, rest) = match a {
+                "" => parse_nested(rest, depth + 1)?,
+                _ => parse_token(a, &inner, rest, depth + 1)?,
+            };
+            let leaf = SpanTree {
+                span: span.clone(),
+                children: vec![],
+            };
+            return Ok((
+                Expression::List(vec![Expression::Symbol(name.to_owned()), exp]),
+                SpanTree {
+                    span: span.clone(),
+                    children: vec![leaf, tree],
+                },
+                rest,
+            ));
+        }
+    }
+    match token {
+        "(" => read_seq(span, rest, depth),
+        ")" => Err(GError::syntax("unexpected `)`", Some(span))),
+        _ => Ok((
+            parse_atom(token),
+            SpanTree {
+                span: span.clone(),
+                children: vec![],
+            },
+            rest,
+        )),
     }
 }
 
-pub fn read_seq(tokens: &[String]) -> Result<(Expression, &[String]), GError> {
+/// open: 左括号的位置
+fn read_seq<'a>(
+    open: &Span,
+    tokens: &'a [Token],
+    depth: usize,
+) -> Result<(Expression, SpanTree, &'a [Token]), GError> {
     let mut res: Vec<Expression> = vec![];
+    let mut children = vec![];
     let mut xs = tokens;
     loop {
         let (next_token, rest) = xs
             .split_first()
-            .ok_or(GError::Reason("could not find closing `)`".to_string()))?;
-        if next_token == ")" {
-            return Ok((Expression::List(res), rest));
+            .ok_or(GError::syntax("could not find closing `)`", Some(open)))?;
+        if next_token.text == ")" {
+            let tree = SpanTree {
+                span: open.clone(),
+                children,
+            };
+            return Ok((Expression::List(res), tree, rest));
         }
-        let (exp, new_xs) = parse(xs)?;
+        let (exp, tree, new_xs) = parse_nested(xs, depth + 1)?;
         res.push(exp);
+        children.push(tree);
         xs = new_xs;
     }
 }
@@ -134,172 +833,83 @@ pub fn parse_atom(token: &str) -> Expression {
     match token {
         "true" => Expression::Bool(true),
         "false" => Expression::Bool(false),
-        _ => {
-            let potential_float = token.parse();
-            match potential_float {
-                Ok(v) => Expression::Number(v),
-                Err(_) => Expression::Symbol(token.to_string().clone()),
-            }
-        }
+        // 没有小数点和指数的数字是整数，超出 i64 的范围时是浮点数
+        _ => match (token.parse(), token.parse()) {
+            (Ok(v), _) => Expression::Int(v),
+            (_, Ok(v)) => Expression::Float(v),
+            _ => Expression::Symbol(token.to_string().clone()),
+        },
     }
 }
 
-pub fn default_env<'a>() -> Environment<'a> {
-    let mut data: HashMap<String, Expression> = HashMap::new();
-    data.insert(
-        "+".to_string(),
-        Expression::Func(|args: &[Expression]| -> Result<Expression, GError> {
-            let sum = parse_list_of_floats(args)?
-                .iter()
-                .fold(0.0, |sum, a| sum + a);
-            Ok(Expression::Number(sum))
-        }),
-    );
-    data.insert(
-        "-".to_string(),
-        Expression::Func(|args: &[Expression]| -> Result<Expression, GError> {
-            let floats = parse_list_of_floats(args)?;
-            let first = *floats
-                .first()
-                .ok_or(GError::Reason("expected at least one number".to_string()))?;
-            let sum_of_rest = floats[1..].iter().fold(0.0, |sum, a| sum + a);
-
-            Ok(Expression::Number(first - sum_of_rest))
-        }),
-    );
-
-    data.insert(
-        "=".to_string(),
-        Expression::Func(|args: &[Expression]| -> Result<Expression, GError> {
-            let floats = parse_list_of_floats(args)?;
-            // 要想比较，需要有两个值
-            if floats.len() != 2 {
-                return Err(GError::Reason("expected two number".to_string()));
-            }
-            // 将第 0 个元素和第 1 个元素进行比较
-            if floats.first().is_none() || floats.get(1).is_none() {
-                return Err(GError::Reason("expected number".to_string()));
-            }
-            let is_ok = floats.first().unwrap().eq(floats.get(1).unwrap());
-            Ok(Expression::Bool(is_ok))
-        }),
-    );
-
-    macro_rules! ensure_tonicity {
-        ($check_fn:expr) => {{
-            |args: &[Expression]| -> Result<Expression, GError> {
-                let floats = parse_list_of_floats(args)?;
-                let first = floats
-                    .first()
-                    .ok_or(GError::Reason("expected at least one number".to_string()))?;
-                let rest = &floats[1..];
-                fn f(prev: &f64, xs: &[f64]) -> bool {
-                    match xs.first() {
-                        #[allow(clippy::redundant_closure_call)]
-                        Some(x) => $check_fn(prev, x) && f(x, &xs[1..]),
-                        None => true,
-                    }
-                }
-                Ok(Expression::Bool(f(first, rest)))
-            }
-        }};
-    }
-    data.insert(
-        ">".to_string(),
-        Expression::Func(ensure_tonicity!(|a, b| a > b)),
-    );
-
-    data.insert(
-        "<".to_string(),
-        Expression::Func(ensure_tonicity!(|a, b| a < b)),
-    );
-
-    data.insert(
-        "<=".to_string(),
-        Expression::Func(ensure_tonicity!(|a, b| a <= b)),
-    );
-
-    data.insert(
-        ">=".to_string(),
-        Expression::Func(ensure_tonicity!(|a, b| a >= b)),
-    );
-
-    Environment { data, outer: None }
-}
-
-pub fn eval(exp: &Expression, env: &mut Environment, config: Config) -> Result<Expression, GError> {
-    match exp {
-        Expression::Bool(_) => Ok(exp.clone()),
-        Expression::Symbol(k) => env_get(k, env)
-            .ok_or(GError::Reason(format!("unexpected symbol k={}", k)))
-            .map(|x| x.clone()),
-        Expression::Number(_a) => Ok(exp.clone()),
-        Expression::List(list) => {
-            let first_form = list
-                .first()
-                .ok_or(GError::Reason("expected a non-empty list".to_string()))?;
-            let arg_forms = &list[1..];
-
-            match eval_built_in_form(first_form, arg_forms, env, config.clone()) {
-                Some(built_in_res) => built_in_res,
-                None => {
-                    let first_eval = eval(first_form, env, config.clone())?;
-                    match first_eval {
-                        Expression::Func(f) => {
-                            let args_eval = arg_forms
-                                .iter()
-                                .map(|x| eval(x, env, config.clone()))
-                                .collect::<Result<Vec<Expression>, GError>>();
-                            f(&args_eval?)
-                        }
-                        Expression::Lambda(lambda) => {
-                            // ->  New
-                            let new_env =
-                                &mut env_for_lambda(lambda.params, arg_forms, env, config.clone())?;
-                            eval(&lambda.body, new_env, config)
-                        }
-                        _ => Err(GError::Reason("first form must be a function".to_string())),
-                    }
-                }
-            }
+/// 在全局环境中被执行的 Glisp 代码，定义了 `when` 、 `unless` 、 `let` 和 `case` 等宏
+/// 它在全局环境被构造时执行，此时 global_env 还不可用，所以其中不能使用 `set!`
+const PRELUDE: &str = include_str!("prelude.gl");
+
+/// 共享的、不可变的全局环境，它只包含内置的函数和 prelude 中定义的宏，只会被构造一次
+/// 需要在每次运行时都有一个干净的环境时，应该使用它的子环境
+pub fn global_env() -> &'static Environment {
+    static GLOBAL_ENV: std::sync::OnceLock<Environment> = std::sync::OnceLock::new();
+    GLOBAL_ENV.get_or_init(|| {
+        let env = builtin_env();
+        if let Err(e) = parse_eval(PRELUDE.to_owned(), &env, None) {
+            use crate::drop::log::LogLevel::*;
+            use crate::macros::*;
+            log!(Error, format!("[ghost-lisp] [prelude] {}", e.diagnostic()));
         }
-        Expression::Func(_) => Err(GError::Reason("unexpected form".to_string())),
-        Expression::String(_) => Ok(exp.clone()),
-        _ => Err(GError::Reason("not supported type.".to_string())),
-    }
+        env
+    })
+}
+
+/// 一个干净的环境，它的外部环境是全局环境，在其中使用 `set` 和 `defmacro` 不会修改全局环境
+pub fn default_env() -> Environment {
+    global_env().child()
+}
+
+fn builtin_env() -> Environment {
+    use super::std::{num_add, num_eq, num_ge, num_gt, num_le, num_lt, num_sub};
+    let mut data: HashMap<String, Expression> = HashMap::new();
+    data.insert("+".to_string(), Expression::Func(num_add));
+    data.insert("-".to_string(), Expression::Func(num_sub));
+    data.insert("=".to_string(), Expression::Func(num_eq));
+    data.insert(">".to_string(), Expression::Func(num_gt));
+    data.insert("<".to_string(), Expression::Func(num_lt));
+    data.insert("<=".to_string(), Expression::Func(num_le));
+    data.insert(">=".to_string(), Expression::Func(num_ge));
+
+    Environment::new(Scope {
+        data,
+        slots: vec![],
+        macros: HashMap::new(),
+        chunk: None,
+        outer: None,
+    })
 }
 
-pub fn func_if(
-    args: &[Expression],
-    env: &mut Environment,
+/// 把语法树编译为字节码并在 env 中执行
+/// spans: exp 的位置树，用于在错误中报告位置
+pub fn eval(
+    exp: &Expression,
+    spans: Option<&SpanTree>,
+    env: &Environment,
     config: Config,
 ) -> Result<Expression, GError> {
-    let test_form = args
-        .first()
-        .ok_or(GError::Reason("expected test form".to_string()))?;
-    let test_eval = eval(test_form, env, config.clone())?;
-    match test_eval {
-        Expression::Bool(b) => {
-            let form_idx = if b { 1 } else { 2 };
-            let res_form = args
-                .get(form_idx)
-                .ok_or(GError::Reason(format!("expected form idx={}", form_idx)))?;
-            eval(res_form, env, config)
-        }
-        _ => Err(GError::Reason(format!(
-            "unexpected test form='{}'",
-            test_form
-        ))),
-    }
+    super::vm::run(compile(exp, spans, env), env, config)
 }
 
-pub fn parse_eval(
+pub fn parse_eval(expr: String, env: &Environment, config: Config) -> Result<Expression, GError> {
+    parse_eval_file(expr, "", env, config)
+}
+
+/// file: 源文件的路径，会出现在错误的位置中
+pub fn parse_eval_file(
     expr: String,
-    env: &mut Environment,
+    file: &str,
+    env: &Environment,
     config: Config,
 ) -> Result<Expression, GError> {
-    let (parsed_exp, _) = parse(&tokenize(expr))?;
-    let evaled_exp = eval(&parsed_exp, env, config)?;
+    let (parsed_exp, spans, _) = parse_spanned(&tokenize_file(expr, file)?)?;
+    let evaled_exp = eval(&parsed_exp, Some(&spans), env, config)?;
     Ok(evaled_exp)
 }
 
@@ -311,73 +921,75 @@ pub fn slurp_expr() -> String {
     expr
 }
 
-fn parse_list_of_floats(args: &[Expression]) -> Result<Vec<f64>, GError> {
-    args.iter().map(parse_single_float).collect()
-}
-
-fn parse_single_float(exp: &Expression) -> Result<f64, GError> {
-    match exp {
-        Expression::Number(num) => Ok(*num),
-        Expression::Bool(b) => Ok((*b).into()),
-        _ => Err(GError::Reason("expect a number".to_string())),
-    }
-}
-
-fn env_for_lambda<'a>(
-    params: Rc<Expression>,
-    args: &[Expression],
-    outer_env: &'a mut Environment,
+/// 以已经被求值的参数调用一个内置的函数或 Lambda
+pub fn call(
+    func: &Expression,
+    args: Vec<Expression>,
     config: Config,
-) -> Result<Environment<'a>, GError> {
-    let ks = parse_list_of_symbol_strings(params)?;
-    if ks.len() != args.len() {
-        return Err(GError::Reason(format!(
-            "expected {} params, got {}",
-            ks.len(),
-            args.len()
-        )));
-    }
-    let vs = eval_forms(args, outer_env, config)?;
-    let mut data: HashMap<String, Expression> = HashMap::new();
-    for (k, v) in ks.iter().zip(vs.iter()) {
-        data.insert(k.clone(), v.clone());
+) -> Result<Expression, GError> {
+    match func {
+        Expression::Func(f) => f(&args),
+        Expression::Lambda(a) => call_lambda(a, args, config),
+        _ => Err(GError::new("first form must be a function".to_string())),
     }
-
-    Ok(Environment {
-        data,
-        outer: Some(outer_env),
-    })
 }
 
-fn eval_forms(
-    args: &[Expression],
-    env: &mut Environment,
+/// 以已经被求值的参数调用一个 Lambda ，它的外部环境是它捕获的环境
+pub fn call_lambda(
+    lambda: &Lambda,
+    args: Vec<Expression>,
     config: Config,
-) -> Result<Vec<Expression>, GError> {
-    args.iter().map(|x| eval(x, env, config.clone())).collect()
-}
-
-fn parse_list_of_symbol_strings(params: Rc<Expression>) -> Result<Vec<String>, GError> {
-    let list = match params.as_ref() {
-        Expression::List(s) => Ok(s.clone()),
-        _ => Err(GError::Reason("expected params to be a list".to_string())),
-    }?;
-    list.iter()
-        .map(|x| match x {
-            Expression::Symbol(s) => Ok(s.clone()),
-            _ => Err(GError::Reason(
-                "expected symbol in the argument list".to_string(),
-            )),
-        })
-        .collect()
+) -> Result<Expression, GError> {
+    super::vm::call_lambda(lambda, args, config)
 }
 
-fn env_get(key: &str, env: &Environment) -> Option<Expression> {
-    match env.data.get(key) {
-        Some(exp) => Some(exp.clone()),
-        None => match env.outer {
-            Some(outer_env) => env_get(key, outer_env),
-            None => None,
-        },
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn texts(tokens: &[Token]) -> Vec<&str> {
+        tokens.iter().map(|a| a.text.as_str()).collect()
+    }
+
+    #[test]
+    fn test_lex() {
+        let tokens = lex("(a \"b c\" ; d\n  ,\"(e)\")", "x.gl", false)
+            .ok()
+            .unwrap();
+        assert_eq!(texts(&tokens), ["(", "a", "\"b c\"", ",\"(e)\"", ")"]);
+        assert_eq!(
+            tokens
+                .iter()
+                .map(|a| a.span.to_string())
+                .collect::<Vec<_>>(),
+            ["x.gl:1:1", "x.gl:1:2", "x.gl:1:4", "x.gl:2:3", "x.gl:2:9"]
+        );
+        // 换行符分隔记号，字符串中的换行被保留
+        let tokens = lex("(a\nb \"c\nd\")", "", false).ok().unwrap();
+        assert_eq!(texts(&tokens), ["(", "a", "b", "\"c\nd\"", ")"]);
+        // 兼容旧的写法时，不认识的转义被原样保留
+        let tokens = lex("(str \"a\\bb\\[\\n\")", "", true).ok().unwrap();
+        assert_eq!(texts(&tokens), ["(", "str", "\"a\\bb\\[\n\"", ")"]);
+        let Err(e) = lex("(str \"a\\bb\")", "", false) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Syntax);
+        assert_eq!(e.span.unwrap().to_string(), "line 1, column 8");
+    }
+
+    #[test]
+    fn test_parse_nesting() {
+        let nested = |n| format!("{}1{}", "(do ".repeat(n), ")".repeat(n));
+        let tokens = tokenize(nested(MAX_NESTING)).ok().unwrap();
+        assert!(parse(&tokens).is_ok());
+        // 读取宏也算作一层嵌套
+        let tokens = tokenize(format!("`{}", nested(MAX_NESTING))).ok().unwrap();
+        let Err(e) = parse(&tokens) else { panic!() };
+        assert_eq!(e.kind, ErrorKind::Syntax);
+        assert_eq!(
+            e.message,
+            format!("expression is nested more than {} levels deep", MAX_NESTING)
+        );
+        assert_eq!(e.span.unwrap().column, MAX_NESTING as u32 * 4 - 1);
     }
 }
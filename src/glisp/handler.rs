@@ -0,0 +1,275 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 请求处理器把一个 URL 模式绑定到一个 Glisp Lambda ，由它动态地生成响应
+//! Lambda 接受五个参数：`(method path query headers body)`
+//! headers 是形如 `(quote (quote name value) ...)` 的列表，name 总是小写的
+//! 如果 Lambda 有第六个参数，它是经过验证的客户端证书的 subject ，没有时是 false ，和 Pipe 中的 `CLIENT_SUBJECT` 相同
+//! Lambda 可以返回一个字符串，它会作为状态码为 200 的响应主体
+//! 也可以返回一个列表 `(status headers body)` ，例如 `(list 201 (list (list "Location" "/a")) "")`
+
+use std::collections::HashMap;
+
+use super::budget::limit;
+use super::core::*;
+use super::sandbox::restrict;
+
+/// pattern: URL 模式，以 `*` 结尾时匹配所有以它之前的部分开头的路径，否则只匹配完全相同的路径
+/// lambda: 处理请求的 Lambda
+#[derive(Clone)]
+pub struct Handler {
+    pub pattern: String,
+    pub lambda: Lambda,
+}
+
+/// 由 Lambda 的返回值转换而来的响应
+#[derive(PartialEq, Debug)]
+pub struct HandlerResponse {
+    pub status: u16,
+    pub headers: Vec<(String, String)>,
+    pub body: String,
+}
+
+impl Handler {
+    /// path: 不包含查询字符串的路径
+    pub fn matches(&self, path: &str) -> bool {
+        match self.pattern.strip_suffix('*') {
+            Some(prefix) => path.starts_with(prefix),
+            None => path == self.pattern,
+        }
+    }
+
+    /// 以请求调用 Lambda ，并把它的返回值转换为响应
+    /// 处理器在运行时不能修改配置，所以这里传入的 Config 总是 None
+    /// 它只有 sandbox 模块中处理请求的代码的权限，并且受到 budget 模块的限制
+    /// client_subject: 经过验证的客户端证书的 subject ，只有 Lambda 有六个参数时才被传入
+    /// 调用中创建的环境在调用结束后被回收，参见 collect
+    pub fn call(
+        &self,
+        method: &str,
+        url: &str,
+        headers: &HashMap<String, String>,
+        body: &[u8],
+        client_subject: Option<&str>,
+    ) -> Result<HandlerResponse, GError> {
+        let (path, query) = url.split_once('?').unwrap_or((url, ""));
+        let mut headers: Vec<_> = headers
+            .iter()
+            .map(|(k, v)| {
+                Expression::List(vec![
+                    Expression::Symbol("quote".to_owned()),
+                    Expression::String(k.to_ascii_lowercase()),
+                    Expression::String(v.clone()),
+                ])
+            })
+            .collect();
+        headers.sort_by_key(|a| a.to_string());
+        headers.insert(0, Expression::Symbol("quote".to_owned()));
+        let mut args = vec![
+            Expression::String(method.to_owned()),
+            Expression::String(path.to_owned()),
+            Expression::String(query.to_owned()),
+            Expression::List(headers),
+            Expression::String(String::from_utf8_lossy(body).into_owned()),
+        ];
+        if matches!(&self.lambda.code.params, Ok(a) if a.len() == 6) {
+            args.push(match client_subject {
+                Some(a) => Expression::String(a.to_owned()),
+                None => Expression::Bool(false),
+            });
+        }
+        let result = collect(|| restrict(|| limit(|| call_lambda(&self.lambda, args, None))))?;
+        to_response(result)
+    }
+}
+
+fn to_response(result: Expression) -> Result<HandlerResponse, GError> {
+    let list = match result {
+        Expression::String(body) => {
+            return Ok(HandlerResponse {
+                status: 200,
+                headers: vec![],
+                body,
+            })
+        }
+        Expression::List(a) => without_quote(a),
+        a => {
+            return Err(GError::new(format!(
+                "handler: Unsupported return value {}",
+                a
+            )))
+        }
+    };
+    let [Expression::Int(status), Expression::List(headers), Expression::String(body)] = &list[..]
+    else {
+        return Err(GError::new(
+            "handler: The return value must be (status headers body)".to_owned(),
+        ));
+    };
+    if !(100..=599).contains(status) {
+        return Err(GError::new(format!(
+            "handler: Unsupported status code {}",
+            status
+        )));
+    }
+    let headers = without_quote(headers.clone())
+        .into_iter()
+        .map(|a| match a {
+            Expression::List(a) => match &without_quote(a)[..] {
+                [Expression::String(k), Expression::String(v)] => Ok((k.clone(), v.clone())),
+                _ => Err(()),
+            },
+            _ => Err(()),
+        })
+        .collect::<Result<_, _>>()
+        .map_err(|_| {
+            GError::new("handler: Each header must be a list of two strings".to_owned())
+        })?;
+    Ok(HandlerResponse {
+        status: *status as u16,
+        headers,
+        body: body.clone(),
+    })
+}
+
+/// 列表可能是 `(quote a b)` 也可能是 `(a b)` ，去掉前者开头的 quote
+fn without_quote(mut list: Vec<Expression>) -> Vec<Expression> {
+    if let Some(Expression::Symbol(a)) = list.first() {
+        if a == "quote" {
+            list.remove(0);
+        }
+    }
+    list
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn handler(pattern: &str, code: &str) -> Handler {
+        match parse_eval(code.to_owned(), &default_env(), None) {
+            Ok(Expression::Lambda(lambda)) => Handler {
+                pattern: pattern.to_owned(),
+                lambda,
+            },
+            _ => panic!(),
+        }
+    }
+
+    #[test]
+    fn test_handler() {
+        let echo = handler(
+            "/api/*",
+            "(lambda (method path query headers body) (str.+ method (str.+ path (str.+ query body))))",
+        );
+        assert!(echo.matches("/api/"));
+        assert!(echo.matches("/api/users"));
+        assert!(!echo.matches("/api"));
+        assert!(handler("/a", "(lambda (a b c d e) a)").matches("/a"));
+        assert!(!handler("/a", "(lambda (a b c d e) a)").matches("/a/b"));
+
+        let response = echo
+            .call("POST", "/api/users?id=1", &HashMap::new(), b"hello", None)
+            .ok()
+            .unwrap();
+        assert_eq!(response.status, 200);
+        assert_eq!(response.body, "POST/api/usersid=1hello");
+
+        let mut headers = HashMap::new();
+        headers.insert("X-Name".to_owned(), "alice".to_owned());
+        let full = handler(
+            "/b",
+            "(lambda (method path query headers body)
+                (list 201 (list (list \"X-Name\" (car (cdr (cdr (car (cdr headers))))))) \"\"))",
+        );
+        assert_eq!(
+            full.call("GET", "/b", &headers, b"", None).ok().unwrap(),
+            HandlerResponse {
+                status: 201,
+                headers: vec![("X-Name".to_owned(), "alice".to_owned())],
+                body: "".to_owned(),
+            }
+        );
+
+        assert!(handler("/c", "(lambda (a b c d e) 1)")
+            .call("GET", "/c", &headers, b"", None)
+            .is_err());
+        assert!(
+            handler("/c", "(lambda (a b c d e) (list 1000 (list) \"\"))")
+                .call("GET", "/c", &headers, b"", None)
+                .is_err()
+        );
+        // 处理器能看到它被创建时的环境中的变量
+        let greet = handler(
+            "/d",
+            "(do (set greet (lambda (name) (str.+ \"hello,\" name))) (lambda (a b c d e) (greet a)))",
+        );
+        assert_eq!(
+            greet
+                .call("GET", "/d", &headers, b"", None)
+                .ok()
+                .unwrap()
+                .body,
+            "hello,GET"
+        );
+        assert!(handler("/c", "(lambda (a) a)")
+            .call("GET", "/c", &headers, b"", None)
+            .is_err());
+
+        // 有六个参数时，第六个参数是客户端证书的 subject
+        let whoami = handler(
+            "/e",
+            "(lambda (a b c d e subject) (if (eq subject false) \"anonymous\" subject))",
+        );
+        assert_eq!(
+            whoami
+                .call("GET", "/e", &headers, b"", Some("CN=alice,O=Example"))
+                .ok()
+                .unwrap()
+                .body,
+            "CN=alice,O=Example"
+        );
+        assert_eq!(
+            whoami
+                .call("GET", "/e", &headers, b"", None)
+                .ok()
+                .unwrap()
+                .body,
+            "anonymous"
+        );
+    }
+
+    #[test]
+    fn test_handler_cycles() {
+        // 每次调用的环境都引用处理器被创建时的环境，如果调用的环境没有被释放，它的引用计数会增加
+        for code in [
+            // 保存在列表中的闭包
+            "(lambda (a b c d e) (do (set fs (list (lambda () fs))) \"ok\"))",
+            // 保存在 Map 中的闭包
+            "(lambda (a b c d e) (do (set m (map.new \"f\" (lambda () m))) \"ok\"))",
+            // 被子环境中的闭包捕获的环境
+            "(lambda (a b c d e) (do (set f (let ((y 1)) (lambda () (f)))) \"ok\"))",
+        ] {
+            let handler = handler("/a", code);
+            let env = handler.lambda.env.clone().unwrap();
+            let before = env.strong_count();
+            for _ in 0..3 {
+                assert_eq!(
+                    handler
+                        .call("GET", "/a", &HashMap::new(), b"", None)
+                        .ok()
+                        .unwrap()
+                        .body,
+                    "ok"
+                );
+            }
+            assert_eq!(env.strong_count(), before, "{}", code);
+        }
+    }
+}
@@ -35,10 +35,28 @@
 //!
 //! 请根据惯例，将功能注册到相关的文件中，如果没有合适的文件，可以另行创建
 //! `core` 子模块定义了所有语法，`std` 子模块定义了所有内置的函数
+//! `compiler` 子模块把语法树编译为字节码，`vm` 子模块执行字节码，REPL 、 `@gl` 和 Pipe 都通过它们运行
+//! 特殊形式（参数不会被直接求值的函数，例如 `if` ）需要增加到 `compiler` 中，并在 `conformance` 中增加用例
+//! `module` 子模块实现了 `require` 和 `provide` ，可以在多个 Glisp 文件之间共享的函数和宏应该放在模块中
+//! `template` 子模块实现了 HTML 模板，模板中的表达式也通过 `compiler` 编译
+//! 读写文件、执行程序等有副作用的内置函数需要先通过 `sandbox` 子模块的检查
+//! 可能产生很大的字符串或列表的内置函数需要通过 `budget` 子模块检查结果的大小
+//! 能用宏表达的语法（例如 `when` 和 `let` ）应该定义在 `prelude.gl` 中，而不是增加新的特殊形式
 //! 如果能通过增加内置函数的方法解决一个问题，就最好不要直接增加语法
 //! 在本项目达到 Stable 阶段之后，最好不要删减或大改旧有功能
 
+pub mod budget;
+pub mod compiler;
 pub mod core;
+pub mod handler;
+pub mod module;
+pub mod pipe;
 pub mod repl;
+pub mod sandbox;
+pub mod template;
+pub mod vm;
 
 mod std;
+
+#[cfg(test)]
+mod conformance;
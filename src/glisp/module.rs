@@ -0,0 +1,299 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! Glisp 的模块系统，`(require "lib/strings.gl")` 加载一个模块并在当前的作用域中定义它导出的变量和宏
+//! 模块在全局环境的一个子环境中执行，并且没有配置，所以模块中不能使用 `serve` 等修改配置的函数
+//! 只有在模块顶层的 `(provide name...)` 中列出的变量和宏会被导出
+//!
+//! `require` 在编译时就加载模块，所以它的参数必须是字符串字面量
+//! 每个模块只会被执行一次，之后的 `require` 直接使用缓存中的结果
+//! 模块的路径先在 `config/` 下查找，然后依次在 SEARCH_PATH 中的每个目录下查找，可以用 `$ +glisp-path <目录>` 添加目录
+//! 路径必须是相对路径，并且不能离开这些目录，所以 `/etc/passwd` 和 `../secret.gl` 都会被拒绝
+//!
+//! 处理请求的代码只能加载 sandbox 模块允许它读取的模块，即使模块已经在缓存中
+//! 模块中的代码出错时，错误信息只包含错误的种类和位置，不包含模块的源代码，因为它可能是不应该被泄露的文件
+
+use std::{
+    cell::RefCell,
+    collections::HashMap,
+    path::{Component, Path, PathBuf},
+    sync::{Arc, Mutex, OnceLock, RwLock},
+};
+
+use super::compiler::Macro;
+use super::core::*;
+use super::sandbox::check_read;
+
+/// 除了 `config/` 以外查找模块的目录
+pub static SEARCH_PATH: RwLock<Vec<String>> = RwLock::new(vec![]);
+
+/// 一个已经被执行的模块导出的变量和宏
+pub struct Module {
+    pub values: Vec<(String, Expression)>,
+    pub macros: Vec<(String, Macro)>,
+}
+
+thread_local! {
+    /// 正在被加载的模块，用于检测循环依赖
+    static LOADING: RefCell<Vec<(PathBuf, String)>> = const { RefCell::new(vec![]) };
+}
+
+fn cache() -> &'static Mutex<HashMap<PathBuf, Arc<Module>>> {
+    static CACHE: OnceLock<Mutex<HashMap<PathBuf, Arc<Module>>>> = OnceLock::new();
+    CACHE.get_or_init(Default::default)
+}
+
+/// 在 `config/` 和 SEARCH_PATH 中查找模块，返回它的规范路径
+/// 被符号链接指向这些目录以外的模块也会被拒绝
+fn resolve(path: &str) -> Result<PathBuf, GError> {
+    let relative = Path::new(path);
+    if !relative
+        .components()
+        .all(|a| matches!(a, Component::Normal(_) | Component::CurDir))
+    {
+        return Err(GError::new(format!(
+            "require: {} is not a relative path inside the module directories",
+            path
+        )));
+    }
+    let search_path = SEARCH_PATH.read().unwrap();
+    std::iter::once("config/")
+        .chain(search_path.iter().map(|a| a.as_str()))
+        .find_map(|dir| {
+            let dir = Path::new(dir).canonicalize().ok()?;
+            let module = dir.join(relative).canonicalize().ok()?;
+            module.starts_with(&dir).then_some(module)
+        })
+        .ok_or(GError::new(format!("require: cannot find {}", path)))
+}
+
+/// 只保留错误的种类和位置，嵌套的 `require` 产生的错误已经被处理过，保持不变
+fn hide_source(e: GError) -> GError {
+    if e.message.starts_with("require: ") {
+        return e;
+    }
+    let message = match &e.span {
+        Some(span) => format!(
+            "{} error at line {}, column {}",
+            e.kind.name(),
+            span.line,
+            span.column
+        ),
+        None => format!("{} error", e.kind.name()),
+    };
+    GError { message, ..e }
+}
+
+/// 加载一个模块，已经被加载过的模块直接从缓存中返回
+pub fn require(path: &str) -> Result<Arc<Module>, GError> {
+    let key = resolve(path)?;
+    check_read("require", &key.to_string_lossy())?;
+    if let Some(a) = cache().lock().unwrap().get(&key) {
+        return Ok(a.clone());
+    }
+
+    let cycle = LOADING.with_borrow_mut(|loading| {
+        let start = loading.iter().position(|(a, _)| *a == key)?;
+        let mut names: Vec<_> = loading[start..].iter().map(|(_, a)| a.as_str()).collect();
+        names.push(path);
+        Some(names.join(" -> "))
+    });
+    if let Some(cycle) = cycle {
+        return Err(GError::new(format!("require: cycle detected: {}", cycle)));
+    }
+
+    LOADING.with_borrow_mut(|loading| loading.push((key.clone(), path.to_owned())));
+    let module = load(&key);
+    LOADING.with_borrow_mut(|loading| loading.pop());
+
+    let module = Arc::new(module.map_err(|e| GError {
+        message: format!("require: {}: {}", path, e.message),
+        ..e
+    })?);
+    cache().lock().unwrap().insert(key, module.clone());
+    Ok(module)
+}
+
+fn load(path: &PathBuf) -> Result<Module, GError> {
+    let source = std::fs::read_to_string(path)
+        .map_err(|_| GError::new(format!("cannot read {}", path.display())))?;
+    let tokens = tokenize_file(source, &path.to_string_lossy()).map_err(hide_source)?;
+    let (exp, spans, _) = parse_spanned(&tokens).map_err(hide_source)?;
+    let env = global_env().child();
+    eval(&exp, Some(&spans), &env, None).map_err(hide_source)?;
+
+    let mut module = Module {
+        values: vec![],
+        macros: vec![],
+    };
+    for name in provided(&exp) {
+        if let Some(a) = env.get_macro(&name) {
+            module.macros.push((name, a));
+        } else if let Some(a) = env.get(&name) {
+            module.values.push((name, a));
+        } else {
+            return Err(GError::new(format!(
+                "provide: unexpected symbol k={}",
+                name
+            )));
+        }
+    }
+    Ok(module)
+}
+
+/// 找出模块顶层（或顶层的 `do` 中）的所有 `(provide ...)` 列出的名字
+fn provided(exp: &Expression) -> Vec<String> {
+    let forms = match exp {
+        Expression::List(list) if list.first() == Some(&Expression::Symbol("do".to_owned())) => {
+            &list[1..]
+        }
+        a => std::slice::from_ref(a),
+    };
+    let mut names = vec![];
+    for form in forms {
+        if let Expression::List(list) = form {
+            if list.first() == Some(&Expression::Symbol("provide".to_owned())) {
+                for a in &list[1..] {
+                    if let Expression::Symbol(name) = a {
+                        names.push(name.clone());
+                    }
+                }
+            }
+        }
+    }
+    names
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中写入模块，返回它们所在的目录
+    fn write_modules(name: &str, files: &[(&str, &str)]) -> String {
+        let dir = std::env::temp_dir().join(format!("ttweb-glisp-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        dir.to_str().unwrap().to_owned() + "/"
+    }
+
+    fn run(source: &str) -> String {
+        match parse_eval(source.to_owned(), &default_env(), None) {
+            Ok(a) => a.to_string(),
+            Err(e) => e.message,
+        }
+    }
+
+    #[test]
+    fn test_require() {
+        let dir = write_modules(
+            "require",
+            &[(
+                "strings.gl",
+                "(do
+                    (set suffix \"!\")
+                    (set shout (lambda (x) (str.+ x suffix)))
+                    (defmacro twice (x) `(str.+ ,x ,x))
+                    (provide shout twice))",
+            )],
+        );
+        SEARCH_PATH.write().unwrap().push(dir.clone());
+        assert_eq!(
+            run("(do (require \"strings.gl\") (shout (twice \"a\")))"),
+            "\"aa!\""
+        );
+        // 没有被导出的变量不可见
+        assert_eq!(
+            run("(do (require \"strings.gl\") suffix)"),
+            "unexpected symbol k=suffix"
+        );
+        // 模块只会被执行一次
+        assert!(Arc::ptr_eq(
+            &require("strings.gl").ok().unwrap(),
+            &require("./strings.gl").ok().unwrap()
+        ));
+        assert_eq!(run("(require \"nope.gl\")"), "require: cannot find nope.gl");
+        SEARCH_PATH.write().unwrap().retain(|a| *a != dir);
+    }
+
+    #[test]
+    fn test_require_paths() {
+        let dir = write_modules("paths", &[("secret.gl", "k=topsecret-value")]);
+        let module_dir = write_modules("paths-modules", &[("a.gl", "(provide)")]);
+        SEARCH_PATH.write().unwrap().push(module_dir.clone());
+        // 绝对路径和离开模块目录的路径都被拒绝
+        let absolute = dir.clone() + "secret.gl";
+        assert_eq!(
+            run(&format!("(require \"{}\")", absolute)),
+            format!(
+                "require: {} is not a relative path inside the module directories",
+                absolute
+            )
+        );
+        let escape = format!(
+            "../{}/secret.gl",
+            Path::new(&dir).file_name().unwrap().to_str().unwrap()
+        );
+        assert!(run(&format!("(require \"{}\")", escape)).contains("is not a relative path"));
+
+        // 模块的源代码不会出现在错误信息中
+        SEARCH_PATH.write().unwrap().push(dir.clone());
+        assert_eq!(
+            run("(require \"secret.gl\")"),
+            "require: secret.gl: name error at line 1, column 1"
+        );
+        SEARCH_PATH
+            .write()
+            .unwrap()
+            .retain(|a| *a != dir && *a != module_dir);
+    }
+
+    #[test]
+    fn test_require_sandbox() {
+        let dir = write_modules("sandbox", &[("allowed.gl", "(do (set x 1) (provide x))")]);
+        SEARCH_PATH.write().unwrap().push(dir.clone());
+        // 加载配置时可以加载，之后模块在缓存中
+        assert_eq!(run("(do (require \"allowed.gl\") x)"), "1");
+        // 处理请求的代码默认只能读取 export/ ，即使模块已经在缓存中也不能加载
+        let pipe = crate::glisp::pipe::Pipe::compile(
+            "(try (eval \"(require \\\"allowed.gl\\\")\") (catch e (map.get e \"kind\")))",
+        )
+        .ok()
+        .unwrap();
+        match pipe.run("", None) {
+            Ok(Expression::String(a)) => assert_eq!(a, "permission"),
+            _ => panic!(),
+        }
+        SEARCH_PATH.write().unwrap().retain(|a| *a != dir);
+    }
+
+    #[test]
+    fn test_require_errors() {
+        let dir = write_modules(
+            "errors",
+            &[
+                ("a.gl", "(do (require \"b.gl\") (set a 1) (provide a))"),
+                ("b.gl", "(do (require \"a.gl\") (set b 1) (provide b))"),
+                ("missing.gl", "(do (set a 1) (provide a b))"),
+            ],
+        );
+        // 循环依赖，模块中的相对路径在 SEARCH_PATH 中查找
+        SEARCH_PATH.write().unwrap().push(dir.clone());
+        assert_eq!(
+            run("(require \"a.gl\")"),
+            "require: a.gl: require: b.gl: require: cycle detected: a.gl -> b.gl -> a.gl"
+        );
+        assert_eq!(
+            run("(require \"missing.gl\")"),
+            "require: missing.gl: provide: unexpected symbol k=b"
+        );
+        SEARCH_PATH.write().unwrap().retain(|a| *a != dir);
+    }
+}
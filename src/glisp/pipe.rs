@@ -0,0 +1,159 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! Pipe 在加载配置时就被解析并编译为字节码，而不是在每个请求中重新解析源代码
+//! 每次运行时，只创建一个保存 `CONTENT` 和 `CLIENT_SUBJECT` 的子环境，它的外部环境是共享的全局环境
+//! 在 Pipe 中使用 `set` 只会修改子环境，所以请求之间不会互相影响
+//! 宏在编译时被展开，在 Pipe 中用 `defmacro` 定义的宏只属于这个 Pipe
+//! Pipe 在处理请求时运行，所以它只有 sandbox 模块中处理请求的代码的权限，并且受到 budget 模块的限制
+
+use std::sync::Arc;
+
+use super::budget::limit;
+use super::compiler::{compile, Chunk};
+use super::core::*;
+use super::sandbox::restrict;
+use super::vm::run;
+
+/// code: Pipe 的字节码，使用 Arc 是为了在运行时可以不持有 RouterConfig 的锁
+#[derive(Clone)]
+pub struct Pipe {
+    pub code: Arc<Chunk>,
+}
+
+impl Pipe {
+    pub fn compile(source: &str) -> Result<Self, GError> {
+        Pipe::compile_file(source, "")
+    }
+
+    /// file: 源文件的路径，运行时的错误会报告其中的位置
+    pub fn compile_file(source: &str, file: &str) -> Result<Self, GError> {
+        let (expression, spans, _) = parse_spanned(&tokenize_file(source.to_owned(), file)?)?;
+        Ok(Pipe {
+            code: compile(&expression, Some(&spans), &global_env().child()),
+        })
+    }
+
+    /// client_subject: 经过验证的客户端证书的 subject ，没有时 `CLIENT_SUBJECT` 是 false
+    /// 运行中创建的环境在运行结束后被回收，参见 collect
+    pub fn run(&self, content: &str, client_subject: Option<&str>) -> Result<Expression, GError> {
+        collect(|| {
+            let env = global_env().child();
+            env.insert("CONTENT".to_owned(), Expression::String(content.to_owned()));
+            env.insert(
+                "CLIENT_SUBJECT".to_owned(),
+                match client_subject {
+                    Some(a) => Expression::String(a.to_owned()),
+                    None => Expression::Bool(false),
+                },
+            );
+            let result = restrict(|| limit(|| run(self.code.clone(), &env, None)));
+            env.release();
+            result
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "(do
+        (set i 0)
+        (set ret CONTENT)
+        (loop
+            (if (> i 20) (return) (pass))
+            (set ret (str.+ ret (slice CONTENT 0 0)))
+            (set i (+ i 1)))
+        ret)";
+
+    #[test]
+    fn test_pipe() {
+        let pipe = Pipe::compile(SOURCE).ok().unwrap();
+        for _ in 0..2 {
+            // 上一次运行中的 set 不会影响下一次运行
+            match pipe.run("ab", None) {
+                Ok(Expression::String(a)) => assert_eq!(a, "ab".to_owned() + &"a".repeat(21)),
+                _ => panic!(),
+            }
+        }
+        match Pipe::compile("(if (eq CLIENT_SUBJECT false) CONTENT \"x\")")
+            .ok()
+            .unwrap()
+            .run("a", Some("CN=alice"))
+        {
+            Ok(Expression::String(a)) => assert_eq!(a, "x"),
+            _ => panic!(),
+        }
+        assert!(Pipe::compile("(do (").is_err());
+    }
+
+    #[test]
+    fn test_pipe_capabilities() {
+        // Pipe 默认不能读取 export/ 以外的文件，也不能执行程序
+        let pipe = Pipe::compile(
+            "(try (read-file \"export/../Cargo.toml\") (catch e (str.+ (map.get e \"kind\") (map.get e \"message\"))))",
+        )
+        .ok()
+        .unwrap();
+        match pipe.run("", None) {
+            Ok(Expression::String(a)) => assert_eq!(
+                a,
+                "permissionread-file: permission denied for export/../Cargo.toml"
+            ),
+            _ => panic!(),
+        }
+        let Err(e) = Pipe::compile("(run \"true\")").ok().unwrap().run("", None) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Permission);
+        // 加载配置时的代码不受限制
+        assert!(parse_eval(
+            "(read-file \"Cargo.toml\")".to_owned(),
+            &default_env(),
+            None
+        )
+        .is_ok());
+    }
+
+    /// 对比每次都重新解析源代码和使用预先解析的 Pipe 的速度
+    /// `cargo test --release bench_pipe -- --ignored --nocapture`
+    #[test]
+    #[ignore]
+    fn bench_pipe() {
+        const ROUNDS: u32 = 10000;
+        let content = "a".repeat(1024);
+        let simple = "(if (eq CLIENT_SUBJECT false) (str.+ CONTENT \" d\") CONTENT)";
+
+        for source in [simple, SOURCE] {
+            let start = std::time::Instant::now();
+            for _ in 0..ROUNDS {
+                let env = &default_env();
+                env.insert("CONTENT".to_owned(), Expression::String(content.clone()));
+                env.insert("CLIENT_SUBJECT".to_owned(), Expression::Bool(false));
+                let _ = parse_eval(source.to_owned(), env, None);
+            }
+            let reparse = start.elapsed();
+
+            let pipe = Pipe::compile(source).ok().unwrap();
+            let start = std::time::Instant::now();
+            for _ in 0..ROUNDS {
+                let _ = pipe.run(&content, None);
+            }
+            let compiled = start.elapsed();
+
+            println!(
+                "reparse: {:?}/run, compiled: {:?}/run, speedup: {:.2}x",
+                reparse / ROUNDS,
+                compiled / ROUNDS,
+                reparse.as_secs_f64() / compiled.as_secs_f64()
+            );
+        }
+    }
+}
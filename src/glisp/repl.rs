@@ -10,16 +10,19 @@ use std::io::Write;
 
 use super::core::*;
 pub fn run_repl() {
-    let env = &mut default_env();
+    let env = &default_env();
     loop {
         print!("glisp > ");
         let _ = std::io::stdout().flush();
         let expr = slurp_expr();
-        match parse_eval(expr, env, None) {
-            Ok(res) => println!("; => {}", res),
-            Err(e) => match e {
-                GError::Reason(msg) => println!("; => {}", msg),
-            },
-        }
+        println!("{}", eval_line(expr, env));
+    }
+}
+
+/// 求值 REPL 中的一行，返回要打印的结果，env 在多行之间共享
+pub fn eval_line(expr: String, env: &Environment) -> String {
+    match parse_eval(expr, env, None) {
+        Ok(res) => format!("; => {}", res),
+        Err(e) => format!("; => {}", e),
     }
 }
@@ -0,0 +1,170 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 限制 Glisp 代码可以读写的文件和可以执行的程序
+//! 加载配置时执行的代码（ `@gl` 和它加载的模块）以及 REPL 拥有全部权限
+//! 处理请求的代码（ Pipe 和请求处理器）使用 request() 中的权限：
+//! 默认只能读取 `export/` 下的文件，不能写入文件，也不能用 `run` 执行程序
+//! 可以用 `$ +glisp-read-dir <目录>` 、 `$ +glisp-write-dir <目录>` 和 `$ +glisp-run <程序>` 为它们增加权限
+//!
+//! 当前的权限保存在线程局部变量中，由 Pipe::run 和 Handler::call 在运行期间通过 restrict 设置
+//! 路径在检查前会被规范化，所以 `export/../config/` 这样的路径不能绕过限制
+
+use std::{
+    cell::RefCell,
+    path::{Component, Path, PathBuf},
+    sync::{OnceLock, RwLock},
+};
+
+use super::core::*;
+
+/// 每一项为 None 时没有限制
+/// read, write: 可以读取和写入的目录
+/// run: 可以被 `run` 执行的程序，必须和 `run` 的第一个参数完全相同
+#[derive(Clone)]
+pub struct Capabilities {
+    pub read: Option<Vec<String>>,
+    pub write: Option<Vec<String>>,
+    pub run: Option<Vec<String>>,
+}
+
+/// 处理请求的代码的权限
+pub fn request() -> &'static RwLock<Capabilities> {
+    static REQUEST: OnceLock<RwLock<Capabilities>> = OnceLock::new();
+    REQUEST.get_or_init(|| {
+        RwLock::new(Capabilities {
+            read: Some(vec!["export/".to_owned()]),
+            write: Some(vec![]),
+            run: Some(vec![]),
+        })
+    })
+}
+
+thread_local! {
+    /// 当前线程正在执行的代码的权限，为 None 时拥有全部权限
+    static CURRENT: RefCell<Option<Capabilities>> = const { RefCell::new(None) };
+}
+
+/// 在 f 执行期间使用处理请求的代码的权限，即使 f 出现 panic 也会恢复原来的权限
+pub fn restrict<T>(f: impl FnOnce() -> T) -> T {
+    struct Restore(Option<Capabilities>);
+    impl Drop for Restore {
+        fn drop(&mut self) {
+            CURRENT.set(self.0.take());
+        }
+    }
+
+    let capabilities = request().read().unwrap().clone();
+    let _restore = Restore(CURRENT.replace(Some(capabilities)));
+    f()
+}
+
+fn denied(fnname: &str, target: &str) -> GError {
+    GError::with_kind(
+        ErrorKind::Permission,
+        format!("{}: permission denied for {}", fnname, target),
+    )
+}
+
+/// 规范化一个可能还不存在的路径：它不存在时规范化它的父目录
+fn resolve(path: &str) -> Option<PathBuf> {
+    let path = Path::new(path);
+    if let Ok(a) = path.canonicalize() {
+        return Some(a);
+    }
+    let name = match path.components().next_back()? {
+        Component::Normal(a) => a,
+        _ => return None,
+    };
+    let parent = match path.parent()? {
+        a if a.as_os_str().is_empty() => Path::new("."),
+        a => a,
+    };
+    Some(parent.canonicalize().ok()?.join(name))
+}
+
+fn check_path(
+    fnname: &str,
+    path: &str,
+    dirs: impl FnOnce(&Capabilities) -> &Option<Vec<String>>,
+) -> Result<(), GError> {
+    CURRENT.with_borrow(|current| {
+        let Some(dirs) = current.as_ref().and_then(|a| dirs(a).as_ref()) else {
+            return Ok(());
+        };
+        let allowed = resolve(path).is_some_and(|path| {
+            dirs.iter()
+                .filter_map(|dir| Path::new(dir).canonicalize().ok())
+                .any(|dir| path.starts_with(dir))
+        });
+        if allowed {
+            Ok(())
+        } else {
+            Err(denied(fnname, path))
+        }
+    })
+}
+
+/// 检查是否可以读取 path ，它可以是文件或目录
+pub fn check_read(fnname: &str, path: &str) -> Result<(), GError> {
+    check_path(fnname, path, |a| &a.read)
+}
+
+pub fn check_write(fnname: &str, path: &str) -> Result<(), GError> {
+    check_path(fnname, path, |a| &a.write)
+}
+
+pub fn check_run(fnname: &str, program: &str) -> Result<(), GError> {
+    CURRENT.with_borrow(
+        |current| match current.as_ref().and_then(|a| a.run.as_ref()) {
+            Some(programs) if !programs.iter().any(|a| a == program) => {
+                Err(denied(fnname, program))
+            }
+            _ => Ok(()),
+        },
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_restrict() {
+        let dir = std::env::temp_dir().join(format!("ttweb-sandbox-{}", std::process::id()));
+        std::fs::create_dir_all(dir.join("public")).unwrap();
+        let public = dir.join("public").to_string_lossy().into_owned();
+        let capabilities = Capabilities {
+            read: Some(vec![public.clone()]),
+            write: Some(vec![]),
+            run: Some(vec!["echo".to_owned()]),
+        };
+        let old = CURRENT.replace(Some(capabilities));
+
+        assert!(check_read("read-file", &format!("{}/a.txt", public)).is_ok());
+        assert!(check_read("read-dir", &public).is_ok());
+        let Err(e) = check_read("read-file", &format!("{}/../secret", public)) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Permission);
+        assert!(check_read("read-file", "/etc/passwd").is_err());
+        assert!(check_write("write-file", &format!("{}/a.txt", public)).is_err());
+        assert!(check_run("run", "echo").is_ok());
+        assert!(check_run("run", "sh").is_err());
+
+        CURRENT.set(old);
+        // 没有限制时拥有全部权限
+        assert!(check_read("read-file", "/etc/passwd").is_ok());
+        assert!(check_run("run", "sh").is_ok());
+        // restrict 结束后恢复原来的权限
+        assert!(restrict(|| check_run("run", "sh")).is_err());
+        assert!(check_run("run", "sh").is_ok());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
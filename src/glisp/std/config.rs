@@ -9,20 +9,14 @@
 use super::macros::*;
 use super::*;
 
-pub fn func_serve(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("serve", args, 3);
-    args_len_max!("serve", args, 3);
-
-    let url = check_type_onlyone!("serve", &args[0], env, String, config.clone())?;
-    let file_path = check_type_onlyone!("serve", &args[1], env, String, config.clone())?;
-    let content_type = check_type_onlyone!("serve", &args[2], env, String, config.clone())?;
+pub fn func_serve(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let url = take_arg!("serve", args, String);
+    let file_path = take_arg!("serve", args, String);
+    let content_type = take_arg!("serve", args, String);
 
     if !std::path::Path::new(&file_path).is_file() {
-        return Err(GError::Reason(
+        return Err(GError::new(
             "serve: The second arg is not a file".to_owned(),
         ));
     }
@@ -35,12 +29,36 @@ pub fn func_serve(
                 file_path: "/../".to_owned() + &file_path,
                 content_type,
                 replace: None,
+                markdown: false,
+                template: None,
             },
         );
         Ok(Expression::Bool(true))
     } else {
-        Err(GError::Reason(
+        Err(GError::new(
             "serve: This function is not supported in this mode".to_owned(),
         ))
     }
 }
+
+pub fn func_handle(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let pattern = take_arg!("handle", args, String);
+    let lambda = take_arg!("handle", args, Lambda);
+
+    if let Some(_config) = config {
+        _config
+            .borrow_mut()
+            .router_config
+            .handlers
+            .push(crate::glisp::handler::Handler {
+                pattern: "/".to_owned() + &pattern,
+                lambda,
+            });
+        Ok(Expression::Bool(true))
+    } else {
+        Err(GError::new(
+            "handle: This function is not supported in this mode".to_owned(),
+        ))
+    }
+}
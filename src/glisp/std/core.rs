@@ -9,27 +9,20 @@
 use super::macros::*;
 use super::*;
 
+/// quote 的参数不会被求值，所以它在编译时就被计算为一个常量
 pub fn func_quote(args: &[Expression]) -> Result<Expression, GError> {
     let _fst = args
         .first()
-        .ok_or(GError::Reason("unexpected args form".to_string()))?;
+        .ok_or(GError::new("unexpected args form".to_string()))?;
     let mut retfst = vec![Expression::Symbol("quote".to_owned())];
     retfst.extend_from_slice(args);
     Ok(Expression::List(retfst))
 }
 
-pub fn func_atom(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("atom", args, 1);
-    args_len_max!("atom", args, 1);
-
-    let fst = eval(&args[0], env, config)?;
-    match fst {
+pub fn func_atom(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match &args[0] {
         Expression::Symbol(_) => Ok(Expression::Bool(true)),
-        Expression::Number(_) => Ok(Expression::Bool(true)),
+        Expression::Int(_) | Expression::Float(_) => Ok(Expression::Bool(true)),
         Expression::Func(_) => Ok(Expression::Bool(true)),
         Expression::Bool(_) => Ok(Expression::Bool(true)),
         Expression::String(_) => Ok(Expression::Bool(true)),
@@ -50,39 +43,30 @@ pub fn func_atom(
     }
 }
 
-pub fn func_eq(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("eq", args, 2);
-    args_len_max!("eq", args, 2);
-
-    let fst = &args[0];
-    let snd = &args[1];
+pub fn func_eq(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    Ok(Expression::Bool(args[0].to_string() == args[1].to_string()))
+}
 
-    if eval(fst, env, config.clone())?.to_string() == eval(snd, env, config)?.to_string() {
-        Ok(Expression::Bool(true))
-    } else {
-        Ok(Expression::Bool(false))
-    }
+/// 和 quote 不同，list 会先对每一个参数求值
+pub fn func_list(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    Ok(Expression::List(to_quote_list!(args)))
 }
 
+/// cons 的参数不会被求值，所以它在编译时就被计算为一个常量
 pub fn func_cons(args: &[Expression]) -> Result<Expression, GError> {
-    args_len_min!("coud", args, 2);
-    args_len_max!("coud", args, 2);
+    check_args_len("coud", args.len(), 2, Some(2))?;
 
     let mut lst1 = match args[0].clone() {
         Expression::List(a) => a,
         _ => {
-            return Err(GError::Reason(
+            return Err(GError::new(
                 "cons can only result a static list".to_string(),
             ))
         }
     };
 
     if lst1.remove(0).to_string() != "quote" {
-        return Err(GError::Reason(
+        return Err(GError::new(
             "cons can only result a static list".to_string(),
         ));
     }
@@ -90,14 +74,14 @@ pub fn func_cons(args: &[Expression]) -> Result<Expression, GError> {
     let mut lst2 = match args[1].clone() {
         Expression::List(a) => a,
         _ => {
-            return Err(GError::Reason(
+            return Err(GError::new(
                 "cons can only result a static list".to_string(),
             ))
         }
     };
 
     if lst2.remove(0).to_string() != "quote" {
-        return Err(GError::Reason(
+        return Err(GError::new(
             "cons can only result a static list".to_string(),
         ));
     }
@@ -107,58 +91,8 @@ pub fn func_cons(args: &[Expression]) -> Result<Expression, GError> {
     Ok(Expression::List(lst1))
 }
 
-pub fn func_cond(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("coud", args, 2);
-
-    let mut i = 0;
-    loop {
-        if i >= args.len() {
-            return Err(GError::Reason("cond: Error2".to_string()));
-        }
-
-        let v = match eval(&args[i * 2].clone(), env, config.clone()) {
-            Ok(a) => a,
-            _ => return Err(GError::Reason("cond: Error3".to_string())),
-        };
-        if let Expression::Bool(true) = v {
-            return eval(&args[i * 2 + 1].clone(), env, config);
-        }
-        i += 1;
-    }
-}
-
-pub fn func_set(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("set", args, 2);
-    args_len_max!("set", args, 2);
-    let var_exp = &args[0];
-    let val_res = &args[1];
-    let evaled_val = eval(val_res, env, config)?;
-
-    match var_exp {
-        Expression::Symbol(ref var_name) => {
-            env.data.insert(var_name.clone(), evaled_val);
-            Ok(var_exp.clone())
-        }
-        _ => Err(GError::Reason("unexpected var name".to_string())),
-    }
-}
-
-pub fn func_car(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("car", args, 1);
-    args_len_max!("car", args, 1);
-    let list = check_type_onlyone!("car", &args[0], env, List, config)?;
+pub fn func_car(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let list = take_arg!("car", args.into_iter(), List);
     if list.is_empty() {
         Ok(Expression::List(vec![]))
     } else {
@@ -166,14 +100,8 @@ pub fn func_car(
     }
 }
 
-pub fn func_cdr(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("cdr", args, 1);
-    args_len_max!("cdr", args, 1);
-    let list = check_type_onlyone!("cdr", &args[0], env, List, config)?;
+pub fn func_cdr(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let list = take_arg!("cdr", args.into_iter(), List);
     if list.len() <= 1 {
         Ok(Expression::List(vec![]))
     } else {
@@ -181,72 +109,41 @@ pub fn func_cdr(
     }
 }
 
-pub fn func_loop(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("loop", args, 2);
-
-    let mut i = 0;
-    loop {
-        if i >= args.len() {
-            i = 0
-        }
-        let res = eval(&args[i], env, config.clone())?;
-        if res == Expression::Symbol("return".to_owned()) {
-            break;
-        }
-        if res == Expression::Symbol("continue".to_owned()) {
-            i = 0;
-            continue;
-        }
-
-        i += 1;
-    }
-
-    Ok(Expression::Bool(true))
-}
-
-pub fn func_do(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("do", args, 1);
-
-    let ret: &mut Expression = &mut Expression::List(vec![]);
-    for e in args {
-        *ret = eval(e, env, config.clone())?;
-    }
-    Ok(ret.clone())
-}
-
-pub fn func_or(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("or", args, 2);
-    args_len_max!("or", args, 2);
-    let bool1 = check_type_onlyone!("or", &args[0], env, Bool, config.clone())?;
-    let bool2 = check_type_onlyone!("or", &args[1], env, Bool, config)?;
+/// 和 and 不同，or 的两个参数总是都会被求值
+pub fn func_or(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let bool1 = take_arg!("or", args, Bool);
+    let bool2 = take_arg!("or", args, Bool);
 
     Ok(Expression::Bool(bool1 || bool2))
 }
 
-pub fn func_and(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("and", args, 2);
-    args_len_max!("and", args, 2);
-    let bool1 = check_type_onlyone!("and", &args[0], env, Bool, config.clone())?;
-    if !bool1 {
-        return Ok(Expression::Bool(false));
+/// `(raise "msg")` 报告一个运行时错误， `(raise e)` 重新报告 `catch` 得到的错误对象，保留它的种类
+pub fn func_raise(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match &args[0] {
+        Expression::String(a) => Err(GError::new(a.clone())),
+        Expression::Map(map) => match (map.get("kind"), map.get("message")) {
+            (Some(Expression::String(kind)), Some(Expression::String(message))) => {
+                Err(GError::with_kind(
+                    ErrorKind::from_name(kind).unwrap_or(ErrorKind::Runtime),
+                    message.clone(),
+                ))
+            }
+            _ => Err(GError::with_kind(
+                ErrorKind::Type,
+                "raise: expected an error object".to_owned(),
+            )),
+        },
+        _ => Err(GError::with_kind(
+            ErrorKind::Type,
+            "raise: Unsupported type".to_owned(),
+        )),
     }
-    let bool2 = check_type_onlyone!("and", &args[1], env, Bool, config)?;
+}
 
-    Ok(Expression::Bool(bool2))
+/// 返回一个不会和用户代码中的符号重名的新符号，用于在宏展开的代码中引入临时变量
+pub fn func_gensym(_args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    static COUNTER: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+    let n = COUNTER.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+    Ok(Expression::Symbol(format!("#:g{}", n)))
 }
@@ -6,62 +6,10 @@
  * if not, see <https://www.gnu.org/licenses/>.
  */
 
-use super::macros::*;
-use super::*;
-
-pub fn func_for_each_eval(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("for-each-eval", args, 2);
-    args_len_max!("for-each-eval", args, 2);
-    let from = check_type_onlyone!("for-each-eval", &args[0], env, List, config.clone())?;
-
-    for e in from {
-        let e_str = e.to_string();
-        env.data.insert(
-            "$$".to_owned(),
-            crate::glisp::core::Expression::String(e_str),
-        );
-        eval(&args[1], env, config.clone())?;
-    }
-    Ok(Expression::Bool(true))
-}
-
-pub fn func_eval(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("eval", args, 1);
-    args_len_max!("eval", args, 1);
-    let meta = check_type_onlyone!("eval", &args[0], env, String, config.clone())?;
+//! `for-each-eval` 、 `eval` 和 `eval-atom` 需要访问当前的作用域，它们由 compiler 模块编译为专门的指令
 
-    let (parsed_exp, _) = parse(&tokenize(meta))?;
-    eval(&parsed_exp, env, config)
-}
-
-pub fn func_meta(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("meta", args, 1);
-    args_len_max!("meta", args, 1);
-    let code = eval(&args[0], env, config)?;
+use super::*;
 
-    Ok(Expression::String(code.to_string()))
+pub fn func_meta(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    Ok(Expression::String(args[0].to_string()))
 }
-
-pub fn func_eval_atom(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("eval-atom", args, 1);
-    args_len_max!("eval-atom", args, 1);
-    let meta = check_type_onlyone!("eval-atom", &args[0], env, String, config.clone())?;
-
-    eval(&parse_atom(&meta), env, config)
-}
\ No newline at end of file
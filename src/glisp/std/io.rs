@@ -8,56 +8,46 @@
 
 use super::macros::*;
 use super::*;
+use crate::glisp::budget::{allocate, charge, check_size};
+use crate::glisp::sandbox::{check_read, check_run, check_write};
 
-pub fn func_console_log(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("log", args, 1);
-    args_len_max!("log", args, 1);
-
-    let str1 = check_type_onlyone!("log", &args[0], env, String, config)?;
+pub fn func_console_log(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("log", args.into_iter(), String);
 
     use crate::drop::log::LogLevel::*;
     use crate::macros::*;
 
     log!(
         Info,
-        format!(
-            "[ghost-lisp] [console.log] {}",
-            str1.replace("\\b", " ").replace("\\n", "\n")
-        )
+        format!("[ghost-lisp] [console.log] {}", legacy_unescape(&str1))
     );
 
     Ok(Expression::Bool(true))
 }
 
-pub fn func_read_file(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("read-file", args, 1);
-    args_len_max!("read-file", args, 1);
-    let filename = check_type_onlyone!("read-file", &args[0], env, String, config)?;
+pub fn func_read_file(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let filename = take_arg!("read-file", args.into_iter(), String);
+    check_read("read-file", &filename)?;
+    // 在读取之前检查大小，避免把很大的文件读入内存
+    if let Ok(metadata) = std::fs::metadata(&filename) {
+        let len = metadata.len().try_into().unwrap_or(usize::MAX);
+        check_size("read-file", len)?;
+        charge(len)?;
+        allocate(len)?;
+    }
 
     if let Ok(a) = std::fs::read_to_string(filename) {
         Ok(Expression::String(a))
     } else {
-        Err(GError::Reason("read-file: not a file".to_owned()))
+        Err(GError::new("read-file: not a file".to_owned()))
     }
 }
 
-pub fn func_write_file(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("write-file", args, 2);
-    args_len_max!("write-file", args, 2);
-    let filename = check_type_onlyone!("write-file", &args[0], env, String, config.clone())?;
-    let str = check_type_onlyone!("write-file", &args[1], env, String, config)?;
+pub fn func_write_file(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let filename = take_arg!("write-file", args, String);
+    let str = take_arg!("write-file", args, String);
+    check_write("write-file", &filename)?;
 
     match std::fs::write(filename, str) {
         Ok(_) => Ok(Expression::Bool(true)),
@@ -65,14 +55,9 @@ pub fn func_write_file(
     }
 }
 
-pub fn func_read_dir(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("read-dir", args, 1);
-    args_len_max!("read-dir", args, 1);
-    let str1 = check_type_onlyone!("read-dir", &args[0], env, String, config)?;
+pub fn func_read_dir(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("read-dir", args.into_iter(), String);
+    check_read("read-dir", &str1)?;
     let dir = std::fs::read_dir(str1);
     match dir {
         Ok(readdir) => {
@@ -88,39 +73,19 @@ pub fn func_read_dir(
     }
 }
 
-pub fn func_run(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("run", args, 1);
-
+pub fn func_run(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
     use std::process::Command;
 
-    let mut command = Command::new(match eval(&args[0], env, config.clone()) {
-        Ok(a) => match a {
-            Expression::String(s) => s,
-            _ => return Err(GError::Reason("run: unsupport type".to_owned())),
-        },
-        a => return a,
-    });
-    let output = if args.len() > 1 {
-        let args_ori = &args[1..];
-        let mut _args = vec![];
-        for e in args_ori {
-            match eval(e, env, config.clone()) {
-                Ok(a) => _args.push(match a {
-                    Expression::String(s) => s,
-                    _ => return Err(GError::Reason("run: unsupport type".to_owned())),
-                }),
-                a => return a,
-            }
-        }
-        command.args(_args).output()
-    } else {
-        command.output()
-    };
-    match output {
+    let mut args = args
+        .into_iter()
+        .map(|a| match a {
+            Expression::String(s) => Ok(s),
+            _ => Err(GError::new("run: unsupport type".to_owned())),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    check_run("run", &args[0])?;
+    let mut command = Command::new(args.remove(0));
+    match command.args(args).output() {
         Ok(a) => Ok(Expression::String(unsafe {
             std::str::from_utf8_unchecked(&a.stdout).to_string()
         })),
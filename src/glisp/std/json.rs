@@ -0,0 +1,88 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! JSON 和 Glisp 的值之间的转换，解析和序列化都由 drop::json 完成
+//! 数组对应列表（带有 `quote` 前缀），对象对应 Map ， `null` 对应符号 `null`
+//! 对象中重复的键只保留最后一个，Map 按键的顺序序列化
+
+use std::collections::BTreeMap;
+
+use super::macros::*;
+use super::*;
+use crate::drop::json::{self, JsonValue};
+use crate::glisp::budget::{allocate, charge, check_size};
+
+fn to_expression(value: JsonValue) -> Expression {
+    match value {
+        JsonValue::Null => Expression::Symbol("null".to_owned()),
+        JsonValue::Bool(a) => Expression::Bool(a),
+        // 没有小数部分且能被 f64 精确表示的数字作为整数
+        JsonValue::Number(a) if a.fract() == 0.0 && a.abs() < 9e15 => Expression::Int(a as i64),
+        JsonValue::Number(a) => Expression::Float(a),
+        JsonValue::String(a) => Expression::String(a),
+        JsonValue::Array(a) => Expression::List(to_quote_list!(a.into_iter().map(to_expression))),
+        JsonValue::Object(a) => Expression::Map(
+            a.into_iter()
+                .map(|(k, v)| (k, to_expression(v)))
+                .collect::<BTreeMap<_, _>>(),
+        ),
+    }
+}
+
+fn to_json(exp: &Expression) -> Result<JsonValue, GError> {
+    Ok(match exp {
+        Expression::Symbol(a) if a == "null" => JsonValue::Null,
+        Expression::Bool(a) => JsonValue::Bool(*a),
+        Expression::Int(a) => JsonValue::Number(*a as f64),
+        Expression::Float(a) => JsonValue::Number(*a),
+        Expression::String(a) => JsonValue::String(a.clone()),
+        Expression::List(a) => {
+            let list = match a.first() {
+                Some(Expression::Symbol(q)) if q == "quote" => &a[1..],
+                _ => &a[..],
+            };
+            JsonValue::Array(list.iter().map(to_json).collect::<Result<_, _>>()?)
+        }
+        Expression::Map(a) => JsonValue::Object(
+            a.iter()
+                .map(|(k, v)| Ok((k.clone(), to_json(v)?)))
+                .collect::<Result<_, GError>>()?,
+        ),
+        _ => {
+            return Err(GError::new(format!(
+                "json.stringify: can not convert {} to JSON",
+                exp
+            )))
+        }
+    })
+}
+
+/// 解析失败时，错误信息包含出错的行号和列号
+pub fn func_json_parse(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str = take_arg!("json.parse", args.into_iter(), String);
+    charge(str.len())?;
+    // 解析的结果不会比输入多出很多
+    allocate(str.len())?;
+    match json::parse(&str) {
+        Ok(a) => Ok(to_expression(a)),
+        Err(e) => Err(GError::new(format!("json.parse: {}", e))),
+    }
+}
+
+/// (json.stringify value [indent]) ，给出 indent 时以 indent 个空格缩进的多行形式序列化
+pub fn func_json_stringify(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let value = to_json(&args[0])?;
+    let str = match args.get(1) {
+        Some(Expression::Int(indent)) => value.to_string_pretty((*indent).max(0) as usize),
+        _ => value.to_string(),
+    };
+    check_size("json.stringify", str.len())?;
+    charge(str.len())?;
+    allocate(str.len())?;
+    Ok(Expression::String(str))
+}
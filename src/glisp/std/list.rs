@@ -0,0 +1,231 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! 列表的高阶函数，它们接受的函数可以是 Lambda 或全局环境中的函数（例如 `+` ）
+//! 由 `list` 和 `quote` 产生的列表以 `quote` 开头，这里的函数忽略它，并且返回以 `quote` 开头的列表
+//! 和 Map 一样，列表是不可变的，这里的函数总是返回一个新的列表
+
+use std::cmp::Ordering;
+
+use super::macros::*;
+use super::*;
+use crate::glisp::budget::{allocate_list, check_size};
+
+/// 列表的元素，不包括开头的 `quote`
+fn elements(list: &[Expression]) -> &[Expression] {
+    match list.first() {
+        Some(Expression::Symbol(a)) if a == "quote" => &list[1..],
+        _ => list,
+    }
+}
+
+pub fn into_elements(mut list: Vec<Expression>) -> Vec<Expression> {
+    if let Some(Expression::Symbol(a)) = list.first() {
+        if a == "quote" {
+            list.remove(0);
+        }
+    }
+    list
+}
+
+/// (map f list) ，对每一个元素调用 f ，返回结果组成的列表
+pub fn func_map(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let list = take_arg!("map", args, List);
+    allocate_list(list.len())?;
+    let result = into_elements(list)
+        .into_iter()
+        .map(|a| call(&func, vec![a], config.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(Expression::List(to_quote_list!(result)))
+}
+
+/// (filter f list) ，f 必须返回 true 或 false
+pub fn func_filter(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let list = take_arg!("filter", args, List);
+    let mut result = vec![];
+    for a in into_elements(list) {
+        match call(&func, vec![a.clone()], config.clone())? {
+            Expression::Bool(true) => result.push(a),
+            Expression::Bool(false) => {}
+            b => {
+                return Err(GError::with_kind(
+                    ErrorKind::Type,
+                    format!("filter: expected true or false, got {}", b),
+                ))
+            }
+        }
+    }
+    allocate_list(result.len())?;
+    Ok(Expression::List(to_quote_list!(result)))
+}
+
+/// (reduce f init list) ，从左到右以 (f acc 元素) 累积， list 为空时返回 init
+pub fn func_reduce(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let init = args.next().unwrap();
+    let list = take_arg!("reduce", args, List);
+    into_elements(list)
+        .into_iter()
+        .try_fold(init, |acc, a| call(&func, vec![acc, a], config.clone()))
+}
+
+/// 只有数字之间、字符串之间可以比较
+fn compare(fnname: &str, a: &Expression, b: &Expression) -> Result<Ordering, GError> {
+    match (a, b) {
+        (Expression::String(a), Expression::String(b)) => Ok(a.cmp(b)),
+        _ => compare_numbers(a, b).ok_or_else(|| {
+            GError::with_kind(
+                ErrorKind::Type,
+                format!("{}: can not compare {} and {}", fnname, a, b),
+            )
+        }),
+    }
+}
+
+/// 按 keys 对 list 进行稳定的排序
+fn sort_by_keys(
+    fnname: &str,
+    list: Vec<Expression>,
+    keys: &[Expression],
+) -> Result<Expression, GError> {
+    let mut error = None;
+    let mut indices = (0..list.len()).collect::<Vec<_>>();
+    indices.sort_by(|a, b| {
+        compare(fnname, &keys[*a], &keys[*b]).unwrap_or_else(|e| {
+            error.get_or_insert(e);
+            Ordering::Equal
+        })
+    });
+    if let Some(e) = error {
+        return Err(e);
+    }
+    let mut list = list.into_iter().map(Some).collect::<Vec<_>>();
+    Ok(Expression::List(to_quote_list!(indices
+        .into_iter()
+        .filter_map(|i| list[i].take()))))
+}
+
+/// (sort list) ，把数字或字符串从小到大排序
+pub fn func_sort(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let list = into_elements(take_arg!("sort", args.into_iter(), List));
+    // 键和结果各是一个列表
+    allocate_list(list.len() * 2)?;
+    let keys = list.clone();
+    sort_by_keys("sort", list, &keys)
+}
+
+/// (sort-by f list) ，按 f 对每个元素返回的数字或字符串从小到大排序，相等的元素保持原来的顺序
+pub fn func_sort_by(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let list = into_elements(take_arg!("sort-by", args, List));
+    allocate_list(list.len() * 2)?;
+    let keys = list
+        .iter()
+        .map(|a| call(&func, vec![a.clone()], config.clone()))
+        .collect::<Result<Vec<_>, _>>()?;
+    sort_by_keys("sort-by", list, &keys)
+}
+
+/// (range end) 、 (range start end) 或 (range start end step) ，不包括 end
+pub fn func_range(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut nums = args.into_iter().map(|a| match a {
+        Expression::Int(a) => Ok(a),
+        _ => Err(GError::with_kind(
+            ErrorKind::Type,
+            "range: Unsupported type".to_owned(),
+        )),
+    });
+    let (start, end, step) = match (nums.next(), nums.next(), nums.next()) {
+        (Some(end), None, _) => (0, end?, 1),
+        (Some(start), Some(end), None) => (start?, end?, 1),
+        (Some(start), Some(end), Some(step)) => (start?, end?, step?),
+        _ => unreachable!(),
+    };
+    if step == 0 {
+        return Err(GError::new("range: step can not be 0".to_owned()));
+    }
+    // 使用 i128 避免计算长度时溢出
+    let len = (end as i128 - start as i128 + step as i128 - step.signum() as i128) / step as i128;
+    let len = len.max(0) as usize;
+    check_size("range", len)?;
+    allocate_list(len)?;
+    Ok(Expression::List(to_quote_list!((0..len).map(|i| {
+        Expression::Int((start as i128 + i as i128 * step as i128) as i64)
+    }))))
+}
+
+/// (append list...) ，把多个列表连接为一个列表
+pub fn func_append(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut result = vec![];
+    for a in args {
+        let Expression::List(list) = a else {
+            return Err(GError::with_kind(
+                ErrorKind::Type,
+                "append: Unsupported type".to_owned(),
+            ));
+        };
+        let list = into_elements(list);
+        check_size("append", result.len() + list.len())?;
+        allocate_list(list.len())?;
+        result.extend(list);
+    }
+    Ok(Expression::List(to_quote_list!(result)))
+}
+
+/// (nth list index) ，index 从 0 开始
+pub fn func_nth(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let list = take_arg!("nth", args, List);
+    let index = take_arg!("nth", args, Int);
+    let list = elements(&list);
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| list.get(i))
+        .cloned()
+        .ok_or_else(|| {
+            GError::new(format!(
+                "nth: index {} out of range for a list of length {}",
+                index,
+                list.len()
+            ))
+        })
+}
+
+/// (zip list...) ，返回由每个列表中相同位置的元素组成的列表，长度是最短的列表的长度
+pub fn func_zip(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let lists = args
+        .into_iter()
+        .map(|a| match a {
+            Expression::List(a) => Ok(into_elements(a)),
+            _ => Err(GError::with_kind(
+                ErrorKind::Type,
+                "zip: Unsupported type".to_owned(),
+            )),
+        })
+        .collect::<Result<Vec<_>, _>>()?;
+    let len = lists.iter().map(|a| a.len()).min().unwrap_or(0);
+    // 结果和其中的每一个列表
+    allocate_list(len.saturating_mul(lists.len() + 2))?;
+    Ok(Expression::List(to_quote_list!((0..len).map(|i| {
+        Expression::List(to_quote_list!(lists.iter().map(|a| a[i].clone())))
+    }))))
+}
+
+/// (apply f list) ，以列表的元素作为参数调用 f
+pub fn func_apply(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let func = args.next().unwrap();
+    let list = take_arg!("apply", args, List);
+    call(&func, into_elements(list), config.clone())
+}
@@ -6,41 +6,22 @@
  * if not, see <https://www.gnu.org/licenses/>.
  */
 
-macro_rules! args_len_max {
-    ($fnname:expr, $args:expr, $ident:expr) => {
-        if $args.len() > $ident {
-            return Err(GError::Reason(format!(
-                "{}: There are more parameters than the maximum {} allowed",
-                std::stringify!($fnname),
-                $ident
-            )));
+/// 取出下一个已经被求值的参数
+/// 参数的个数和类型已经在编译和执行时被检查过，这里的检查只是为了防御
+macro_rules! take_arg {
+    ($fnname:expr, $args:expr, $_type:ident) => {
+        match $args.next() {
+            Some(Expression::$_type(a)) => a,
+            _ => {
+                return Err(GError::with_kind(
+                    ErrorKind::Type,
+                    format!("{}: Unsupported type", $fnname),
+                ))
+            }
         }
     };
 }
-pub(super) use args_len_max;
-
-macro_rules! args_len_min {
-    ($fnname:expr, $args:expr, $ident:expr) => {
-        if $args.len() < $ident {
-            return Err(GError::Reason(format!(
-                "{}: There are more parameters than the minimum {} allowed",
-                std::stringify!($fnname),
-                $ident
-            )));
-        }
-    };
-}
-pub(super) use args_len_min;
-
-macro_rules! check_type_onlyone {
-    ($fnname:expr, $value:expr, $env:ident, $_type:ident, $config:expr) => {
-        match eval($value, $env, $config) {
-            Ok(Expression::$_type(a)) => Ok(a),
-            _ => Err(GError::Reason(format!("{}: Unsupported type", $fnname))),
-        }
-    };
-}
-pub(super) use check_type_onlyone;
+pub(super) use take_arg;
 
 macro_rules! to_quote_list {
     ($list:expr) => {{
@@ -0,0 +1,104 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Map 的键总是字符串，和其它的值一样，Map 是不可变的
+//! `map.set` 和 `map.remove` 返回一个新的 Map ，而不是修改原来的 Map
+
+use std::collections::BTreeMap;
+
+use super::macros::*;
+use super::*;
+
+/// (map.new key1 value1 key2 value2 ...)
+pub fn func_map_new(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    if !args.len().is_multiple_of(2) {
+        return Err(GError::new("map.new: expected key-value pairs".to_owned()));
+    }
+    let mut map = BTreeMap::new();
+    let mut args = args.into_iter();
+    while let Some(key) = args.next() {
+        let Expression::String(key) = key else {
+            return Err(GError::new("map.new: Unsupported type".to_owned()));
+        };
+        map.insert(key, args.next().unwrap());
+    }
+    Ok(Expression::Map(map))
+}
+
+/// 键不存在时返回 default ，没有 default 时返回 false
+pub fn func_map_get(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let map = take_arg!("map.get", args, Map);
+    let key = take_arg!("map.get", args, String);
+    Ok(map
+        .get(&key)
+        .cloned()
+        .or(args.next())
+        .unwrap_or(Expression::Bool(false)))
+}
+
+pub fn func_map_set(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let mut map = take_arg!("map.set", args, Map);
+    let key = take_arg!("map.set", args, String);
+    map.insert(key, args.next().unwrap());
+    Ok(Expression::Map(map))
+}
+
+pub fn func_map_remove(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let mut map = take_arg!("map.remove", args, Map);
+    let key = take_arg!("map.remove", args, String);
+    map.remove(&key);
+    Ok(Expression::Map(map))
+}
+
+pub fn func_map_has(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let map = take_arg!("map.has", args, Map);
+    let key = take_arg!("map.has", args, String);
+    Ok(Expression::Bool(map.contains_key(&key)))
+}
+
+pub fn func_map_len(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let map = take_arg!("map.len", args.into_iter(), Map);
+    Ok(Expression::Int(map.len() as i64))
+}
+
+/// 按键的顺序返回所有的键
+pub fn func_map_keys(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let map = take_arg!("map.keys", args.into_iter(), Map);
+    Ok(Expression::List(to_quote_list!(map
+        .into_keys()
+        .map(Expression::String))))
+}
+
+/// 按键的顺序返回所有的值
+pub fn func_map_values(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let map = take_arg!("map.values", args.into_iter(), Map);
+    Ok(Expression::List(to_quote_list!(map.into_values())))
+}
+
+/// 返回所有的键值对，每一个键值对是一个 `(key value)` 列表
+pub fn func_map_entries(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let map = take_arg!("map.entries", args.into_iter(), Map);
+    Ok(Expression::List(to_quote_list!(map.into_iter().map(
+        |(k, v)| Expression::List(vec![Expression::String(k), v])
+    ))))
+}
+
+/// 按键的顺序对每一个键值对调用一次 func ，参数是键和值
+pub fn func_map_for_each(args: Vec<Expression>, config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let map = take_arg!("map.for-each", args, Map);
+    let func = args.next().unwrap();
+    for (k, v) in map {
+        call(&func, vec![Expression::String(k), v], config.clone())?;
+    }
+    Ok(Expression::Bool(true))
+}
@@ -0,0 +1,27 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! Markdown 的渲染由 drop::markdown 完成，支持的语法见它的注释
+
+use super::macros::*;
+use super::*;
+use crate::drop::markdown;
+use crate::glisp::budget::{allocate, charge, check_size};
+
+/// (markdown->html str) ，把 Markdown 渲染为 HTML
+pub fn func_markdown_to_html(
+    args: Vec<Expression>,
+    _config: &Config,
+) -> Result<Expression, GError> {
+    let str = take_arg!("markdown->html", args.into_iter(), String);
+    charge(str.len())?;
+    let html = markdown::to_html(&str);
+    check_size("markdown->html", html.len())?;
+    allocate(html.len())?;
+    Ok(Expression::String(html))
+}
@@ -6,78 +6,260 @@
  * if not, see <https://www.gnu.org/licenses/>.
  */
 
+//! # 本模块的总则
+//! 这里定义了所有参数会被求值的内置函数，它们接受已经被求值的参数
+//! 参数不会（或不总是）被求值的内置函数，例如 `if` 和 `loop` ，是特殊形式，它们由 compiler 模块直接编译为字节码
+//! 内置函数在编译时就被解析为它在 BUILT_INS 中的位置，而不是在每次调用时按名字查找
+//! 和内置函数同名的变量优先于内置函数，参见 compiler 模块
+
 mod config;
 mod core;
 mod eval;
 mod io;
+mod json;
+mod list;
 mod macros;
+mod map;
+mod markdown;
+mod num;
+mod re;
 mod str;
+mod template;
 
 use super::core::*;
 use config::*;
 use core::*;
+pub use core::{func_cons, func_quote};
 use eval::*;
 use io::*;
+use json::*;
+pub use list::into_elements;
+use list::*;
+use map::*;
+use markdown::*;
+use num::*;
+pub use num::{num_add, num_eq, num_ge, num_gt, num_le, num_lt, num_sub};
+use re::*;
 use str::*;
+use template::*;
+
+/// 参数的类型，除了 Any 以外，参数在求值时出现的错误和类型错误都会被报告为 `<函数名>: Unsupported type`
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ArgType {
+    Any,
+    String,
+    /// 整数或浮点数
+    Number,
+    Int,
+    List,
+    Bool,
+    Lambda,
+    StringOrList,
+    Map,
+    /// 内置的函数或 Lambda
+    Function,
+}
 
-pub fn eval_built_in_form(
-    exp: &Expression,
-    other_args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Option<Result<Expression, GError>> {
-    match exp {
-        Expression::Symbol(symbol) => match symbol.as_ref() {
-            "if" => Some(func_if(other_args, env, config)),
-            "set" => Some(func_set(other_args, env, config)),
-            "lambda" => Some(func_lambda(other_args)),
-            "quote" => Some(func_quote(other_args)),
-            "atom" => Some(func_atom(other_args, env, config)),
-            "eq" => Some(func_eq(other_args, env, config)),
-            "car" => Some(func_car(other_args, env, config)),
-            "cdr" => Some(func_cdr(other_args, env, config)),
-            "cons" => Some(func_cons(other_args)),
-            "cond" => Some(func_cond(other_args, env, config)),
-            "length" => Some(func_length(other_args, env, config)),
-            "str.=" => Some(func_str_eq(other_args, env, config)),
-            "str.!=" => Some(func_str_ne(other_args, env, config)),
-            "str.<" => Some(func_str_lt(other_args, env, config)),
-            "str.<=" => Some(func_str_le(other_args, env, config)),
-            "str.>" => Some(func_str_gt(other_args, env, config)),
-            "str.>=" => Some(func_str_ge(other_args, env, config)),
-            "last" => Some(func_last(other_args, env, config)),
-            "chars" => Some(func_chars(other_args, env, config)),
-            "find" => Some(func_find(other_args, env, config)),
-            "contains" => Some(func_contains(other_args, env, config)),
-            "insert" => Some(func_insert(other_args, env, config)),
-            "begin" => Some(func_begin(other_args, env, config)),
-            "is-empty" => Some(func_is_empty(other_args, env, config)),
-            "remove" => Some(func_remove(other_args, env, config)),
-            "reverse" => Some(func_reverse(other_args, env, config)),
-            "rfind" => Some(func_rfind(other_args, env, config)),
-            "slice" => Some(func_slice(other_args, env, config)),
-            "log" => Some(func_console_log(other_args, env, config)),
-            "loop" => Some(func_loop(other_args, env, config)),
-            "read-file" => Some(func_read_file(other_args, env, config)),
-            "write-file" => Some(func_write_file(other_args, env, config)),
-            "do" => Some(func_do(other_args, env, config)),
-            "meta" => Some(func_meta(other_args, env, config)),
-            "eval-atom" => Some(func_eval_atom(other_args, env, config)),
-            "str" => Some(func_str(other_args, env, config)),
-            "str.+" => Some(func_str_plus(other_args, env, config)),
-            "or" => Some(func_or(other_args, env, config)),
-            "and" => Some(func_and(other_args, env, config)),
-            "lines" => Some(func_lines(other_args, env, config)),
-            "return" => Some(Ok(Expression::Symbol("return".to_owned()))),
-            "continue" => Some(Ok(Expression::Symbol("continue".to_owned()))),
-            "pass" => Some(Ok(Expression::Symbol("pass".to_owned()))),
-            "read-dir" => Some(func_read_dir(other_args, env, config)),
-            "for-each-eval" => Some(func_for_each_eval(other_args, env, config)),
-            "eval" => Some(func_eval(other_args, env, config)),
-            "run" => Some(func_run(other_args, env, config)),
-            "serve" => Some(func_serve(other_args, env, config)),
-            _ => None,
-        },
-        _ => None,
+impl ArgType {
+    pub fn check(&self, exp: &Expression) -> bool {
+        matches!(
+            (self, exp),
+            (ArgType::Any, _)
+                | (ArgType::String, Expression::String(_))
+                | (ArgType::Number, Expression::Int(_) | Expression::Float(_))
+                | (ArgType::Int, Expression::Int(_))
+                | (ArgType::List, Expression::List(_))
+                | (ArgType::Bool, Expression::Bool(_))
+                | (ArgType::Lambda, Expression::Lambda(_))
+                | (ArgType::Map, Expression::Map(_))
+                | (
+                    ArgType::Function,
+                    Expression::Func(_) | Expression::Lambda(_)
+                )
+                | (
+                    ArgType::StringOrList,
+                    Expression::String(_) | Expression::List(_)
+                )
+        )
+    }
+}
+
+/// name: 函数名
+/// min, max: 参数个数的范围， max 为 None 时没有上限
+/// args: 每个参数的类型，超出它的长度的参数使用最后一个类型
+/// func: 函数本身，它的参数已经被求值并通过了类型检查
+pub struct BuiltIn {
+    pub name: &'static str,
+    pub min: usize,
+    pub max: Option<usize>,
+    pub args: &'static [ArgType],
+    pub func: fn(Vec<Expression>, &Config) -> Result<Expression, GError>,
+}
+
+impl BuiltIn {
+    pub fn arg_type(&self, index: usize) -> ArgType {
+        *self
+            .args
+            .get(index)
+            .or(self.args.last())
+            .unwrap_or(&ArgType::Any)
+    }
+}
+
+macro_rules! built_in {
+    ($name:expr, $min:expr, $max:expr, [$($arg:ident),*], $func:expr) => {
+        BuiltIn {
+            name: $name,
+            min: $min,
+            max: $max,
+            args: &[$(ArgType::$arg),*],
+            func: $func,
+        }
+    };
+}
+
+pub static BUILT_INS: &[BuiltIn] = &[
+    built_in!("atom", 1, Some(1), [Any], func_atom),
+    built_in!("eq", 2, Some(2), [Any, Any], func_eq),
+    built_in!("car", 1, Some(1), [List], func_car),
+    built_in!("cdr", 1, Some(1), [List], func_cdr),
+    built_in!("list", 0, None, [Any], func_list),
+    built_in!("or", 2, Some(2), [Bool, Bool], func_or),
+    built_in!("gensym", 0, Some(0), [Any], func_gensym),
+    built_in!("*", 0, None, [Number], func_mul),
+    built_in!("/", 1, None, [Number], func_div),
+    built_in!("mod", 2, Some(2), [Number], func_mod),
+    built_in!("floor", 1, Some(1), [Number], func_floor),
+    built_in!("round", 1, Some(1), [Number], func_round),
+    built_in!("abs", 1, Some(1), [Number], func_abs),
+    built_in!("min", 1, None, [Number], func_min),
+    built_in!("max", 1, None, [Number], func_max),
+    built_in!(
+        "number->string",
+        1,
+        Some(1),
+        [Number],
+        func_number_to_string
+    ),
+    built_in!("raise", 1, Some(1), [Any], func_raise),
+    built_in!("length", 1, Some(1), [StringOrList], func_length),
+    built_in!("str.=", 2, Some(2), [String, String], func_str_eq),
+    built_in!("str.!=", 2, Some(2), [String, String], func_str_ne),
+    built_in!("str.<", 2, Some(2), [String, String], func_str_lt),
+    built_in!("str.<=", 2, Some(2), [String, String], func_str_le),
+    built_in!("str.>", 2, Some(2), [String, String], func_str_gt),
+    built_in!("str.>=", 2, Some(2), [String, String], func_str_ge),
+    built_in!("last", 1, Some(1), [String], func_last),
+    built_in!("chars", 1, Some(1), [String], func_chars),
+    built_in!("find", 2, Some(2), [String, String], func_find),
+    built_in!("contains", 2, Some(2), [String, String], func_contains),
+    built_in!("insert", 3, Some(3), [String, Int, String], func_insert),
+    built_in!("begin", 1, Some(1), [String], func_begin),
+    built_in!("is-empty", 1, Some(1), [String], func_is_empty),
+    built_in!("remove", 2, Some(3), [String, Int, Int], func_remove),
+    built_in!("reverse", 1, Some(1), [String], func_reverse),
+    built_in!("rfind", 2, Some(2), [String, String], func_rfind),
+    built_in!("slice", 3, Some(3), [String, Int, Int], func_slice),
+    built_in!("str", 1, Some(1), [String], func_str),
+    built_in!("str.+", 2, Some(2), [String, String], func_str_plus),
+    built_in!("lines", 1, Some(1), [String], func_lines),
+    built_in!("re.match", 2, Some(2), [String, String], func_re_match),
+    built_in!(
+        "re.find-all",
+        2,
+        Some(2),
+        [String, String],
+        func_re_find_all
+    ),
+    built_in!(
+        "re.replace",
+        3,
+        Some(3),
+        [String, String, String],
+        func_re_replace
+    ),
+    built_in!("re.split", 2, Some(2), [String, String], func_re_split),
+    built_in!(
+        "markdown->html",
+        1,
+        Some(1),
+        [String],
+        func_markdown_to_html
+    ),
+    built_in!("render", 1, Some(2), [String, Map], func_render),
+    built_in!("log", 1, Some(1), [String], func_console_log),
+    built_in!("read-file", 1, Some(1), [String], func_read_file),
+    built_in!("write-file", 2, Some(2), [String, String], func_write_file),
+    built_in!("read-dir", 1, Some(1), [String], func_read_dir),
+    built_in!("run", 1, None, [Any], func_run),
+    built_in!("meta", 1, Some(1), [Any], func_meta),
+    built_in!("serve", 3, Some(3), [String, String, String], func_serve),
+    built_in!("handle", 2, Some(2), [String, Lambda], func_handle),
+    built_in!("json.parse", 1, Some(1), [String], func_json_parse),
+    built_in!(
+        "json.stringify",
+        1,
+        Some(2),
+        [Any, Int],
+        func_json_stringify
+    ),
+    built_in!("map.new", 0, None, [Any], func_map_new),
+    built_in!("map.get", 2, Some(3), [Map, String, Any], func_map_get),
+    built_in!("map.set", 3, Some(3), [Map, String, Any], func_map_set),
+    built_in!("map.remove", 2, Some(2), [Map, String], func_map_remove),
+    built_in!("map.has", 2, Some(2), [Map, String], func_map_has),
+    built_in!("map.len", 1, Some(1), [Map], func_map_len),
+    built_in!("map.keys", 1, Some(1), [Map], func_map_keys),
+    built_in!("map.values", 1, Some(1), [Map], func_map_values),
+    built_in!("map.entries", 1, Some(1), [Map], func_map_entries),
+    built_in!(
+        "map.for-each",
+        2,
+        Some(2),
+        [Map, Function],
+        func_map_for_each
+    ),
+    built_in!("map", 2, Some(2), [Function, List], func_map),
+    built_in!("filter", 2, Some(2), [Function, List], func_filter),
+    built_in!("reduce", 3, Some(3), [Function, Any, List], func_reduce),
+    built_in!("sort", 1, Some(1), [List], func_sort),
+    built_in!("sort-by", 2, Some(2), [Function, List], func_sort_by),
+    built_in!("range", 1, Some(3), [Int], func_range),
+    built_in!("append", 0, None, [List], func_append),
+    built_in!("nth", 2, Some(2), [List, Int], func_nth),
+    built_in!("zip", 1, None, [List], func_zip),
+    built_in!("apply", 2, Some(2), [Function, List], func_apply),
+];
+
+/// 返回内置函数在 BUILT_INS 中的位置
+pub fn built_in(name: &str) -> Option<usize> {
+    BUILT_INS.iter().position(|a| a.name == name)
+}
+
+/// 检查参数的个数，所有内置函数和特殊形式的参数个数错误都使用这里的信息
+pub fn check_args_len(
+    name: &str,
+    len: usize,
+    min: usize,
+    max: Option<usize>,
+) -> Result<(), GError> {
+    if len < min {
+        return Err(GError::with_kind(
+            ErrorKind::Arity,
+            format!(
+                "\"{}\": There are more parameters than the minimum {} allowed",
+                name, min
+            ),
+        ));
+    }
+    match max {
+        Some(max) if len > max => Err(GError::with_kind(
+            ErrorKind::Arity,
+            format!(
+                "\"{}\": There are more parameters than the maximum {} allowed",
+                name, max
+            ),
+        )),
+        _ => Ok(()),
     }
 }
@@ -0,0 +1,246 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 数字有整数 (i64) 和浮点数 (f64) 两种
+//! 整数之间的运算结果是整数，溢出时报错；有浮点数参与的运算结果是浮点数
+//! `/` 在整数能被整除时返回整数，否则返回浮点数； `floor` 和 `round` 总是返回整数
+//! `+` 、 `-` 、 `=` 和比较函数是全局环境中的函数，它们把 true 和 false 当作 1 和 0
+
+use super::*;
+
+#[derive(Clone, Copy)]
+enum Num {
+    Int(i64),
+    Float(f64),
+}
+
+impl Num {
+    fn from_expression(exp: &Expression) -> Result<Self, GError> {
+        match exp {
+            Expression::Int(a) => Ok(Num::Int(*a)),
+            Expression::Float(a) => Ok(Num::Float(*a)),
+            Expression::Bool(a) => Ok(Num::Int(*a as i64)),
+            _ => Err(GError::with_kind(
+                ErrorKind::Type,
+                "expect a number".to_string(),
+            )),
+        }
+    }
+
+    fn to_expression(self) -> Expression {
+        match self {
+            Num::Int(a) => Expression::Int(a),
+            Num::Float(a) => Expression::Float(a),
+        }
+    }
+
+    fn as_f64(self) -> f64 {
+        match self {
+            Num::Int(a) => a as f64,
+            Num::Float(a) => a,
+        }
+    }
+}
+
+fn nums(args: &[Expression]) -> Result<Vec<Num>, GError> {
+    args.iter().map(Num::from_expression).collect()
+}
+
+fn overflow() -> GError {
+    GError::new("integer overflow".to_owned())
+}
+
+/// 对两个数做运算，两个都是整数时使用 int ，它返回 None 表示溢出
+fn apply(
+    a: Num,
+    b: Num,
+    int: fn(i64, i64) -> Option<i64>,
+    float: fn(f64, f64) -> f64,
+) -> Result<Num, GError> {
+    match (a, b) {
+        (Num::Int(a), Num::Int(b)) => int(a, b).map(Num::Int).ok_or_else(overflow),
+        (a, b) => Ok(Num::Float(float(a.as_f64(), b.as_f64()))),
+    }
+}
+
+/// 两个整数的比较是精确的，否则作为浮点数比较
+fn compare(a: Num, b: Num) -> Option<std::cmp::Ordering> {
+    match (a, b) {
+        (Num::Int(a), Num::Int(b)) => Some(a.cmp(&b)),
+        (a, b) => a.as_f64().partial_cmp(&b.as_f64()),
+    }
+}
+
+/// 比较两个数字，有一个不是数字时返回 None
+pub fn compare_numbers(a: &Expression, b: &Expression) -> Option<std::cmp::Ordering> {
+    compare(Num::from_expression(a).ok()?, Num::from_expression(b).ok()?)
+}
+
+pub fn num_add(args: &[Expression]) -> Result<Expression, GError> {
+    nums(args)?
+        .into_iter()
+        .try_fold(Num::Int(0), |sum, a| {
+            apply(sum, a, i64::checked_add, |a, b| a + b)
+        })
+        .map(Num::to_expression)
+}
+
+pub fn num_sub(args: &[Expression]) -> Result<Expression, GError> {
+    let nums = nums(args)?;
+    let first = *nums
+        .first()
+        .ok_or(GError::new("expected at least one number".to_string()))?;
+    nums[1..]
+        .iter()
+        .try_fold(first, |rest, a| {
+            apply(rest, *a, i64::checked_sub, |a, b| a - b)
+        })
+        .map(Num::to_expression)
+}
+
+pub fn num_eq(args: &[Expression]) -> Result<Expression, GError> {
+    let nums = nums(args)?;
+    // 要想比较，需要有两个值
+    let [a, b] = nums[..] else {
+        return Err(GError::new("expected two number".to_string()));
+    };
+    Ok(Expression::Bool(
+        compare(a, b) == Some(std::cmp::Ordering::Equal),
+    ))
+}
+
+macro_rules! ensure_tonicity {
+    ($fnname:ident, $check_fn:expr) => {
+        pub fn $fnname(args: &[Expression]) -> Result<Expression, GError> {
+            let nums = nums(args)?;
+            if nums.is_empty() {
+                return Err(GError::new("expected at least one number".to_string()));
+            }
+            let ok = nums
+                .windows(2)
+                .all(|a| compare(a[0], a[1]).is_some_and($check_fn));
+            Ok(Expression::Bool(ok))
+        }
+    };
+}
+
+ensure_tonicity!(num_gt, |a: std::cmp::Ordering| a.is_gt());
+ensure_tonicity!(num_lt, |a: std::cmp::Ordering| a.is_lt());
+ensure_tonicity!(num_le, |a: std::cmp::Ordering| a.is_le());
+ensure_tonicity!(num_ge, |a: std::cmp::Ordering| a.is_ge());
+
+pub fn func_mul(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    nums(&args)?
+        .into_iter()
+        .try_fold(Num::Int(1), |product, a| {
+            apply(product, a, i64::checked_mul, |a, b| a * b)
+        })
+        .map(Num::to_expression)
+}
+
+pub fn func_div(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let nums = nums(&args)?;
+    // 只有一个参数时求它的倒数
+    let (first, rest) = match nums[..] {
+        [_] => (Num::Int(1), &nums[..]),
+        _ => (nums[0], &nums[1..]),
+    };
+    rest.iter()
+        .try_fold(first, |quotient, a| match (quotient, *a) {
+            (Num::Int(_), Num::Int(0)) => Err(GError::new("/: division by zero".to_owned())),
+            (Num::Int(a), Num::Int(b)) if a.checked_rem(b) == Some(0) => {
+                a.checked_div(b).map(Num::Int).ok_or_else(overflow)
+            }
+            (a, b) => Ok(Num::Float(a.as_f64() / b.as_f64())),
+        })
+        .map(Num::to_expression)
+}
+
+/// 结果的符号和除数相同，例如 `(mod -1 3)` 是 2
+pub fn func_mod(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let nums = nums(&args)?;
+    match (nums[0], nums[1]) {
+        (Num::Int(_), Num::Int(0)) => Err(GError::new("mod: division by zero".to_owned())),
+        (Num::Int(a), Num::Int(b)) => {
+            // a % b 的符号和 a 相同，所以在符号不同时需要加上 b
+            // 只有 i64::MIN % -1 会溢出，它的余数是 0
+            let r = a.checked_rem(b).unwrap_or(0);
+            Ok(Expression::Int(if r != 0 && (r < 0) != (b < 0) {
+                r + b
+            } else {
+                r
+            }))
+        }
+        (a, b) => {
+            let (a, b) = (a.as_f64(), b.as_f64());
+            Ok(Expression::Float(a - b * (a / b).floor()))
+        }
+    }
+}
+
+/// 把浮点数转换为整数，超出 i64 的范围时报错
+fn to_int(name: &str, a: f64) -> Result<Expression, GError> {
+    if a.is_finite() && a >= i64::MIN as f64 && a < i64::MAX as f64 {
+        Ok(Expression::Int(a as i64))
+    } else {
+        Err(GError::new(format!("{}: {} is out of range", name, a)))
+    }
+}
+
+pub fn func_floor(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match Num::from_expression(&args[0])? {
+        Num::Int(a) => Ok(Expression::Int(a)),
+        Num::Float(a) => to_int("floor", a.floor()),
+    }
+}
+
+/// 和 Rust 的 f64::round 相同，正好在中间时远离 0 取整
+pub fn func_round(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match Num::from_expression(&args[0])? {
+        Num::Int(a) => Ok(Expression::Int(a)),
+        Num::Float(a) => to_int("round", a.round()),
+    }
+}
+
+pub fn func_abs(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match Num::from_expression(&args[0])? {
+        Num::Int(a) => a.checked_abs().map(Expression::Int).ok_or_else(overflow),
+        Num::Float(a) => Ok(Expression::Float(a.abs())),
+    }
+}
+
+/// 有浮点数参与时，结果是浮点数
+fn extremum(args: &[Expression], pick: std::cmp::Ordering) -> Result<Expression, GError> {
+    let nums = nums(args)?;
+    let mut best = nums[0];
+    for a in &nums[1..] {
+        if compare(*a, best) == Some(pick) {
+            best = *a;
+        }
+    }
+    if nums.iter().any(|a| matches!(a, Num::Float(_))) {
+        best = Num::Float(best.as_f64());
+    }
+    Ok(best.to_expression())
+}
+
+pub fn func_min(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    extremum(&args, std::cmp::Ordering::Less)
+}
+
+pub fn func_max(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    extremum(&args, std::cmp::Ordering::Greater)
+}
+
+pub fn func_number_to_string(
+    args: Vec<Expression>,
+    _config: &Config,
+) -> Result<Expression, GError> {
+    Ok(Expression::String(args[0].to_string()))
+}
@@ -0,0 +1,87 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! 正则表达式，匹配由 drop::regex 完成，支持的语法见它的注释
+//! 所有函数的第一个参数都是模式，模式有错误时报错并给出出错的位置
+
+use super::macros::*;
+use super::*;
+use crate::drop::regex::{Captures, Regex};
+use crate::glisp::budget::{allocate, allocate_list, charge, check_size};
+
+/// 编译模式，并按最坏的情况（ text 的每个字符都要经过模式的每个部分）消耗预算
+fn compile(fnname: &str, pattern: &str, text: &str) -> Result<Regex, GError> {
+    charge(text.len().saturating_mul(pattern.len().max(1)))?;
+    Regex::new(pattern).map_err(|e| GError::new(format!("{}: {}", fnname, e)))
+}
+
+/// 匹配的字符串和每个捕获组组成的列表，没有参与匹配的组为 false
+fn to_list(text: &str, captures: Captures) -> Expression {
+    Expression::List(to_quote_list!(captures.into_iter().map(|a| match a {
+        Some(range) => Expression::String(text[range].to_owned()),
+        None => Expression::Bool(false),
+    })))
+}
+
+/// (re.match pattern str) ，返回第一个匹配的字符串和捕获组组成的列表，没有匹配时返回 false
+pub fn func_re_match(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let pattern = take_arg!("re.match", args, String);
+    let str = take_arg!("re.match", args, String);
+    let re = compile("re.match", &pattern, &str)?;
+
+    Ok(match re.captures_at(&str, 0) {
+        Some(captures) => to_list(&str, captures),
+        None => Expression::Bool(false),
+    })
+}
+
+/// (re.find-all pattern str) ，返回所有不重叠的匹配的字符串
+pub fn func_re_find_all(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let pattern = take_arg!("re.find-all", args, String);
+    let str = take_arg!("re.find-all", args, String);
+    let re = compile("re.find-all", &pattern, &str)?;
+
+    let all = re.captures_all(&str);
+    check_size("re.find-all", all.len())?;
+    allocate_list(all.len())?;
+    Ok(Expression::List(to_quote_list!(all.into_iter().map(|a| {
+        Expression::String(str[a[0].clone().unwrap()].to_owned())
+    }))))
+}
+
+/// (re.replace pattern str replacement) ，替换所有的匹配， replacement 中的 `$1` 代表第一个捕获组
+pub fn func_re_replace(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let pattern = take_arg!("re.replace", args, String);
+    let str = take_arg!("re.replace", args, String);
+    let replacement = take_arg!("re.replace", args, String);
+    let re = compile("re.replace", &pattern, &str)?;
+
+    let result = re.replace_all(&str, &replacement);
+    check_size("re.replace", result.len())?;
+    allocate(result.len())?;
+    Ok(Expression::String(result))
+}
+
+/// (re.split pattern str) ，以匹配为分隔符分割字符串
+pub fn func_re_split(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let pattern = take_arg!("re.split", args, String);
+    let str = take_arg!("re.split", args, String);
+    let re = compile("re.split", &pattern, &str)?;
+
+    let parts = re.split(&str);
+    check_size("re.split", parts.len())?;
+    allocate_list(parts.len())?;
+    allocate(str.len())?;
+    Ok(Expression::List(to_quote_list!(parts
+        .into_iter()
+        .map(|a| Expression::String(a.to_owned())))))
+}
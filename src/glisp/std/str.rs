@@ -6,182 +6,130 @@
  * if not, see <https://www.gnu.org/licenses/>.
  */
 
-use super::macros::*;
-use super::*;
-pub fn func_length(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("length", args, 1);
-    args_len_max!("length", args, 1);
-    let arg_res = check_type_onlyone!("length", &args[0], env, String, config.clone());
-    match arg_res {
-        Ok(str) => Ok(Expression::Number(str.chars().count() as f64)),
-        _ => {
-            let arg = check_type_onlyone!("length", &args[0], env, List, config)?;
-            Ok(Expression::Number(arg.len() as f64))
-        }
-    }
-}
+//! 字符串的下标和长度都以 Unicode 标量值（即 Rust 的 char ）计，而不是字节
+//! 下标超出范围时报错，而不是 panic
 
-pub fn func_str_eq(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.=", args, 2);
-    args_len_max!("str.=", args, 2);
-    let str1 = check_type_onlyone!("str.=", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.=", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::eq(&str1, &str2)))
-}
+use std::ops::Range;
 
-pub fn func_str_ne(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.!=", args, 2);
-    args_len_max!("str.!=", args, 2);
-    let str1 = check_type_onlyone!("str.!=", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.!=", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::ne(&str1, &str2)))
-}
+use super::macros::*;
+use super::*;
+use crate::glisp::budget::{allocate, allocate_list, check_size};
 
-pub fn func_str_lt(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.<", args, 2);
-    args_len_max!("str.<", args, 2);
-    let str1 = check_type_onlyone!("str.<", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.<", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::lt(&str1, &str2)))
+pub fn func_length(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    match &args[0] {
+        Expression::String(str) => Ok(Expression::Int(str.chars().count() as i64)),
+        Expression::List(arg) => Ok(Expression::Int(arg.len() as i64)),
+        _ => Err(GError::new("length: Unsupported type".to_owned())),
+    }
 }
 
-pub fn func_str_le(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.<=", args, 2);
-    args_len_max!("str.<=", args, 2);
-    let str1 = check_type_onlyone!("str.<=", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.<=", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::le(&str1, &str2)))
-}
+macro_rules! str_compare {
+    ($fnname:ident, $name:expr, $op:path) => {
+        pub fn $fnname(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+            let mut args = args.into_iter();
+            let str1 = take_arg!($name, args, String);
+            let str2 = take_arg!($name, args, String);
 
-pub fn func_str_gt(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.>", args, 2);
-    args_len_max!("str.>", args, 2);
-    let str1 = check_type_onlyone!("str.>", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.>", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::gt(&str1, &str2)))
+            Ok(Expression::Bool($op(&str1, &str2)))
+        }
+    };
 }
 
-pub fn func_str_ge(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.>=", args, 2);
-    args_len_max!("str.>=", args, 2);
-    let str1 = check_type_onlyone!("str.>=", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.>=", &args[1], env, String, config)?;
-
-    Ok(Expression::Bool(str::ge(&str1, &str2)))
-}
+str_compare!(func_str_eq, "str.=", str::eq);
+str_compare!(func_str_ne, "str.!=", str::ne);
+str_compare!(func_str_lt, "str.<", str::lt);
+str_compare!(func_str_le, "str.<=", str::le);
+str_compare!(func_str_gt, "str.>", str::gt);
+str_compare!(func_str_ge, "str.>=", str::ge);
 
-pub fn func_last(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("last", args, 1);
-    args_len_max!("last", args, 1);
-    let str = check_type_onlyone!("last", &args[0], env, String, config)?;
-    match str.chars().nth_back(0) {
+/// 和 begin 一样，空字符串没有最后一个字符，返回 false
+pub fn func_last(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str = take_arg!("last", args.into_iter(), String);
+    match str.chars().next_back() {
         Some(a) => Ok(Expression::String(a.to_string())),
-        _ => Ok(Expression::List(vec![])),
+        _ => Ok(Expression::Bool(false)),
     }
 }
 
-pub fn func_chars(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("chars", args, 1);
-    args_len_max!("chars", args, 1);
-    let str = check_type_onlyone!("chars", &args[0], env, String, config)?;
+pub fn func_chars(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str = take_arg!("chars", args.into_iter(), String);
+    // 每个字符都是一个字符串
+    allocate_list(str.len())?;
+    allocate(str.len())?;
 
     Ok(Expression::List(to_quote_list!(str
         .chars()
         .map(|x| Expression::String(x.to_string()))
         .collect::<Vec<_>>())))
 }
-pub fn func_find(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("find", args, 2);
-    args_len_max!("find", args, 2);
-    let str1 = check_type_onlyone!("find", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("find", &args[1], env, String, config)?;
+pub fn func_find(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let str1 = take_arg!("find", args, String);
+    let str2 = take_arg!("find", args, String);
 
     Ok(if let Some(a) = str1.find(&str2) {
-        Expression::Number(a as f64)
+        Expression::Int(char_count(&str1[..a]))
     } else {
         Expression::Bool(false)
     })
 }
-pub fn func_contains(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("contains", args, 2);
-    args_len_max!("contains", args, 2);
-    let str1 = check_type_onlyone!("contains", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("contains", &args[1], env, String, config)?;
+pub fn func_contains(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let str1 = take_arg!("contains", args, String);
+    let str2 = take_arg!("contains", args, String);
 
     Ok(Expression::Bool(str1.contains(&str2)))
 }
-pub fn func_insert(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("insert", args, 3);
-    args_len_max!("insert", args, 3);
-    let mut str1 = check_type_onlyone!("insert", &args[0], env, String, config.clone())?;
-    let num = check_type_onlyone!("insert", &args[1], env, Number, config.clone())?;
-    let str2 = check_type_onlyone!("insert", &args[2], env, String, config)?;
-    str1.insert_str(num as usize, &str2);
+fn char_count(str: &str) -> i64 {
+    str.chars().count() as i64
+}
+
+fn out_of_range(fnname: &str, index: i64, str: &str) -> GError {
+    GError::new(format!(
+        "{}: index {} out of range for a string of length {}",
+        fnname,
+        index,
+        char_count(str)
+    ))
+}
+
+/// 把第 index 个字符的下标转换为字节偏移量， index 可以等于字符串的长度，此时指向字符串的末尾
+fn byte_offset(fnname: &str, str: &str, index: i64) -> Result<usize, GError> {
+    usize::try_from(index)
+        .ok()
+        .and_then(|i| str.char_indices().map(|(a, _)| a).chain([str.len()]).nth(i))
+        .ok_or_else(|| out_of_range(fnname, index, str))
+}
+
+/// 从第 start 个字符到第 end 个字符（包括 end ）的字节范围， end 为 start - 1 时范围为空
+fn byte_range(fnname: &str, str: &str, start: i64, end: i64) -> Result<Range<usize>, GError> {
+    let start_offset = byte_offset(fnname, str, start)?;
+    if end < start - 1 {
+        return Err(GError::new(format!(
+            "{}: invalid range from {} to {}",
+            fnname, start, end
+        )));
+    }
+    match end.checked_add(1).filter(|a| *a <= char_count(str)) {
+        Some(a) => Ok(start_offset..byte_offset(fnname, str, a)?),
+        None => Err(out_of_range(fnname, end, str)),
+    }
+}
+
+pub fn func_insert(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let mut str1 = take_arg!("insert", args, String);
+    let num = take_arg!("insert", args, Int);
+    let str2 = take_arg!("insert", args, String);
+    check_size("insert", str1.len() + str2.len())?;
+    allocate(str1.len() + str2.len())?;
+    str1.insert_str(byte_offset("insert", &str1, num)?, &str2);
 
     Ok(Expression::String(str1))
 }
 
-pub fn func_begin(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("begin", args, 1);
-    args_len_max!("begin", args, 1);
-    let str1 = check_type_onlyone!("begin", &args[0], env, String, config)?;
+pub fn func_begin(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("begin", args.into_iter(), String);
 
     Ok(if let Some(a) = str1.chars().next() {
         Expression::String(a.to_string())
@@ -190,133 +138,90 @@ pub fn func_begin(
     })
 }
 
-pub fn func_is_empty(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("is-empty", args, 1);
-    args_len_max!("is-empty", args, 1);
-    let str1 = check_type_onlyone!("is-empty", &args[0], env, String, config)?;
+pub fn func_is_empty(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("is-empty", args.into_iter(), String);
 
     Ok(Expression::Bool(str1.is_empty()))
 }
 
-pub fn func_remove(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("remove", args, 2);
-    args_len_max!("remove", args, 3);
-    let mut str1 = check_type_onlyone!("remove", &args[0], env, String, config.clone())?;
-    let num1 = check_type_onlyone!("remove", &args[1], env, Number, config.clone())? as usize;
-
-    if args.len() == 2 {
-        str1.remove(num1);
-        Ok(Expression::String(str1))
+pub fn func_remove(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let len = args.len();
+    let mut args = args.into_iter();
+    let mut str1 = take_arg!("remove", args, String);
+    let num1 = take_arg!("remove", args, Int);
+    let num2 = if len == 2 {
+        num1
     } else {
-        let num2 = check_type_onlyone!("remove", &args[2], env, Number, config)? as usize;
-        str1.drain(num1..num2 + 1);
-        Ok(Expression::String(str1))
-    }
+        take_arg!("remove", args, Int)
+    };
+    str1.drain(byte_range("remove", &str1, num1, num2)?);
+
+    Ok(Expression::String(str1))
 }
 
-pub fn func_reverse(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("reverse", args, 1);
-    args_len_max!("reverse", args, 1);
-    let str1 = check_type_onlyone!("reverse", &args[0], env, String, config)?;
+pub fn func_reverse(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("reverse", args.into_iter(), String);
+    allocate(str1.len())?;
 
     Ok(Expression::String(str1.chars().rev().collect::<String>()))
 }
 
-pub fn func_rfind(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("rfind", args, 2);
-    args_len_max!("rfind", args, 2);
-    let str1 = check_type_onlyone!("rfind", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("rfind", &args[1], env, String, config)?;
+pub fn func_rfind(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let str1 = take_arg!("rfind", args, String);
+    let str2 = take_arg!("rfind", args, String);
 
     Ok(if let Some(a) = str1.rfind(&str2) {
-        Expression::Number(a as f64)
+        Expression::Int(char_count(&str1[..a]))
     } else {
         Expression::Bool(false)
     })
 }
 
-pub fn func_slice(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("slice", args, 3);
-    args_len_max!("slice", args, 3);
-    let str1 = check_type_onlyone!("slice", &args[0], env, String, config.clone())?;
-    let num1 = check_type_onlyone!("slice", &args[1], env, Number, config.clone())? as usize;
-    let num2 = check_type_onlyone!("slice", &args[2], env, Number, config)? as usize;
-
-    let chars = str1.chars();
-
-    if chars.clone().count() <= num2 {
-        return Err(GError::Reason(format!(
-            "str.slice: index {} out of {}",
-            num2,
-            str1.len()
-        )));
-    }
+pub fn func_slice(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let str1 = take_arg!("slice", args, String);
+    let num1 = take_arg!("slice", args, Int);
+    let num2 = take_arg!("slice", args, Int);
+
     Ok(Expression::String(
-        str1.chars().collect::<Vec<_>>()[num1..num2 + 1]
-            .iter()
-            .collect(),
+        str1[byte_range("slice", &str1, num1, num2)?].to_owned(),
     ))
 }
 
-pub fn func_str(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str", args, 1);
-    args_len_max!("str", args, 1);
-    let meta = check_type_onlyone!("str", &args[0], env, String, config)?;
+/// 旧的字符串写法， `\b` 、 `\[` 、 `\]` 和 `\'` 分别代表空格、 `(` 、 `)` 和 `"`
+/// 字符串字面量可以直接包含这些字符之后，只有 LEGACY_STRINGS 打开时才转换它们
+pub fn legacy_unescape(str1: &str) -> String {
+    if !LEGACY_STRINGS.load(std::sync::atomic::Ordering::Relaxed) {
+        return str1.to_owned();
+    }
+    str1.replace("\\b", " ")
+        .replace("\\n", "\n")
+        .replace("\\[", "(")
+        .replace("\\]", ")")
+        .replace("\\'", "\"")
+}
 
-    Ok(Expression::String(
-        meta.replace("\\b", " ")
-            .replace("\\n", "\n")
-            .replace("\\[", "(")
-            .replace("\\]", ")")
-            .replace("\\'", "\""),
-    ))
+pub fn func_str(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let meta = take_arg!("str", args.into_iter(), String);
+
+    Ok(Expression::String(legacy_unescape(&meta)))
 }
 
-pub fn func_str_plus(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("str.+", args, 2);
-    args_len_max!("str.+", args, 2);
-    let str1 = check_type_onlyone!("str.+", &args[0], env, String, config.clone())?;
-    let str2 = check_type_onlyone!("str.+", &args[1], env, String, config)?;
+pub fn func_str_plus(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let str1 = take_arg!("str.+", args, String);
+    let str2 = take_arg!("str.+", args, String);
+    check_size("str.+", str1.len() + str2.len())?;
+    allocate(str1.len() + str2.len())?;
 
     Ok(Expression::String(str1 + &str2))
 }
 
-pub fn func_lines(
-    args: &[Expression],
-    env: &mut Environment,
-    config: Config,
-) -> Result<Expression, GError> {
-    args_len_min!("lines", args, 1);
-    args_len_max!("lines", args, 1);
-    let str1 = check_type_onlyone!("lines", &args[0], env, String, config)?;
+pub fn func_lines(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let str1 = take_arg!("lines", args.into_iter(), String);
+    allocate_list(str1.lines().count())?;
+    allocate(str1.len())?;
 
     Ok(Expression::List(
         str1.lines()
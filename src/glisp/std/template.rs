@@ -0,0 +1,30 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! 模板由 glisp::template 解析和渲染，支持的语法见它的注释
+
+use super::macros::*;
+use super::*;
+use crate::glisp::template::Template;
+
+/// (render name) 或 (render name vars) ，以 Map vars 中的变量渲染已经被 `template` 指令加载的模板
+pub fn func_render(args: Vec<Expression>, _config: &Config) -> Result<Expression, GError> {
+    let mut args = args.into_iter();
+    let name = take_arg!("render", args, String);
+    let vars = match args.next() {
+        Some(Expression::Map(a)) => a,
+        _ => Default::default(),
+    };
+    let Some(template) = Template::get(&name) else {
+        return Err(GError::new(format!(
+            "render: template {} is not loaded",
+            name
+        )));
+    };
+    Ok(Expression::String(template.render(vars)?))
+}
@@ -0,0 +1,808 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! HTML 模板，模板中的表达式是 Glisp 代码，例如：
+//! ```html
+//! {% extends "base.html" %}
+//! {% block content %}
+//! <h1>{{ title }}</h1>
+//! {% for item in items %}
+//!     {% if (> (length item) 0) %}<p>{{ item }}</p>{% else %}<hr />{% endif %}
+//! {% endfor %}
+//! {% include "footer.html" %}
+//! {% endblock %}
+//! ```
+//! `{{ expr }}` 输出转义之后的值，`{{{ expr }}}` 原样输出，`{# ... #}` 是注释
+//! 标签在第一个结束符处结束，所以表达式中的字符串不能包含 `}}` 或 `%}`
+//!
+//! 模板在加载配置时（ `template` 指令）就被解析，其中的表达式被编译为字节码， `include` 和 `extends` 也在这时被展开
+//! 所以渲染时不会再读取文件，修改模板之后需要重新加载配置
+//! 渲染时，变量保存在全局环境的一个子环境中，所以模板中可以使用内置函数和 prelude 中的宏
+//! 变量来自 `render` 的参数，或者被挂载的模板的变量文件
+//! 处理请求时的渲染受到 sandbox 和 budget 模块的限制
+
+use std::{
+    collections::{BTreeMap, HashMap},
+    sync::{Arc, OnceLock, RwLock},
+};
+
+use super::budget::{allocate, check_size, limit};
+use super::compiler::{compile, Chunk};
+use super::core::*;
+use super::sandbox::restrict;
+use super::std::into_elements;
+use super::vm::run;
+
+/// 一个已经被编译的模板
+pub struct Template {
+    nodes: Vec<Node>,
+}
+
+/// 被 `template` 指令挂载到一个 URL 的模板
+/// vars: 每次渲染时使用的变量，来自加载配置时求值的变量文件
+#[derive(Clone)]
+pub struct Mounted {
+    pub template: Arc<Template>,
+    pub vars: BTreeMap<String, Expression>,
+}
+
+impl Mounted {
+    /// 在处理请求时渲染，它和请求处理器一样受到限制
+    pub fn render(&self) -> Result<String, GError> {
+        collect(|| restrict(|| limit(|| self.template.render(self.vars.clone()))))
+    }
+}
+
+#[derive(Clone)]
+enum Node {
+    Text(String),
+    /// escape: 是否转义 HTML
+    Output {
+        code: Arc<Chunk>,
+        escape: bool,
+    },
+    /// 依次判断每个条件，都不成立时渲染 otherwise
+    If {
+        branches: Vec<(Arc<Chunk>, Span, Vec<Node>)>,
+        otherwise: Vec<Node>,
+    },
+    For {
+        name: String,
+        list: Arc<Chunk>,
+        span: Span,
+        body: Vec<Node>,
+    },
+}
+
+/// 模板的源代码被分割为文本和标签，注释已经被去掉
+/// source: 标签的内容， span: 它在文件中的位置
+enum Item {
+    Text(String),
+    Output {
+        source: String,
+        span: Span,
+        escape: bool,
+    },
+    Statement {
+        source: String,
+        span: Span,
+    },
+}
+
+fn registry() -> &'static RwLock<HashMap<String, Arc<Template>>> {
+    static TEMPLATES: OnceLock<RwLock<HashMap<String, Arc<Template>>>> = OnceLock::new();
+    TEMPLATES.get_or_init(Default::default)
+}
+
+fn error(kind: ErrorKind, message: String, span: &Span) -> GError {
+    GError {
+        span: Some(span.clone()),
+        ..GError::with_kind(kind, message)
+    }
+}
+
+/// 把在标签的内容中的位置转换为在文件中的位置
+fn shift(span: &mut Span, at: &Span) {
+    if span.line == 1 {
+        span.column += at.column - 1;
+    }
+    span.line += at.line - 1;
+}
+
+impl Template {
+    /// 加载并编译 `export/<name>` ，之后可以用 get 或 Glisp 的 `render` 使用它
+    pub fn load(name: &str) -> Result<Arc<Template>, GError> {
+        Template::load_from("export/", name)
+    }
+
+    /// dir: 模板和它引用的文件所在的目录
+    fn load_from(dir: &str, name: &str) -> Result<Arc<Template>, GError> {
+        let mut loader = Loader {
+            dir: dir.to_owned(),
+            stack: vec![],
+        };
+        let nodes = loader.load(name, &HashMap::new(), None)?;
+        let template = Arc::new(Template { nodes });
+        registry()
+            .write()
+            .unwrap()
+            .insert(name.to_owned(), template.clone());
+        Ok(template)
+    }
+
+    /// 获取已经被加载的模板
+    pub fn get(name: &str) -> Option<Arc<Template>> {
+        registry().read().unwrap().get(name).cloned()
+    }
+
+    /// vars: 模板中可以使用的变量
+    pub fn render(&self, vars: BTreeMap<String, Expression>) -> Result<String, GError> {
+        let env = global_env().child();
+        for (k, v) in vars {
+            env.insert(k, v);
+        }
+        let mut out = String::new();
+        let result = render_nodes(&self.nodes, &env, &mut out);
+        env.release();
+        result.map(|_| out)
+    }
+}
+
+fn render_nodes(nodes: &[Node], env: &Environment, out: &mut String) -> Result<(), GError> {
+    for node in nodes {
+        match node {
+            Node::Text(a) => {
+                allocate(a.len())?;
+                out.push_str(a)
+            }
+            Node::Output { code, escape } => {
+                let value = match run(code.clone(), env, None)? {
+                    Expression::String(a) => a,
+                    a => a.to_string(),
+                };
+                allocate(value.len())?;
+                if *escape {
+                    escape_html(out, &value);
+                } else {
+                    out.push_str(&value);
+                }
+            }
+            Node::If {
+                branches,
+                otherwise,
+            } => {
+                let mut body = otherwise;
+                for (code, span, nodes) in branches {
+                    match run(code.clone(), env, None)? {
+                        Expression::Bool(true) => {
+                            body = nodes;
+                            break;
+                        }
+                        Expression::Bool(false) => {}
+                        a => {
+                            return Err(error(
+                                ErrorKind::Type,
+                                format!("template: expected true or false, got {}", a),
+                                span,
+                            ))
+                        }
+                    }
+                }
+                render_nodes(body, env, out)?;
+            }
+            Node::For {
+                name,
+                list,
+                span,
+                body,
+            } => {
+                let Expression::List(list) = run(list.clone(), env, None)? else {
+                    return Err(error(
+                        ErrorKind::Type,
+                        "template: for needs a list".to_owned(),
+                        span,
+                    ));
+                };
+                for a in into_elements(list) {
+                    let scope = env.child();
+                    scope.insert(name.clone(), a);
+                    let result = render_nodes(body, &scope, out);
+                    scope.release();
+                    result?;
+                }
+            }
+        }
+        check_size("render", out.len())?;
+    }
+    Ok(())
+}
+
+fn escape_html(out: &mut String, str: &str) {
+    for c in str.chars() {
+        match c {
+            '&' => out.push_str("&amp;"),
+            '<' => out.push_str("&lt;"),
+            '>' => out.push_str("&gt;"),
+            '"' => out.push_str("&quot;"),
+            '\'' => out.push_str("&#39;"),
+            c => out.push(c),
+        }
+    }
+}
+
+/// 把模板的源代码分割为文本和标签
+fn split(source: &str, file: &Arc<str>) -> Result<Vec<Item>, GError> {
+    let mut items = vec![];
+    let mut rest = source;
+    let (mut line, mut column) = (1, 1);
+    // 把 rest 向前移动 len 个字节，同时更新行号和列号
+    let mut advance = |rest: &mut &str, len: usize| {
+        for c in rest[..len].chars() {
+            if c == '\n' {
+                line += 1;
+                column = 1;
+            } else {
+                column += 1;
+            }
+        }
+        *rest = &rest[len..];
+        Span {
+            file: file.clone(),
+            line,
+            column,
+        }
+    };
+    while let Some(start) = rest.find('{') {
+        let (open, close) = match &rest[start..] {
+            a if a.starts_with("{{{") => ("{{{", "}}}"),
+            a if a.starts_with("{{") => ("{{", "}}"),
+            a if a.starts_with("{%") => ("{%", "%}"),
+            a if a.starts_with("{#") => ("{#", "#}"),
+            _ => {
+                items.push(Item::Text(rest[..=start].to_owned()));
+                advance(&mut rest, start + 1);
+                continue;
+            }
+        };
+        items.push(Item::Text(rest[..start].to_owned()));
+        let tag = advance(&mut rest, start);
+        let Some(end) = rest[open.len()..].find(close).map(|a| a + open.len()) else {
+            return Err(error(
+                ErrorKind::Syntax,
+                format!("template: unclosed {}", open),
+                &tag,
+            ));
+        };
+        let source = rest[open.len()..end].to_owned();
+        let span = advance(&mut rest, open.len());
+        advance(&mut rest, end - open.len() + close.len());
+        match open {
+            "{#" => {}
+            "{%" => items.push(Item::Statement { source, span }),
+            _ => items.push(Item::Output {
+                source,
+                span,
+                escape: open == "{{",
+            }),
+        }
+    }
+    items.push(Item::Text(rest.to_owned()));
+    Ok(items)
+}
+
+/// 编译标签中的一个 Glisp 表达式， at 是它在文件中的位置
+fn expression(source: &str, at: &Span) -> Result<Arc<Chunk>, GError> {
+    let shifted = |mut e: GError| {
+        match &mut e.span {
+            Some(a) => shift(a, at),
+            None => e.span = Some(at.clone()),
+        }
+        e
+    };
+    let mut tokens = tokenize_file(source.to_owned(), &at.file).map_err(shifted)?;
+    if tokens.is_empty() {
+        return Err(error(
+            ErrorKind::Syntax,
+            "template: expected an expression".to_owned(),
+            at,
+        ));
+    }
+    tokens.iter_mut().for_each(|a| shift(&mut a.span, at));
+    let (exp, spans, rest) = parse_spanned(&tokens).map_err(|e| match e.span {
+        Some(_) => e,
+        None => shifted(e),
+    })?;
+    if let Some(a) = rest.first() {
+        return Err(error(
+            ErrorKind::Syntax,
+            format!("template: unexpected {}", a.text),
+            &a.span,
+        ));
+    }
+    Ok(compile(&exp, Some(&spans), &global_env().child()))
+}
+
+/// 分割语句标签的关键字和其余的部分，并计算其余的部分的位置
+fn keyword<'a>(source: &'a str, span: &Span) -> (&'a str, &'a str, Span) {
+    let trimmed = source.trim_start();
+    let (word, rest) = trimmed
+        .split_once(char::is_whitespace)
+        .unwrap_or((trimmed, ""));
+    let rest = rest.trim_start();
+    let offset = source.len() - rest.len();
+    let mut at = span.clone();
+    for c in source[..offset].chars() {
+        if c == '\n' {
+            at.line += 1;
+            at.column = 1;
+        } else {
+            at.column += 1;
+        }
+    }
+    (word, rest, at)
+}
+
+/// `"file"` 形式的参数
+fn file_name<'a>(word: &str, rest: &'a str, span: &Span) -> Result<&'a str, GError> {
+    rest.trim()
+        .strip_prefix('"')
+        .and_then(|a| a.strip_suffix('"'))
+        .filter(|a| !a.is_empty())
+        .ok_or_else(|| {
+            error(
+                ErrorKind::Syntax,
+                format!("template: {} needs a file name in quotes", word),
+                span,
+            )
+        })
+}
+
+/// dir: 模板所在的目录
+/// stack: 正在被加载的模板，用于检测循环的 `include` 和 `extends`
+struct Loader {
+    dir: String,
+    stack: Vec<String>,
+}
+
+/// 解析一个模板时的状态
+/// overrides: 继承自己的模板中定义的块，它们替换自身中同名的块
+/// blocks: 自身中定义的块
+struct Parser<'a> {
+    items: std::vec::IntoIter<Item>,
+    overrides: &'a HashMap<String, Vec<Node>>,
+    blocks: HashMap<String, Vec<Node>>,
+}
+
+impl Loader {
+    /// at: `include` 或 `extends` 标签的位置
+    fn load(
+        &mut self,
+        name: &str,
+        overrides: &HashMap<String, Vec<Node>>,
+        at: Option<&Span>,
+    ) -> Result<Vec<Node>, GError> {
+        let path = self.dir.clone() + name;
+        let at_error = |message: String| match at {
+            Some(a) => error(ErrorKind::Runtime, message, a),
+            None => GError::new(message),
+        };
+        if self.stack.iter().any(|a| a == name) {
+            return Err(at_error(format!(
+                "template: {} includes or extends itself",
+                name
+            )));
+        }
+        let source = std::fs::read_to_string(&path)
+            .map_err(|_| at_error(format!("template: cannot read {}", path)))?;
+        let items = split(&source, &path.as_str().into())?;
+
+        self.stack.push(name.to_owned());
+        let result = self.parse(items, overrides);
+        self.stack.pop();
+        result
+    }
+
+    fn parse(
+        &mut self,
+        items: Vec<Item>,
+        overrides: &HashMap<String, Vec<Node>>,
+    ) -> Result<Vec<Node>, GError> {
+        // `extends` 必须是第一个标签
+        let first = items.iter().find(|a| match a {
+            Item::Text(a) => !a.trim().is_empty(),
+            _ => true,
+        });
+        let parent = match first {
+            Some(Item::Statement { source, span }) => match keyword(source, span) {
+                ("extends", rest, _) => Some((file_name("extends", rest, span)?.to_owned(), span)),
+                _ => None,
+            },
+            _ => None,
+        }
+        .map(|(a, span)| (a, span.clone()));
+
+        let mut parser = Parser {
+            items: items.into_iter(),
+            overrides,
+            blocks: HashMap::new(),
+        };
+        if parent.is_some() {
+            parser.items.find(|a| matches!(a, Item::Statement { .. }));
+        }
+        let (nodes, end) = parser.nodes(self, &[])?;
+        if let Some(end) = end {
+            return Err(error(
+                ErrorKind::Syntax,
+                format!("template: unexpected {}", end.word),
+                &end.span,
+            ));
+        }
+        match parent {
+            // 只有块会被保留，继承的模板中的其它内容被忽略
+            Some((parent, span)) => {
+                let mut blocks = parser.blocks;
+                blocks.extend(overrides.iter().map(|(k, v)| (k.clone(), v.clone())));
+                self.load(&parent, &blocks, Some(&span))
+            }
+            None => Ok(nodes),
+        }
+    }
+}
+
+/// 结束一段节点的语句标签
+/// rest: 关键字之后的部分， at: rest 的位置， span: 标签的位置
+struct End {
+    word: String,
+    rest: String,
+    at: Span,
+    span: Span,
+}
+
+impl Parser<'_> {
+    /// 解析节点，直到遇到 ends 中的一个关键字或者模板的结尾
+    fn nodes(
+        &mut self,
+        loader: &mut Loader,
+        ends: &[&str],
+    ) -> Result<(Vec<Node>, Option<End>), GError> {
+        let mut nodes = vec![];
+        while let Some(item) = self.items.next() {
+            let (source, span) = match item {
+                Item::Text(a) => {
+                    match nodes.last_mut() {
+                        Some(Node::Text(last)) => last.push_str(&a),
+                        _ if a.is_empty() => {}
+                        _ => nodes.push(Node::Text(a)),
+                    }
+                    continue;
+                }
+                Item::Output {
+                    source,
+                    span,
+                    escape,
+                } => {
+                    let code = expression(&source, &span)?;
+                    nodes.push(Node::Output { code, escape });
+                    continue;
+                }
+                Item::Statement { source, span } => (source, span),
+            };
+            let (word, rest, at) = keyword(&source, &span);
+            if ends.contains(&word) {
+                let end = End {
+                    word: word.to_owned(),
+                    rest: rest.to_owned(),
+                    at,
+                    span,
+                };
+                return Ok((nodes, Some(end)));
+            }
+            match word {
+                "if" => nodes.push(self.if_node(loader, rest, at, &span)?),
+                "for" => nodes.push(self.for_node(loader, rest, &at, &span)?),
+                "include" => {
+                    let name = file_name(word, rest, &span)?;
+                    nodes.extend(loader.load(name, &HashMap::new(), Some(&span))?);
+                }
+                "block" => nodes.extend(self.block(loader, rest, &span)?),
+                _ => {
+                    return Err(error(
+                        ErrorKind::Syntax,
+                        format!("template: unexpected {}", word),
+                        &span,
+                    ))
+                }
+            }
+        }
+        Ok((nodes, None))
+    }
+
+    /// 解析节点，直到遇到 ends 中的一个关键字，没有遇到时报错
+    fn body(
+        &mut self,
+        loader: &mut Loader,
+        ends: &[&str],
+        start: &Span,
+    ) -> Result<(Vec<Node>, End), GError> {
+        match self.nodes(loader, ends)? {
+            (nodes, Some(end)) => Ok((nodes, end)),
+            (_, None) => Err(error(
+                ErrorKind::Syntax,
+                format!("template: missing {}", ends[ends.len() - 1]),
+                start,
+            )),
+        }
+    }
+
+    fn if_node(
+        &mut self,
+        loader: &mut Loader,
+        condition: &str,
+        at: Span,
+        span: &Span,
+    ) -> Result<Node, GError> {
+        let mut branches = vec![];
+        let (mut condition, mut at) = (condition.to_owned(), at);
+        loop {
+            let code = expression(&condition, &at)?;
+            let (nodes, end) = self.body(loader, &["elif", "else", "endif"], span)?;
+            branches.push((code, at, nodes));
+            let otherwise = match end.word.as_str() {
+                "elif" => {
+                    (condition, at) = (end.rest, end.at);
+                    continue;
+                }
+                "else" => self.body(loader, &["endif"], span)?.0,
+                _ => vec![],
+            };
+            return Ok(Node::If {
+                branches,
+                otherwise,
+            });
+        }
+    }
+
+    fn for_node(
+        &mut self,
+        loader: &mut Loader,
+        rest: &str,
+        at: &Span,
+        span: &Span,
+    ) -> Result<Node, GError> {
+        let (name, rest, at) = keyword(rest, at);
+        let (word, rest, at) = keyword(rest, &at);
+        if name.is_empty() || word != "in" {
+            return Err(error(
+                ErrorKind::Syntax,
+                "template: for needs a name, in and a list".to_owned(),
+                span,
+            ));
+        }
+        let list = expression(rest, &at)?;
+        let (body, _) = self.body(loader, &["endfor"], span)?;
+        Ok(Node::For {
+            name: name.to_owned(),
+            list,
+            span: span.clone(),
+            body,
+        })
+    }
+
+    fn block(&mut self, loader: &mut Loader, rest: &str, span: &Span) -> Result<Vec<Node>, GError> {
+        let name = rest.trim();
+        if name.is_empty() || name.contains(char::is_whitespace) {
+            return Err(error(
+                ErrorKind::Syntax,
+                "template: block needs a name".to_owned(),
+                span,
+            ));
+        }
+        let (nodes, _) = self.body(loader, &["endblock"], span)?;
+        if self.blocks.insert(name.to_owned(), nodes.clone()).is_some() {
+            return Err(error(
+                ErrorKind::Syntax,
+                format!("template: block {} is defined twice", name),
+                span,
+            ));
+        }
+        Ok(self.overrides.get(name).cloned().unwrap_or(nodes))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// 在临时目录中写入模板，返回它们所在的目录
+    fn write_templates(name: &str, files: &[(&str, &str)]) -> String {
+        let dir =
+            std::env::temp_dir().join(format!("ttweb-template-{}-{}", name, std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        for (file, source) in files {
+            std::fs::write(dir.join(file), source).unwrap();
+        }
+        dir.to_str().unwrap().to_owned() + "/"
+    }
+
+    fn vars(source: &str) -> BTreeMap<String, Expression> {
+        match parse_eval(source.to_owned(), &default_env(), None) {
+            Ok(Expression::Map(a)) => a,
+            _ => panic!(),
+        }
+    }
+
+    fn render(dir: &str, name: &str, vars: BTreeMap<String, Expression>) -> String {
+        match Template::load_from(dir, name).and_then(|a| a.render(vars)) {
+            Ok(a) => a,
+            Err(e) => match e.span {
+                Some(span) => format!("{} at {}:{}", e.message, span.line, span.column),
+                None => e.message,
+            },
+        }
+    }
+
+    #[test]
+    fn test_template() {
+        let dir = write_templates(
+            "render",
+            &[
+                (
+                    "page.html",
+                    "<h1>{{ title }}</h1>{# 注释 #}\n\
+                     {% for item in items %}<li>{{ (str.+ item \"!\") }}</li>{% endfor %}\n\
+                     {% if (> n 1) %}many{% elif (= n 1) %}one{% else %}none{% endif %}\n\
+                     {{{ raw }}}{% include \"footer.html\" %}",
+                ),
+                ("footer.html", "<footer>{{ n }}</footer>"),
+            ],
+        );
+        assert_eq!(
+            render(
+                &dir,
+                "page.html",
+                vars("(map.new \"title\" \"<a & 'b'>\" \"items\" (list \"x\" \"y\") \"n\" 1 \"raw\" \"<br />\")")
+            ),
+            "<h1>&lt;a &amp; &#39;b&#39;&gt;</h1>\n\
+             <li>x!</li><li>y!</li>\n\
+             one\n\
+             <br /><footer>1</footer>"
+        );
+        assert_eq!(
+            render(
+                &dir,
+                "page.html",
+                vars("(map.new \"title\" \"\" \"items\" (list) \"n\" 0 \"raw\" \"\")")
+            ),
+            "<h1></h1>\n\nnone\n<footer>0</footer>"
+        );
+    }
+
+    #[test]
+    fn test_template_extends() {
+        let dir = write_templates(
+            "extends",
+            &[
+                (
+                    "base.html",
+                    "<title>{% block title %}Site{% endblock %}</title>\
+                     {% block body %}<main>{% block content %}{% endblock %}</main>{% endblock %}",
+                ),
+                (
+                    "layout.html",
+                    "{% extends \"base.html\" %}{% block content %}layout{% endblock %}",
+                ),
+                (
+                    "page.html",
+                    "\n{% extends \"layout.html\" %}\n\
+                     被忽略的内容\n\
+                     {% block title %}{{ title }}{% endblock %}\n\
+                     {% block content %}page{% endblock %}",
+                ),
+            ],
+        );
+        assert_eq!(
+            render(&dir, "layout.html", BTreeMap::new()),
+            "<title>Site</title><main>layout</main>"
+        );
+        // 继承链中最后的模板中的块优先
+        assert_eq!(
+            render(&dir, "page.html", vars("(map.new \"title\" \"a\")")),
+            "<title>a</title><main>page</main>"
+        );
+    }
+
+    #[test]
+    fn test_template_errors() {
+        let dir = write_templates(
+            "errors",
+            &[
+                ("unclosed.html", "a\n  {{ x"),
+                (
+                    "missing.html",
+                    "{% if true %}\n{% for a in (list 1) %}{% endif %}",
+                ),
+                ("unknown.html", "a\n{% while true %}"),
+                ("extra.html", "{{ a b }}"),
+                ("self.html", "{% include \"self.html\" %}"),
+                ("not-found.html", "\n{% include \"nope.html\" %}"),
+                ("late.html", "a{% extends \"self.html\" %}"),
+                (
+                    "twice.html",
+                    "{% block a %}{% endblock %}{% block a %}{% endblock %}",
+                ),
+                ("runtime.html", "a\n  b {{ (+ 1 (str.+ x 1)) }}"),
+                ("condition.html", "{% if\n  x %}{% endif %}"),
+                ("list.html", "{% for a in x %}{% endfor %}"),
+            ],
+        );
+        let x = || vars("(map.new \"x\" \"s\")");
+        assert_eq!(
+            render(&dir, "unclosed.html", x()),
+            "template: unclosed {{ at 2:3"
+        );
+        assert_eq!(
+            render(&dir, "missing.html", x()),
+            "template: unexpected endif at 2:26"
+        );
+        assert_eq!(
+            render(&dir, "unknown.html", x()),
+            "template: unexpected while at 2:3"
+        );
+        assert_eq!(
+            render(&dir, "extra.html", x()),
+            "template: unexpected b at 1:6"
+        );
+        assert_eq!(
+            render(&dir, "self.html", x()),
+            "template: self.html includes or extends itself at 1:3"
+        );
+        assert_eq!(
+            render(&dir, "not-found.html", x()),
+            format!("template: cannot read {}nope.html at 2:3", dir)
+        );
+        assert_eq!(
+            render(&dir, "late.html", x()),
+            "template: unexpected extends at 1:4"
+        );
+        assert_eq!(
+            render(&dir, "twice.html", x()),
+            "template: block a is defined twice at 1:30"
+        );
+        assert_eq!(
+            render(&dir, "runtime.html", x()),
+            "str.+: Unsupported type at 2:13"
+        );
+        assert_eq!(
+            render(&dir, "condition.html", x()),
+            "template: expected true or false, got \"s\" at 2:3"
+        );
+        assert_eq!(
+            render(&dir, "list.html", x()),
+            "template: for needs a list at 1:3"
+        );
+    }
+
+    #[test]
+    fn test_render() {
+        let dir = write_templates("builtin", &[("hello.html", "Hello, {{ name }}!")]);
+        Template::load_from(&dir, "hello.html").ok().unwrap();
+        let run = |source: &str| match parse_eval(source.to_owned(), &default_env(), None) {
+            Ok(a) => a.to_string(),
+            Err(e) => e.message,
+        };
+        assert_eq!(
+            run("(render \"hello.html\" (map.new \"name\" \"<Glisp>\"))"),
+            "\"Hello, &lt;Glisp&gt;!\""
+        );
+        assert_eq!(
+            run("(render \"nope.html\")"),
+            "render: template nope.html is not loaded"
+        );
+    }
+}
@@ -0,0 +1,650 @@
+/* Tiny Tiny Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 执行 compiler 模块生成的字节码的栈式虚拟机
+//!
+//! ## 作用域
+//! Ghost Lisp 是词法作用域的：Lambda 在被创建时捕获当前的环境，每次调用它都会创建一个新的作用域，它的外部环境是被捕获的环境
+//! 顶层代码的作用域就是传入的 Environment
+//! 查找变量的顺序是：槽位、按名字保存的变量，然后是外部环境
+//!
+//! ## 尾调用
+//! 尾调用会替换当前调用帧的作用域，而不是在它之上创建一个新的调用帧，所以尾递归只使用固定大小的内存
+//! 调用帧保存在堆上，而不是 Rust 的调用栈上；它的个数超过 MAX_DEPTH 时报错，所以无限的非尾递归不会耗尽内存
+//! 只有内置函数和宏调用 Lambda 时才会嵌套虚拟机，嵌套的个数受到 MAX_NESTED_VMS 的限制
+//!
+//! ## 错误
+//! 出错时，每个调用帧的 pc 指向它正在执行的指令的下一条，这些指令的位置组成了错误的位置和调用栈
+//! 被尾调用替换的调用帧不会出现在调用栈中
+//! 在 `try` 之中发生的错误不会结束执行，而是回退到 `try` 所在的调用帧，把错误对象压栈并跳转到 `catch`
+
+use std::{
+    cell::Cell,
+    sync::{atomic::Ordering, Arc},
+};
+
+use super::budget::{allocate_list, check_size, step};
+use super::compiler::*;
+use super::core::*;
+use super::std::BUILT_INS;
+
+/// owns_scope: 为 false 时，调用帧是顶层代码或 `eval` 创建的，它的作用域不属于它自己
+/// base: 调用帧开始时栈的高度，返回时栈被恢复到这个高度
+struct Frame {
+    chunk: Arc<Chunk>,
+    pc: usize,
+    scope: Environment,
+    owns_scope: bool,
+    base: usize,
+}
+
+/// 一个正在执行的 `try` ，记录了开始时调用帧的个数、栈的高度和 Mask 的个数
+/// pc: catch 的位置
+struct Catch {
+    frames: usize,
+    stack: usize,
+    masks: usize,
+    pc: usize,
+}
+
+struct Vm<'c, 'g> {
+    config: &'c Config<'g>,
+    stack: Vec<Expression>,
+    frames: Vec<Frame>,
+    masks: Vec<Mask>,
+    catches: Vec<Catch>,
+}
+
+/// 在 env 中执行顶层代码
+pub fn run(chunk: Arc<Chunk>, env: &Environment, config: Config) -> Result<Expression, GError> {
+    let mut vm = Vm::new(&config);
+    vm.frames.push(Frame {
+        chunk,
+        pc: 0,
+        scope: env.clone(),
+        owns_scope: false,
+        base: 0,
+    });
+    vm.execute()
+}
+
+/// 内置函数（例如 `map` ）和宏通过 call_lambda 调用 Lambda 时，会在 Rust 的调用栈上创建一个嵌套的虚拟机
+/// 嵌套的虚拟机的个数受到这个限制，所以在 Lambda 中递归地调用 `map` 等函数不会耗尽调用栈
+pub const MAX_NESTED_VMS: usize = 64;
+
+thread_local! {
+    static NESTED_VMS: Cell<usize> = const { Cell::new(0) };
+}
+
+/// 以已经被求值的参数调用一个 Lambda ，它的外部环境是它捕获的环境
+pub fn call_lambda(
+    lambda: &Lambda,
+    args: Vec<Expression>,
+    config: Config,
+) -> Result<Expression, GError> {
+    struct Leave;
+    impl Drop for Leave {
+        fn drop(&mut self) {
+            NESTED_VMS.set(NESTED_VMS.get() - 1);
+        }
+    }
+
+    check_call(&Expression::Lambda(lambda.clone()), args.len())?;
+    if NESTED_VMS.get() >= MAX_NESTED_VMS {
+        return Err(GError::new(format!(
+            "calls through built-in functions are nested more than {} levels deep",
+            MAX_NESTED_VMS
+        )));
+    }
+    NESTED_VMS.set(NESTED_VMS.get() + 1);
+    let _leave = Leave;
+    let mut vm = Vm::new(&config);
+    vm.frames.push(Frame {
+        chunk: lambda.code.clone(),
+        pc: 0,
+        scope: new_scope(lambda, args),
+        owns_scope: true,
+        base: 0,
+    });
+    vm.execute()
+}
+
+/// 检查 head 是否可以以 argc 个参数被调用
+fn check_call(head: &Expression, argc: usize) -> Result<(), GError> {
+    match head {
+        Expression::Func(_) => Ok(()),
+        Expression::Lambda(lambda) => match &lambda.code.params {
+            Ok(params) if params.len() != argc => Err(GError::with_kind(
+                ErrorKind::Arity,
+                format!("expected {} params, got {}", params.len(), argc),
+            )),
+            Ok(_) => Ok(()),
+            Err(msg) => Err(GError::new(msg.clone())),
+        },
+        _ => Err(GError::new("first form must be a function".to_string())),
+    }
+}
+
+/// 为 Lambda 的一次调用创建作用域
+fn new_scope(lambda: &Lambda, args: Vec<Expression>) -> Environment {
+    let mut slots = vec![None; lambda.code.slots.len()];
+    if let Ok(params) = &lambda.code.params {
+        for (slot, arg) in params.iter().zip(args) {
+            slots[*slot as usize] = Some(arg);
+        }
+    }
+    Environment::new(Scope {
+        data: Default::default(),
+        slots,
+        macros: Default::default(),
+        chunk: Some(lambda.code.clone()),
+        outer: lambda.env.clone(),
+    })
+}
+
+fn constant_message(chunk: &Chunk, index: u32) -> GError {
+    match &chunk.constants[index as usize] {
+        Expression::String(a) => GError::new(a.clone()),
+        a => GError::new(a.to_string()),
+    }
+}
+
+fn unexpected_symbol(name: &str) -> GError {
+    GError::with_kind(ErrorKind::Name, format!("unexpected symbol k={}", name))
+}
+
+impl<'c, 'g> Vm<'c, 'g> {
+    fn new(config: &'c Config<'g>) -> Self {
+        Vm {
+            config,
+            stack: vec![],
+            frames: vec![],
+            masks: vec![],
+            catches: vec![],
+        }
+    }
+
+    fn execute(&mut self) -> Result<Expression, GError> {
+        let result = self
+            .execute_frames()
+            .map_err(|e| self.mask(self.locate(e), 0));
+        for frame in self.frames.drain(..) {
+            if frame.owns_scope {
+                frame.scope.release();
+            }
+        }
+        result
+    }
+
+    /// 在 Mask 之中发生的错误被替换为最外层的 Mask 的信息，只考虑第 from 个之后的 Mask
+    fn mask(&self, e: GError, from: usize) -> GError {
+        match self.masks.get(from) {
+            Some(mask) => GError {
+                kind: match mask {
+                    Mask::Type(_) => ErrorKind::Type,
+                    Mask::Cond => e.kind,
+                },
+                message: mask.message(),
+                ..e
+            },
+            None => e,
+        }
+    }
+
+    /// 从内到外，每个调用帧正在执行的指令的位置被加入错误的调用栈
+    /// 错误还没有位置时（它不是由嵌套的虚拟机或解析器报告的），最内层的位置就是它的位置
+    fn locate(&self, mut e: GError) -> GError {
+        let mut spans = self
+            .frames
+            .iter()
+            .rev()
+            .filter_map(|a| a.chunk.spans.get(a.pc.checked_sub(1)?).cloned().flatten());
+        if e.span.is_none() {
+            e.span = spans.next();
+        }
+        e.trace.extend(spans);
+        e
+    }
+
+    /// 在创建新的调用帧之前检查调用帧的个数
+    fn check_depth(&self) -> Result<(), GError> {
+        let max = MAX_DEPTH.load(Ordering::Relaxed);
+        if self.frames.len() >= max {
+            return Err(GError::new(format!(
+                "maximum evaluation depth {} exceeded",
+                max
+            )));
+        }
+        Ok(())
+    }
+
+    fn scope(&self) -> &Environment {
+        &self.frames.last().unwrap().scope
+    }
+
+    fn pop(&mut self) -> Expression {
+        self.stack.pop().unwrap_or(Expression::Bool(false))
+    }
+
+    /// 出错时把 pc 保存到当前的调用帧，用于报告出错的位置
+    /// 有正在执行的 `try` 时，回退到它所在的调用帧并继续执行它的 catch
+    fn execute_frames(&mut self) -> Result<Expression, GError> {
+        let frame = self.frames.last().unwrap();
+        let mut chunk = frame.chunk.clone();
+        let mut pc = frame.pc;
+        loop {
+            let e = match self.execute_ops(&mut chunk, &mut pc) {
+                Ok(a) => return Ok(a),
+                Err(e) => e,
+            };
+            if let Some(frame) = self.frames.last_mut() {
+                frame.pc = pc;
+            }
+            let Some(catch) = self.catches.pop() else {
+                return Err(e);
+            };
+            let e = self.mask(self.locate(e), catch.masks);
+            while self.frames.len() > catch.frames {
+                let frame = self.frames.pop().unwrap();
+                if frame.owns_scope {
+                    frame.scope.release();
+                }
+            }
+            self.stack.truncate(catch.stack);
+            self.masks.truncate(catch.masks);
+            self.stack.push(e.to_expression());
+            chunk = self.frames.last().unwrap().chunk.clone();
+            pc = catch.pc;
+        }
+    }
+
+    fn execute_ops(
+        &mut self,
+        chunk: &mut Arc<Chunk>,
+        pc: &mut usize,
+    ) -> Result<Expression, GError> {
+        loop {
+            step()?;
+            let op = chunk.ops[*pc];
+            *pc += 1;
+            match op {
+                Op::Const(i) => self.stack.push(chunk.constants[i as usize].clone()),
+                Op::Closure(i) => {
+                    let Expression::Lambda(mut lambda) = chunk.constants[i as usize].clone() else {
+                        return Err(constant_message(chunk, i));
+                    };
+                    lambda.env = Some(self.scope().clone());
+                    self.stack.push(Expression::Lambda(lambda));
+                }
+                Op::GetSlot(i) => {
+                    let outer = {
+                        let scope = self.scope().read();
+                        let name = &chunk.slots[i as usize];
+                        match scope.slots[i as usize].as_ref().or(scope.data.get(name)) {
+                            Some(value) => Ok(value.clone()),
+                            // 槽位还没有被设置，使用外部的同名变量
+                            None => Err(scope.outer.clone()),
+                        }
+                    };
+                    let value = match outer {
+                        Ok(value) => value,
+                        Err(outer) => {
+                            let name = &chunk.slots[i as usize];
+                            outer
+                                .and_then(|a| a.get(name))
+                                .ok_or_else(|| unexpected_symbol(name))?
+                        }
+                    };
+                    self.stack.push(value);
+                }
+                Op::GetName(i) => {
+                    let name = &chunk.names[i as usize];
+                    let value = self
+                        .scope()
+                        .get(name)
+                        .ok_or_else(|| unexpected_symbol(name))?;
+                    self.stack.push(value);
+                }
+                Op::SetSlot(i) => {
+                    let value = self.pop();
+                    self.scope().write().slots[i as usize] = Some(value);
+                }
+                Op::SetName(i) => {
+                    let value = self.pop();
+                    self.scope().insert(chunk.names[i as usize].clone(), value);
+                }
+                Op::Assign(i) => {
+                    let value = self.pop();
+                    let name = &chunk.names[i as usize];
+                    if !self.scope().assign(name, value) {
+                        return Err(GError::new(format!("set!: unexpected symbol k={}", name)));
+                    }
+                }
+                Op::Pop => {
+                    self.pop();
+                }
+                Op::Jump(target) => *pc = target as usize,
+                Op::If { otherwise, message } => match self.pop() {
+                    Expression::Bool(true) => {}
+                    Expression::Bool(false) => *pc = otherwise as usize,
+                    _ => return Err(constant_message(chunk, message)),
+                },
+                Op::JumpIfFalse(target) => {
+                    if let Some(Expression::Bool(false)) = self.stack.last() {
+                        *pc = target as usize;
+                    } else {
+                        self.pop();
+                    }
+                }
+                Op::CondTest(target) => {
+                    if self.pop() != Expression::Bool(true) {
+                        *pc = target as usize;
+                    }
+                }
+                Op::LoopCheck { start, end } => {
+                    if let Expression::Symbol(a) = self.pop() {
+                        match a.as_ref() {
+                            "return" => *pc = end as usize,
+                            "continue" => *pc = start as usize,
+                            _ => {}
+                        }
+                    }
+                }
+                Op::ForEachNext { end } => {
+                    let len = self.stack.len();
+                    let next = match &self.stack[len - 2..] {
+                        [Expression::List(list), Expression::Int(i)] => {
+                            list.get(*i as usize).map(|a| a.to_string())
+                        }
+                        _ => None,
+                    };
+                    match next {
+                        Some(a) => {
+                            if let Expression::Int(i) = &mut self.stack[len - 1] {
+                                *i += 1;
+                            }
+                            self.stack.push(Expression::String(a));
+                        }
+                        None => {
+                            self.stack.truncate(len - 2);
+                            *pc = end as usize;
+                        }
+                    }
+                }
+                Op::Mask(mask) => self.masks.push(mask),
+                Op::Unmask(arg_type) => {
+                    let mask = self.masks.pop();
+                    match (self.stack.last(), mask) {
+                        (Some(a), Some(mask)) if !arg_type.check(a) => {
+                            // 让 execute 报告最外层的 Mask
+                            self.masks.push(mask);
+                            return Err(GError::new(mask.message()));
+                        }
+                        _ => {}
+                    }
+                }
+                Op::BuiltIn { index, argc } => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    let value = (BUILT_INS[index as usize].func)(args, self.config)?;
+                    self.stack.push(value);
+                }
+                Op::PrepareCall(argc) => check_call(self.stack.last().unwrap(), argc as usize)?,
+                Op::Call(argc) | Op::TailCall(argc) => {
+                    let args = self.stack.split_off(self.stack.len() - argc as usize);
+                    let lambda = match self.pop() {
+                        Expression::Func(f) => {
+                            let value = f(&args)?;
+                            self.stack.push(value);
+                            continue;
+                        }
+                        Expression::Lambda(lambda) => lambda,
+                        _ => return Err(GError::new("first form must be a function".to_string())),
+                    };
+                    let scope = new_scope(&lambda, args);
+                    if let Op::TailCall(_) = op {
+                        // 用新的作用域替换当前的作用域
+                        let frame = self.frames.last_mut().unwrap();
+                        self.stack.truncate(frame.base);
+                        frame.chunk = lambda.code.clone();
+                        std::mem::replace(&mut frame.scope, scope).release();
+                    } else {
+                        self.frames.last_mut().unwrap().pc = *pc;
+                        self.check_depth()?;
+                        self.frames.push(Frame {
+                            chunk: lambda.code.clone(),
+                            pc: 0,
+                            scope,
+                            owns_scope: true,
+                            base: self.stack.len(),
+                        });
+                    }
+                    *chunk = lambda.code;
+                    *pc = 0;
+                }
+                Op::EvalCode => {
+                    let Expression::String(code) = self.pop() else {
+                        return Err(GError::new("eval: Unsupported type".to_owned()));
+                    };
+                    let (parsed_exp, _) = parse(&tokenize(code)?)?;
+                    let scope = self.scope().clone();
+                    let scope_chunk = scope.read().chunk.clone();
+                    self.check_depth()?;
+                    self.frames.last_mut().unwrap().pc = *pc;
+                    *chunk = match scope_chunk {
+                        Some(a) => compile_in_scope(&parsed_exp, &a, &scope),
+                        None => compile(&parsed_exp, None, &scope),
+                    };
+                    *pc = 0;
+                    self.frames.push(Frame {
+                        chunk: chunk.clone(),
+                        pc: *pc,
+                        scope,
+                        owns_scope: false,
+                        base: self.stack.len(),
+                    });
+                }
+                Op::EvalAtom => {
+                    let Expression::String(atom) = self.pop() else {
+                        return Err(GError::new("eval-atom: Unsupported type".to_owned()));
+                    };
+                    let value = match parse_atom(&atom) {
+                        Expression::Symbol(name) => self
+                            .scope()
+                            .get(&name)
+                            .ok_or_else(|| unexpected_symbol(&name))?,
+                        a => a,
+                    };
+                    self.stack.push(value);
+                }
+                Op::Segment => {
+                    let value = self.pop();
+                    self.stack.push(Expression::List(vec![value]));
+                }
+                Op::Splice => match self.pop() {
+                    Expression::List(mut list) => {
+                        if let Some(Expression::Symbol(a)) = list.first() {
+                            if a == "quote" {
+                                list.remove(0);
+                            }
+                        }
+                        self.stack.push(Expression::List(list));
+                    }
+                    _ => return Err(GError::new("unquote-splicing: Unsupported type".to_owned())),
+                },
+                Op::Concat(count) => {
+                    let lists = self.stack.split_off(self.stack.len() - count as usize);
+                    let mut list = vec![];
+                    for a in lists {
+                        if let Expression::List(a) = a {
+                            check_size("quasiquote", list.len() + a.len())?;
+                            allocate_list(a.len())?;
+                            list.extend(a);
+                        }
+                    }
+                    self.stack.push(Expression::List(list));
+                }
+                Op::Try(handler) => self.catches.push(Catch {
+                    frames: self.frames.len(),
+                    stack: self.stack.len(),
+                    masks: self.masks.len(),
+                    pc: handler as usize,
+                }),
+                Op::EndTry(end) => {
+                    self.catches.pop();
+                    *pc = end as usize;
+                }
+                Op::Fail { kind, message } => {
+                    let e = constant_message(chunk, message);
+                    return Err(GError::with_kind(kind, e.message));
+                }
+                Op::Return => {
+                    let value = self.pop();
+                    let frame = self.frames.pop().unwrap();
+                    self.stack.truncate(frame.base);
+                    if frame.owns_scope {
+                        frame.scope.release();
+                    }
+                    let Some(frame) = self.frames.last() else {
+                        return Ok(value);
+                    };
+                    self.stack.push(value);
+                    *chunk = frame.chunk.clone();
+                    *pc = frame.pc;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn run_str(source: &str) -> Expression {
+        match parse_eval(source.to_owned(), &default_env(), None) {
+            Ok(a) => a,
+            Err(e) => panic!("{}", e.diagnostic()),
+        }
+    }
+
+    #[test]
+    fn test_tail_call() {
+        // 尾递归不会增加调用栈的深度
+        assert!(
+            run_str(
+                "(do (set count (lambda (n acc) (if (= n 0) acc (count (- n 1) (+ acc 1))))) (count 100000 0))"
+            ) == Expression::Int(100000)
+        );
+        // 相互递归的尾调用
+        assert!(
+            run_str(
+                "(do (set even (lambda (n) (if (= n 0) true (odd (- n 1)))))
+                     (set odd (lambda (n) (if (= n 0) false (even (- n 1)))))
+                     (even 10001))"
+            ) == Expression::Bool(false)
+        );
+        // 非尾递归的调用帧保存在堆上，而不是 Rust 的调用栈上
+        assert!(
+            run_str("(do (set sum (lambda (n) (if (= n 0) 0 (+ n (sum (- n 1)))))) (sum 1000))")
+                == Expression::Int(500500)
+        );
+    }
+
+    #[test]
+    fn test_max_depth() {
+        // cond 和 do 的最后一个表达式也处于尾部位置
+        assert!(
+            run_str(
+                "(do (set count (lambda (n) (cond (= n 0) \"done\" true (do (pass) (count (- n 1)))))) (count 100000))"
+            ) == Expression::String("done".to_owned())
+        );
+        let max = MAX_DEPTH.load(Ordering::Relaxed);
+        let Err(e) = parse_eval(
+            "(do (set f (lambda (n) (+ 1 (f n)))) (f 0))".to_owned(),
+            &default_env(),
+            None,
+        ) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Runtime);
+        assert_eq!(
+            e.message,
+            format!("maximum evaluation depth {} exceeded", max)
+        );
+        // 调用栈中只有被尾调用替换之后剩下的调用帧
+        assert_eq!(e.trace.len(), max - 1);
+        // 超过深度的错误可以被 try 捕获，此时调用帧已经被回退
+        assert!(
+            run_str(
+                "(do (set f (lambda (n) (+ 1 (f n)))) (try (f 0) (catch e (map.get e \"kind\"))))"
+            ) == Expression::String("runtime".to_owned())
+        );
+        // eval 也会创建调用帧
+        assert!(parse_eval(
+            "(do (set g \"(eval g)\") (eval g))".to_owned(),
+            &default_env(),
+            None
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_call_lambda() {
+        let Expression::Lambda(lambda) = run_str("(lambda (a b) (str.+ a b))") else {
+            panic!()
+        };
+        let args = vec![
+            Expression::String("a".to_owned()),
+            Expression::String("b".to_owned()),
+        ];
+        assert!(call_lambda(&lambda, args, None).ok() == Some(Expression::String("ab".to_owned())));
+        assert!(call_lambda(&lambda, vec![], None).is_err());
+    }
+
+    #[test]
+    fn test_error_location() {
+        let path =
+            std::env::temp_dir().join(format!("ttweb-glisp-error-{}.gl", std::process::id()));
+        let path = path.to_str().unwrap();
+        let source = "(do\n  (set f (lambda (x)\n\t(+ x nope)))\n  (f 1))";
+        std::fs::write(path, source).unwrap();
+        let Err(e) = parse_eval_file(source.to_owned(), path, &default_env(), None) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Name);
+        assert_eq!(e.message, "unexpected symbol k=nope");
+        let span = e.span.clone().unwrap();
+        assert_eq!((span.line, span.column), (3, 7));
+        // 调用栈中是调用 f 的位置
+        assert_eq!(
+            e.trace
+                .iter()
+                .map(|a| (a.line, a.column))
+                .collect::<Vec<_>>(),
+            [(4, 3)]
+        );
+        assert_eq!(
+            e.diagnostic(),
+            format!(
+                "name error: unexpected symbol k=nope\n  --> {0}:3:7\n  |\n3 | \t(+ x nope)))\n  | \t     ^\n  at {0}:4:3",
+                path
+            )
+        );
+
+        let Err(e) = parse_eval("(do\n  (+ 1 2)".to_owned(), &default_env(), None) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Syntax);
+        assert_eq!(e.span.unwrap().to_string(), "line 1, column 1");
+        let Err(e) = parse_eval("(length \"a\" \"b\")".to_owned(), &default_env(), None) else {
+            panic!()
+        };
+        assert_eq!(e.kind, ErrorKind::Arity);
+    }
+}
@@ -0,0 +1,852 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 一个 ACME 客户端，用于自动申请和续期证书
+//!
+//! 流程如下：
+//! 1. 读取目录 (directory) ，得到其它接口的 URL
+//! 2. 用账户密钥注册（或找回）账户，得到账户的 URL (kid)
+//! 3. 创建订单，对订单中的每个授权，应答 HTTP-01 挑战
+//!    挑战的内容被存放在 CHALLENGES 中，由 Router 在 `/.well-known/acme-challenge/` 下提供
+//! 4. 用证书密钥生成 CSR 并完成 (finalize) 订单，然后下载证书链
+//!
+//! 所有请求都用账户密钥进行 JWS 签名，新的账户密钥和每次续期的证书密钥都是 ECDSA P-256 密钥，
+//! 即 JWS 中的 ES256 和证书中的 ecdsa-with-SHA256 ，Let's Encrypt 等 CA 都支持它们
+//! 以前生成的 Ed25519 账户密钥仍然可以被读取，此时签名算法是 EdDSA
+//!
+//! 和 ACME 服务器的 HTTPS 连接会验证服务器的证书链，被信任的 CA 证书来自配置或系统，
+//! 找不到任何 CA 证书时直接失败，而不是不验证就继续
+//!
+//! 账户密钥、证书密钥和证书都存放在 `config/acme/` 下
+//! 密钥是 PKCS#8 格式的 PEM 文件，证书是 PEM 格式的证书链
+//!
+//! ACME，参见：https://datatracker.ietf.org/doc/html/rfc8555
+//! JWS 中的 ECDSA，参见：https://datatracker.ietf.org/doc/html/rfc7518#section-3.4
+//! JWS 中的 EdDSA，参见：https://datatracker.ietf.org/doc/html/rfc8037
+
+use std::io::{Read, Write};
+use std::sync::Mutex;
+use std::time::Duration;
+
+use super::client;
+use super::der;
+use super::ecdsa::{self, EcdsaKeyPair};
+use super::hash::{HashFunction, Sha256};
+use super::key::PrivateKey;
+use super::server;
+use super::tls::TLSError;
+use super::x509::Certificate;
+use crate::config::AcmeConfig;
+use crate::drop::base64;
+use crate::drop::json::{self, JsonValue};
+use crate::drop::log::LogLevel::*;
+use crate::i18n::LOG;
+use crate::macros::*;
+
+pub const CHALLENGE_PATH: &str = "/.well-known/acme-challenge/";
+const STORAGE_DIR: &str = "config/acme/";
+/// 没有配置 CA 证书时，依次尝试的系统 CA 证书文件
+const SYSTEM_CA_FILES: [&str; 3] = [
+    "/etc/ssl/certs/ca-certificates.crt",
+    "/etc/pki/tls/certs/ca-bundle.crt",
+    "/etc/ssl/cert.pem",
+];
+/// 两次检查证书是否需要续期的间隔
+const CHECK_INTERVAL: Duration = Duration::from_secs(12 * 60 * 60);
+/// 申请失败后，过多久再重试
+const RETRY_INTERVAL: Duration = Duration::from_secs(60 * 60);
+/// 轮询授权和订单状态的最大次数
+const MAX_POLLS: u32 = 30;
+
+/// 正在进行的 HTTP-01 挑战，每一项是 (token, key authorization)
+static CHALLENGES: Mutex<Vec<(String, String)>> = Mutex::new(Vec::new());
+
+#[derive(Debug)]
+pub enum AcmeError {
+    Io(std::io::Error),
+    Tls(TLSError),
+    /// 服务端返回了非 2xx 的状态码，附带状态码和响应体（通常是一个 problem document）
+    Http(u16, String),
+    Protocol(&'static str),
+}
+impl std::fmt::Display for AcmeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AcmeError::Io(e) => write!(f, "{}", e),
+            AcmeError::Tls(e) => write!(f, "TLS error: {:?}", e),
+            AcmeError::Http(code, body) => write!(f, "HTTP {}: {}", code, body),
+            AcmeError::Protocol(msg) => write!(f, "{}", msg),
+        }
+    }
+}
+impl From<std::io::Error> for AcmeError {
+    fn from(e: std::io::Error) -> Self {
+        AcmeError::Io(e)
+    }
+}
+
+/// 如果 url 是一个正在进行的挑战，返回要响应的 key authorization
+pub fn challenge_response(url: &str) -> Option<String> {
+    let token = url.strip_prefix(CHALLENGE_PATH)?;
+    CHALLENGES
+        .lock()
+        .unwrap()
+        .iter()
+        .find(|(k, _)| k == token)
+        .map(|(_, v)| v.clone())
+}
+
+#[cfg(test)]
+pub fn add_challenge(token: &str, key_authorization: &str) {
+    CHALLENGES
+        .lock()
+        .unwrap()
+        .push((token.to_owned(), key_authorization.to_owned()));
+}
+
+/// 启动一个线程，在证书不存在或快要过期时申请证书
+pub fn start(config: AcmeConfig) {
+    if config.domains.is_empty() {
+        return;
+    }
+    std::thread::spawn(move || loop {
+        let interval = if needs_renewal(&config) {
+            log!(Info, format!("{}{}", LOG[38], config.domains.join(", ")));
+            match issue(&config) {
+                Ok(()) => {
+                    log!(Info, format!("{}{}", LOG[39], certificate_path(&config)));
+                    CHECK_INTERVAL
+                }
+                Err(e) => {
+                    log!(Error, format!("{}{}", LOG[40], e));
+                    RETRY_INTERVAL
+                }
+            }
+        } else {
+            CHECK_INTERVAL
+        };
+        std::thread::sleep(interval);
+    });
+}
+
+pub fn certificate_path(config: &AcmeConfig) -> String {
+    format!("{}{}.crt", STORAGE_DIR, config.domains[0])
+}
+
+pub fn certificate_key_path(config: &AcmeConfig) -> String {
+    format!("{}{}.key", STORAGE_DIR, config.domains[0])
+}
+
+/// 证书不存在、无法解析，或者剩余的有效期少于 renew_days 天时，需要续期
+fn needs_renewal(config: &AcmeConfig) -> bool {
+    let pem = match std::fs::read_to_string(certificate_path(config)) {
+        Ok(a) => a,
+        Err(_) => return true,
+    };
+    let not_after = match der::pem_decode(&pem).first() {
+        Some((_, certificate)) => match Certificate::parse(certificate) {
+            Ok(a) => a.not_after,
+            Err(_) => return true,
+        },
+        None => return true,
+    };
+    let now = crate::drop::time::time_difference::get_utc_timestamp();
+    not_after - now < config.renew_days as i64 * 24 * 60 * 60
+}
+
+/// 申请一张包含所有域名的证书，并把它和它的密钥保存到 `config/acme/`
+pub fn issue(config: &AcmeConfig) -> Result<(), AcmeError> {
+    std::fs::create_dir_all(STORAGE_DIR)?;
+    let account_key = load_or_create_key(&format!("{}account.key", STORAGE_DIR))?;
+    let trusted = load_trust_anchors(config)?;
+    let mut account = Account::new(&config.directory, account_key, trusted)?;
+    account.register(config.contact.as_deref())?;
+
+    let identifiers = config
+        .domains
+        .iter()
+        .map(|a| {
+            JsonValue::Object(vec![
+                ("type".to_owned(), JsonValue::String("dns".to_owned())),
+                ("value".to_owned(), JsonValue::String(a.clone())),
+            ])
+        })
+        .collect();
+    let payload = JsonValue::Object(vec![(
+        "identifiers".to_owned(),
+        JsonValue::Array(identifiers),
+    )]);
+    let new_order = account.directory.new_order.clone();
+    let response = account.post(&new_order, Some(&payload))?;
+    let order_url = response
+        .header("location")
+        .ok_or(AcmeError::Protocol("order has no location"))?
+        .to_owned();
+    let order = response.json()?;
+
+    let authorizations = order
+        .get("authorizations")
+        .and_then(|a| a.as_array())
+        .ok_or(AcmeError::Protocol("order has no authorizations"))?;
+    let mut tokens = vec![];
+    let result = authorize_all(&mut account, authorizations, &mut tokens);
+    CHALLENGES
+        .lock()
+        .unwrap()
+        .retain(|(k, _)| !tokens.contains(k));
+    result?;
+
+    // 每次续期都使用新的证书密钥
+    let certificate_key = PrivateKey::Ecdsa(EcdsaKeyPair::generate(ecdsa::p256())?);
+    let finalize = order
+        .get("finalize")
+        .and_then(|a| a.as_str())
+        .ok_or(AcmeError::Protocol("order has no finalize url"))?;
+    let payload = JsonValue::Object(vec![(
+        "csr".to_owned(),
+        JsonValue::String(base64::encode_url(&csr(&certificate_key, &config.domains))),
+    )]);
+    account.post(finalize, Some(&payload))?;
+    let order = account.poll(&order_url, "valid")?;
+    let certificate_url = order
+        .get("certificate")
+        .and_then(|a| a.as_str())
+        .ok_or(AcmeError::Protocol("order has no certificate url"))?;
+    let certificate = account.post(certificate_url, None)?.body;
+    if der::pem_decode(&String::from_utf8_lossy(&certificate)).is_empty() {
+        return Err(AcmeError::Protocol("invalid certificate chain"));
+    }
+
+    let key_pem = der::pem_encode("PRIVATE KEY", &certificate_key.to_pkcs8());
+    write_private(&certificate_key_path(config), key_pem.as_bytes())?;
+    std::fs::write(certificate_path(config), &certificate)?;
+    // 处理连接的线程在每次握手时都从 SSL_IDENTITY 构造 TLS 配置，所以新的证书立即生效
+    let mut identity = crate::config::SSL_IDENTITY.write().unwrap();
+    identity.certificate = Some(certificate);
+    identity.pravite_key = Some(key_pem.into_bytes());
+    Ok(())
+}
+
+/// 对每个授权应答 HTTP-01 挑战，并等待它们生效
+/// tokens: 被加入 CHALLENGES 的 token ，无论成功与否，调用者都应该在之后清理它们
+fn authorize_all(
+    account: &mut Account,
+    authorizations: &[JsonValue],
+    tokens: &mut Vec<String>,
+) -> Result<(), AcmeError> {
+    let thumbprint = jwk_thumbprint(&account.key);
+    for url in authorizations {
+        let url = url
+            .as_str()
+            .ok_or(AcmeError::Protocol("invalid authorization"))?;
+        let authorization = account.post(url, None)?.json()?;
+        if authorization.get("status").and_then(|a| a.as_str()) == Some("valid") {
+            continue;
+        }
+        let challenge = authorization
+            .get("challenges")
+            .and_then(|a| a.as_array())
+            .and_then(|a| {
+                a.iter()
+                    .find(|a| a.get("type").and_then(|a| a.as_str()) == Some("http-01"))
+            })
+            .ok_or(AcmeError::Protocol("no http-01 challenge"))?;
+        let token = challenge
+            .get("token")
+            .and_then(|a| a.as_str())
+            .ok_or(AcmeError::Protocol("challenge has no token"))?;
+        let challenge_url = challenge
+            .get("url")
+            .and_then(|a| a.as_str())
+            .ok_or(AcmeError::Protocol("challenge has no url"))?;
+
+        CHALLENGES
+            .lock()
+            .unwrap()
+            .push((token.to_owned(), format!("{}.{}", token, thumbprint)));
+        tokens.push(token.to_owned());
+
+        account.post(challenge_url, Some(&JsonValue::Object(vec![])))?;
+        account.poll(url, "valid")?;
+    }
+    Ok(())
+}
+
+struct Directory {
+    new_nonce: String,
+    new_account: String,
+    new_order: String,
+}
+
+/// 读取被信任的 CA 证书 (DER 格式)
+fn load_trust_anchors(config: &AcmeConfig) -> Result<Vec<Vec<u8>>, AcmeError> {
+    let data = match &config.ca_certificates {
+        Some(path) => std::fs::read(path)?,
+        None => SYSTEM_CA_FILES
+            .iter()
+            .find_map(|a| std::fs::read(a).ok())
+            .unwrap_or_default(),
+    };
+    let trusted = server::load_certificates(&data);
+    if trusted.is_empty() {
+        return Err(AcmeError::Protocol("no trusted CA certificates"));
+    }
+    Ok(trusted)
+}
+
+struct Account {
+    key: PrivateKey,
+    /// 被信任的 CA 证书，用于验证 ACME 服务器
+    trusted: Vec<Vec<u8>>,
+    directory: Directory,
+    /// 账户的 URL ，注册之前是 None ，此时用 jwk 代替 kid
+    kid: Option<String>,
+    nonce: Option<String>,
+}
+
+impl Account {
+    fn new(directory_url: &str, key: PrivateKey, trusted: Vec<Vec<u8>>) -> Result<Self, AcmeError> {
+        let directory = request("GET", directory_url, None, &trusted)?.json()?;
+        let get = |k| -> Result<String, AcmeError> {
+            directory
+                .get(k)
+                .and_then(|a| a.as_str())
+                .map(|a| a.to_owned())
+                .ok_or(AcmeError::Protocol("invalid directory"))
+        };
+        Ok(Account {
+            key,
+            trusted,
+            directory: Directory {
+                new_nonce: get("newNonce")?,
+                new_account: get("newAccount")?,
+                new_order: get("newOrder")?,
+            },
+            kid: None,
+            nonce: None,
+        })
+    }
+
+    /// 注册账户，如果该密钥已经注册过，服务端会返回已有的账户
+    fn register(&mut self, contact: Option<&str>) -> Result<(), AcmeError> {
+        let mut payload = vec![("termsOfServiceAgreed".to_owned(), JsonValue::Bool(true))];
+        if let Some(contact) = contact {
+            payload.push((
+                "contact".to_owned(),
+                JsonValue::Array(vec![JsonValue::String(contact.to_owned())]),
+            ));
+        }
+        let url = self.directory.new_account.clone();
+        let response = self.post(&url, Some(&JsonValue::Object(payload)))?;
+        self.kid = Some(
+            response
+                .header("location")
+                .ok_or(AcmeError::Protocol("account has no location"))?
+                .to_owned(),
+        );
+        Ok(())
+    }
+
+    /// 发送一个 JWS 签名的 POST 请求，payload 为 None 时是 POST-as-GET
+    /// 如果服务端认为 nonce 无效，会用新的 nonce 重试一次
+    fn post(&mut self, url: &str, payload: Option<&JsonValue>) -> Result<Response, AcmeError> {
+        let payload = match payload {
+            Some(a) => base64::encode_url(a.to_string().as_bytes()),
+            None => String::new(),
+        };
+        let mut retried = false;
+        loop {
+            let nonce = match self.nonce.take() {
+                Some(a) => a,
+                None => self.fetch_nonce()?,
+            };
+            let body = self.sign(url, &nonce, &payload);
+            let response = request("POST", url, Some(body.as_bytes()), &self.trusted)?;
+            if let Some(nonce) = response.header("replay-nonce") {
+                self.nonce = Some(nonce.to_owned());
+            }
+            if (200..300).contains(&response.status) {
+                return Ok(response);
+            }
+            let is_bad_nonce = response.json().ok().and_then(|a| {
+                a.get("type")
+                    .and_then(|a| a.as_str())
+                    .map(|a| a == "urn:ietf:params:acme:error:badNonce")
+            }) == Some(true);
+            if retried || !is_bad_nonce {
+                return Err(AcmeError::Http(
+                    response.status,
+                    String::from_utf8_lossy(&response.body).into_owned(),
+                ));
+            }
+            retried = true;
+        }
+    }
+
+    fn fetch_nonce(&self) -> Result<String, AcmeError> {
+        request("HEAD", &self.directory.new_nonce, None, &self.trusted)?
+            .header("replay-nonce")
+            .map(|a| a.to_owned())
+            .ok_or(AcmeError::Protocol("no nonce"))
+    }
+
+    /// 生成 flattened JSON 格式的 JWS
+    fn sign(&self, url: &str, nonce: &str, payload: &str) -> String {
+        let mut protected = vec![
+            (
+                "alg".to_owned(),
+                JsonValue::String(algorithm(&self.key).to_owned()),
+            ),
+            ("nonce".to_owned(), JsonValue::String(nonce.to_owned())),
+            ("url".to_owned(), JsonValue::String(url.to_owned())),
+        ];
+        match &self.kid {
+            Some(kid) => protected.push(("kid".to_owned(), JsonValue::String(kid.clone()))),
+            None => protected.push(("jwk".to_owned(), jwk(&self.key))),
+        }
+        jws(
+            &self.key,
+            &JsonValue::Object(protected).to_string(),
+            payload,
+        )
+    }
+
+    /// 轮询一个授权或订单，直到其状态变为 status
+    fn poll(&mut self, url: &str, status: &str) -> Result<JsonValue, AcmeError> {
+        for _ in 0..MAX_POLLS {
+            let response = self.post(url, None)?;
+            let retry_after = response
+                .header("retry-after")
+                .and_then(|a| a.parse().ok())
+                .unwrap_or(2)
+                .clamp(1, 10);
+            let value = response.json()?;
+            match value.get("status").and_then(|a| a.as_str()) {
+                Some(a) if a == status => return Ok(value),
+                Some("pending") | Some("processing") | Some("ready") => {
+                    std::thread::sleep(Duration::from_secs(retry_after))
+                }
+                _ => {
+                    return Err(AcmeError::Http(
+                        response.status,
+                        String::from_utf8_lossy(&response.body).into_owned(),
+                    ))
+                }
+            }
+        }
+        Err(AcmeError::Protocol("timed out"))
+    }
+}
+
+/// JWS 的签名算法
+fn algorithm(key: &PrivateKey) -> &'static str {
+    match key {
+        PrivateKey::Ed25519(_) => "EdDSA",
+        PrivateKey::Ecdsa(key) if std::ptr::eq(key.curve, ecdsa::p384()) => "ES384",
+        PrivateKey::Ecdsa(_) => "ES256",
+    }
+}
+
+/// 生成 flattened JSON 格式的 JWS ，payload 应该已经被 base64url 编码
+/// ECDSA 签名是 r || s 格式，而不是 X.509 中的 DER 格式
+fn jws(key: &PrivateKey, protected: &str, payload: &str) -> String {
+    let protected = base64::encode_url(protected.as_bytes());
+    let input = format!("{}.{}", protected, payload);
+    let signature = match key {
+        PrivateKey::Ed25519(key) => key.sign(input.as_bytes()).to_vec(),
+        PrivateKey::Ecdsa(key) => key.sign(input.as_bytes()),
+    };
+    JsonValue::Object(vec![
+        ("protected".to_owned(), JsonValue::String(protected)),
+        ("payload".to_owned(), JsonValue::String(payload.to_owned())),
+        (
+            "signature".to_owned(),
+            JsonValue::String(base64::encode_url(&signature)),
+        ),
+    ])
+    .to_string()
+}
+
+/// 键按字典序排列，这也是计算 thumbprint 所需的规范形式
+fn jwk(key: &PrivateKey) -> JsonValue {
+    let string = |a: &str| JsonValue::String(a.to_owned());
+    match key {
+        PrivateKey::Ed25519(key) => JsonValue::Object(vec![
+            ("crv".to_owned(), string("Ed25519")),
+            ("kty".to_owned(), string("OKP")),
+            ("x".to_owned(), string(&base64::encode_url(&key.public_key))),
+        ]),
+        PrivateKey::Ecdsa(key) => {
+            // 公钥是 0x04 || x || y
+            let (x, y) = key.public_key[1..].split_at(key.curve.bytes());
+            let crv = if std::ptr::eq(key.curve, ecdsa::p384()) {
+                "P-384"
+            } else {
+                "P-256"
+            };
+            JsonValue::Object(vec![
+                ("crv".to_owned(), string(crv)),
+                ("kty".to_owned(), string("EC")),
+                ("x".to_owned(), string(&base64::encode_url(x))),
+                ("y".to_owned(), string(&base64::encode_url(y))),
+            ])
+        }
+    }
+}
+
+/// See: https://datatracker.ietf.org/doc/html/rfc7638
+fn jwk_thumbprint(key: &PrivateKey) -> String {
+    base64::encode_url(&Sha256::digest(jwk(key).to_string().as_bytes()))
+}
+
+/// 生成一个 PKCS#10 证书签名请求，第一个域名作为 commonName ，所有域名都被放入 subjectAltName
+fn csr(key: &PrivateKey, domains: &[String]) -> Vec<u8> {
+    let name = der::sequence(&[der::set(&[der::sequence(&[
+        der::oid(der::OID_COMMON_NAME),
+        der::utf8_string(&domains[0]),
+    ])])]);
+    let alt_names: Vec<Vec<u8>> = domains
+        .iter()
+        .map(|a| der::context(2, false, a.as_bytes()))
+        .collect();
+    let extensions = der::sequence(&[der::sequence(&[
+        der::oid(der::OID_SUBJECT_ALT_NAME),
+        der::octet_string(&der::sequence(&alt_names)),
+    ])]);
+    let attributes = der::context(
+        0,
+        true,
+        &der::sequence(&[
+            der::oid(der::OID_EXTENSION_REQUEST),
+            der::set(&[extensions]),
+        ]),
+    );
+    let info = der::sequence(&[
+        der::small_integer(0),
+        name,
+        key.subject_public_key_info(),
+        attributes,
+    ]);
+    let signature = key.sign(&info);
+    der::sequence(&[info, key.signature_algorithm(), der::bit_string(&signature)])
+}
+
+/// 读取账户密钥，它不存在时生成一个新的 P-256 密钥
+fn load_or_create_key(path: &str) -> Result<PrivateKey, AcmeError> {
+    if let Ok(pem) = std::fs::read_to_string(path) {
+        return der::pem_decode(&pem)
+            .iter()
+            .find(|(label, _)| label == "PRIVATE KEY")
+            .and_then(|(_, data)| PrivateKey::from_pkcs8(data))
+            .ok_or(AcmeError::Protocol("invalid account key"));
+    }
+    let key = PrivateKey::Ecdsa(EcdsaKeyPair::generate(ecdsa::p256())?);
+    write_private(
+        path,
+        der::pem_encode("PRIVATE KEY", &key.to_pkcs8()).as_bytes(),
+    )?;
+    Ok(key)
+}
+
+/// 写入一个私钥文件，它只能被本用户读写 (0600)
+/// 如果文件已经存在，会先收紧它的权限，再写入新的内容
+fn write_private(path: &str, data: &[u8]) -> std::io::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create(true).truncate(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    let mut file = options.open(path)?;
+    #[cfg(unix)]
+    file.set_permissions(std::os::unix::fs::PermissionsExt::from_mode(0o600))?;
+    file.write_all(data)
+}
+
+struct Response {
+    status: u16,
+    /// 键已被转换为小写
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+impl Response {
+    fn header(&self, key: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(k, _)| k == key)
+            .map(|(_, v)| v.as_str())
+    }
+    fn json(&self) -> Result<JsonValue, AcmeError> {
+        std::str::from_utf8(&self.body)
+            .ok()
+            .and_then(|a| json::parse(a).ok())
+            .ok_or(AcmeError::Protocol("invalid JSON"))
+    }
+}
+
+/// 发送一个 HTTP/1.1 请求，每个请求都使用新的连接
+/// 支持 `http://` 和 `https://` ，后者使用 client 模块，并用 trusted 验证服务器的证书
+fn request(
+    method: &str,
+    url: &str,
+    body: Option<&[u8]>,
+    trusted: &[Vec<u8>],
+) -> Result<Response, AcmeError> {
+    let (secure, rest) = if let Some(a) = url.strip_prefix("https://") {
+        (true, a)
+    } else if let Some(a) = url.strip_prefix("http://") {
+        (false, a)
+    } else {
+        return Err(AcmeError::Protocol("unsupported url"));
+    };
+    let (authority, path) = match rest.find('/') {
+        Some(a) => (&rest[..a], &rest[a..]),
+        None => (rest, "/"),
+    };
+    let (host, port) = match authority.rsplit_once(':') {
+        Some((host, port)) if !port.contains(']') => (
+            host,
+            port.parse()
+                .map_err(|_| AcmeError::Protocol("invalid port"))?,
+        ),
+        _ => (authority, if secure { 443 } else { 80 }),
+    };
+
+    let mut head = format!(
+        "{} {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: Tiny-Tiny-Web/2\r\nConnection: close\r\n",
+        method, path, authority
+    );
+    if let Some(body) = body {
+        head += &format!(
+            "Content-Type: application/jose+json\r\nContent-Length: {}\r\n",
+            body.len()
+        );
+    }
+    head += "\r\n";
+    let mut data = head.into_bytes();
+    data.extend(body.unwrap_or_default());
+
+    let stream = std::net::TcpStream::connect((host.trim_matches(|c| c == '[' || c == ']'), port))?;
+    stream.set_read_timeout(Some(Duration::from_secs(30)))?;
+    stream.set_write_timeout(Some(Duration::from_secs(30)))?;
+    let mut raw = vec![];
+    if secure {
+        let mut stream =
+            client::connect(stream, host.trim_matches(|c| c == '[' || c == ']'), trusted)
+                .map_err(AcmeError::Tls)?;
+        stream.write_all(&data)?;
+        stream.read_to_end(&mut raw)?;
+        stream.close();
+    } else {
+        let mut stream = stream;
+        stream.write_all(&data)?;
+        stream.read_to_end(&mut raw)?;
+    }
+    parse_response(&raw, method == "HEAD")
+}
+
+fn parse_response(raw: &[u8], no_body: bool) -> Result<Response, AcmeError> {
+    let invalid = AcmeError::Protocol("invalid HTTP response");
+    let split = match raw.windows(4).position(|a| a == b"\r\n\r\n") {
+        Some(a) => a,
+        None => return Err(invalid),
+    };
+    let head = match std::str::from_utf8(&raw[..split]) {
+        Ok(a) => a,
+        Err(_) => return Err(invalid),
+    };
+    let mut lines = head.split("\r\n");
+    let status = match lines.next().and_then(|a| a.split(' ').nth(1)) {
+        Some(a) => a
+            .parse()
+            .map_err(|_| AcmeError::Protocol("invalid status"))?,
+        None => return Err(invalid),
+    };
+    let headers: Vec<(String, String)> = lines
+        .filter_map(|a| a.split_once(':'))
+        .map(|(k, v)| (k.trim().to_ascii_lowercase(), v.trim().to_owned()))
+        .collect();
+    let mut response = Response {
+        status,
+        headers,
+        body: vec![],
+    };
+    if no_body {
+        return Ok(response);
+    }
+    let body = &raw[split + 4..];
+    response.body = if response
+        .header("transfer-encoding")
+        .is_some_and(|a| a.eq_ignore_ascii_case("chunked"))
+    {
+        decode_chunked(body).ok_or(invalid)?
+    } else if let Some(len) = response
+        .header("content-length")
+        .and_then(|a| a.parse().ok())
+    {
+        body.get(..len).ok_or(invalid)?.to_vec()
+    } else {
+        body.to_vec()
+    };
+    Ok(response)
+}
+
+fn decode_chunked(mut data: &[u8]) -> Option<Vec<u8>> {
+    let mut body = vec![];
+    loop {
+        let line_end = data.windows(2).position(|a| a == b"\r\n")?;
+        let size = std::str::from_utf8(&data[..line_end]).ok()?;
+        let size = usize::from_str_radix(size.split(';').next()?.trim(), 16).ok()?;
+        data = &data[line_end + 2..];
+        if size == 0 {
+            return Some(body);
+        }
+        body.extend(data.get(..size)?);
+        data = data.get(size + 2..)?;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::curve25519::Ed25519KeyPair;
+
+    #[test]
+    fn test_jws_rfc8037() {
+        // RFC 8037 A.1, A.3, A.4
+        let seed = base64::decode("nWGxne_9WmC6hEr0kuwsxERJxWl7MmkZcDusAxyuf2A").unwrap();
+        let key = PrivateKey::Ed25519(Ed25519KeyPair::from_seed(&seed[..].try_into().unwrap()));
+        assert_eq!(
+            jwk(&key).to_string(),
+            r#"{"crv":"Ed25519","kty":"OKP","x":"11qYAYKxCrfVS_7TyWQHOg7hcvPapiMlrwIaaPcHURo"}"#
+        );
+        assert_eq!(
+            jwk_thumbprint(&key),
+            "kPrK_qmxVWaYVA9wwBF6Iuo3vVzz7TxHCTwXBygrS4k"
+        );
+        assert_eq!(algorithm(&key), "EdDSA");
+        let jws = json::parse(&jws(
+            &key,
+            r#"{"alg":"EdDSA"}"#,
+            &base64::encode_url(b"Example of Ed25519 signing"),
+        ))
+        .unwrap();
+        assert_eq!(
+            jws.get("signature").unwrap().as_str().unwrap(),
+            "hgyY0il_MGCjP0JzlnLWG1PPOt7-09PGcvMg3AIbQR6dWbhijcNR4ki4iylGjg5BhVsPt9g7sVvpAr_MuM0KAg"
+        );
+    }
+
+    /// RFC 6979 A.2.5 的 P-256 私钥
+    fn p256_key() -> PrivateKey {
+        let private_key = base64::decode("ya-p2EW6dRZrXCFXZ7HWk05Qw9s26JsSe4piKxIPZyE").unwrap();
+        PrivateKey::Ecdsa(EcdsaKeyPair::from_private_key(ecdsa::p256(), &private_key).unwrap())
+    }
+
+    #[test]
+    fn test_jws_es256() {
+        // 期望值由 Python 的 cryptography 库（确定性 ECDSA）生成
+        let key = p256_key();
+        assert_eq!(
+            jwk(&key).to_string(),
+            r#"{"crv":"P-256","kty":"EC","x":"YP7UuiVanTHJYet0xjVtaMBJuJI7Yfps5mliLmDyn7Y","y":"eQP-EAi4vJmkGunpVii8ZPLxsgwtfp9Rd6PClNRGIpk"}"#
+        );
+        assert_eq!(
+            jwk_thumbprint(&key),
+            "DOvxvJiAdIqVWIkFt5hDtCunXLF0BV4-JGv4f-ALSm0"
+        );
+        assert_eq!(algorithm(&key), "ES256");
+        let jws = json::parse(&jws(
+            &key,
+            r#"{"alg":"ES256"}"#,
+            &base64::encode_url(b"payload"),
+        ))
+        .unwrap();
+        assert_eq!(
+            jws.get("signature").unwrap().as_str().unwrap(),
+            "9sBiNbsBJE8FOJUTk3zHk2LAeT3NU6U5RsI_eCCELpV4VdVWMsfCDY96c1docV77XqdmZkOvw0quH8sfcXTMqw"
+        );
+    }
+
+    /// 返回 CSR 的 (certificationRequestInfo, 签名算法, 签名)
+    fn parse_csr(csr: &[u8]) -> (Vec<u8>, Vec<u64>, Vec<u8>) {
+        let mut reader = der::DerReader::new(csr)
+            .read_expect(der::SEQUENCE)
+            .unwrap()
+            .reader();
+        let info = reader.read_expect(der::SEQUENCE).unwrap();
+        let algorithm = reader.read_expect(der::SEQUENCE).unwrap();
+        let signature = reader.read_expect(der::BIT_STRING).unwrap();
+        (
+            info.raw.to_vec(),
+            algorithm.reader().read().unwrap().oid().unwrap(),
+            signature.bit_string().unwrap().to_vec(),
+        )
+    }
+
+    #[test]
+    fn test_csr() {
+        let domains = ["example.com".to_owned(), "www.example.com".to_owned()];
+        let key = Ed25519KeyPair::from_seed(&[7; 32]);
+        let public_key = key.public_key;
+        let (info, algorithm, signature) = parse_csr(&csr(&PrivateKey::Ed25519(key), &domains));
+        assert_eq!(algorithm, der::OID_ED25519);
+        assert!(super::super::curve25519::ed25519_verify(
+            &public_key,
+            &info,
+            signature[..].try_into().unwrap()
+        ));
+
+        let key = p256_key();
+        let (info, algorithm, signature) = parse_csr(&csr(&key, &domains));
+        assert_eq!(algorithm, der::OID_ECDSA_SHA256);
+        let PrivateKey::Ecdsa(key) = key else {
+            unreachable!()
+        };
+        assert!(ecdsa::verify(
+            ecdsa::p256(),
+            &key.public_key,
+            &Sha256::digest(&info),
+            &ecdsa::signature_from_der(&signature, 32).unwrap()
+        ));
+    }
+
+    #[test]
+    #[cfg(unix)]
+    fn test_write_private() {
+        use std::os::unix::fs::PermissionsExt;
+        let path = std::env::temp_dir().join(format!("ttweb-acme-key-{}", std::process::id()));
+        let path = path.to_str().unwrap();
+        std::fs::write(path, "old").unwrap();
+        std::fs::set_permissions(path, std::fs::Permissions::from_mode(0o644)).unwrap();
+        write_private(path, b"new").unwrap();
+        let metadata = std::fs::metadata(path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        assert_eq!(std::fs::read(path).unwrap(), b"new");
+        std::fs::remove_file(path).unwrap();
+        write_private(path, b"key").unwrap();
+        let metadata = std::fs::metadata(path).unwrap();
+        assert_eq!(metadata.permissions().mode() & 0o777, 0o600);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn test_parse_response() {
+        let response = parse_response(
+            b"HTTP/1.1 201 Created\r\nReplay-Nonce: abc\r\nTransfer-Encoding: chunked\r\n\r\n\
+              4\r\n{\"a\"\r\n3;x=y\r\n:1}\r\n0\r\n\r\n",
+            false,
+        )
+        .unwrap();
+        assert_eq!(response.status, 201);
+        assert_eq!(response.header("replay-nonce"), Some("abc"));
+        assert_eq!(
+            response.json().unwrap().get("a"),
+            Some(&JsonValue::Number(1.0))
+        );
+    }
+}
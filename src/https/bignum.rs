@@ -0,0 +1,288 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供奇数模数下的大整数模运算，ECDSA 和 RSA 都建立在它之上
+//!
+//! 整数以小端序的 u64 数组 (limb) 表示，数组的长度总是和模数相同，值总是小于模数
+//! 乘法使用蒙哥马利乘法，所以参与乘法的数要先用 Modulus::to_mont 转换到蒙哥马利形式，
+//! 加法和减法对两种形式都适用
+//!
+//! 除了 Modulus::new 和比较之外，运算的时间和内存访问都不依赖于数据本身，
+//! 条件分支都以掩码代替，这样签名时不会通过时间泄露私钥
+//!
+//! 例如，计算 a * b mod m ：
+//! ```
+//! let m = Modulus::new(&m_bytes);
+//! let a = m.to_mont(&m.decode(&a_bytes)?);
+//! let b = m.to_mont(&m.decode(&b_bytes)?);
+//! m.encode(&m.to_normal(&m.mul(&a, &b)));
+//! ```
+//!
+//! See: https://en.wikipedia.org/wiki/Montgomery_modular_multiplication
+
+pub type Limbs = Vec<u64>;
+
+pub struct Modulus {
+    m: Limbs,
+    /// -m^-1 mod 2^64
+    m_inv: u64,
+    /// R^2 mod m ，其中 R = 2^(64 * m.len())
+    r2: Limbs,
+    /// m 的字节数
+    bytes: usize,
+}
+
+/// 返回 a + b + carry 的低 64 位和进位
+fn adc(a: u64, b: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + b as u128 + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+/// 返回 a - b - borrow 的低 64 位和借位
+fn sbb(a: u64, b: u64, borrow: u64) -> (u64, u64) {
+    let t = (a as u128).wrapping_sub(b as u128 + borrow as u128);
+    (t as u64, (t >> 127) as u64)
+}
+
+/// 返回 a + b * c + carry 的低 64 位和高 64 位
+fn mac(a: u64, b: u64, c: u64, carry: u64) -> (u64, u64) {
+    let t = a as u128 + b as u128 * c as u128 + carry as u128;
+    (t as u64, (t >> 64) as u64)
+}
+
+/// mask 为全 1 时返回 b ，为 0 时返回 a
+pub fn select(a: &[u64], b: &[u64], mask: u64) -> Limbs {
+    a.iter().zip(b).map(|(a, b)| a ^ ((a ^ b) & mask)).collect()
+}
+
+/// 以大端序的字节解析一个整数，它必须能被放入 len 个 limb 中
+fn limbs_from_bytes(bytes: &[u8], len: usize) -> Option<Limbs> {
+    let skip = bytes.iter().take_while(|a| **a == 0).count();
+    let bytes = &bytes[skip..];
+    if bytes.len() > len * 8 {
+        return None;
+    }
+    let mut limbs = vec![0; len];
+    for (i, byte) in bytes.iter().rev().enumerate() {
+        limbs[i / 8] |= (*byte as u64) << (i % 8 * 8);
+    }
+    Some(limbs)
+}
+
+/// a - b ，返回差和借位
+fn sub_limbs(a: &[u64], b: &[u64]) -> (Limbs, u64) {
+    let mut borrow = 0;
+    let mut out = Vec::with_capacity(a.len());
+    for (a, b) in a.iter().zip(b) {
+        let (d, c) = sbb(*a, *b, borrow);
+        out.push(d);
+        borrow = c;
+    }
+    (out, borrow)
+}
+
+pub fn is_zero(a: &[u64]) -> bool {
+    a.iter().fold(0, |acc, a| acc | a) == 0
+}
+
+impl Modulus {
+    /// m: 大端序的奇数模数
+    pub fn new(m: &[u8]) -> Self {
+        let skip = m.iter().take_while(|a| **a == 0).count();
+        let bytes = m.len() - skip;
+        let m = limbs_from_bytes(m, bytes.div_ceil(8)).unwrap();
+        debug_assert!(m[0] & 1 == 1);
+        // 牛顿迭代，每一次迭代使正确的位数翻倍
+        let mut inv: u64 = 1;
+        for _ in 0..6 {
+            inv = inv.wrapping_mul(2u64.wrapping_sub(m[0].wrapping_mul(inv)));
+        }
+        let mut modulus = Modulus {
+            m_inv: inv.wrapping_neg(),
+            r2: vec![],
+            bytes,
+            m,
+        };
+        // 从 1 开始加倍 2 * 64 * len 次，得到 R^2 mod m
+        let mut r2 = modulus.small(1);
+        for _ in 0..128 * modulus.m.len() {
+            r2 = modulus.add(&r2, &r2);
+        }
+        modulus.r2 = r2;
+        modulus
+    }
+
+    /// 模数的字节数
+    pub fn bytes(&self) -> usize {
+        self.bytes
+    }
+
+    /// 一个小整数，不是蒙哥马利形式
+    pub fn small(&self, a: u64) -> Limbs {
+        let mut limbs = vec![0; self.m.len()];
+        limbs[0] = a;
+        limbs
+    }
+
+    /// 以大端序的字节解析一个整数，如果它不小于模数，返回 None
+    pub fn decode(&self, bytes: &[u8]) -> Option<Limbs> {
+        let limbs = limbs_from_bytes(bytes, self.m.len())?;
+        let (_, borrow) = sub_limbs(&limbs, &self.m);
+        (borrow == 1).then_some(limbs)
+    }
+
+    /// 以大端序的字节解析一个小于 2m 的整数，并对 m 取模
+    pub fn decode_reduced(&self, bytes: &[u8]) -> Option<Limbs> {
+        let limbs = limbs_from_bytes(bytes, self.m.len())?;
+        let (d, borrow) = sub_limbs(&limbs, &self.m);
+        Some(select(&d, &limbs, borrow.wrapping_neg()))
+    }
+
+    /// 转换为大端序的字节，长度和模数相同
+    pub fn encode(&self, a: &[u64]) -> Vec<u8> {
+        let mut out: Vec<u8> = a.iter().rev().flat_map(|a| a.to_be_bytes()).collect();
+        out.drain(..out.len() - self.bytes);
+        out
+    }
+
+    pub fn add(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let mut carry = 0;
+        let mut sum = Vec::with_capacity(a.len());
+        for (a, b) in a.iter().zip(b) {
+            let (s, c) = adc(*a, *b, carry);
+            sum.push(s);
+            carry = c;
+        }
+        let (d, borrow) = sub_limbs(&sum, &self.m);
+        // 有进位，或者没有借位时，和不小于 m
+        select(&sum, &d, (carry | (borrow ^ 1)).wrapping_neg())
+    }
+
+    pub fn sub(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let (d, borrow) = sub_limbs(a, b);
+        let mask = borrow.wrapping_neg();
+        let mut carry = 0;
+        d.iter()
+            .zip(&self.m)
+            .map(|(d, m)| {
+                let (s, c) = adc(*d, m & mask, carry);
+                carry = c;
+                s
+            })
+            .collect()
+    }
+
+    /// 蒙哥马利乘法，返回 a * b / R mod m
+    pub fn mul(&self, a: &[u64], b: &[u64]) -> Limbs {
+        let n = self.m.len();
+        let mut t = vec![0u64; n + 2];
+        for b in b {
+            let mut carry = 0;
+            for j in 0..n {
+                (t[j], carry) = mac(t[j], a[j], *b, carry);
+            }
+            (t[n], carry) = adc(t[n], carry, 0);
+            t[n + 1] = carry;
+
+            let u = t[0].wrapping_mul(self.m_inv);
+            let (_, mut carry) = mac(t[0], u, self.m[0], 0);
+            for j in 1..n {
+                (t[j - 1], carry) = mac(t[j], u, self.m[j], carry);
+            }
+            (t[n - 1], carry) = adc(t[n], carry, 0);
+            t[n] = t[n + 1] + carry;
+        }
+        let (d, borrow) = sub_limbs(&t[..n], &self.m);
+        // t[n] 为 1 ，或者没有借位时，结果不小于 m
+        select(&t[..n], &d, (t[n] | (borrow ^ 1)).wrapping_neg())
+    }
+
+    pub fn to_mont(&self, a: &[u64]) -> Limbs {
+        self.mul(a, &self.r2)
+    }
+
+    pub fn to_normal(&self, a: &[u64]) -> Limbs {
+        self.mul(a, &self.small(1))
+    }
+
+    /// 蒙哥马利形式的 1
+    pub fn one(&self) -> Limbs {
+        self.to_mont(&self.small(1))
+    }
+
+    /// 返回 a^e ，a 和结果都是蒙哥马利形式，e 是大端序的字节
+    /// 每一位都进行一次乘法，然后根据该位选择结果，所以时间不依赖于 e
+    pub fn pow(&self, a: &[u64], e: &[u8]) -> Limbs {
+        let mut result = self.one();
+        for byte in e {
+            for i in (0..8).rev() {
+                result = self.mul(&result, &result);
+                let product = self.mul(&result, a);
+                result = select(&result, &product, ((byte >> i) as u64 & 1).wrapping_neg());
+            }
+        }
+        result
+    }
+
+    /// 返回 a^-1 ，a 是蒙哥马利形式，模数必须是素数
+    /// 根据费马小定理，a^-1 = a^(m-2)
+    pub fn inv(&self, a: &[u64]) -> Limbs {
+        let exponent = self.sub(&self.m, &self.small(2));
+        self.pow(a, &self.encode(&exponent))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_small() {
+        let m = Modulus::new(&[0x03, 0xe9]); // 1001
+        let a = m.to_mont(&m.decode(&[0x03, 0x84]).unwrap()); // 900
+        let b = m.to_mont(&m.decode(&[0x01, 0xf4]).unwrap()); // 500
+        let value = |a: &Limbs| u16::from_be_bytes(m.encode(&m.to_normal(a)).try_into().unwrap());
+        assert_eq!(value(&m.mul(&a, &b)), 551);
+        assert_eq!(value(&m.add(&a, &b)), 399);
+        assert_eq!(value(&m.sub(&b, &a)), 601);
+        assert_eq!(value(&m.sub(&a, &a)), 0);
+        assert_eq!(value(&m.pow(&a, &[0x00, 0x05])), 100);
+        assert!(m.decode(&[0x03, 0xe9]).is_none());
+        assert_eq!(m.decode_reduced(&[0x03, 0xea]), Some(m.small(1)));
+    }
+
+    #[test]
+    fn test_p256_inverse() {
+        let p = Modulus::new(&from_hex(
+            "ffffffff00000001000000000000000000000000ffffffffffffffffffffffff",
+        ));
+        let a = p.to_mont(&p.small(0x1234_5678_9abc_def0));
+        assert_eq!(p.to_normal(&p.mul(&a, &p.inv(&a))), p.small(1));
+    }
+
+    #[test]
+    fn test_rsa_pow() {
+        // 2^65537 mod (2^127 - 1) = 2^(65537 mod 127) = 2^5
+        let m = Modulus::new(&from_hex("7fffffffffffffffffffffffffffffff"));
+        let two = m.to_mont(&m.small(2));
+        let result = m.to_normal(&m.pow(&two, &[0x01, 0x00, 0x01]));
+        assert_eq!(
+            to_hex(&m.encode(&result)),
+            "00000000000000000000000000000020"
+        );
+    }
+}
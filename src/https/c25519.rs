@@ -908,21 +908,21 @@ unsafe extern "C" fn sha512_block(mut s: *mut sha512_state, mut blk: *const uint
         i += 1;
     }
     let ref mut fresh16 = (*s).h[0 as libc::c_int as usize];
-    *fresh16 = (*fresh16 as libc::c_ulong).wrapping_add(a as u32) as uint64_t as uint64_t;
+    *fresh16 = (*fresh16).wrapping_add(a);
     let ref mut fresh17 = (*s).h[1 as libc::c_int as usize];
-    *fresh17 = (*fresh17 as libc::c_ulong).wrapping_add(b as u32) as uint64_t as uint64_t;
+    *fresh17 = (*fresh17).wrapping_add(b);
     let ref mut fresh18 = (*s).h[2 as libc::c_int as usize];
-    *fresh18 = (*fresh18 as libc::c_ulong).wrapping_add(c as u32) as uint64_t as uint64_t;
+    *fresh18 = (*fresh18).wrapping_add(c);
     let ref mut fresh19 = (*s).h[3 as libc::c_int as usize];
-    *fresh19 = (*fresh19 as libc::c_ulong).wrapping_add(d as u32) as uint64_t as uint64_t;
+    *fresh19 = (*fresh19).wrapping_add(d);
     let ref mut fresh20 = (*s).h[4 as libc::c_int as usize];
-    *fresh20 = (*fresh20 as libc::c_ulong).wrapping_add(e as u32) as uint64_t as uint64_t;
+    *fresh20 = (*fresh20).wrapping_add(e);
     let ref mut fresh21 = (*s).h[5 as libc::c_int as usize];
-    *fresh21 = (*fresh21 as libc::c_ulong).wrapping_add(f as u32) as uint64_t as uint64_t;
+    *fresh21 = (*fresh21).wrapping_add(f);
     let ref mut fresh22 = (*s).h[6 as libc::c_int as usize];
-    *fresh22 = (*fresh22 as libc::c_ulong).wrapping_add(g as u32) as uint64_t as uint64_t;
+    *fresh22 = (*fresh22).wrapping_add(g);
     let ref mut fresh23 = (*s).h[7 as libc::c_int as usize];
-    *fresh23 = (*fresh23 as libc::c_ulong).wrapping_add(h as u32) as uint64_t as uint64_t;
+    *fresh23 = (*fresh23).wrapping_add(h);
 }
 #[no_mangle]
 unsafe extern "C" fn sha512_final(
@@ -1919,7 +1919,7 @@ unsafe fn sm_pack(r: *mut uint8_t, k: *const uint8_t) {
         t: [0; 32],
         z: [0; 32],
     };
-    ed25519_smult(r as *mut ed25519_pt, &mut ed25519_base, k);
+    ed25519_smult(&mut p, &mut ed25519_base, k);
     pp(r, &p);
 }
 unsafe fn edsign_sec_to_pub(_pub: *mut u8, secret: *const u8) {
@@ -1959,10 +1959,9 @@ unsafe fn hash_with_prefix(
         i = 128 - prefix_size;
         while i + 128 <= len {
             sha512_block(&mut s, message.wrapping_add(i as usize));
-
-            sha512_final(&mut s, message.wrapping_add(i as usize), len + prefix_size);
             i += 128
         }
+        sha512_final(&mut s, message.wrapping_add(i as usize), len + prefix_size);
     }
     sha512_get(&s, init_block, 0, 64);
     fprime_from_bytes(out_fp, init_block, 64, ed25519_order.as_mut_ptr());
@@ -0,0 +1,404 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供 ChaCha20-Poly1305 AEAD 算法，它是 TLS 1.3 的 TLS_CHACHA20_POLY1305_SHA256 密码套件的基础
+//! 它不需要硬件加速也能在常数时间内运行，所以适合本项目
+//!
+//! seal 返回的数据是密文后紧接 16 字节的认证标签
+//! open 在认证失败时返回 None ，此时不会返回任何解密后的数据
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8439
+
+pub const KEY_SIZE: usize = 32;
+pub const NONCE_SIZE: usize = 12;
+pub const TAG_SIZE: usize = 16;
+
+fn quarter_round(s: &mut [u32; 16], a: usize, b: usize, c: usize, d: usize) {
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(16);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(12);
+    s[a] = s[a].wrapping_add(s[b]);
+    s[d] = (s[d] ^ s[a]).rotate_left(8);
+    s[c] = s[c].wrapping_add(s[d]);
+    s[b] = (s[b] ^ s[c]).rotate_left(7);
+}
+
+pub fn chacha20_block(key: &[u8; KEY_SIZE], counter: u32, nonce: &[u8; NONCE_SIZE]) -> [u8; 64] {
+    let mut state = [0u32; 16];
+    state[..4].copy_from_slice(&[0x61707865, 0x3320646e, 0x79622d32, 0x6b206574]);
+    for i in 0..8 {
+        state[4 + i] = u32::from_le_bytes(key[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+    state[12] = counter;
+    for i in 0..3 {
+        state[13 + i] = u32::from_le_bytes(nonce[i * 4..i * 4 + 4].try_into().unwrap());
+    }
+
+    let mut working = state;
+    for _ in 0..10 {
+        quarter_round(&mut working, 0, 4, 8, 12);
+        quarter_round(&mut working, 1, 5, 9, 13);
+        quarter_round(&mut working, 2, 6, 10, 14);
+        quarter_round(&mut working, 3, 7, 11, 15);
+        quarter_round(&mut working, 0, 5, 10, 15);
+        quarter_round(&mut working, 1, 6, 11, 12);
+        quarter_round(&mut working, 2, 7, 8, 13);
+        quarter_round(&mut working, 3, 4, 9, 14);
+    }
+
+    let mut out = [0; 64];
+    for i in 0..16 {
+        out[i * 4..i * 4 + 4].copy_from_slice(&working[i].wrapping_add(state[i]).to_le_bytes());
+    }
+    out
+}
+
+/// 加密和解密是同一个操作
+pub fn chacha20_xor(key: &[u8; KEY_SIZE], counter: u32, nonce: &[u8; NONCE_SIZE], data: &mut [u8]) {
+    for (i, chunk) in data.chunks_mut(64).enumerate() {
+        let block = chacha20_block(key, counter.wrapping_add(i as u32), nonce);
+        for (a, b) in chunk.iter_mut().zip(block) {
+            *a ^= b;
+        }
+    }
+}
+
+/// Poly1305 一次性消息认证码，内部以 5 个 26 位的分量表示 130 位的整数
+pub struct Poly1305 {
+    r: [u32; 5],
+    h: [u32; 5],
+    pad: [u32; 4],
+    buffer: [u8; 16],
+    buffer_len: usize,
+}
+impl Poly1305 {
+    pub fn new(key: &[u8; 32]) -> Self {
+        let le = |i: usize| u32::from_le_bytes(key[i..i + 4].try_into().unwrap());
+        Poly1305 {
+            r: [
+                le(0) & 0x3ffffff,
+                (le(3) >> 2) & 0x3ffff03,
+                (le(6) >> 4) & 0x3ffc0ff,
+                (le(9) >> 6) & 0x3f03fff,
+                (le(12) >> 8) & 0x00fffff,
+            ],
+            h: [0; 5],
+            pad: [le(16), le(20), le(24), le(28)],
+            buffer: [0; 16],
+            buffer_len: 0,
+        }
+    }
+
+    /// hibit: 完整分组为 1 << 24 ，最后一个不完整的分组已经手动补了 1 ，所以为 0
+    fn block(&mut self, block: &[u8; 16], hibit: u32) {
+        let le = |i: usize| u32::from_le_bytes(block[i..i + 4].try_into().unwrap());
+        let [r0, r1, r2, r3, r4] = self.r.map(|a| a as u64);
+        let (s1, s2, s3, s4) = (r1 * 5, r2 * 5, r3 * 5, r4 * 5);
+
+        let h0 = (self.h[0] + (le(0) & 0x3ffffff)) as u64;
+        let h1 = (self.h[1] + ((le(3) >> 2) & 0x3ffffff)) as u64;
+        let h2 = (self.h[2] + ((le(6) >> 4) & 0x3ffffff)) as u64;
+        let h3 = (self.h[3] + ((le(9) >> 6) & 0x3ffffff)) as u64;
+        let h4 = (self.h[4] + ((le(12) >> 8) | hibit)) as u64;
+
+        let d0 = h0 * r0 + h1 * s4 + h2 * s3 + h3 * s2 + h4 * s1;
+        let mut d1 = h0 * r1 + h1 * r0 + h2 * s4 + h3 * s3 + h4 * s2;
+        let mut d2 = h0 * r2 + h1 * r1 + h2 * r0 + h3 * s4 + h4 * s3;
+        let mut d3 = h0 * r3 + h1 * r2 + h2 * r1 + h3 * r0 + h4 * s4;
+        let mut d4 = h0 * r4 + h1 * r3 + h2 * r2 + h3 * r1 + h4 * r0;
+
+        let mut c = d0 >> 26;
+        let mut h0 = d0 & 0x3ffffff;
+        d1 += c;
+        c = d1 >> 26;
+        let h1 = d1 & 0x3ffffff;
+        d2 += c;
+        c = d2 >> 26;
+        let h2 = d2 & 0x3ffffff;
+        d3 += c;
+        c = d3 >> 26;
+        let h3 = d3 & 0x3ffffff;
+        d4 += c;
+        c = d4 >> 26;
+        let h4 = d4 & 0x3ffffff;
+        h0 += c * 5;
+        c = h0 >> 26;
+        h0 &= 0x3ffffff;
+
+        self.h = [h0 as u32, (h1 + c) as u32, h2 as u32, h3 as u32, h4 as u32];
+    }
+
+    pub fn update(&mut self, mut data: &[u8]) {
+        if self.buffer_len > 0 {
+            let take = (16 - self.buffer_len).min(data.len());
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+            self.buffer_len += take;
+            data = &data[take..];
+            if self.buffer_len < 16 {
+                return;
+            }
+            let block = self.buffer;
+            self.block(&block, 1 << 24);
+            self.buffer_len = 0;
+        }
+        let mut blocks = data.chunks_exact(16);
+        for block in &mut blocks {
+            self.block(block.try_into().unwrap(), 1 << 24);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
+    }
+
+    pub fn finalize(mut self) -> [u8; 16] {
+        if self.buffer_len > 0 {
+            let mut block = [0; 16];
+            block[..self.buffer_len].copy_from_slice(&self.buffer[..self.buffer_len]);
+            block[self.buffer_len] = 1;
+            self.block(&block, 0);
+        }
+
+        // 完全进位
+        let mut h = self.h;
+        let mut c;
+        c = h[1] >> 26;
+        h[1] &= 0x3ffffff;
+        h[2] += c;
+        c = h[2] >> 26;
+        h[2] &= 0x3ffffff;
+        h[3] += c;
+        c = h[3] >> 26;
+        h[3] &= 0x3ffffff;
+        h[4] += c;
+        c = h[4] >> 26;
+        h[4] &= 0x3ffffff;
+        h[0] += c * 5;
+        c = h[0] >> 26;
+        h[0] &= 0x3ffffff;
+        h[1] += c;
+
+        // 计算 h - p ，并在常数时间内选择 h 或 h - p
+        let mut g = [0u32; 5];
+        g[0] = h[0].wrapping_add(5);
+        c = g[0] >> 26;
+        g[0] &= 0x3ffffff;
+        for i in 1..4 {
+            g[i] = h[i].wrapping_add(c);
+            c = g[i] >> 26;
+            g[i] &= 0x3ffffff;
+        }
+        // 最高的一位不能被截断，否则 h >= p 时会选择 h
+        g[4] = h[4].wrapping_add(c).wrapping_sub(1 << 26);
+        let mask = (g[4] >> 31).wrapping_sub(1);
+        for i in 0..5 {
+            h[i] = (h[i] & !mask) | (g[i] & mask);
+        }
+
+        // h = h % 2^128 + pad
+        let h0 = h[0] | (h[1] << 26);
+        let h1 = (h[1] >> 6) | (h[2] << 20);
+        let h2 = (h[2] >> 12) | (h[3] << 14);
+        let h3 = (h[3] >> 18) | (h[4] << 8);
+        let mut out = [0; 16];
+        let mut f: u64 = 0;
+        for (i, (h, pad)) in [h0, h1, h2, h3].into_iter().zip(self.pad).enumerate() {
+            f = h as u64 + pad as u64 + (f >> 32);
+            out[i * 4..i * 4 + 4].copy_from_slice(&(f as u32).to_le_bytes());
+        }
+        out
+    }
+}
+
+fn poly1305_key(key: &[u8; KEY_SIZE], nonce: &[u8; NONCE_SIZE]) -> [u8; 32] {
+    chacha20_block(key, 0, nonce)[..32].try_into().unwrap()
+}
+
+fn aead_tag(
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    ciphertext: &[u8],
+) -> [u8; 16] {
+    let zeros = [0; 16];
+    let mut mac = Poly1305::new(&poly1305_key(key, nonce));
+    mac.update(aad);
+    mac.update(&zeros[..(16 - aad.len() % 16) % 16]);
+    mac.update(ciphertext);
+    mac.update(&zeros[..(16 - ciphertext.len() % 16) % 16]);
+    mac.update(&(aad.len() as u64).to_le_bytes());
+    mac.update(&(ciphertext.len() as u64).to_le_bytes());
+    mac.finalize()
+}
+
+pub fn seal(
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    plaintext: &[u8],
+) -> Vec<u8> {
+    let mut out = plaintext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut out);
+    let tag = aead_tag(key, nonce, aad, &out);
+    out.extend(tag);
+    out
+}
+
+pub fn open(
+    key: &[u8; KEY_SIZE],
+    nonce: &[u8; NONCE_SIZE],
+    aad: &[u8],
+    data: &[u8],
+) -> Option<Vec<u8>> {
+    if data.len() < TAG_SIZE {
+        return None;
+    }
+    let (ciphertext, tag) = data.split_at(data.len() - TAG_SIZE);
+    let expected = aead_tag(key, nonce, aad, ciphertext);
+    if !constant_time_eq(&expected, tag) {
+        return None;
+    }
+    let mut out = ciphertext.to_vec();
+    chacha20_xor(key, 1, nonce, &mut out);
+    Some(out)
+}
+
+/// 比较两个字节串是否相等，耗时与它们的内容无关
+pub fn constant_time_eq(a: &[u8], b: &[u8]) -> bool {
+    if a.len() != b.len() {
+        return false;
+    }
+    a.iter().zip(b).fold(0, |acc, (a, b)| acc | (a ^ b)) == 0
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        let str: String = str.split_whitespace().collect();
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    const SUNSCREEN: &[u8] = b"Ladies and Gentlemen of the class of '99: If I could offer you only one tip for the future, sunscreen would be it.";
+
+    #[test]
+    fn test_chacha20_block() {
+        // RFC 8439 2.3.2
+        let key: Vec<u8> = (0..32).collect();
+        let nonce = from_hex("000000090000004a00000000");
+        let block = chacha20_block(&key.try_into().unwrap(), 1, &nonce.try_into().unwrap());
+        assert_eq!(
+            to_hex(&block),
+            "10f1e7e4d13b5915500fdd1fa32071c4c7d1f4c733c068030422aa9ac3d46c4e\
+             d2826446079faa0914c2d705d98b02a2b5129cd1de164eb9cbd083e8a2503c4e"
+        );
+    }
+
+    #[test]
+    fn test_chacha20_encryption() {
+        // RFC 8439 2.4.2
+        let key: Vec<u8> = (0..32).collect();
+        let nonce = from_hex("000000000000004a00000000");
+        let mut data = SUNSCREEN.to_vec();
+        chacha20_xor(
+            &key.try_into().unwrap(),
+            1,
+            &nonce.try_into().unwrap(),
+            &mut data,
+        );
+        assert_eq!(
+            to_hex(&data),
+            "6e2e359a2568f98041ba0728dd0d6981e97e7aec1d4360c20a27afccfd9fae0b\
+             f91b65c5524733ab8f593dabcd62b3571639d624e65152ab8f530c359f0861d8\
+             07ca0dbf500d6a6156a38e088a22b65e52bc514d16ccf806818ce91ab7793736\
+             5af90bbf74a35be6b40b8eedf2785e42874d"
+        );
+    }
+
+    #[test]
+    fn test_poly1305() {
+        // RFC 8439 2.5.2
+        let key = from_hex("85d6be7857556d337f4452fe42d506a80103808afb0db2fd4abff6af4149f51b");
+        let mut mac = Poly1305::new(&key.try_into().unwrap());
+        mac.update(b"Cryptographic Forum ");
+        mac.update(b"Research Group");
+        assert_eq!(to_hex(&mac.finalize()), "a8061dc1305136c6c22b8baf0c0127a9");
+    }
+
+    #[test]
+    fn test_aead() {
+        // RFC 8439 2.8.2
+        let key = from_hex("808182838485868788898a8b8c8d8e8f909192939495969798999a9b9c9d9e9f");
+        let key: [u8; 32] = key.try_into().unwrap();
+        let nonce: [u8; 12] = from_hex("070000004041424344454647").try_into().unwrap();
+        let aad = from_hex("50515253c0c1c2c3c4c5c6c7");
+        let sealed = seal(&key, &nonce, &aad, SUNSCREEN);
+        assert_eq!(
+            to_hex(&sealed[sealed.len() - 16..]),
+            "1ae10b594f09e26a7e902ecbd0600691"
+        );
+        assert_eq!(to_hex(&sealed[..16]), "d31a8d34648e60db7b86afbc53ef7ec2");
+        assert_eq!(open(&key, &nonce, &aad, &sealed).unwrap(), SUNSCREEN);
+
+        let mut tampered = sealed.clone();
+        tampered[0] ^= 1;
+        assert!(open(&key, &nonce, &aad, &tampered).is_none());
+        assert!(open(&key, &nonce, b"", &sealed).is_none());
+    }
+
+    #[test]
+    fn test_chacha20_block_zero_key() {
+        // RFC 8439 A.1 Test Vector #1
+        let block = chacha20_block(&[0; 32], 0, &[0; 12]);
+        assert_eq!(
+            to_hex(&block),
+            "76b8e0ada0f13d90405d6ae55386bd28bdd219b8a08ded1aa836efcc8b770dc7\
+             da41597c5157488d7724e03fb8d84a376a43b8f41518a11cc387b669b2ee6586"
+        );
+    }
+
+    #[test]
+    fn test_poly1305_edge_cases() {
+        // RFC 8439 A.3 Test Vector #5, #6, #8, #9 ，测试对 2^130 - 5 取模时的进位
+        let tag = |key: &str, data: &str| {
+            let mut mac = Poly1305::new(&from_hex(key).try_into().unwrap());
+            mac.update(&from_hex(data));
+            to_hex(&mac.finalize())
+        };
+        let r2 = "02000000000000000000000000000000 00000000000000000000000000000000";
+        assert_eq!(
+            tag(r2, "ffffffffffffffffffffffffffffffff"),
+            "03000000000000000000000000000000"
+        );
+        assert_eq!(
+            tag(
+                "02000000000000000000000000000000 ffffffffffffffffffffffffffffffff",
+                "02000000000000000000000000000000"
+            ),
+            "03000000000000000000000000000000"
+        );
+        assert_eq!(
+            tag(
+                "01000000000000000000000000000000 00000000000000000000000000000000",
+                "ffffffffffffffffffffffffffffffff fbfefefefefefefefefefefefefefefe \
+                 01010101010101010101010101010101"
+            ),
+            "00000000000000000000000000000000"
+        );
+        assert_eq!(
+            tag(r2, "fdffffffffffffffffffffffffffffff"),
+            "faffffffffffffffffffffffffffffff"
+        );
+    }
+}
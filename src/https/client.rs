@@ -0,0 +1,508 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 一个最小的 TLS 1.3 客户端，用于本项目主动发起的 HTTPS 请求（例如 ACME）
+//! connect 完成握手后返回 TlsStream ，它实现了 Read 和 Write ，可以像 TcpStream 一样使用
+//!
+//! 服务端的证书链被 x509 模块根据调用者提供的被信任的证书验证，并且它的 subjectAltName 必须包含 server_name
+//! 然后用证书的公钥验证 CertificateVerify ，任何一步失败都会使握手失败
+//! 没有被信任的证书时，connect 直接返回错误，而不是跳过验证
+//!
+//! 服务端请求客户端证书时，connect 发送一个空的证书列表，connect_with_certificate 发送给定的证书链
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8446
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+
+use super::chacha20poly1305::constant_time_eq;
+use super::curve25519::{x25519_keypair, x25519_shared};
+use super::key::PrivateKey;
+use super::tls::TLSError;
+use super::tls13::*;
+use super::x509;
+
+/// 客户端的证书链 (DER 格式，终端实体证书在前) 和它的私钥
+pub struct ClientCertificate {
+    pub certificates: Vec<Vec<u8>>,
+    pub key: PrivateKey,
+}
+
+/// 完成握手，返回可以读写的连接
+/// server_name: 用于 SNI 和证书验证的主机名，如果是 IP 地址则不发送 SNI
+/// trusted: 被信任的证书 (DER 格式)
+pub fn connect<S: Read + Write>(
+    stream: S,
+    server_name: &str,
+    trusted: &[Vec<u8>],
+) -> Result<TlsStream<S>, TLSError> {
+    connect_with_certificate(stream, server_name, trusted, None)
+}
+
+/// 和 connect 相同，但在服务端请求客户端证书时发送 certificate
+/// 如果服务端不支持它的私钥的签名算法，仍然发送一个空的证书列表
+pub fn connect_with_certificate<S: Read + Write>(
+    mut stream: S,
+    server_name: &str,
+    trusted: &[Vec<u8>],
+    certificate: Option<&ClientCertificate>,
+) -> Result<TlsStream<S>, TLSError> {
+    if trusted.is_empty() {
+        return Err(TLSError::BadCertificate);
+    }
+    let random = crate::drop::random::get_random_bytes(96).map_err(TLSError::Io)?;
+    let (private_key, public_key) = x25519_keypair(&random[64..].try_into().unwrap());
+
+    let mut extensions = vec![];
+    if server_name.parse::<std::net::IpAddr>().is_err() {
+        let mut name = vec![0];
+        name.extend(with_length(2, server_name.as_bytes()));
+        extensions.extend(extension(EXTENSION_SERVER_NAME, &with_length(2, &name)));
+    }
+    extensions.extend(extension(
+        EXTENSION_SUPPORTED_GROUPS,
+        &with_length(2, &GROUP_X25519.to_be_bytes()),
+    ));
+    let signature_algorithms: Vec<u8> = SUPPORTED_SIGNATURE_SCHEMES
+        .iter()
+        .flat_map(|a| a.to_be_bytes())
+        .collect();
+    extensions.extend(extension(
+        EXTENSION_SIGNATURE_ALGORITHMS,
+        &with_length(2, &signature_algorithms),
+    ));
+    extensions.extend(extension(
+        EXTENSION_SUPPORTED_VERSIONS,
+        &with_length(1, &TLS1_3.to_be_bytes()),
+    ));
+    let mut key_share = GROUP_X25519.to_be_bytes().to_vec();
+    key_share.extend(with_length(2, &public_key));
+    extensions.extend(extension(EXTENSION_KEY_SHARE, &with_length(2, &key_share)));
+
+    let mut body = vec![3, 3];
+    body.extend(&random[..32]);
+    body.extend(with_length(1, &random[32..64])); // legacy_session_id ，用于中间设备兼容模式
+    body.extend(with_length(
+        2,
+        &CIPHER_CHACHA20_POLY1305_SHA256.to_be_bytes(),
+    ));
+    body.extend([1, 0]);
+    body.extend(with_length(2, &extensions));
+    let client_hello = handshake_message(HANDSHAKE_CLIENT_HELLO, &body);
+
+    let mut transcript = Transcript::new();
+    transcript.update(&client_hello);
+    let mut record = record_header(CONTENT_HANDSHAKE, client_hello.len()).to_vec();
+    record.extend(&client_hello);
+    stream.write_all(&record).map_err(TLSError::Io)?;
+
+    // ServerHello
+    let mut handshake_buffer = HandshakeBuffer::default();
+    let server_hello = loop {
+        if let Some(message) = handshake_buffer.next_message() {
+            break message;
+        }
+        let (header, payload) = read_record(&mut stream)?;
+        match header[0] {
+            CONTENT_HANDSHAKE => handshake_buffer.push(&payload),
+            CONTENT_ALERT => return Err(TLSError::Alert(*payload.get(1).unwrap_or(&0))),
+            _ => return Err(TLSError::HandshakeFailure),
+        }
+    };
+    if server_hello.0 != HANDSHAKE_SERVER_HELLO {
+        send_plain_alert(&mut stream, ALERT_UNEXPECTED_MESSAGE);
+        return Err(TLSError::HandshakeFailure);
+    }
+    let server_public_key = match parse_server_hello(&server_hello.1[4..]) {
+        Ok(a) => a,
+        Err(e) => {
+            send_plain_alert(&mut stream, ALERT_HANDSHAKE_FAILURE);
+            return Err(e);
+        }
+    };
+    transcript.update(&server_hello.1);
+
+    let shared = x25519_shared(&private_key, &server_public_key);
+    if shared == [0; 32] {
+        send_plain_alert(&mut stream, ALERT_HANDSHAKE_FAILURE);
+        return Err(TLSError::HandshakeFailure);
+    }
+    let mut schedule = KeySchedule::new(None);
+    schedule.handshake(&shared);
+    let client_handshake_secret = schedule.derive_secret(b"c hs traffic", &transcript.current());
+    let server_handshake_secret = schedule.derive_secret(b"s hs traffic", &transcript.current());
+    let mut read_keys = TrafficKeys::new(&server_handshake_secret);
+    let mut write_keys = TrafficKeys::new(&client_handshake_secret);
+    let mut send_alert = |stream: &mut S, description: u8| {
+        let _ = stream.write_all(&write_keys.seal(CONTENT_ALERT, &[2, description]));
+    };
+
+    // EncryptedExtensions, CertificateRequest, Certificate, CertificateVerify, Finished
+    let mut peer_certificates = vec![];
+    let mut leaf = None;
+    let mut verified = false;
+    let mut certificate_request = None;
+    let now = crate::drop::time::time_difference::get_utc_timestamp();
+    'handshake: loop {
+        while let Some((handshake_type, message)) = handshake_buffer.next_message() {
+            match handshake_type {
+                HANDSHAKE_ENCRYPTED_EXTENSIONS => {}
+                HANDSHAKE_CERTIFICATE_REQUEST => {
+                    certificate_request = Some(parse_certificate_request(&message[4..])?);
+                }
+                HANDSHAKE_CERTIFICATE if leaf.is_none() => {
+                    peer_certificates = parse_certificate(&message[4..])?.1;
+                    match x509::verify_chain(
+                        &peer_certificates,
+                        trusted,
+                        now,
+                        x509::OID_SERVER_AUTH,
+                    ) {
+                        Ok(a) if a.matches_host(server_name) => leaf = Some(a),
+                        _ => {
+                            send_alert(&mut stream, ALERT_BAD_CERTIFICATE);
+                            return Err(TLSError::BadCertificate);
+                        }
+                    }
+                }
+                HANDSHAKE_CERTIFICATE_VERIFY if leaf.is_some() && !verified => {
+                    let mut reader = ByteReader::new(&message[4..]);
+                    let scheme = reader.u16()?;
+                    let signature = reader.vector(2)?;
+                    verified = verify_signature_scheme(
+                        leaf.as_ref().unwrap(),
+                        scheme,
+                        &certificate_verify_content(true, &transcript.current()),
+                        signature,
+                    );
+                    if !verified {
+                        send_alert(&mut stream, ALERT_DECRYPT_ERROR);
+                        return Err(TLSError::BadCertificate);
+                    }
+                }
+                HANDSHAKE_FINISHED if verified => {
+                    let expected =
+                        finished_verify_data(&server_handshake_secret, &transcript.current());
+                    if !constant_time_eq(&expected, &message[4..]) {
+                        return Err(TLSError::DecryptError);
+                    }
+                    transcript.update(&message);
+                    break 'handshake;
+                }
+                _ => {
+                    send_alert(&mut stream, ALERT_UNEXPECTED_MESSAGE);
+                    return Err(TLSError::HandshakeFailure);
+                }
+            }
+            transcript.update(&message);
+        }
+        let (header, payload) = read_record(&mut stream)?;
+        match header[0] {
+            CONTENT_CHANGE_CIPHER_SPEC => {}
+            CONTENT_APPLICATION_DATA => match read_keys.open(&header, &payload)? {
+                (CONTENT_HANDSHAKE, data) => handshake_buffer.push(&data),
+                (CONTENT_ALERT, data) => return Err(TLSError::Alert(*data.get(1).unwrap_or(&0))),
+                _ => return Err(TLSError::HandshakeFailure),
+            },
+            CONTENT_ALERT => return Err(TLSError::Alert(*payload.get(1).unwrap_or(&0))),
+            _ => return Err(TLSError::HandshakeFailure),
+        }
+    }
+
+    schedule.master();
+    let client_application_secret = schedule.derive_secret(b"c ap traffic", &transcript.current());
+    let server_application_secret = schedule.derive_secret(b"s ap traffic", &transcript.current());
+
+    let mut out = vec![CONTENT_CHANGE_CIPHER_SPEC, 3, 3, 0, 1, 1];
+    if let Some((context, signature_algorithms)) = certificate_request {
+        let certificate =
+            certificate.filter(|a| signature_algorithms.contains(&a.key.signature_scheme()));
+        let mut list = vec![];
+        for a in certificate.iter().flat_map(|a| &a.certificates) {
+            list.extend(with_length(3, a));
+            list.extend(with_length(2, &[]));
+        }
+        let mut body = with_length(1, &context);
+        body.extend(with_length(3, &list));
+        let message = handshake_message(HANDSHAKE_CERTIFICATE, &body);
+        transcript.update(&message);
+        out.extend(write_keys.seal(CONTENT_HANDSHAKE, &message));
+        if let Some(certificate) = certificate {
+            let signature = certificate
+                .key
+                .sign(&certificate_verify_content(false, &transcript.current()));
+            let mut body = certificate.key.signature_scheme().to_be_bytes().to_vec();
+            body.extend(with_length(2, &signature));
+            let message = handshake_message(HANDSHAKE_CERTIFICATE_VERIFY, &body);
+            transcript.update(&message);
+            out.extend(write_keys.seal(CONTENT_HANDSHAKE, &message));
+        }
+    }
+    let finished = handshake_message(
+        HANDSHAKE_FINISHED,
+        &finished_verify_data(&client_handshake_secret, &transcript.current()),
+    );
+    out.extend(write_keys.seal(CONTENT_HANDSHAKE, &finished));
+    stream.write_all(&out).map_err(TLSError::Io)?;
+
+    Ok(TlsStream::new(
+        stream,
+        server_application_secret,
+        client_application_secret,
+        handshake_buffer,
+        peer_certificates,
+    ))
+}
+
+/// 返回 certificate_request_context 和服务端接受的签名算法
+fn parse_certificate_request(body: &[u8]) -> Result<(Vec<u8>, Vec<u16>), TLSError> {
+    let mut reader = ByteReader::new(body);
+    let context = reader.vector(1)?.to_vec();
+    let mut signature_algorithms = vec![];
+    for (extension_type, data) in reader.extensions()? {
+        if extension_type == EXTENSION_SIGNATURE_ALGORITHMS {
+            let mut list = ByteReader::new(ByteReader::new(data).vector(2)?);
+            while !list.is_empty() {
+                signature_algorithms.push(list.u16()?);
+            }
+        }
+    }
+    Ok((context, signature_algorithms))
+}
+
+/// 返回服务端的 X25519 公钥
+fn parse_server_hello(body: &[u8]) -> Result<[u8; 32], TLSError> {
+    let mut reader = ByteReader::new(body);
+    reader.u16()?; // legacy_version
+    if reader.bytes(32)? == HELLO_RETRY_REQUEST_RANDOM {
+        // 只在服务端不支持 X25519 时才会发生
+        return Err(TLSError::HandshakeFailure);
+    }
+    reader.vector(1)?; // legacy_session_id_echo
+    if reader.u16()? != CIPHER_CHACHA20_POLY1305_SHA256 {
+        return Err(TLSError::UndefinedCiperSuite);
+    }
+    reader.u8()?;
+    let mut version = None;
+    let mut public_key = None;
+    for (extension_type, data) in reader.extensions()? {
+        let mut data = ByteReader::new(data);
+        match extension_type {
+            EXTENSION_SUPPORTED_VERSIONS => version = Some(data.u16()?),
+            EXTENSION_KEY_SHARE => {
+                if data.u16()? != GROUP_X25519 {
+                    return Err(TLSError::HandshakeFailure);
+                }
+                public_key = Some(data.vector(2)?);
+            }
+            _ => {}
+        }
+    }
+    if version != Some(TLS1_3) {
+        return Err(TLSError::HandshakeFailure);
+    }
+    match public_key {
+        Some(a) if a.len() == 32 => Ok(a.try_into().unwrap()),
+        _ => Err(TLSError::HandshakeFailure),
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::https::der;
+    use crate::https::ecdsa::{self, EcdsaKeyPair};
+    use crate::https::key::PrivateKey;
+    use crate::https::server::{self, ServerConfig};
+    use std::net::{TcpListener, TcpStream};
+    use std::time::Duration;
+
+    pub fn p256(seed: u8) -> PrivateKey {
+        PrivateKey::Ecdsa(EcdsaKeyPair::from_private_key(ecdsa::p256(), &[seed; 32]).unwrap())
+    }
+
+    /// 签发一个从 1970 年到 9999 年有效的证书
+    /// extensions: 除了 subjectAltName 之外的扩展，san 为空时不添加 subjectAltName
+    pub fn issue(
+        subject: &str,
+        san: &[&str],
+        key: &PrivateKey,
+        issuer: &str,
+        issuer_key: &PrivateKey,
+        extensions: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let not_after = der::encode(der::GENERALIZED_TIME, b"99991231235959Z");
+        issue_until(subject, san, key, issuer, issuer_key, extensions, not_after)
+    }
+
+    /// 和 issue 相同，但证书在 not_after (DER 编码的时间) 之后过期
+    pub fn issue_until(
+        subject: &str,
+        san: &[&str],
+        key: &PrivateKey,
+        issuer: &str,
+        issuer_key: &PrivateKey,
+        extensions: &[Vec<u8>],
+        not_after: Vec<u8>,
+    ) -> Vec<u8> {
+        let name = |cn: &str| {
+            der::sequence(&[der::set(&[der::sequence(&[
+                der::oid(der::OID_COMMON_NAME),
+                der::utf8_string(cn),
+            ])])])
+        };
+        let mut extensions = extensions.to_vec();
+        if !san.is_empty() {
+            let names: Vec<Vec<u8>> = san
+                .iter()
+                .map(|a| der::encode(0x82, a.as_bytes()))
+                .collect();
+            extensions.push(der::sequence(&[
+                der::oid(der::OID_SUBJECT_ALT_NAME),
+                der::octet_string(&der::sequence(&names)),
+            ]));
+        }
+        let mut tbs = vec![
+            der::context(0, true, &der::small_integer(2)),
+            der::small_integer(1),
+            issuer_key.signature_algorithm(),
+            name(issuer),
+            der::sequence(&[der::encode(der::UTC_TIME, b"700101000000Z"), not_after]),
+            name(subject),
+            key.subject_public_key_info(),
+        ];
+        if !extensions.is_empty() {
+            tbs.push(der::context(3, true, &der::sequence(&extensions)));
+        }
+        let tbs = der::sequence(&tbs);
+        let signature = issuer_key.sign(&tbs);
+        der::sequence(&[
+            tbs,
+            issuer_key.signature_algorithm(),
+            der::bit_string(&signature),
+        ])
+    }
+
+    /// 客户端的握手结果，以及服务端收到的客户端证书链
+    pub type LoopbackResult = (Result<(), TLSError>, Result<Vec<Vec<u8>>, TLSError>);
+
+    /// 在另一个线程中运行服务端，握手后服务端发送 "ping" ，客户端回复 "pong"
+    /// 由服务端先发送，这样服务端在握手后拒绝客户端时，客户端能读到它的警报
+    pub fn loopback(
+        config: ServerConfig,
+        server_name: &str,
+        trusted: &[Vec<u8>],
+    ) -> LoopbackResult {
+        loopback_with_certificate(config, server_name, trusted, None)
+    }
+
+    /// 和 loopback 相同，但客户端在服务端请求时发送 certificate
+    pub fn loopback_with_certificate(
+        config: ServerConfig,
+        server_name: &str,
+        trusted: &[Vec<u8>],
+        certificate: Option<&ClientCertificate>,
+    ) -> LoopbackResult {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            stream
+                .set_read_timeout(Some(Duration::from_secs(10)))
+                .unwrap();
+            let mut header = [0; 5];
+            stream.read_exact(&mut header).map_err(TLSError::Io)?;
+            let mut stream = server::accept(stream, header, &config)?;
+            stream.write_all(b"ping").map_err(TLSError::Io)?;
+            let mut buf = [0; 4];
+            stream.read_exact(&mut buf).map_err(TLSError::Io)?;
+            assert_eq!(&buf, b"pong");
+            stream.close();
+            Ok(stream.peer_certificates.clone())
+        });
+
+        let stream = TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(Duration::from_secs(10)))
+            .unwrap();
+        let client = connect_with_certificate(stream, server_name, trusted, certificate).and_then(
+            |mut stream| {
+                let mut buf = [0; 4];
+                stream.read_exact(&mut buf).map_err(TLSError::Io)?;
+                assert_eq!(&buf, b"ping");
+                stream.write_all(b"pong").map_err(TLSError::Io)?;
+                Ok(())
+            },
+        );
+        (client, server.join().unwrap())
+    }
+
+    pub fn server_config(certificates: Vec<Vec<u8>>, key: &PrivateKey) -> ServerConfig {
+        let key = der::pem_encode("PRIVATE KEY", &key.to_pkcs8());
+        let mut config = ServerConfig::new(&certificates[0], key.as_bytes()).unwrap();
+        config.certificates = certificates;
+        config
+    }
+
+    #[test]
+    fn test_verify_server() {
+        let root_key = p256(1);
+        let server_key = p256(2);
+        let ca = der::sequence(&[
+            der::oid(&[2, 5, 29, 19]),
+            der::octet_string(&der::sequence(&[der::encode(der::BOOLEAN, &[0xff])])),
+        ]);
+        let root = issue("root", &[], &root_key, "root", &root_key, &[ca]);
+        let leaf = issue(
+            "localhost",
+            &["localhost"],
+            &server_key,
+            "root",
+            &root_key,
+            &[],
+        );
+        let trusted = std::slice::from_ref(&root);
+
+        let (client, server) = loopback(
+            server_config(vec![leaf.clone()], &server_key),
+            "localhost",
+            trusted,
+        );
+        assert!(client.is_ok());
+        assert_eq!(server.unwrap(), Vec::<Vec<u8>>::new());
+
+        // 主机名不匹配、证书不被信任、没有被信任的证书
+        let (client, server) = loopback(
+            server_config(vec![leaf.clone()], &server_key),
+            "example.com",
+            trusted,
+        );
+        assert!(matches!(client, Err(TLSError::BadCertificate)));
+        assert!(matches!(
+            server,
+            Err(TLSError::Alert(ALERT_BAD_CERTIFICATE))
+        ));
+        let other = issue("root", &[], &p256(3), "root", &p256(3), &[]);
+        let (client, _) = loopback(
+            server_config(vec![leaf.clone()], &server_key),
+            "localhost",
+            &[other],
+        );
+        assert!(matches!(client, Err(TLSError::BadCertificate)));
+        assert!(matches!(
+            connect(std::io::Cursor::new(vec![]), "localhost", &[]),
+            Err(TLSError::BadCertificate)
+        ));
+
+        // 证书有效，但服务端的私钥不是证书的私钥
+        let (client, _) = loopback(server_config(vec![leaf], &p256(4)), "localhost", trusted);
+        assert!(matches!(client, Err(TLSError::BadCertificate)));
+    }
+}
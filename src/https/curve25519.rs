@@ -0,0 +1,250 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! c25519 模块保持了原项目基于裸指针的接口，本模块为其提供安全的封装
+//! 本项目的其它模块应该使用本模块，而非直接调用 c25519 模块
+//!
+//! Ed25519 的私钥以 64 字节表示：32 字节的种子 + 32 字节的公钥
+//! 这和 Go 等语言的标准库一致
+//!
+//! Ed25519，参见：https://datatracker.ietf.org/doc/html/rfc8032
+//! X25519，参见：https://datatracker.ietf.org/doc/html/rfc7748
+
+#![allow(dead_code)]
+
+use super::c25519;
+use super::der::{self, DerReader};
+
+pub struct Ed25519KeyPair {
+    pub private_key: [u8; 64],
+    pub public_key: [u8; 32],
+}
+impl Ed25519KeyPair {
+    pub fn from_seed(seed: &[u8; 32]) -> Self {
+        let mut seed = *seed;
+        let mut private_key = [0; 64];
+        let mut public_key = [0; 32];
+        unsafe {
+            c25519::compact_ed25519_keygen(
+                private_key.as_mut_ptr(),
+                public_key.as_mut_ptr(),
+                seed.as_mut_ptr(),
+            )
+        };
+        Ed25519KeyPair {
+            private_key,
+            public_key,
+        }
+    }
+    pub fn seed(&self) -> [u8; 32] {
+        self.private_key[..32].try_into().unwrap()
+    }
+    /// 以 PKCS#8 格式 (DER) 编码私钥
+    /// See: https://datatracker.ietf.org/doc/html/rfc8410#section-7
+    pub fn to_pkcs8(&self) -> Vec<u8> {
+        der::sequence(&[
+            der::small_integer(0),
+            der::sequence(&[der::oid(der::OID_ED25519)]),
+            der::octet_string(&der::octet_string(&self.seed())),
+        ])
+    }
+    /// 解析 PKCS#8 格式 (DER) 的私钥，如果不是 Ed25519 私钥，返回 None
+    pub fn from_pkcs8(data: &[u8]) -> Option<Self> {
+        let key = DerReader::new(data).read_expect(der::SEQUENCE).ok()?;
+        let mut reader = key.reader();
+        reader.read_expect(der::INTEGER).ok()?;
+        let algorithm = reader.read_expect(der::SEQUENCE).ok()?;
+        if algorithm.reader().read().ok()?.oid().ok()? != der::OID_ED25519 {
+            return None;
+        }
+        let private_key = reader.read_expect(der::OCTET_STRING).ok()?;
+        let seed = DerReader::new(private_key.content)
+            .read_expect(der::OCTET_STRING)
+            .ok()?;
+        Some(Self::from_seed(seed.content.try_into().ok()?))
+    }
+    pub fn sign(&self, message: &[u8]) -> [u8; 64] {
+        let mut signature = [0; 64];
+        let mut private_key = self.private_key;
+        unsafe {
+            c25519::compact_ed25519_sign(
+                signature.as_mut_ptr(),
+                private_key.as_mut_ptr(),
+                message.as_ptr(),
+                message.len() as u32,
+            )
+        };
+        signature
+    }
+}
+
+pub fn ed25519_verify(public_key: &[u8; 32], message: &[u8], signature: &[u8; 64]) -> bool {
+    let mut public_key = *public_key;
+    let mut signature = *signature;
+    unsafe {
+        c25519::compact_ed25519_verify(
+            signature.as_mut_ptr(),
+            public_key.as_mut_ptr(),
+            message.as_ptr(),
+            message.len() as u32,
+        )
+    }
+}
+
+/// 返回 (私钥, 公钥)
+pub fn x25519_keypair(seed: &[u8; 32]) -> ([u8; 32], [u8; 32]) {
+    let mut seed = *seed;
+    let mut private_key = [0; 32];
+    let mut public_key = [0; 32];
+    unsafe {
+        c25519::compact_x25519_keygen(
+            private_key.as_mut_ptr(),
+            public_key.as_mut_ptr(),
+            seed.as_mut_ptr(),
+        )
+    };
+    (private_key, public_key)
+}
+
+/// 按照 RFC 7748 的要求，忽略对方公钥的最高位，c25519 模块本身不会这样做
+pub fn x25519_shared(private_key: &[u8; 32], their_public_key: &[u8; 32]) -> [u8; 32] {
+    let mut their_public_key = *their_public_key;
+    their_public_key[31] &= 0x7f;
+    let mut shared = [0; 32];
+    unsafe {
+        c25519::compact_x25519_shared(
+            shared.as_mut_ptr(),
+            private_key.as_ptr(),
+            their_public_key.as_ptr(),
+        )
+    };
+    shared
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_ed25519_rfc8032() {
+        // RFC 8032 7.1 TEST 2
+        let seed = from_hex("4ccd089b28ff96da9db6c346ec114e0f5b8a319f35aba624da8cf6ed4fb8a6fb");
+        let pair = Ed25519KeyPair::from_seed(&seed.try_into().unwrap());
+        assert_eq!(
+            to_hex(&pair.public_key),
+            "3d4017c3e843895a92b70aa74d1b7ebc9c982ccf2ec4968cc0cd55f12af4660c"
+        );
+        let signature = pair.sign(&[0x72]);
+        assert_eq!(
+            to_hex(&signature),
+            "92a009a9f0d4cab8720e820b5f642540a2b27b5416503f8fb3762223ebdb69da\
+             085ac1e43e15996e458f3613d0f11d8c387b2eaeb4302aeeb00d291612bb0c00"
+        );
+        assert!(ed25519_verify(&pair.public_key, &[0x72], &signature));
+        assert!(!ed25519_verify(&pair.public_key, &[0x73], &signature));
+        assert_eq!(
+            Ed25519KeyPair::from_pkcs8(&pair.to_pkcs8())
+                .unwrap()
+                .public_key,
+            pair.public_key
+        );
+    }
+
+    #[test]
+    fn test_ed25519_long_message() {
+        // 超过一个 SHA-512 分组的消息，结果与其它实现交叉验证
+        let seed: Vec<u8> = (0..32).collect();
+        let pair = Ed25519KeyPair::from_seed(&seed.try_into().unwrap());
+        let message: Vec<u8> = (0..300).map(|i| (i % 251) as u8).collect();
+        let signature = pair.sign(&message);
+        assert_eq!(
+            to_hex(&signature),
+            "bee060e289b2e20e361555db5f92385da7711359721295c9bf0c5804442bbf98\
+             a07212fbffd800d76eb996d28c0282993e431dba9048e2ea20a9ea6cdd235a02"
+        );
+        assert!(ed25519_verify(&pair.public_key, &message, &signature));
+    }
+
+    #[test]
+    fn test_x25519_rfc7748() {
+        // RFC 7748 6.1
+        let alice = from_hex("77076d0a7318a57d3c16c17251b26645df4c2f87ebc0992ab177fba51db92c2a");
+        let bob = from_hex("5dab087e624a8a4b79e17f8b83800ee66f3bb1292618b6fd1c2f8b27ff88e0eb");
+        let (alice_private, alice_public) = x25519_keypair(&alice.try_into().unwrap());
+        let (bob_private, bob_public) = x25519_keypair(&bob.try_into().unwrap());
+        assert_eq!(
+            to_hex(&alice_public),
+            "8520f0098930a754748b7ddcb43ef75a0dbf3a0d26381af4eba4a98eaa9b4e6a"
+        );
+        let shared = x25519_shared(&alice_private, &bob_public);
+        assert_eq!(shared, x25519_shared(&bob_private, &alice_public));
+        assert_eq!(
+            to_hex(&shared),
+            "4a5d9d5ba4ce2de1728e3bf480350f25e07e21c947d19e3376f09b3c1e161742"
+        );
+    }
+
+    #[test]
+    fn test_ed25519_rfc8032_more() {
+        // RFC 8032 7.1 TEST 1 (空消息) 和 TEST 3
+        for (seed, public_key, message, signature) in [
+            (
+                "9d61b19deffd5a60ba844af492ec2cc44449c5697b326919703bac031cae7f60",
+                "d75a980182b10ab7d54bfed3c964073a0ee172f3daa62325af021a68f707511a",
+                "",
+                "e5564300c360ac729086e2cc806e828a84877f1eb8e5d974d873e065224901555fb8821590a33bacc61e39701cf9b46bd25bf5f0595bbe24655141438e7a100b",
+            ),
+            (
+                "c5aa8df43f9f837bedb7442f31dcb7b166d38535076f094b85ce3a2e0b4458f7",
+                "fc51cd8e6218a1a38da47ed00230f0580816ed13ba3303ac5deb911548908025",
+                "af82",
+                "6291d657deec24024827e69c3abe01a30ce548a284743a445e3680d7db5ac3ac18ff9b538d16f290ae67f760984dc6594a7c15e9716ed28dc027beceea1ec40a",
+            ),
+        ] {
+            let pair = Ed25519KeyPair::from_seed(&from_hex(seed).try_into().unwrap());
+            assert_eq!(to_hex(&pair.public_key), public_key);
+            let signature_bytes = pair.sign(&from_hex(message));
+            assert_eq!(to_hex(&signature_bytes), signature);
+            assert!(ed25519_verify(
+                &pair.public_key,
+                &from_hex(message),
+                &signature_bytes
+            ));
+        }
+    }
+
+    #[test]
+    fn test_x25519_rfc7748_scalar_mult() {
+        // RFC 7748 5.2 ，私钥没有被 clamp ，公钥的最高位被置为 1
+        for (scalar, u, output) in [
+            (
+                "a546e36bf0527c9d3b16154b82465edd62144c0ac1fc5a18506a2244ba449ac4",
+                "e6db6867583030db3594c1a424b15f7c726624ec26b3353b10a903a6d0ab1c4c",
+                "c3da55379de9c6908e94ea4df28d084f32eccf03491c71f754b4075577a28552",
+            ),
+            (
+                "4b66e9d4d1b4673c5ad22691957d6af5c11b6421e0ea01d42ca4169e7918ba0d",
+                "e5210f12786811d3f4b7959d0538ae2c31dbe7106fc03c3efc4cd549c715a493",
+                "95cbde9476e8907d7aade45cb4b873f88b595a68799fa152e6f8f7647aac7957",
+            ),
+        ] {
+            let (private_key, _) = x25519_keypair(&from_hex(scalar).try_into().unwrap());
+            let shared = x25519_shared(&private_key, &from_hex(u).try_into().unwrap());
+            assert_eq!(to_hex(&shared), output);
+        }
+    }
+}
@@ -0,0 +1,397 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供 ASN.1 DER 编码的构造和解析，以及 PEM 格式的转换
+//! 证书、证书签名请求 (CSR)、PKCS#8 私钥等都以 DER 编码
+//!
+//! 构造时，每个函数返回一个完整的 TLV (tag-length-value) ，例如：
+//! ```
+//! let csr_info = sequence(&[small_integer(0), name, spki, context(0, true, &[])]);
+//! ```
+//!
+//! 解析时，DerReader 每次读取一个 TLV ，DerObject::reader 可以进入一个构造类型的内部
+//!
+//! ASN.1 DER，参见：https://www.itu.int/rec/T-REC-X.690
+//! PEM，参见：https://datatracker.ietf.org/doc/html/rfc7468
+
+#![allow(dead_code)]
+
+use crate::drop::base64;
+
+pub const BOOLEAN: u8 = 0x01;
+pub const INTEGER: u8 = 0x02;
+pub const BIT_STRING: u8 = 0x03;
+pub const OCTET_STRING: u8 = 0x04;
+pub const NULL: u8 = 0x05;
+pub const OID: u8 = 0x06;
+pub const UTF8_STRING: u8 = 0x0c;
+pub const PRINTABLE_STRING: u8 = 0x13;
+pub const IA5_STRING: u8 = 0x16;
+pub const UTC_TIME: u8 = 0x17;
+pub const GENERALIZED_TIME: u8 = 0x18;
+pub const SEQUENCE: u8 = 0x30;
+pub const SET: u8 = 0x31;
+
+/// Ed25519 的算法标识符 1.3.101.112
+pub const OID_ED25519: &[u64] = &[1, 3, 101, 112];
+/// X.509 Name 中的 commonName 2.5.4.3
+pub const OID_COMMON_NAME: &[u64] = &[2, 5, 4, 3];
+/// PKCS#9 extensionRequest 1.2.840.113549.1.9.14
+pub const OID_EXTENSION_REQUEST: &[u64] = &[1, 2, 840, 113549, 1, 9, 14];
+/// subjectAltName 2.5.29.17
+pub const OID_SUBJECT_ALT_NAME: &[u64] = &[2, 5, 29, 17];
+/// rsaEncryption 1.2.840.113549.1.1.1
+pub const OID_RSA_ENCRYPTION: &[u64] = &[1, 2, 840, 113549, 1, 1, 1];
+/// sha256WithRSAEncryption 1.2.840.113549.1.1.11
+pub const OID_SHA256_WITH_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 11];
+/// sha384WithRSAEncryption 1.2.840.113549.1.1.12
+pub const OID_SHA384_WITH_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 12];
+/// sha512WithRSAEncryption 1.2.840.113549.1.1.13
+pub const OID_SHA512_WITH_RSA: &[u64] = &[1, 2, 840, 113549, 1, 1, 13];
+/// SHA-256 2.16.840.1.101.3.4.2.1
+pub const OID_SHA256: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 1];
+/// SHA-384 2.16.840.1.101.3.4.2.2
+pub const OID_SHA384: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 2];
+/// SHA-512 2.16.840.1.101.3.4.2.3
+pub const OID_SHA512: &[u64] = &[2, 16, 840, 1, 101, 3, 4, 2, 3];
+/// 椭圆曲线公钥 id-ecPublicKey 1.2.840.10045.2.1
+pub const OID_EC_PUBLIC_KEY: &[u64] = &[1, 2, 840, 10045, 2, 1];
+/// P-256 曲线 prime256v1 1.2.840.10045.3.1.7
+pub const OID_P256: &[u64] = &[1, 2, 840, 10045, 3, 1, 7];
+/// P-384 曲线 secp384r1 1.3.132.0.34
+pub const OID_P384: &[u64] = &[1, 3, 132, 0, 34];
+/// ecdsa-with-SHA256 1.2.840.10045.4.3.2
+pub const OID_ECDSA_SHA256: &[u64] = &[1, 2, 840, 10045, 4, 3, 2];
+/// ecdsa-with-SHA384 1.2.840.10045.4.3.3
+pub const OID_ECDSA_SHA384: &[u64] = &[1, 2, 840, 10045, 4, 3, 3];
+/// ecdsa-with-SHA512 1.2.840.10045.4.3.4
+pub const OID_ECDSA_SHA512: &[u64] = &[1, 2, 840, 10045, 4, 3, 4];
+
+/// 这个错误运用于一切解析失败的情况
+#[derive(Debug)]
+pub struct DerError;
+
+pub fn encode(tag: u8, content: &[u8]) -> Vec<u8> {
+    let mut vec = vec![tag];
+    let len = content.len();
+    if len < 0x80 {
+        vec.push(len as u8);
+    } else {
+        let bytes = len.to_be_bytes();
+        let skip = bytes.iter().take_while(|a| **a == 0).count();
+        vec.push(0x80 | (bytes.len() - skip) as u8);
+        vec.extend(&bytes[skip..]);
+    }
+    vec.extend(content);
+    vec
+}
+
+pub fn sequence(items: &[Vec<u8>]) -> Vec<u8> {
+    encode(SEQUENCE, &items.concat())
+}
+
+pub fn set(items: &[Vec<u8>]) -> Vec<u8> {
+    encode(SET, &items.concat())
+}
+
+/// 以大端序的无符号整数编码，会去掉多余的前导零，并在需要时补一个零以保证其为正数
+pub fn integer(bytes: &[u8]) -> Vec<u8> {
+    let skip = bytes.iter().take_while(|a| **a == 0).count();
+    let bytes = &bytes[skip.min(bytes.len().saturating_sub(1))..];
+    let mut content = vec![];
+    if bytes.is_empty() || bytes[0] & 0x80 != 0 {
+        content.push(0);
+    }
+    content.extend(bytes);
+    encode(INTEGER, &content)
+}
+
+pub fn small_integer(n: u64) -> Vec<u8> {
+    integer(&n.to_be_bytes())
+}
+
+pub fn oid(arcs: &[u64]) -> Vec<u8> {
+    let mut content = vec![(arcs[0] * 40 + arcs[1]) as u8];
+    for arc in &arcs[2..] {
+        let mut bytes = vec![(arc & 0x7f) as u8];
+        let mut arc = arc >> 7;
+        while arc != 0 {
+            bytes.push((arc & 0x7f) as u8 | 0x80);
+            arc >>= 7;
+        }
+        content.extend(bytes.iter().rev());
+    }
+    encode(OID, &content)
+}
+
+/// 没有未使用的位的位串，这是公钥和签名的情况
+pub fn bit_string(bytes: &[u8]) -> Vec<u8> {
+    let mut content = vec![0];
+    content.extend(bytes);
+    encode(BIT_STRING, &content)
+}
+
+pub fn octet_string(bytes: &[u8]) -> Vec<u8> {
+    encode(OCTET_STRING, bytes)
+}
+
+pub fn utf8_string(str: &str) -> Vec<u8> {
+    encode(UTF8_STRING, str.as_bytes())
+}
+
+/// 上下文相关的标签，例如 `[0]`
+/// constructed 为 true 时是显式标签或构造类型，否则是隐式标签的原始类型
+pub fn context(n: u8, constructed: bool, content: &[u8]) -> Vec<u8> {
+    encode(0x80 | if constructed { 0x20 } else { 0 } | n, content)
+}
+
+/// 一个被解析的 TLV
+/// raw: 包括标签和长度在内的完整编码，验证签名时需要用到
+#[derive(Debug, Clone, Copy)]
+pub struct DerObject<'a> {
+    pub tag: u8,
+    pub content: &'a [u8],
+    pub raw: &'a [u8],
+}
+impl<'a> DerObject<'a> {
+    pub fn reader(&self) -> DerReader<'a> {
+        DerReader::new(self.content)
+    }
+
+    pub fn oid(&self) -> Result<Vec<u64>, DerError> {
+        if self.tag != OID || self.content.is_empty() {
+            return Err(DerError);
+        }
+        let mut arcs = vec![
+            (self.content[0] / 40).min(2) as u64,
+            self.content[0] as u64 - (self.content[0] / 40).min(2) as u64 * 40,
+        ];
+        let mut arc: u64 = 0;
+        for byte in &self.content[1..] {
+            arc = arc.checked_shl(7).ok_or(DerError)? | (byte & 0x7f) as u64;
+            if byte & 0x80 == 0 {
+                arcs.push(arc);
+                arc = 0;
+            }
+        }
+        Ok(arcs)
+    }
+
+    /// 返回去掉了“未使用的位数”字节的位串内容
+    pub fn bit_string(&self) -> Result<&'a [u8], DerError> {
+        if self.tag != BIT_STRING || self.content.first() != Some(&0) {
+            return Err(DerError);
+        }
+        Ok(&self.content[1..])
+    }
+
+    pub fn string(&self) -> Result<&'a str, DerError> {
+        match self.tag {
+            UTF8_STRING | PRINTABLE_STRING | IA5_STRING => {
+                std::str::from_utf8(self.content).map_err(|_| DerError)
+            }
+            _ => Err(DerError),
+        }
+    }
+
+    /// 把 UTCTime 或 GeneralizedTime 转换为 Unix 时间戳，只支持以 `Z` 结尾的格式
+    pub fn time(&self) -> Result<i64, DerError> {
+        let str = std::str::from_utf8(self.content).map_err(|_| DerError)?;
+        let str = str.strip_suffix('Z').ok_or(DerError)?;
+        if !str.bytes().all(|c| c.is_ascii_digit()) {
+            return Err(DerError);
+        }
+        let (year, rest) = match (self.tag, str.len()) {
+            (UTC_TIME, 12) => {
+                let year: i64 = str[..2].parse().unwrap();
+                (if year < 50 { 2000 + year } else { 1900 + year }, &str[2..])
+            }
+            (GENERALIZED_TIME, 14) => (str[..4].parse().unwrap(), &str[4..]),
+            _ => return Err(DerError),
+        };
+        let field = |i: usize| -> i64 { rest[i * 2..i * 2 + 2].parse().unwrap() };
+        let (month, day) = (field(0), field(1));
+        if !(1..=12).contains(&month) || !(1..=31).contains(&day) {
+            return Err(DerError);
+        }
+        Ok(days_from_civil(year, month, day) * 86400 + field(2) * 3600 + field(3) * 60 + field(4))
+    }
+}
+
+/// 从公历日期计算距 1970-01-01 的天数
+/// See: https://howardhinnant.github.io/date_algorithms.html#days_from_civil
+pub fn days_from_civil(year: i64, month: i64, day: i64) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let doy = (153 * (if month > 2 { month - 3 } else { month + 9 }) + 2) / 5 + day - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146097 + doe - 719468
+}
+
+pub struct DerReader<'a> {
+    data: &'a [u8],
+}
+impl<'a> DerReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        DerReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn peek_tag(&self) -> Option<u8> {
+        self.data.first().copied()
+    }
+
+    pub fn read(&mut self) -> Result<DerObject<'a>, DerError> {
+        let data = self.data;
+        if data.len() < 2 {
+            return Err(DerError);
+        }
+        let tag = data[0];
+        let (len, header) = if data[1] < 0x80 {
+            (data[1] as usize, 2)
+        } else {
+            let n = (data[1] & 0x7f) as usize;
+            if n == 0 || n > 4 || data.len() < 2 + n {
+                return Err(DerError);
+            }
+            let len = data[2..2 + n]
+                .iter()
+                .fold(0usize, |acc, a| acc << 8 | *a as usize);
+            (len, 2 + n)
+        };
+        let end = header.checked_add(len).ok_or(DerError)?;
+        if data.len() < end {
+            return Err(DerError);
+        }
+        self.data = &data[end..];
+        Ok(DerObject {
+            tag,
+            content: &data[header..end],
+            raw: &data[..end],
+        })
+    }
+
+    pub fn read_expect(&mut self, tag: u8) -> Result<DerObject<'a>, DerError> {
+        let object = self.read()?;
+        if object.tag != tag {
+            return Err(DerError);
+        }
+        Ok(object)
+    }
+
+    /// 如果下一个 TLV 的标签是 tag ，则读取它，否则什么都不做
+    pub fn read_optional(&mut self, tag: u8) -> Result<Option<DerObject<'a>>, DerError> {
+        if self.peek_tag() == Some(tag) {
+            Ok(Some(self.read()?))
+        } else {
+            Ok(None)
+        }
+    }
+}
+
+/// 把 DER 数据编码为 PEM 格式，每行 64 个字符
+pub fn pem_encode(label: &str, der: &[u8]) -> String {
+    let mut str = format!("-----BEGIN {}-----\n", label);
+    let encoded = base64::encode(der);
+    for line in encoded.as_bytes().chunks(64) {
+        str.push_str(std::str::from_utf8(line).unwrap());
+        str.push('\n');
+    }
+    str.push_str(&format!("-----END {}-----\n", label));
+    str
+}
+
+/// 解析文本中所有的 PEM 块，返回 (标签, DER 数据) 的列表，无法解码的块会被跳过
+pub fn pem_decode(str: &str) -> Vec<(String, Vec<u8>)> {
+    let mut vec = vec![];
+    let mut current: Option<(String, String)> = None;
+    for line in str.lines() {
+        let line = line.trim();
+        if let Some(label) = line
+            .strip_prefix("-----BEGIN ")
+            .and_then(|a| a.strip_suffix("-----"))
+        {
+            current = Some((label.to_owned(), String::new()));
+        } else if let Some(label) = line
+            .strip_prefix("-----END ")
+            .and_then(|a| a.strip_suffix("-----"))
+        {
+            if let Some((begin, body)) = current.take() {
+                if begin == label {
+                    if let Some(der) = base64::decode(&body) {
+                        vec.push((begin, der));
+                    }
+                }
+            }
+        } else if let Some((_, body)) = &mut current {
+            body.push_str(line);
+        }
+    }
+    vec
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    #[test]
+    fn test_der_encode() {
+        assert_eq!(to_hex(&oid(OID_ED25519)), "06032b6570");
+        assert_eq!(
+            to_hex(&oid(OID_EXTENSION_REQUEST)),
+            "06092a864886f70d01090e"
+        );
+        assert_eq!(to_hex(&small_integer(0)), "020100");
+        assert_eq!(to_hex(&small_integer(128)), "02020080");
+        assert_eq!(to_hex(&integer(&[0, 0, 1, 2])), "02020102");
+        assert_eq!(to_hex(&encode(OCTET_STRING, &[0; 200]))[..8], *"0481c800");
+        assert_eq!(to_hex(&encode(OCTET_STRING, &[0; 300]))[..8], *"0482012c");
+    }
+
+    #[test]
+    fn test_der_decode() {
+        let der = sequence(&[
+            oid(OID_SUBJECT_ALT_NAME),
+            encode(UTC_TIME, b"491231235959Z"),
+            encode(GENERALIZED_TIME, b"20500101000000Z"),
+            octet_string(&[7; 300]),
+        ]);
+        let mut reader = DerReader::new(&der);
+        let seq = reader.read_expect(SEQUENCE).unwrap();
+        assert!(reader.is_empty());
+        assert_eq!(seq.raw, &der[..]);
+        let mut reader = seq.reader();
+        assert_eq!(reader.read().unwrap().oid().unwrap(), OID_SUBJECT_ALT_NAME);
+        assert_eq!(reader.read().unwrap().time().unwrap(), 2524607999);
+        assert_eq!(reader.read().unwrap().time().unwrap(), 2524608000);
+        assert!(reader.read_optional(SEQUENCE).unwrap().is_none());
+        assert_eq!(reader.read_expect(OCTET_STRING).unwrap().content.len(), 300);
+        assert!(reader.read().is_err());
+
+        // 长度超过了剩余的数据
+        assert!(DerReader::new(&[0x30, 0x03, 0x01]).read().is_err());
+    }
+
+    #[test]
+    fn test_pem() {
+        let der: Vec<u8> = (0..100).collect();
+        let pem = pem_encode("CERTIFICATE", &der);
+        assert_eq!(pem.lines().nth(1).unwrap().len(), 64);
+        let text = pem.clone() + "garbage\n" + &pem_encode("PRIVATE KEY", &[1, 2, 3]);
+        let decoded = pem_decode(&text);
+        assert_eq!(decoded.len(), 2);
+        assert_eq!(decoded[0], ("CERTIFICATE".to_owned(), der));
+        assert_eq!(decoded[1], ("PRIVATE KEY".to_owned(), vec![1, 2, 3]));
+    }
+}
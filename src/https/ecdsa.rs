@@ -0,0 +1,554 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供 P-256 和 P-384 曲线上的 ECDSA 签名和验证
+//!
+//! 点以射影坐标 (X:Y:Z) 表示，坐标都是蒙哥马利形式，无穷远点是 (0:1:0)
+//! 点加法使用 a = -3 的完整公式，它对加倍和无穷远点同样适用，所以标量乘法中没有特殊情况，
+//! 配合 bignum 模块的掩码选择，签名的时间不依赖于私钥和 k
+//!
+//! 签名使用 RFC 6979 的确定性 k ，所以不依赖随机数的质量
+//! 签名是 r || s 的原始格式（JWS 使用它），signature_to_der 和 signature_from_der 可以和 DER 格式互相转换（X.509 和 TLS 使用它）
+//!
+//! 公钥是 SEC1 的未压缩格式 0x04 || x || y
+//! 私钥可以是 PKCS#8 格式，或者 SEC1 的 "EC PRIVATE KEY" 格式
+//!
+//! ECDSA，参见：https://www.secg.org/sec1-v2.pdf
+//! 完整的加法公式，参见：https://eprint.iacr.org/2015/1060 （Algorithm 4）
+//! 确定性 k ，参见：https://datatracker.ietf.org/doc/html/rfc6979
+//! 私钥格式，参见：https://datatracker.ietf.org/doc/html/rfc5915
+
+#![allow(dead_code)]
+
+use std::sync::OnceLock;
+
+use super::bignum::{self, Limbs, Modulus};
+use super::der::{self, DerReader};
+use super::hash::{HashFunction, Hmac, Sha256, Sha384};
+
+pub struct Curve {
+    pub oid: &'static [u64],
+    /// 坐标的模数
+    p: Modulus,
+    /// 基点的阶
+    n: Modulus,
+    /// 曲线方程 y^2 = x^3 - 3x + b 中的 b
+    b: Limbs,
+    g: Point,
+}
+
+#[derive(Clone)]
+struct Point {
+    x: Limbs,
+    y: Limbs,
+    z: Limbs,
+}
+
+fn from_hex(str: &str) -> Vec<u8> {
+    (0..str.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+        .collect()
+}
+
+impl Curve {
+    fn new(oid: &'static [u64], p: &str, n: &str, b: &str, gx: &str, gy: &str) -> Self {
+        let p = Modulus::new(&from_hex(p));
+        let mont = |a| p.to_mont(&p.decode(&from_hex(a)).unwrap());
+        let g = Point {
+            x: mont(gx),
+            y: mont(gy),
+            z: p.one(),
+        };
+        Curve {
+            oid,
+            b: mont(b),
+            n: Modulus::new(&from_hex(n)),
+            g,
+            p,
+        }
+    }
+
+    /// 坐标和标量的字节数
+    pub fn bytes(&self) -> usize {
+        self.p.bytes()
+    }
+
+    fn identity(&self) -> Point {
+        Point {
+            x: self.p.small(0),
+            y: self.p.one(),
+            z: self.p.small(0),
+        }
+    }
+
+    /// 完整的射影坐标加法，a = -3
+    fn add(&self, a: &Point, b: &Point) -> Point {
+        let f = &self.p;
+        let (x1, y1, z1, x2, y2, z2) = (&a.x, &a.y, &a.z, &b.x, &b.y, &b.z);
+        let t0 = f.mul(x1, x2);
+        let t1 = f.mul(y1, y2);
+        let t2 = f.mul(z1, z2);
+        let t3 = f.mul(&f.add(x1, y1), &f.add(x2, y2));
+        let t3 = f.sub(&t3, &f.add(&t0, &t1));
+        let t4 = f.mul(&f.add(y1, z1), &f.add(y2, z2));
+        let t4 = f.sub(&t4, &f.add(&t1, &t2));
+        let x3 = f.mul(&f.add(x1, z1), &f.add(x2, z2));
+        let y3 = f.sub(&x3, &f.add(&t0, &t2));
+        let z3 = f.mul(&self.b, &t2);
+        let x3 = f.sub(&y3, &z3);
+        let z3 = f.add(&x3, &x3);
+        let x3 = f.add(&x3, &z3);
+        let z3 = f.sub(&t1, &x3);
+        let x3 = f.add(&t1, &x3);
+        let y3 = f.mul(&self.b, &y3);
+        let t1 = f.add(&t2, &t2);
+        let t2 = f.add(&t1, &t2);
+        let y3 = f.sub(&f.sub(&y3, &t2), &t0);
+        let t1 = f.add(&y3, &y3);
+        let y3 = f.add(&t1, &y3);
+        let t1 = f.add(&t0, &t0);
+        let t0 = f.sub(&f.add(&t1, &t0), &t2);
+        let t1 = f.mul(&t4, &y3);
+        let t2 = f.mul(&t0, &y3);
+        let y3 = f.add(&f.mul(&x3, &z3), &t2);
+        let x3 = f.sub(&f.mul(&x3, &t3), &t1);
+        let z3 = f.add(&f.mul(&z3, &t4), &f.mul(&t3, &t0));
+        Point {
+            x: x3,
+            y: y3,
+            z: z3,
+        }
+    }
+
+    /// 返回 scalar * point ，scalar 是大端序的字节
+    /// 每一位都进行一次加倍和一次加法，然后根据该位选择结果
+    fn mul(&self, point: &Point, scalar: &[u8]) -> Point {
+        let mut result = self.identity();
+        for byte in scalar {
+            for i in (0..8).rev() {
+                result = self.add(&result, &result);
+                let sum = self.add(&result, point);
+                let mask = ((byte >> i) as u64 & 1).wrapping_neg();
+                result = Point {
+                    x: bignum::select(&result.x, &sum.x, mask),
+                    y: bignum::select(&result.y, &sum.y, mask),
+                    z: bignum::select(&result.z, &sum.z, mask),
+                };
+            }
+        }
+        result
+    }
+
+    /// 返回仿射坐标 (x, y) ，它们不是蒙哥马利形式，无穷远点返回 None
+    fn to_affine(&self, point: &Point) -> Option<(Limbs, Limbs)> {
+        if bignum::is_zero(&point.z) {
+            return None;
+        }
+        let z_inv = self.p.inv(&point.z);
+        Some((
+            self.p.to_normal(&self.p.mul(&point.x, &z_inv)),
+            self.p.to_normal(&self.p.mul(&point.y, &z_inv)),
+        ))
+    }
+
+    fn encode_point(&self, point: &Point) -> Option<Vec<u8>> {
+        let (x, y) = self.to_affine(point)?;
+        let mut out = vec![4];
+        out.extend(self.p.encode(&x));
+        out.extend(self.p.encode(&y));
+        Some(out)
+    }
+
+    /// 解析未压缩格式的点，并检查它在曲线上
+    fn decode_point(&self, data: &[u8]) -> Option<Point> {
+        let len = self.bytes();
+        if data.len() != 1 + 2 * len || data[0] != 4 {
+            return None;
+        }
+        let f = &self.p;
+        let x = f.to_mont(&f.decode(&data[1..1 + len])?);
+        let y = f.to_mont(&f.decode(&data[1 + len..])?);
+        let three_x = f.add(&f.add(&x, &x), &x);
+        let rhs = f.add(&f.sub(&f.mul(&f.mul(&x, &x), &x), &three_x), &self.b);
+        if f.mul(&y, &y) != rhs {
+            return None;
+        }
+        Some(Point { x, y, z: f.one() })
+    }
+
+    /// 把摘要转换为小于 n 的整数，摘要比 n 长时取最左边的部分
+    fn digest_to_scalar(&self, digest: &[u8]) -> Limbs {
+        let digest = &digest[..digest.len().min(self.n.bytes())];
+        self.n.decode_reduced(digest).unwrap()
+    }
+
+    /// 解析 [1, n-1] 范围内的标量
+    fn scalar(&self, bytes: &[u8]) -> Option<Limbs> {
+        let a = self.n.decode(bytes)?;
+        (!bignum::is_zero(&a)).then_some(a)
+    }
+}
+
+pub fn p256() -> &'static Curve {
+    static CURVE: OnceLock<Curve> = OnceLock::new();
+    CURVE.get_or_init(|| {
+        Curve::new(
+            der::OID_P256,
+            "ffffffff00000001000000000000000000000000ffffffffffffffffffffffff",
+            "ffffffff00000000ffffffffffffffffbce6faada7179e84f3b9cac2fc632551",
+            "5ac635d8aa3a93e7b3ebbd55769886bc651d06b0cc53b0f63bce3c3e27d2604b",
+            "6b17d1f2e12c4247f8bce6e563a440f277037d812deb33a0f4a13945d898c296",
+            "4fe342e2fe1a7f9b8ee7eb4a7c0f9e162bce33576b315ececbb6406837bf51f5",
+        )
+    })
+}
+
+pub fn p384() -> &'static Curve {
+    static CURVE: OnceLock<Curve> = OnceLock::new();
+    CURVE.get_or_init(|| {
+        Curve::new(
+            der::OID_P384,
+            "fffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffffe\
+             ffffffff0000000000000000ffffffff",
+            "ffffffffffffffffffffffffffffffffffffffffffffffffc7634d81f4372ddf\
+             581a0db248b0a77aecec196accc52973",
+            "b3312fa7e23ee7e4988e056be3f82d19181d9c6efe8141120314088f5013875a\
+             c656398d8a2ed19d2a85c8edd3ec2aef",
+            "aa87ca22be8b05378eb1c71ef320ad746e1d3b628ba79b9859f741e082542a38\
+             5502f25dbf55296c3a545e3872760ab7",
+            "3617de4a96262c6f5d9e98bf9292dc29f8f41dbd289a147ce9da3113b5f0b8c0\
+             0a60b1ce1d7e819d7a431d7c90ea0e5f",
+        )
+    })
+}
+
+/// 根据曲线的 OID 返回曲线，不支持的曲线返回 None
+pub fn curve_from_oid(oid: &[u64]) -> Option<&'static Curve> {
+    if oid == der::OID_P256 {
+        Some(p256())
+    } else if oid == der::OID_P384 {
+        Some(p384())
+    } else {
+        None
+    }
+}
+
+pub struct EcdsaKeyPair {
+    pub curve: &'static Curve,
+    /// 大端序的私钥 d ，长度和曲线的坐标相同
+    pub private_key: Vec<u8>,
+    /// 未压缩格式的公钥
+    pub public_key: Vec<u8>,
+}
+
+impl EcdsaKeyPair {
+    /// 如果私钥不在 [1, n-1] 范围内，返回 None
+    pub fn from_private_key(curve: &'static Curve, private_key: &[u8]) -> Option<Self> {
+        let d = curve.scalar(private_key)?;
+        let private_key = curve.n.encode(&d);
+        let public_key = curve.encode_point(&curve.mul(&curve.g, &private_key))?;
+        Some(EcdsaKeyPair {
+            curve,
+            private_key,
+            public_key,
+        })
+    }
+
+    pub fn generate(curve: &'static Curve) -> std::io::Result<Self> {
+        loop {
+            let random = crate::drop::random::get_random_bytes(curve.bytes())?;
+            if let Some(key) = Self::from_private_key(curve, &random) {
+                return Ok(key);
+            }
+        }
+    }
+
+    /// 算法标识符 (id-ecPublicKey, 曲线)
+    fn algorithm(&self) -> Vec<u8> {
+        der::sequence(&[der::oid(der::OID_EC_PUBLIC_KEY), der::oid(self.curve.oid)])
+    }
+
+    pub fn subject_public_key_info(&self) -> Vec<u8> {
+        der::sequence(&[self.algorithm(), der::bit_string(&self.public_key)])
+    }
+
+    /// 以 PKCS#8 格式 (DER) 编码私钥，内部的 ECPrivateKey 带有公钥而不带曲线参数，这和 OpenSSL 一致
+    pub fn to_pkcs8(&self) -> Vec<u8> {
+        let ec_private_key = der::sequence(&[
+            der::small_integer(1),
+            der::octet_string(&self.private_key),
+            der::context(1, true, &der::bit_string(&self.public_key)),
+        ]);
+        der::sequence(&[
+            der::small_integer(0),
+            self.algorithm(),
+            der::octet_string(&ec_private_key),
+        ])
+    }
+
+    /// 解析 PKCS#8 格式 (DER) 的私钥，如果不是 P-256 或 P-384 私钥，返回 None
+    pub fn from_pkcs8(data: &[u8]) -> Option<Self> {
+        let key = DerReader::new(data).read_expect(der::SEQUENCE).ok()?;
+        let mut reader = key.reader();
+        reader.read_expect(der::INTEGER).ok()?;
+        let mut algorithm = reader.read_expect(der::SEQUENCE).ok()?.reader();
+        if algorithm.read().ok()?.oid().ok()? != der::OID_EC_PUBLIC_KEY {
+            return None;
+        }
+        let curve = curve_from_oid(&algorithm.read().ok()?.oid().ok()?)?;
+        let private_key = reader.read_expect(der::OCTET_STRING).ok()?;
+        Self::from_ec_private_key(private_key.content, Some(curve))
+    }
+
+    /// 解析 SEC1 格式 (DER) 的私钥，即 PEM 中的 "EC PRIVATE KEY"
+    pub fn from_sec1(data: &[u8]) -> Option<Self> {
+        Self::from_ec_private_key(data, None)
+    }
+
+    /// curve: PKCS#8 的算法标识符中的曲线，为 None 时 ECPrivateKey 必须带有曲线参数
+    fn from_ec_private_key(data: &[u8], curve: Option<&'static Curve>) -> Option<Self> {
+        let key = DerReader::new(data).read_expect(der::SEQUENCE).ok()?;
+        let mut reader = key.reader();
+        if reader.read_expect(der::INTEGER).ok()?.content != [1] {
+            return None;
+        }
+        let private_key = reader.read_expect(der::OCTET_STRING).ok()?;
+        let parameters = match reader.read_optional(0xa0).ok()? {
+            Some(a) => Some(curve_from_oid(&a.reader().read().ok()?.oid().ok()?)?),
+            None => None,
+        };
+        let curve = match (curve, parameters) {
+            (Some(a), Some(b)) if !std::ptr::eq(a, b) => return None,
+            (Some(a), _) | (None, Some(a)) => a,
+            (None, None) => return None,
+        };
+        Self::from_private_key(curve, private_key.content)
+    }
+
+    /// 签名 message ，P-256 使用 SHA-256 ，P-384 使用 SHA-384
+    /// 返回 r || s 格式的签名
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        if std::ptr::eq(self.curve, p384()) {
+            self.sign_digest::<Sha384>(&Sha384::digest(message))
+        } else {
+            self.sign_digest::<Sha256>(&Sha256::digest(message))
+        }
+    }
+
+    /// H: 用于生成确定性 k 的散列函数，它应该和生成 digest 的散列函数相同
+    pub fn sign_digest<H: HashFunction>(&self, digest: &[u8]) -> Vec<u8> {
+        let curve = self.curve;
+        let n = &curve.n;
+        let e = n.to_mont(&curve.digest_to_scalar(digest));
+        let d = n.to_mont(&n.decode(&self.private_key).unwrap());
+
+        // RFC 6979 3.2
+        let mut seed = self.private_key.clone();
+        seed.extend(n.encode(&curve.digest_to_scalar(digest)));
+        let mut v = vec![1; H::OUTPUT_SIZE];
+        let mut k = vec![0; H::OUTPUT_SIZE];
+        for separator in [0, 1] {
+            let mut mac = Hmac::<H>::new(&k);
+            mac.update(&v);
+            mac.update(&[separator]);
+            mac.update(&seed);
+            k = mac.finalize();
+            v = super::hash::hmac::<H>(&k, &v);
+        }
+        loop {
+            let mut t = vec![];
+            while t.len() < curve.bytes() {
+                v = super::hash::hmac::<H>(&k, &v);
+                t.extend(&v);
+            }
+            t.truncate(curve.bytes());
+            if let Some(signature) = self.sign_with_k(&t, &e, &d) {
+                return signature;
+            }
+            let mut mac = Hmac::<H>::new(&k);
+            mac.update(&v);
+            mac.update(&[0]);
+            k = mac.finalize();
+            v = super::hash::hmac::<H>(&k, &v);
+        }
+    }
+
+    /// e, d: 蒙哥马利形式的摘要和私钥
+    /// 如果 k 不在 [1, n-1] 范围内，或者 r 或 s 为 0 ，返回 None
+    fn sign_with_k(&self, k: &[u8], e: &[u64], d: &[u64]) -> Option<Vec<u8>> {
+        let curve = self.curve;
+        let n = &curve.n;
+        let k = curve.scalar(k)?;
+        let (x, _) = curve.to_affine(&curve.mul(&curve.g, &n.encode(&k)))?;
+        let r = n.decode_reduced(&curve.p.encode(&x))?;
+        let k_inv = n.inv(&n.to_mont(&k));
+        let s = n.to_normal(&n.mul(&k_inv, &n.add(e, &n.mul(&n.to_mont(&r), d))));
+        if bignum::is_zero(&r) || bignum::is_zero(&s) {
+            return None;
+        }
+        let mut signature = n.encode(&r);
+        signature.extend(n.encode(&s));
+        Some(signature)
+    }
+}
+
+/// 验证一个 r || s 格式的签名
+/// public_key: 未压缩格式的公钥
+/// digest: 被签名的消息的摘要
+pub fn verify(curve: &Curve, public_key: &[u8], digest: &[u8], signature: &[u8]) -> bool {
+    let len = curve.bytes();
+    if signature.len() != 2 * len {
+        return false;
+    }
+    let (Some(q), Some(r), Some(s)) = (
+        curve.decode_point(public_key),
+        curve.scalar(&signature[..len]),
+        curve.scalar(&signature[len..]),
+    ) else {
+        return false;
+    };
+    let n = &curve.n;
+    let w = n.inv(&n.to_mont(&s));
+    let u1 = n.to_normal(&n.mul(&n.to_mont(&curve.digest_to_scalar(digest)), &w));
+    let u2 = n.to_normal(&n.mul(&n.to_mont(&r), &w));
+    let point = curve.add(
+        &curve.mul(&curve.g, &n.encode(&u1)),
+        &curve.mul(&q, &n.encode(&u2)),
+    );
+    let Some((x, _)) = curve.to_affine(&point) else {
+        return false;
+    };
+    n.decode_reduced(&curve.p.encode(&x)) == Some(r)
+}
+
+/// 把 r || s 格式的签名转换为 DER 格式的 Ecdsa-Sig-Value
+pub fn signature_to_der(signature: &[u8]) -> Vec<u8> {
+    let (r, s) = signature.split_at(signature.len() / 2);
+    der::sequence(&[der::integer(r), der::integer(s)])
+}
+
+/// 把 DER 格式的签名转换为 r || s 格式，len 是曲线坐标的字节数
+pub fn signature_from_der(data: &[u8], len: usize) -> Option<Vec<u8>> {
+    let sequence = DerReader::new(data).read_expect(der::SEQUENCE).ok()?;
+    let mut reader = sequence.reader();
+    let mut signature = vec![];
+    for _ in 0..2 {
+        let integer = reader.read_expect(der::INTEGER).ok()?.content;
+        let skip = integer.iter().take_while(|a| **a == 0).count();
+        let integer = &integer[skip..];
+        if integer.len() > len {
+            return None;
+        }
+        signature.extend(std::iter::repeat_n(0, len - integer.len()));
+        signature.extend(integer);
+    }
+    reader.is_empty().then_some(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    // RFC 6979 A.2.5
+    const P256_PRIVATE_KEY: &str =
+        "c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721";
+
+    #[test]
+    fn test_p256_rfc6979() {
+        let key = EcdsaKeyPair::from_private_key(p256(), &from_hex(P256_PRIVATE_KEY)).unwrap();
+        assert_eq!(
+            to_hex(&key.public_key),
+            "0460fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb6\
+             7903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299"
+        );
+        let signature = key.sign(b"sample");
+        assert_eq!(
+            to_hex(&signature),
+            "efd48b2aacb6a8fd1140dd9cd45e81d69d2c877b56aaf991c34d0ea84eaf3716\
+             f7cb1c942d657c41d436c7a1b6e29f65f3e900dbb9aff4064dc4ab2f843acda8"
+        );
+        let digest = Sha256::digest(b"sample");
+        assert!(verify(p256(), &key.public_key, &digest, &signature));
+        assert!(!verify(
+            p256(),
+            &key.public_key,
+            &Sha256::digest(b"other"),
+            &signature
+        ));
+        let mut bad = signature.clone();
+        bad[40] ^= 1;
+        assert!(!verify(p256(), &key.public_key, &digest, &bad));
+        let mut bad_key = key.public_key.clone();
+        bad_key[64] ^= 1;
+        assert!(!verify(p256(), &bad_key, &digest, &signature));
+    }
+
+    #[test]
+    fn test_p384_verify() {
+        // 由 Python 的 cryptography 库生成
+        let public_key = from_hex(
+            "04d8a17e55b8e2f47bc369fd9616695ce273dce4e307830a65f5a4a9aa8967c987aeea7ad365677a13bfd65c3fe5a66939bd\
+             c53542c5213a51345dba8220abb23c71659a4d850b3bbaff8d035bd08a59eb32099c96a9049e4d7d435cf675713bc8",
+        );
+        let signature = signature_from_der(
+            &from_hex(
+                "306402301ef80d41af73baa552345a64c63a5eaf583d62a9872e48c31ba25b31830f98e865de54ef9c61f36d805fb640b50a9d8f\
+                 0230066c1e7718f12898a5a4ff063d1c708b04305a816e907b49b9a5a29bf51e28c92594b677766a581e56c382949b120f81",
+            ),
+            48,
+        )
+        .unwrap();
+        let digest = Sha384::digest(b"sample");
+        assert!(verify(p384(), &public_key, &digest, &signature));
+        assert!(!verify(
+            p384(),
+            &public_key,
+            &Sha384::digest(b"other"),
+            &signature
+        ));
+        assert_eq!(
+            signature_from_der(&signature_to_der(&signature), 48),
+            Some(signature)
+        );
+    }
+
+    #[test]
+    fn test_key_formats() {
+        // 由 Python 的 cryptography 库生成
+        let pkcs8 = from_hex(
+            "308187020100301306072a8648ce3d020106082a8648ce3d030107046d306b0201010420c9afa9d845ba75166b5c2157\
+             67b1d6934e50c3db36e89b127b8a622b120f6721a1440342000460fed4ba255a9d31c961eb74c6356d68c049b8923b61\
+             fa6ce669622e60f29fb67903fe1008b8bc99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299",
+        );
+        let sec1 = from_hex(
+            "30770201010420c9afa9d845ba75166b5c215767b1d6934e50c3db36e89b127b8a622b120f6721a00a06082a8648ce3d\
+             030107a1440342000460fed4ba255a9d31c961eb74c6356d68c049b8923b61fa6ce669622e60f29fb67903fe1008b8bc\
+             99a41ae9e95628bc64f2f1b20c2d7e9f5177a3c294d4462299",
+        );
+        let key = EcdsaKeyPair::from_pkcs8(&pkcs8).unwrap();
+        assert_eq!(to_hex(&key.private_key), P256_PRIVATE_KEY);
+        assert_eq!(key.to_pkcs8(), pkcs8);
+        let key = EcdsaKeyPair::from_sec1(&sec1).unwrap();
+        assert_eq!(to_hex(&key.private_key), P256_PRIVATE_KEY);
+        assert!(EcdsaKeyPair::from_pkcs8(&sec1).is_none());
+
+        let key = EcdsaKeyPair::generate(p384()).unwrap();
+        let signature = key.sign(b"message");
+        assert!(verify(
+            p384(),
+            &key.public_key,
+            &Sha384::digest(b"message"),
+            &signature
+        ));
+        let key = EcdsaKeyPair::from_pkcs8(&key.to_pkcs8()).unwrap();
+        assert!(std::ptr::eq(key.curve, p384()));
+    }
+}
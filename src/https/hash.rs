@@ -0,0 +1,623 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供 TLS 及其它模块所需要的散列算法及基于散列的算法
+//! 所有的散列函数都实现了 HashFunction trait ，可以流式的输入数据
+//! 基于散列的算法（HMAC, PRF, HKDF）对 HashFunction 是泛型的
+//!
+//! 例如：
+//! ```
+//! let mut sha = Sha256::new();
+//! sha.update(b"a");
+//! sha.update(b"bc");
+//! let digest = sha.finalize(); // 等价于 Sha256::digest(b"abc")
+//! ```
+//!
+//! SHA-2，参见：https://datatracker.ietf.org/doc/html/rfc6234
+//! HMAC，参见：https://datatracker.ietf.org/doc/html/rfc2104
+//! TLS 1.2 PRF，参见：https://datatracker.ietf.org/doc/html/rfc5246#section-5
+//! HKDF，参见：https://datatracker.ietf.org/doc/html/rfc5869
+//! TLS 1.3 HKDF-Expand-Label，参见：https://datatracker.ietf.org/doc/html/rfc8446#section-7.1
+#![allow(dead_code)]
+
+pub trait HashFunction: Clone {
+    /// 内部分组的字节数，HMAC 需要用到
+    const BLOCK_SIZE: usize;
+    /// 输出的摘要的字节数
+    const OUTPUT_SIZE: usize;
+
+    fn new() -> Self;
+    fn update(&mut self, data: &[u8]);
+    fn finalize(self) -> Vec<u8>;
+
+    fn digest(data: &[u8]) -> Vec<u8> {
+        let mut hash = Self::new();
+        hash.update(data);
+        hash.finalize()
+    }
+}
+
+const SHA256_K: [u32; 64] = [
+    0x428a2f98, 0x71374491, 0xb5c0fbcf, 0xe9b5dba5, 0x3956c25b, 0x59f111f1, 0x923f82a4, 0xab1c5ed5,
+    0xd807aa98, 0x12835b01, 0x243185be, 0x550c7dc3, 0x72be5d74, 0x80deb1fe, 0x9bdc06a7, 0xc19bf174,
+    0xe49b69c1, 0xefbe4786, 0x0fc19dc6, 0x240ca1cc, 0x2de92c6f, 0x4a7484aa, 0x5cb0a9dc, 0x76f988da,
+    0x983e5152, 0xa831c66d, 0xb00327c8, 0xbf597fc7, 0xc6e00bf3, 0xd5a79147, 0x06ca6351, 0x14292967,
+    0x27b70a85, 0x2e1b2138, 0x4d2c6dfc, 0x53380d13, 0x650a7354, 0x766a0abb, 0x81c2c92e, 0x92722c85,
+    0xa2bfe8a1, 0xa81a664b, 0xc24b8b70, 0xc76c51a3, 0xd192e819, 0xd6990624, 0xf40e3585, 0x106aa070,
+    0x19a4c116, 0x1e376c08, 0x2748774c, 0x34b0bcb5, 0x391c0cb3, 0x4ed8aa4a, 0x5b9cca4f, 0x682e6ff3,
+    0x748f82ee, 0x78a5636f, 0x84c87814, 0x8cc70208, 0x90befffa, 0xa4506ceb, 0xbef9a3f7, 0xc67178f2,
+];
+
+const SHA256_INIT: [u32; 8] = [
+    0x6a09e667, 0xbb67ae85, 0x3c6ef372, 0xa54ff53a, 0x510e527f, 0x9b05688c, 0x1f83d9ab, 0x5be0cd19,
+];
+
+const SHA512_K: [u64; 80] = [
+    0x428a2f98d728ae22, 0x7137449123ef65cd, 0xb5c0fbcfec4d3b2f, 0xe9b5dba58189dbbc,
+    0x3956c25bf348b538, 0x59f111f1b605d019, 0x923f82a4af194f9b, 0xab1c5ed5da6d8118,
+    0xd807aa98a3030242, 0x12835b0145706fbe, 0x243185be4ee4b28c, 0x550c7dc3d5ffb4e2,
+    0x72be5d74f27b896f, 0x80deb1fe3b1696b1, 0x9bdc06a725c71235, 0xc19bf174cf692694,
+    0xe49b69c19ef14ad2, 0xefbe4786384f25e3, 0x0fc19dc68b8cd5b5, 0x240ca1cc77ac9c65,
+    0x2de92c6f592b0275, 0x4a7484aa6ea6e483, 0x5cb0a9dcbd41fbd4, 0x76f988da831153b5,
+    0x983e5152ee66dfab, 0xa831c66d2db43210, 0xb00327c898fb213f, 0xbf597fc7beef0ee4,
+    0xc6e00bf33da88fc2, 0xd5a79147930aa725, 0x06ca6351e003826f, 0x142929670a0e6e70,
+    0x27b70a8546d22ffc, 0x2e1b21385c26c926, 0x4d2c6dfc5ac42aed, 0x53380d139d95b3df,
+    0x650a73548baf63de, 0x766a0abb3c77b2a8, 0x81c2c92e47edaee6, 0x92722c851482353b,
+    0xa2bfe8a14cf10364, 0xa81a664bbc423001, 0xc24b8b70d0f89791, 0xc76c51a30654be30,
+    0xd192e819d6ef5218, 0xd69906245565a910, 0xf40e35855771202a, 0x106aa07032bbd1b8,
+    0x19a4c116b8d2d0c8, 0x1e376c085141ab53, 0x2748774cdf8eeb99, 0x34b0bcb5e19b48a8,
+    0x391c0cb3c5c95a63, 0x4ed8aa4ae3418acb, 0x5b9cca4f7763e373, 0x682e6ff3d6b2b8a3,
+    0x748f82ee5defb2fc, 0x78a5636f43172f60, 0x84c87814a1f0ab72, 0x8cc702081a6439ec,
+    0x90befffa23631e28, 0xa4506cebde82bde9, 0xbef9a3f7b2c67915, 0xc67178f2e372532b,
+    0xca273eceea26619c, 0xd186b8c721c0c207, 0xeada7dd6cde0eb1e, 0xf57d4f7fee6ed178,
+    0x06f067aa72176fba, 0x0a637dc5a2c898a6, 0x113f9804bef90dae, 0x1b710b35131c471b,
+    0x28db77f523047d84, 0x32caab7b40c72493, 0x3c9ebe0a15c9bebc, 0x431d67c49c100d4c,
+    0x4cc5d4becb3e42b6, 0x597f299cfc657e2a, 0x5fcb6fab3ad6faec, 0x6c44198c4a475817,
+];
+
+const SHA512_INIT: [u64; 8] = [
+    0x6a09e667f3bcc908,
+    0xbb67ae8584caa73b,
+    0x3c6ef372fe94f82b,
+    0xa54ff53a5f1d36f1,
+    0x510e527fade682d1,
+    0x9b05688c2b3e6c1f,
+    0x1f83d9abfb41bd6b,
+    0x5be0cd19137e2179,
+];
+
+const SHA384_INIT: [u64; 8] = [
+    0xcbbb9d5dc1059ed8,
+    0x629a292a367cd507,
+    0x9159015a3070dd17,
+    0x152fecd8f70e5939,
+    0x67332667ffc00b31,
+    0x8eb44a8768581511,
+    0xdb0c2e0d64f98fa7,
+    0x47b5481dbefa4fa4,
+];
+
+#[derive(Clone)]
+pub struct Sha256 {
+    state: [u32; 8],
+    buffer: [u8; 64],
+    buffer_len: usize,
+    length: u64,
+}
+impl Sha256 {
+    fn compress(state: &mut [u32; 8], block: &[u8]) {
+        let mut w = [0u32; 64];
+        for (i, chunk) in block.chunks_exact(4).enumerate() {
+            w[i] = u32::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for i in 16..64 {
+            let s0 = w[i - 15].rotate_right(7) ^ w[i - 15].rotate_right(18) ^ (w[i - 15] >> 3);
+            let s1 = w[i - 2].rotate_right(17) ^ w[i - 2].rotate_right(19) ^ (w[i - 2] >> 10);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..64 {
+            let s1 = e.rotate_right(6) ^ e.rotate_right(11) ^ e.rotate_right(25);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA256_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(2) ^ a.rotate_right(13) ^ a.rotate_right(22);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+}
+impl HashFunction for Sha256 {
+    const BLOCK_SIZE: usize = 64;
+    const OUTPUT_SIZE: usize = 32;
+
+    fn new() -> Self {
+        Sha256 {
+            state: SHA256_INIT,
+            buffer: [0; 64],
+            buffer_len: 0,
+            length: 0,
+        }
+    }
+    fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u64);
+        if self.buffer_len > 0 {
+            let take = (64 - self.buffer_len).min(data.len());
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+            self.buffer_len += take;
+            data = &data[take..];
+            if self.buffer_len < 64 {
+                return;
+            }
+            let block = self.buffer;
+            Self::compress(&mut self.state, &block);
+            self.buffer_len = 0;
+        }
+        let mut blocks = data.chunks_exact(64);
+        for block in &mut blocks {
+            Self::compress(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
+    }
+    fn finalize(mut self) -> Vec<u8> {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        let padding_len = if self.buffer_len < 56 {
+            56 - self.buffer_len
+        } else {
+            120 - self.buffer_len
+        };
+        padding.resize(padding_len, 0);
+        padding.extend(bit_length.to_be_bytes());
+        // 填充部分不应该被计入消息长度，但此时长度已经被取出，所以无需恢复
+        self.update(&padding);
+        self.state.iter().flat_map(|x| x.to_be_bytes()).collect()
+    }
+}
+
+/// SHA-512 和 SHA-384 的共同实现，二者只有初始状态和输出长度不同
+#[derive(Clone)]
+struct Sha512Core {
+    state: [u64; 8],
+    buffer: [u8; 128],
+    buffer_len: usize,
+    length: u128,
+}
+impl Sha512Core {
+    fn new(init: [u64; 8]) -> Self {
+        Sha512Core {
+            state: init,
+            buffer: [0; 128],
+            buffer_len: 0,
+            length: 0,
+        }
+    }
+    fn compress(state: &mut [u64; 8], block: &[u8]) {
+        let mut w = [0u64; 80];
+        for (i, chunk) in block.chunks_exact(8).enumerate() {
+            w[i] = u64::from_be_bytes(chunk.try_into().unwrap());
+        }
+        for i in 16..80 {
+            let s0 = w[i - 15].rotate_right(1) ^ w[i - 15].rotate_right(8) ^ (w[i - 15] >> 7);
+            let s1 = w[i - 2].rotate_right(19) ^ w[i - 2].rotate_right(61) ^ (w[i - 2] >> 6);
+            w[i] = w[i - 16]
+                .wrapping_add(s0)
+                .wrapping_add(w[i - 7])
+                .wrapping_add(s1);
+        }
+
+        let [mut a, mut b, mut c, mut d, mut e, mut f, mut g, mut h] = *state;
+        for i in 0..80 {
+            let s1 = e.rotate_right(14) ^ e.rotate_right(18) ^ e.rotate_right(41);
+            let ch = (e & f) ^ (!e & g);
+            let temp1 = h
+                .wrapping_add(s1)
+                .wrapping_add(ch)
+                .wrapping_add(SHA512_K[i])
+                .wrapping_add(w[i]);
+            let s0 = a.rotate_right(28) ^ a.rotate_right(34) ^ a.rotate_right(39);
+            let maj = (a & b) ^ (a & c) ^ (b & c);
+            let temp2 = s0.wrapping_add(maj);
+
+            h = g;
+            g = f;
+            f = e;
+            e = d.wrapping_add(temp1);
+            d = c;
+            c = b;
+            b = a;
+            a = temp1.wrapping_add(temp2);
+        }
+
+        for (s, v) in state.iter_mut().zip([a, b, c, d, e, f, g, h]) {
+            *s = s.wrapping_add(v);
+        }
+    }
+    fn update(&mut self, mut data: &[u8]) {
+        self.length = self.length.wrapping_add(data.len() as u128);
+        if self.buffer_len > 0 {
+            let take = (128 - self.buffer_len).min(data.len());
+            self.buffer[self.buffer_len..self.buffer_len + take].copy_from_slice(&data[..take]);
+            self.buffer_len += take;
+            data = &data[take..];
+            if self.buffer_len < 128 {
+                return;
+            }
+            let block = self.buffer;
+            Self::compress(&mut self.state, &block);
+            self.buffer_len = 0;
+        }
+        let mut blocks = data.chunks_exact(128);
+        for block in &mut blocks {
+            Self::compress(&mut self.state, block);
+        }
+        let rest = blocks.remainder();
+        self.buffer[..rest.len()].copy_from_slice(rest);
+        self.buffer_len = rest.len();
+    }
+    fn finalize(mut self, output_size: usize) -> Vec<u8> {
+        let bit_length = self.length.wrapping_mul(8);
+        let mut padding = vec![0x80];
+        let padding_len = if self.buffer_len < 112 {
+            112 - self.buffer_len
+        } else {
+            240 - self.buffer_len
+        };
+        padding.resize(padding_len, 0);
+        padding.extend(bit_length.to_be_bytes());
+        self.update(&padding);
+        let mut out: Vec<u8> = self.state.iter().flat_map(|x| x.to_be_bytes()).collect();
+        out.truncate(output_size);
+        out
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha512(Sha512Core);
+impl HashFunction for Sha512 {
+    const BLOCK_SIZE: usize = 128;
+    const OUTPUT_SIZE: usize = 64;
+
+    fn new() -> Self {
+        Sha512(Sha512Core::new(SHA512_INIT))
+    }
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+    fn finalize(self) -> Vec<u8> {
+        self.0.finalize(Self::OUTPUT_SIZE)
+    }
+}
+
+#[derive(Clone)]
+pub struct Sha384(Sha512Core);
+impl HashFunction for Sha384 {
+    const BLOCK_SIZE: usize = 128;
+    const OUTPUT_SIZE: usize = 48;
+
+    fn new() -> Self {
+        Sha384(Sha512Core::new(SHA384_INIT))
+    }
+    fn update(&mut self, data: &[u8]) {
+        self.0.update(data)
+    }
+    fn finalize(self) -> Vec<u8> {
+        self.0.finalize(Self::OUTPUT_SIZE)
+    }
+}
+
+/// 流式的 HMAC
+/// 使用 Hmac::<Sha256>::new(key) 创建，然后像散列函数一样 update 和 finalize
+#[derive(Clone)]
+pub struct Hmac<H: HashFunction> {
+    inner: H,
+    outer: H,
+}
+impl<H: HashFunction> Hmac<H> {
+    pub fn new(key: &[u8]) -> Self {
+        let mut block_key = if key.len() > H::BLOCK_SIZE {
+            H::digest(key)
+        } else {
+            key.to_vec()
+        };
+        block_key.resize(H::BLOCK_SIZE, 0);
+
+        let mut inner = H::new();
+        inner.update(&block_key.iter().map(|b| b ^ 0x36).collect::<Vec<_>>());
+        let mut outer = H::new();
+        outer.update(&block_key.iter().map(|b| b ^ 0x5c).collect::<Vec<_>>());
+        Hmac { inner, outer }
+    }
+    pub fn update(&mut self, data: &[u8]) {
+        self.inner.update(data)
+    }
+    pub fn finalize(self) -> Vec<u8> {
+        let mut outer = self.outer;
+        outer.update(&self.inner.finalize());
+        outer.finalize()
+    }
+}
+
+pub fn hmac<H: HashFunction>(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<H>::new(key);
+    mac.update(data);
+    mac.finalize()
+}
+
+/// TLS 1.2 的伪随机函数 PRF(secret, label, seed)，即 P_hash(secret, label + seed)
+/// TLS 1.2 的所有标准密码套件都使用 SHA-256 ，除非密码套件另有规定（例如 *_SHA384）
+pub fn prf_tls12<H: HashFunction>(
+    secret: &[u8],
+    label: &[u8],
+    seed: &[u8],
+    length: usize,
+) -> Vec<u8> {
+    let mut label_seed = label.to_vec();
+    label_seed.extend(seed);
+
+    let mut out = Vec::with_capacity(length + H::OUTPUT_SIZE);
+    // A(0) = seed, A(i) = HMAC(secret, A(i-1))
+    let mut a = hmac::<H>(secret, &label_seed);
+    while out.len() < length {
+        let mut mac = Hmac::<H>::new(secret);
+        mac.update(&a);
+        mac.update(&label_seed);
+        out.extend(mac.finalize());
+        a = hmac::<H>(secret, &a);
+    }
+    out.truncate(length);
+    out
+}
+
+/// HKDF-Extract(salt, IKM) -> PRK
+/// 如果没有提供 salt ，根据标准，使用长度为 HashLen 的全零字符串
+pub fn hkdf_extract<H: HashFunction>(salt: &[u8], ikm: &[u8]) -> Vec<u8> {
+    if salt.is_empty() {
+        hmac::<H>(&vec![0; H::OUTPUT_SIZE], ikm)
+    } else {
+        hmac::<H>(salt, ikm)
+    }
+}
+
+/// HKDF-Expand(PRK, info, L) -> OKM
+/// 根据标准，L 不能超过 255 * HashLen ，超过时返回 None
+pub fn hkdf_expand<H: HashFunction>(prk: &[u8], info: &[u8], length: usize) -> Option<Vec<u8>> {
+    if length > 255 * H::OUTPUT_SIZE {
+        return None;
+    }
+    let mut out = Vec::with_capacity(length + H::OUTPUT_SIZE);
+    let mut t: Vec<u8> = vec![];
+    let mut counter: u8 = 1;
+    while out.len() < length {
+        let mut mac = Hmac::<H>::new(prk);
+        mac.update(&t);
+        mac.update(info);
+        mac.update(&[counter]);
+        t = mac.finalize();
+        out.extend(&t);
+        counter = counter.wrapping_add(1);
+    }
+    out.truncate(length);
+    Some(out)
+}
+
+/// TLS 1.3 的 HKDF-Expand-Label(Secret, Label, Context, Length)
+/// label 不需要包含 "tls13 " 前缀，本函数会自动加上
+pub fn hkdf_expand_label<H: HashFunction>(
+    secret: &[u8],
+    label: &[u8],
+    context: &[u8],
+    length: u16,
+) -> Option<Vec<u8>> {
+    let full_label_len = 6 + label.len();
+    if full_label_len > 255 || context.len() > 255 {
+        return None;
+    }
+    let mut info = length.to_be_bytes().to_vec();
+    info.push(full_label_len as u8);
+    info.extend(b"tls13 ");
+    info.extend(label);
+    info.push(context.len() as u8);
+    info.extend(context);
+    hkdf_expand::<H>(secret, &info, length.into())
+}
+
+/// 以小写十六进制字符串表示一段字节，多用于日志和 ETag
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        let str: String = str.split_whitespace().collect();
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn test_sha256() {
+        assert_eq!(
+            to_hex(&Sha256::digest(b"")),
+            "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+        );
+        assert_eq!(
+            to_hex(&Sha256::digest(b"abc")),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+        assert_eq!(
+            to_hex(&Sha256::digest(
+                b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"
+            )),
+            "248d6a61d20638b8e5c026930c3e6039a33ce45964ff2167f6ecedd419db06c1"
+        );
+    }
+
+    #[test]
+    fn test_sha256_streaming() {
+        // 一百万个 'a' ，以不对齐分组的方式输入
+        let mut sha = Sha256::new();
+        let chunk = [b'a'; 999];
+        let mut left = 1_000_000;
+        while left > 0 {
+            let n = left.min(chunk.len());
+            sha.update(&chunk[..n]);
+            left -= n;
+        }
+        assert_eq!(
+            to_hex(&sha.finalize()),
+            "cdc76e5c9914fb9281a1c7e284d73e67f1809a48a497200e046d39ccc7112cd0"
+        );
+    }
+
+    #[test]
+    fn test_sha512_and_sha384() {
+        assert_eq!(
+            to_hex(&Sha512::digest(b"abc")),
+            "ddaf35a193617abacc417349ae20413112e6fa4e89a97ea20a9eeee64b55d39a\
+             2192992a274fc1a836ba3c23a3feebbd454d4423643ce80e2a9ac94fa54ca49f"
+        );
+        assert_eq!(
+            to_hex(&Sha384::digest(b"abc")),
+            "cb00753f45a35e8bb5a03d699ac65007272c32ab0eded1631a8b605a43ff5bed\
+             8086072ba1e7cc2358baeca134c825a7"
+        );
+        let two_blocks = b"abcdefghbcdefghicdefghijdefghijkefghijklfghijklmghijklmn\
+hijklmnoijklmnopjklmnopqklmnopqrlmnopqrsmnopqrstnopqrstu";
+        let mut sha = Sha512::new();
+        for b in two_blocks.chunks(7) {
+            sha.update(b);
+        }
+        assert_eq!(
+            to_hex(&sha.finalize()),
+            "8e959b75dae313da8cf4f72814fc143f8f7779c6eb9f7fa17299aeadb6889018\
+             501d289e4900f7e4331b99dec4b5433ac7d329eeb6dd26545e96e55b874be909"
+        );
+    }
+
+    #[test]
+    fn test_hmac() {
+        // RFC 4231 Test Case 1 和 Test Case 2
+        let key = [0x0b; 20];
+        assert_eq!(
+            to_hex(&hmac::<Sha256>(&key, b"Hi There")),
+            "b0344c61d8db38535ca8afceaf0bf12b881dc200c9833da726e9376c2e32cff7"
+        );
+        assert_eq!(
+            to_hex(&hmac::<Sha384>(&key, b"Hi There")),
+            "afd03944d84895626b0825f4ab46907f15f9dadbe4101ec682aa034c7cebc59c\
+             faea9ea9076ede7f4af152e8b2fa9cb6"
+        );
+        assert_eq!(
+            to_hex(&hmac::<Sha512>(&key, b"Hi There")),
+            "87aa7cdea5ef619d4ff0b4241a1d6cb02379f4e2ce4ec2787ad0b30545e17cde\
+             daa833b7d6b8a702038b274eaea3f4e4be9d914eeb61f1702e696c203a126854"
+        );
+        assert_eq!(
+            to_hex(&hmac::<Sha256>(b"Jefe", b"what do ya want for nothing?")),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+        // RFC 4231 Test Case 6: 比分组更长的 key
+        assert_eq!(
+            to_hex(&hmac::<Sha256>(
+                &[0xaa; 131],
+                b"Test Using Larger Than Block-Size Key - Hash Key First"
+            )),
+            "60e431591ee0b67f0d8a26aacbf5b77f8e0bc6213728c5140546040f0ee37f54"
+        );
+    }
+
+    #[test]
+    fn test_prf_tls12() {
+        let secret = from_hex("9b be 43 6b a9 40 f0 17 b1 76 52 84 9a 71 db 35");
+        let seed = from_hex("a0 ba 9f 93 6c da 31 18 27 a6 f7 96 ff d5 19 8c");
+        let out = prf_tls12::<Sha256>(&secret, b"test label", &seed, 100);
+        assert_eq!(
+            out,
+            from_hex(
+                "e3 f2 29 ba 72 7b e1 7b 8d 12 26 20 55 7c d4 53 c2 aa b2 1d 07 c3 d4 95
+                 32 9b 52 d4 e6 1e db 5a 6b 30 17 91 e9 0d 35 c9 c9 a4 6b 4e 14 ba f9 af
+                 0f a0 22 f7 07 7d ef 17 ab fd 37 97 c0 56 4b ab 4f bc 91 66 6e 9d ef 9b
+                 97 fc e3 4f 79 67 89 ba a4 80 82 d1 22 ee 42 c5 a7 2e 5a 51 10 ff f7 01
+                 87 34 7b 66"
+            )
+        );
+    }
+
+    #[test]
+    fn test_hkdf() {
+        // RFC 5869 Test Case 1
+        let ikm = [0x0b; 22];
+        let salt = from_hex("000102030405060708090a0b0c");
+        let info = from_hex("f0f1f2f3f4f5f6f7f8f9");
+        let prk = hkdf_extract::<Sha256>(&salt, &ikm);
+        assert_eq!(
+            to_hex(&prk),
+            "077709362c2e32df0ddc3f0dc47bba6390b6c73bb50f9c3122ec844ad7c2b3e5"
+        );
+        assert_eq!(
+            to_hex(&hkdf_expand::<Sha256>(&prk, &info, 42).unwrap()),
+            "3cb25f25faacd57a90434f64d0362f2a2d2d0a90cf1a5a4c5db02d56ecc4c5bf34007208d5b887185865"
+        );
+        // RFC 5869 Test Case 3: 没有 salt 和 info
+        let prk = hkdf_extract::<Sha256>(&[], &ikm);
+        assert_eq!(
+            to_hex(&hkdf_expand::<Sha256>(&prk, &[], 42).unwrap()),
+            "8da4e775a563c18f715f802a063c5a31b8a11f5c5ee1879ec3454e5f3c738d2d9d201395faa4b61a96c8"
+        );
+        assert!(hkdf_expand::<Sha256>(&prk, &[], 255 * 32 + 1).is_none());
+    }
+
+    #[test]
+    fn test_hkdf_expand_label() {
+        // RFC 8448 Simple 1-RTT Handshake: derived secret for handshake
+        let early_secret = hkdf_extract::<Sha256>(&[], &[0; 32]);
+        assert_eq!(
+            to_hex(&early_secret),
+            "33ad0a1c607ec03b09e6cd9893680ce210adf300aa1f2660e1b22e10f170f92a"
+        );
+        let derived = hkdf_expand_label::<Sha256>(
+            &early_secret,
+            b"derived",
+            &Sha256::digest(b""),
+            32,
+        )
+        .unwrap();
+        assert_eq!(
+            to_hex(&derived),
+            "6f2615a108c702c5678f54fc9dbab69716c076189c48250cebeac3576c3611ba"
+        );
+    }
+}
@@ -0,0 +1,99 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! PrivateKey 统一了本项目支持的两种签名密钥：Ed25519 和 ECDSA (P-256, P-384)
+//! TLS 服务端、ACME 的 CSR 等只需要“用证书的密钥签名”的地方都应该使用它
+//!
+//! sign 返回的签名格式和 X.509 及 TLS 中的一致：Ed25519 是 64 字节的原始签名，ECDSA 是 DER 格式
+//! JWS 中的 ECDSA 签名是 r || s 格式，需要的话直接使用 EcdsaKeyPair::sign
+
+#![allow(dead_code)]
+
+use super::curve25519::Ed25519KeyPair;
+use super::der;
+use super::ecdsa::{self, EcdsaKeyPair};
+use super::tls13::{SIGNATURE_ECDSA_P256_SHA256, SIGNATURE_ECDSA_P384_SHA384, SIGNATURE_ED25519};
+
+pub enum PrivateKey {
+    Ed25519(Ed25519KeyPair),
+    Ecdsa(EcdsaKeyPair),
+}
+
+impl PrivateKey {
+    /// 读取 PEM 或 DER 格式的 PKCS#8 私钥、PEM 格式的 SEC1 私钥 ("EC PRIVATE KEY")，或者 32 字节的 Ed25519 种子
+    pub fn load(data: &[u8]) -> Option<Self> {
+        if let Ok(str) = std::str::from_utf8(data) {
+            if str.contains("-----BEGIN") {
+                return der::pem_decode(str)
+                    .iter()
+                    .find_map(|(label, der)| match label.as_str() {
+                        "PRIVATE KEY" => Self::from_pkcs8(der),
+                        "EC PRIVATE KEY" => EcdsaKeyPair::from_sec1(der).map(PrivateKey::Ecdsa),
+                        _ => None,
+                    });
+            }
+        }
+        match data.try_into() {
+            Ok(seed) => Some(PrivateKey::Ed25519(Ed25519KeyPair::from_seed(seed))),
+            Err(_) => Self::from_pkcs8(data),
+        }
+    }
+
+    pub fn from_pkcs8(data: &[u8]) -> Option<Self> {
+        Ed25519KeyPair::from_pkcs8(data)
+            .map(PrivateKey::Ed25519)
+            .or_else(|| EcdsaKeyPair::from_pkcs8(data).map(PrivateKey::Ecdsa))
+    }
+
+    pub fn to_pkcs8(&self) -> Vec<u8> {
+        match self {
+            PrivateKey::Ed25519(key) => key.to_pkcs8(),
+            PrivateKey::Ecdsa(key) => key.to_pkcs8(),
+        }
+    }
+
+    pub fn subject_public_key_info(&self) -> Vec<u8> {
+        match self {
+            PrivateKey::Ed25519(key) => der::sequence(&[
+                der::sequence(&[der::oid(der::OID_ED25519)]),
+                der::bit_string(&key.public_key),
+            ]),
+            PrivateKey::Ecdsa(key) => key.subject_public_key_info(),
+        }
+    }
+
+    /// 用于 X.509 和 CSR 的签名算法标识符
+    pub fn signature_algorithm(&self) -> Vec<u8> {
+        match self {
+            PrivateKey::Ed25519(_) => der::sequence(&[der::oid(der::OID_ED25519)]),
+            PrivateKey::Ecdsa(key) if std::ptr::eq(key.curve, ecdsa::p384()) => {
+                der::sequence(&[der::oid(der::OID_ECDSA_SHA384)])
+            }
+            PrivateKey::Ecdsa(_) => der::sequence(&[der::oid(der::OID_ECDSA_SHA256)]),
+        }
+    }
+
+    /// TLS 1.3 的签名算法 (SignatureScheme)
+    pub fn signature_scheme(&self) -> u16 {
+        match self {
+            PrivateKey::Ed25519(_) => SIGNATURE_ED25519,
+            PrivateKey::Ecdsa(key) if std::ptr::eq(key.curve, ecdsa::p384()) => {
+                SIGNATURE_ECDSA_P384_SHA384
+            }
+            PrivateKey::Ecdsa(_) => SIGNATURE_ECDSA_P256_SHA256,
+        }
+    }
+
+    pub fn sign(&self, message: &[u8]) -> Vec<u8> {
+        match self {
+            PrivateKey::Ed25519(key) => key.sign(message).to_vec(),
+            PrivateKey::Ecdsa(key) => ecdsa::signature_to_der(&key.sign(message)),
+        }
+    }
+}
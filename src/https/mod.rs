@@ -18,6 +18,12 @@
 //!
 //! # 子模块
 //!
+//! ## acme
+//! ACME 客户端，通过 HTTP-01 挑战自动申请和续期证书，挑战由 Router 应答
+//!
+//! ## bignum
+//! 奇数模数下的大整数模运算（蒙哥马利乘法），ECDSA 和 RSA 建立在它之上
+//!
 //! ## c25519
 //! 该模块为本模块增加 c25519 曲线相关算法的支持，该模块和本项目的其它模块不同的是，其采用 CC0 协议
 //! 这是一个加密、解密、签名、验签的算法，在 TLS 通信中起到重要作用
@@ -29,10 +35,62 @@
 //! 本 Rust 转写的 Github 仓库：https://github.com/duoduo70/Compact-C25519-rs
 //! 原始的 Python 实现和该算法的相关论文，参见：https://www.dlbeer.co.nz/oss/c25519.html
 //!
+//! ## chacha20poly1305
+//! ChaCha20-Poly1305 AEAD 算法，用于 TLS 1.3 的记录层等需要认证加密的地方
+//!
+//! ## client
+//! 一个最小的 TLS 1.3 客户端，验证服务端的证书链和主机名，ACME 客户端使用它
+//!
+//! ## curve25519
+//! c25519 模块的安全封装，提供 Ed25519 签名、验签和 X25519 密钥交换
+//!
+//! ## der
+//! ASN.1 DER 编码的构造和解析，以及 PEM 格式的转换，证书和私钥的读写都需要它
+//!
+//! ## ecdsa
+//! P-256 和 P-384 曲线上的 ECDSA 签名和验签，以及 EC 私钥的读写
+//!
+//! ## hash
+//! 该模块提供 SHA-256, SHA-384, SHA-512 散列函数（支持流式输入），以及基于它们的 HMAC, TLS 1.2 PRF 和 HKDF
+//! c25519 模块内部有一份独立的 SHA-512 实现，它是原项目的一部分，所以没有被替换
+//! 除此之外，TLS 的密钥计划、签名、ETag 等需要散列的地方都应该使用本模块
+//!
+//! ## key
+//! 统一 Ed25519 和 ECDSA 私钥的读取、编码和签名，TLS 服务端和 ACME 客户端使用它
+//!
+//! ## rsa
+//! RSA 签名的验证 (PKCS#1 v1.5 和 PSS)，只用于验证 CA 的证书链和对方的 CertificateVerify
+//!
+//! ## server
+//! TLS 1.3 服务端，支持可选的客户端证书认证和会话恢复
+//!
+//! ## session
+//! TLS 1.3 会话恢复所需的会话缓存和加密的会话票据
+//!
+//! ## tls13
+//! TLS 1.3 客户端和服务端共用的记录层、密钥计划和握手消息的封装
+//!
+//! ## x509
+//! X.509 证书的解析、证书链的验证和主机名的匹配，支持 Ed25519, ECDSA 和 RSA 签名的证书链
+//!
 //! ## tls
 //! 该模块是本模块的核心子模块，其增加了 TLS 传输协议的支持
 //! TLS 协议是一个极为复杂的传输协议集合，涉及论文之多以至于无法在本总则中提及
 //! 另请查看该模块之总则
 
+pub mod acme;
+pub mod bignum;
 pub mod c25519;
+pub mod chacha20poly1305;
+pub mod client;
+pub mod curve25519;
+pub mod der;
+pub mod ecdsa;
+pub mod hash;
+pub mod key;
+pub mod rsa;
+pub mod server;
+pub mod session;
 pub mod tls;
+pub mod tls13;
+pub mod x509;
@@ -0,0 +1,209 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块只提供 RSA 签名的验证，用于验证 CA 的证书链和服务端的 CertificateVerify
+//! 本项目不生成 RSA 密钥，也不用它签名
+//!
+//! 证书中的签名使用 PKCS#1 v1.5 ，TLS 1.3 的 CertificateVerify 使用 PSS （盐的长度等于摘要的长度）
+//! 验证只用到公钥，所以不需要在意时间侧信道
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8017
+
+#![allow(dead_code)]
+
+use super::bignum::Modulus;
+use super::chacha20poly1305::constant_time_eq;
+use super::der::{self, DerReader};
+use super::hash::HashFunction;
+
+/// 能被验证的最长的模数，以位计
+const MAX_MODULUS_BITS: usize = 8192;
+
+pub struct RsaPublicKey {
+    n: Modulus,
+    /// 大端序的公开指数
+    e: Vec<u8>,
+    /// 模数的位数
+    bits: usize,
+}
+
+impl RsaPublicKey {
+    /// 解析 PKCS#1 的 RSAPublicKey ，即 subjectPublicKeyInfo 中位串的内容
+    pub fn parse(data: &[u8]) -> Option<Self> {
+        let key = DerReader::new(data).read_expect(der::SEQUENCE).ok()?;
+        let mut reader = key.reader();
+        let n = reader.read_expect(der::INTEGER).ok()?.content;
+        let e = reader.read_expect(der::INTEGER).ok()?.content;
+        let n = &n[n.iter().take_while(|a| **a == 0).count()..];
+        let bits = (n.len() * 8).checked_sub(n.first()?.leading_zeros() as usize)?;
+        if !(512..=MAX_MODULUS_BITS).contains(&bits) || n.last()? & 1 == 0 || e.is_empty() {
+            return None;
+        }
+        Some(RsaPublicKey {
+            n: Modulus::new(n),
+            e: e.to_vec(),
+            bits,
+        })
+    }
+
+    /// 返回 signature^e mod n ，它的长度和模数相同
+    fn public(&self, signature: &[u8]) -> Option<Vec<u8>> {
+        if signature.len() != self.n.bytes() {
+            return None;
+        }
+        let s = self.n.to_mont(&self.n.decode(signature)?);
+        Some(self.n.encode(&self.n.to_normal(&self.n.pow(&s, &self.e))))
+    }
+
+    /// 验证 RSASSA-PKCS1-v1_5 签名
+    /// hash_oid: 散列函数的 OID ，它被写入 DigestInfo
+    pub fn verify_pkcs1(&self, hash_oid: &[u64], digest: &[u8], signature: &[u8]) -> bool {
+        let Some(em) = self.public(signature) else {
+            return false;
+        };
+        let digest_info = der::sequence(&[
+            der::sequence(&[der::oid(hash_oid), der::encode(der::NULL, &[])]),
+            der::octet_string(digest),
+        ]);
+        let Some(padding) = em.len().checked_sub(digest_info.len() + 3) else {
+            return false;
+        };
+        let mut expected = vec![0, 1];
+        expected.extend(std::iter::repeat_n(0xff, padding));
+        expected.push(0);
+        expected.extend(digest_info);
+        constant_time_eq(&em, &expected)
+    }
+
+    /// 验证 RSASSA-PSS 签名，MGF1 使用和摘要相同的散列函数，盐的长度等于摘要的长度
+    pub fn verify_pss<H: HashFunction>(&self, digest: &[u8], signature: &[u8]) -> bool {
+        let Some(em) = self.public(signature) else {
+            return false;
+        };
+        let em_bits = self.bits - 1;
+        let em_len = em_bits.div_ceil(8);
+        // em_bits 是 8 的倍数时，编码比模数短一个字节，最高的字节必须为 0
+        let (zeros, em) = em.split_at(em.len() - em_len);
+        let h_len = H::OUTPUT_SIZE;
+        if zeros.iter().any(|a| *a != 0)
+            || digest.len() != h_len
+            || em_len < 2 * h_len + 2
+            || em[em_len - 1] != 0xbc
+        {
+            return false;
+        }
+        let (masked_db, h) = em[..em_len - 1].split_at(em_len - h_len - 1);
+        let unused_bits = 8 * em_len - em_bits;
+        if masked_db[0] & !(0xff >> unused_bits) != 0 {
+            return false;
+        }
+        let mut db: Vec<u8> = masked_db
+            .iter()
+            .zip(mgf1::<H>(h, masked_db.len()))
+            .map(|(a, b)| a ^ b)
+            .collect();
+        db[0] &= 0xff >> unused_bits;
+        let (padding, salt) = db.split_at(db.len() - h_len);
+        let (zeros, one) = padding.split_at(padding.len() - 1);
+        if zeros.iter().any(|a| *a != 0) || one != [1] {
+            return false;
+        }
+        let mut hash = H::new();
+        hash.update(&[0; 8]);
+        hash.update(digest);
+        hash.update(salt);
+        constant_time_eq(&hash.finalize(), h)
+    }
+}
+
+/// See: https://datatracker.ietf.org/doc/html/rfc8017#appendix-B.2.1
+fn mgf1<H: HashFunction>(seed: &[u8], len: usize) -> Vec<u8> {
+    let mut out = vec![];
+    let mut counter: u32 = 0;
+    while out.len() < len {
+        let mut hash = H::new();
+        hash.update(seed);
+        hash.update(&counter.to_be_bytes());
+        out.extend(hash.finalize());
+        counter += 1;
+    }
+    out.truncate(len);
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::{Sha256, Sha384};
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    // 以下的密钥和签名由 Python 的 cryptography 库生成，消息都是 "sample"
+
+    #[test]
+    fn test_rsa_1024_sha256() {
+        let key = RsaPublicKey::parse(&from_hex(
+            "30818902818100aefa2a70a8b7a1fd1373d5d00421a50c5d0e8a129467b7bf042d435615593d1e7a05e1f766a4ac8948\
+             6f4e211f4d27001297043a3aadd967ed459ca42bbe3683274c5ed226d123d4a8b3d287fbaa39c3283698a64cde324d1c\
+             121bf2d04aab9fd1e5020b0e015bcdfaae639b9bff80790d3103adc6ce0e754a5aa0483c377d170203010001",
+        ))
+        .unwrap();
+        let pkcs1 = from_hex(
+            "39bd8889920a4b0b2f0c21e13d7019933178d178f4c250f0906c55fd7134893ef94e12138e2708f19250fb2dd522409c\
+             757512474cacc31ce860ab5a7037ca07525bdde84303aa051ee62c6a03bb54bfb1d3bc5105cea9b7cede7caace38e4d2\
+             f768333193d422302d41eadde148d83e8ddc465237d668a501574e023d279c5e",
+        );
+        let pss = from_hex(
+            "83589c78b37528b6a11f3bf637f7760b44d8c9af006cd4b0d57b509e8ddcfed9bba9fa3d5ff2c326a2eab811cc7d1aa6\
+             0ca2719ff9bfde7446f7138f9e16454bb6de53a1cf28a68b848f6d3907aae8b4dd85025e10f250a434db0eb61b6c6494\
+             9ee3ddecbb171592835f7488529d8d6115c86681ecb5338b62b17cca9918fbdd",
+        );
+        let digest = Sha256::digest(b"sample");
+        assert!(key.verify_pkcs1(der::OID_SHA256, &digest, &pkcs1));
+        assert!(key.verify_pss::<Sha256>(&digest, &pss));
+        assert!(!key.verify_pkcs1(der::OID_SHA256, &Sha256::digest(b"other"), &pkcs1));
+        assert!(!key.verify_pkcs1(der::OID_SHA384, &digest, &pkcs1));
+        assert!(!key.verify_pss::<Sha256>(&Sha256::digest(b"other"), &pss));
+        assert!(!key.verify_pss::<Sha256>(&digest, &pkcs1));
+        let mut bad = pss.clone();
+        bad[5] ^= 1;
+        assert!(!key.verify_pss::<Sha256>(&digest, &bad));
+        assert!(!key.verify_pss::<Sha256>(&digest, &pss[1..]));
+    }
+
+    #[test]
+    fn test_rsa_1025_sha384() {
+        // 模数的位数减一是 8 的倍数，PSS 的编码比模数短一个字节
+        let key = RsaPublicKey::parse(&from_hex(
+            "30818902818101e94667d358db6787e7cd5364e9fc72d42bbe6aeaced03699d91762bfb16d5a639ff2939096c289dfe6\
+             e551252fe08007f8b4d6ac9c5e3f3a9d0c91b4359b821c4f019b721779e5a88825da07dae25545c1fdcc7d319c73b8f8\
+             edc49c785f3d1ce467b11229cf8b00686d9efe34a43872496876dc6a23f97612edaf794e7634fb0203010001",
+        ))
+        .unwrap();
+        let pkcs1 = from_hex(
+            "00ae4cd68a8d02f9b496dfb90c37a86c92ffad3f97dc201e962442088344ea04621420a93fef72d2955c1d2433540ee8\
+             567bbb81faea9daa9fb26f3dd0184a817a08c0bcca20582fe02ec609ce68b0209abb8908e16b46ed5d3798465f7fd3e0\
+             266a6a834a599510f3b4fbdece6e1393472bc8ed9f67d34a793b91c66e35f588de",
+        );
+        let pss = from_hex(
+            "01853ae4928358f2d75804dc932482da3aa3feb105fc74bd7fbd39f334bf8367ca8bac0c14512758b2f96a60cbf70500\
+             070609d2f44f871bbeda0b16d05b9d8afd252279e4d120d2e487d0d10f345890d66ab38640541c399c04a95b959cc08f\
+             75242ef2d7aa70ccf441cd91f1aad740550d82fe85680fcb0e410cf7f17b040d99",
+        );
+        let digest = Sha384::digest(b"sample");
+        assert!(key.verify_pkcs1(der::OID_SHA384, &digest, &pkcs1));
+        assert!(key.verify_pss::<Sha384>(&digest, &pss));
+        assert!(!key.verify_pss::<Sha384>(&Sha384::digest(b"other"), &pss));
+    }
+}
@@ -0,0 +1,776 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 一个 TLS 1.3 服务端，accept 完成握手后返回 TlsStream ，它可以像 TcpStream 一样使用
+//!
+//! 服务端的证书可以使用 Ed25519 或 ECDSA (P-256, P-384) 密钥，参见 key 模块
+//! 密钥交换只支持 X25519 ，密码套件只支持 TLS_CHACHA20_POLY1305_SHA256
+//! 如果客户端支持 X25519 但没有发送它的 key_share ，服务端会发送 HelloRetryRequest
+//!
+//! 服务端可以要求客户端证书 (ClientAuth) ，客户端的证书链会被 x509 模块根据 client_ca 验证
+//! 客户端证书可以使用 Ed25519, ECDSA 或 RSA (PSS) 签名
+//! 验证成功后，TlsStream::peer_certificates 是客户端的证书链，否则它是空的
+//!
+//! session_lifetime 不为 0 时，握手完成后服务端会发送一个 NewSessionTicket ，参见 session 模块
+//! 客户端用它恢复会话时 (psk_dhe_ke) ，双方仍然进行 X25519 密钥交换，但跳过证书的发送和验证
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8446
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+use std::net::SocketAddr;
+
+use super::chacha20poly1305::constant_time_eq;
+use super::curve25519::{x25519_keypair, x25519_shared};
+use super::der;
+use super::hash::{hkdf_expand_label, HashFunction, Sha256};
+use super::key::PrivateKey;
+use super::session::{self, Session};
+use super::tls::TLSError;
+use super::tls13::*;
+use super::x509;
+
+/// 是否向客户端请求证书
+/// No: 不请求
+/// Optional: 请求，但客户端可以不发送证书
+/// Required: 请求，客户端不发送证书时握手失败
+#[derive(Clone, Copy, PartialEq, Default, Debug)]
+pub enum ClientAuth {
+    #[default]
+    No,
+    Optional,
+    Required,
+}
+
+impl ClientAuth {
+    /// 接受了一个连接的监听地址的设置
+    /// binds: 每个监听地址的设置，绑定到未指定的地址（例如 `0.0.0.0:443`）时，对该端口上的所有地址都有效
+    /// local_addr: 连接的本地地址，没有匹配的设置时返回 No
+    pub fn for_listener(binds: &[(SocketAddr, ClientAuth)], local_addr: SocketAddr) -> Self {
+        binds
+            .iter()
+            .find(|(a, _)| {
+                a.port() == local_addr.port()
+                    && (a.ip() == local_addr.ip() || a.ip().is_unspecified())
+            })
+            .map_or(ClientAuth::No, |(_, a)| *a)
+    }
+}
+
+/// certificates: 服务端的证书链（DER 格式），第一个是服务端自己的证书
+/// key: 服务端证书的私钥
+/// client_ca: 用于验证客户端证书的 CA 证书（DER 格式）
+/// session_lifetime: 会话票据的有效期（秒），为 0 时不发送票据，也不恢复会话
+/// session_cache: 为 true 时会话被保存在内存中，票据只是会话 ID ，否则使用加密的无状态票据
+pub struct ServerConfig {
+    pub certificates: Vec<Vec<u8>>,
+    pub key: PrivateKey,
+    pub client_auth: ClientAuth,
+    pub client_ca: Vec<Vec<u8>>,
+    pub session_lifetime: u32,
+    pub session_cache: bool,
+}
+
+impl ServerConfig {
+    /// certificate: 参见 load_certificates
+    /// key: 参见 PrivateKey::load
+    pub fn new(certificate: &[u8], key: &[u8]) -> Option<Self> {
+        let certificates = load_certificates(certificate);
+        if certificates.is_empty() {
+            return None;
+        }
+        Some(ServerConfig {
+            certificates,
+            key: PrivateKey::load(key)?,
+            client_auth: ClientAuth::No,
+            client_ca: vec![],
+            session_lifetime: 0,
+            session_cache: false,
+        })
+    }
+}
+
+/// 读取 PEM 格式的证书链（可以有多个证书），或者 DER 格式的单个证书
+pub fn load_certificates(data: &[u8]) -> Vec<Vec<u8>> {
+    if let Ok(str) = std::str::from_utf8(data) {
+        if str.contains("-----BEGIN") {
+            return der::pem_decode(str)
+                .into_iter()
+                .filter(|(label, _)| label == "CERTIFICATE")
+                .map(|(_, der)| der)
+                .collect();
+        }
+    }
+    if data.is_empty() {
+        vec![]
+    } else {
+        vec![data.to_vec()]
+    }
+}
+
+/// ClientHello 中本服务端关心的部分
+#[derive(Default)]
+struct ClientHello {
+    session_id: Vec<u8>,
+    supports_tls13: bool,
+    supports_cipher: bool,
+    supports_x25519: bool,
+    signature_algorithms: Vec<u16>,
+    key_share: Option<[u8; 32]>,
+    supports_psk_dhe_ke: bool,
+    psk: Option<OfferedPsk>,
+}
+
+/// pre_shared_key 扩展中的第一个 PSK ，本服务端只会尝试它
+/// binders_len: 整个 binders 列表（包括长度）的长度，计算 binder 时 ClientHello 要去掉这一部分
+struct OfferedPsk {
+    identity: Vec<u8>,
+    binder: Vec<u8>,
+    binders_len: usize,
+}
+
+/// 完成握手
+/// header: 已经被读取的第一个记录的记录头，它必须是一个握手记录
+pub fn accept<S: Read + Write>(
+    mut stream: S,
+    header: [u8; 5],
+    config: &ServerConfig,
+) -> Result<TlsStream<S>, TLSError> {
+    let now = crate::drop::time::time_difference::get_utc_timestamp();
+    let mut handshake_buffer = HandshakeBuffer::default();
+    let mut first_header = Some(header);
+    let (handshake_type, mut client_hello_message) =
+        read_plain_message(&mut stream, &mut handshake_buffer, &mut first_header)?;
+    if handshake_type != HANDSHAKE_CLIENT_HELLO {
+        send_plain_alert(&mut stream, ALERT_UNEXPECTED_MESSAGE);
+        return Err(TLSError::HandshakeFailure);
+    }
+    let mut client_hello = parse_client_hello(&client_hello_message[4..])?;
+    check_client_hello(&mut stream, &client_hello, config)?;
+
+    let mut transcript = Transcript::new();
+    let retried = client_hello.key_share.is_none();
+    if retried {
+        // HelloRetryRequest ，第一个 ClientHello 在 transcript 中被它的散列代替
+        transcript.update(&handshake_message(
+            HANDSHAKE_MESSAGE_HASH,
+            &Sha256::digest(&client_hello_message),
+        ));
+        let retry = server_hello(
+            &HELLO_RETRY_REQUEST_RANDOM,
+            &client_hello.session_id,
+            &extension(EXTENSION_KEY_SHARE, &GROUP_X25519.to_be_bytes()),
+        );
+        transcript.update(&retry);
+        let mut out = record_header(CONTENT_HANDSHAKE, retry.len()).to_vec();
+        out.extend(&retry);
+        out.extend([CONTENT_CHANGE_CIPHER_SPEC, 3, 3, 0, 1, 1]);
+        stream.write_all(&out).map_err(TLSError::Io)?;
+
+        let (handshake_type, message) =
+            read_plain_message(&mut stream, &mut handshake_buffer, &mut first_header)?;
+        if handshake_type != HANDSHAKE_CLIENT_HELLO {
+            send_plain_alert(&mut stream, ALERT_UNEXPECTED_MESSAGE);
+            return Err(TLSError::HandshakeFailure);
+        }
+        client_hello_message = message;
+        client_hello = parse_client_hello(&client_hello_message[4..])?;
+        check_client_hello(&mut stream, &client_hello, config)?;
+        if client_hello.key_share.is_none() {
+            send_plain_alert(&mut stream, ALERT_ILLEGAL_PARAMETER);
+            return Err(TLSError::HandshakeFailure);
+        }
+    }
+    let resumed = match resume_session(
+        config,
+        &client_hello,
+        &client_hello_message,
+        &transcript,
+        now,
+    ) {
+        Ok(a) => a,
+        Err(e) => {
+            send_plain_alert(&mut stream, ALERT_DECRYPT_ERROR);
+            return Err(e);
+        }
+    };
+    transcript.update(&client_hello_message);
+
+    let random = crate::drop::random::get_random_bytes(64).map_err(TLSError::Io)?;
+    let (private_key, public_key) = x25519_keypair(&random[32..].try_into().unwrap());
+    let shared = x25519_shared(&private_key, &client_hello.key_share.unwrap());
+    if shared == [0; 32] {
+        send_plain_alert(&mut stream, ALERT_ILLEGAL_PARAMETER);
+        return Err(TLSError::HandshakeFailure);
+    }
+    let mut key_share = GROUP_X25519.to_be_bytes().to_vec();
+    key_share.extend(with_length(2, &public_key));
+    let mut extensions = extension(EXTENSION_KEY_SHARE, &key_share);
+    if resumed.is_some() {
+        extensions.extend(extension(EXTENSION_PRE_SHARED_KEY, &0u16.to_be_bytes()));
+    }
+    let hello = server_hello(&random[..32], &client_hello.session_id, &extensions);
+    transcript.update(&hello);
+
+    let mut schedule = KeySchedule::new(resumed.as_ref().map(|a| a.psk.as_slice()));
+    schedule.handshake(&shared);
+    let client_handshake_secret = schedule.derive_secret(b"c hs traffic", &transcript.current());
+    let server_handshake_secret = schedule.derive_secret(b"s hs traffic", &transcript.current());
+    let mut write_keys = TrafficKeys::new(&server_handshake_secret);
+
+    // EncryptedExtensions, CertificateRequest, Certificate, CertificateVerify, Finished
+    // 恢复会话时只有 EncryptedExtensions 和 Finished
+    let mut flight = handshake_message(HANDSHAKE_ENCRYPTED_EXTENSIONS, &with_length(2, &[]));
+    if resumed.is_none() {
+        if config.client_auth != ClientAuth::No {
+            let mut body = with_length(1, &[]);
+            body.extend(with_length(
+                2,
+                &extension(
+                    EXTENSION_SIGNATURE_ALGORITHMS,
+                    &with_length(
+                        2,
+                        &SUPPORTED_SIGNATURE_SCHEMES
+                            .iter()
+                            .flat_map(|a| a.to_be_bytes())
+                            .collect::<Vec<u8>>(),
+                    ),
+                ),
+            ));
+            flight.extend(handshake_message(HANDSHAKE_CERTIFICATE_REQUEST, &body));
+        }
+        let mut list = vec![];
+        for certificate in &config.certificates {
+            list.extend(with_length(3, certificate));
+            list.extend(with_length(2, &[]));
+        }
+        let mut body = with_length(1, &[]);
+        body.extend(with_length(3, &list));
+        flight.extend(handshake_message(HANDSHAKE_CERTIFICATE, &body));
+        transcript.update(&flight);
+
+        let signature = config
+            .key
+            .sign(&certificate_verify_content(true, &transcript.current()));
+        let mut body = config.key.signature_scheme().to_be_bytes().to_vec();
+        body.extend(with_length(2, &signature));
+        let certificate_verify = handshake_message(HANDSHAKE_CERTIFICATE_VERIFY, &body);
+        transcript.update(&certificate_verify);
+        flight.extend(certificate_verify);
+    } else {
+        transcript.update(&flight);
+    }
+
+    let finished = handshake_message(
+        HANDSHAKE_FINISHED,
+        &finished_verify_data(&server_handshake_secret, &transcript.current()),
+    );
+    transcript.update(&finished);
+    flight.extend(finished);
+
+    let mut out = record_header(CONTENT_HANDSHAKE, hello.len()).to_vec();
+    out.extend(&hello);
+    if !retried {
+        // 中间设备兼容模式，参见 RFC 8446 D.4 ，只在第一个握手消息之后发送一次
+        out.extend([CONTENT_CHANGE_CIPHER_SPEC, 3, 3, 0, 1, 1]);
+    }
+    for chunk in flight.chunks(MAX_FRAGMENT) {
+        out.extend(write_keys.seal(CONTENT_HANDSHAKE, chunk));
+    }
+    stream.write_all(&out).map_err(TLSError::Io)?;
+
+    schedule.master();
+    let client_application_secret = schedule.derive_secret(b"c ap traffic", &transcript.current());
+    let server_application_secret = schedule.derive_secret(b"s ap traffic", &transcript.current());
+    // 服务端的 Finished 之后，服务端发送的记录（包括下面的警报）都使用应用数据的密钥
+    write_keys = TrafficKeys::new(&server_application_secret);
+
+    // 客户端的 Certificate, CertificateVerify, Finished
+    let mut read_keys = TrafficKeys::new(&client_handshake_secret);
+    let mut peer_certificates = match &resumed {
+        Some(a) => a.peer_certificates.clone(),
+        None => vec![],
+    };
+    let mut next_message =
+        |stream: &mut S| read_encrypted_message(stream, &mut read_keys, &mut handshake_buffer);
+    let mut message = next_message(&mut stream)?;
+    if resumed.is_none() && config.client_auth != ClientAuth::No {
+        if message.0 != HANDSHAKE_CERTIFICATE {
+            send_alert(&mut stream, &mut write_keys, ALERT_UNEXPECTED_MESSAGE);
+            return Err(TLSError::HandshakeFailure);
+        }
+        let (_, chain) = parse_certificate(&message.1[4..])?;
+        transcript.update(&message.1);
+        if chain.is_empty() {
+            if config.client_auth == ClientAuth::Required {
+                send_alert(&mut stream, &mut write_keys, ALERT_CERTIFICATE_REQUIRED);
+                return Err(TLSError::BadCertificate);
+            }
+        } else {
+            let leaf =
+                match x509::verify_chain(&chain, &config.client_ca, now, x509::OID_CLIENT_AUTH) {
+                    Ok(a) => a,
+                    Err(_) => {
+                        send_alert(&mut stream, &mut write_keys, ALERT_BAD_CERTIFICATE);
+                        return Err(TLSError::BadCertificate);
+                    }
+                };
+            let message = next_message(&mut stream)?;
+            if message.0 != HANDSHAKE_CERTIFICATE_VERIFY
+                || !verify_certificate_verify(&leaf, &message.1[4..], &transcript.current())
+            {
+                send_alert(&mut stream, &mut write_keys, ALERT_DECRYPT_ERROR);
+                return Err(TLSError::BadCertificate);
+            }
+            transcript.update(&message.1);
+            peer_certificates = chain;
+        }
+        message = next_message(&mut stream)?;
+    }
+    let expected = finished_verify_data(&client_handshake_secret, &transcript.current());
+    if message.0 != HANDSHAKE_FINISHED || !constant_time_eq(&expected, &message.1[4..]) {
+        send_alert(&mut stream, &mut write_keys, ALERT_DECRYPT_ERROR);
+        return Err(TLSError::DecryptError);
+    }
+    transcript.update(&message.1);
+
+    let mut tls = TlsStream::new(
+        stream,
+        client_application_secret,
+        server_application_secret,
+        handshake_buffer,
+        peer_certificates,
+    );
+    if config.session_lifetime > 0 && client_hello.supports_psk_dhe_ke {
+        let resumption_master_secret = schedule.derive_secret(b"res master", &transcript.current());
+        send_new_session_ticket(&mut tls, config, &resumption_master_secret, now)?;
+    }
+    Ok(tls)
+}
+
+/// 保存当前会话，并发送它的票据
+/// 每个连接只发送一个票据，所以 ticket_nonce 总是 0
+fn send_new_session_ticket<S: Read + Write>(
+    tls: &mut TlsStream<S>,
+    config: &ServerConfig,
+    resumption_master_secret: &[u8],
+    now: i64,
+) -> Result<(), TLSError> {
+    let ticket_nonce = [0];
+    let random = crate::drop::random::get_random_bytes(4).map_err(TLSError::Io)?;
+    let age_add = u32::from_be_bytes(random.try_into().unwrap());
+    let session = Session {
+        psk: hkdf_expand_label::<Sha256>(
+            resumption_master_secret,
+            b"resumption",
+            &ticket_nonce,
+            32,
+        )
+        .unwrap(),
+        created: now,
+        age_add,
+        peer_certificates: tls.peer_certificates.clone(),
+    };
+    let ticket = match session::issue(session, config.session_lifetime, config.session_cache, now) {
+        Some(a) if a.len() <= u16::MAX as usize => a,
+        _ => return Ok(()),
+    };
+    let mut body = config.session_lifetime.to_be_bytes().to_vec();
+    body.extend(age_add.to_be_bytes());
+    body.extend(with_length(1, &ticket_nonce));
+    body.extend(with_length(2, &ticket));
+    body.extend(with_length(2, &[]));
+    tls.send_handshake(&handshake_message(HANDSHAKE_NEW_SESSION_TICKET, &body))
+        .map_err(TLSError::Io)
+}
+
+/// 如果客户端提供了可以恢复的会话，验证它的 binder 并返回该会话
+/// 会话不存在、已经过期或不符合当前配置时返回 None ，此时进行完整的握手
+/// binder 不正确时返回错误，此时握手必须被中止
+/// message: 完整的 ClientHello 消息
+/// transcript: 不包括该 ClientHello 的 transcript
+fn resume_session(
+    config: &ServerConfig,
+    hello: &ClientHello,
+    message: &[u8],
+    transcript: &Transcript,
+    now: i64,
+) -> Result<Option<Session>, TLSError> {
+    let Some(psk) = &hello.psk else {
+        return Ok(None);
+    };
+    if config.session_lifetime == 0 || !hello.supports_psk_dhe_ke {
+        return Ok(None);
+    }
+    let Some(session) = session::resume(&psk.identity, config.session_lifetime, now) else {
+        return Ok(None);
+    };
+    if config.client_auth == ClientAuth::Required && session.peer_certificates.is_empty() {
+        return Ok(None);
+    }
+    let binder_key =
+        KeySchedule::new(Some(&session.psk)).derive_secret(b"res binder", &Sha256::digest(b""));
+    let mut transcript = transcript.clone();
+    transcript.update(&message[..message.len() - psk.binders_len]);
+    let expected = finished_verify_data(&binder_key, &transcript.current());
+    if !constant_time_eq(&expected, &psk.binder) {
+        return Err(TLSError::DecryptError);
+    }
+    Ok(Some(session))
+}
+
+/// 读取一个未加密的握手消息，返回 (类型, 包括头部的完整消息)
+/// first_header: 已经被读取、但还没有读取内容的记录头
+fn read_plain_message<S: Read + Write>(
+    stream: &mut S,
+    handshake_buffer: &mut HandshakeBuffer,
+    first_header: &mut Option<[u8; 5]>,
+) -> Result<(u8, Vec<u8>), TLSError> {
+    loop {
+        if let Some(message) = handshake_buffer.next_message() {
+            return Ok(message);
+        }
+        let (header, payload) = match first_header.take() {
+            Some(header) => {
+                let payload = read_record_payload(stream, &header)?;
+                (header, payload)
+            }
+            None => read_record(stream)?,
+        };
+        match header[0] {
+            CONTENT_HANDSHAKE => handshake_buffer.push(&payload),
+            CONTENT_CHANGE_CIPHER_SPEC => {}
+            CONTENT_ALERT => return Err(TLSError::Alert(*payload.get(1).unwrap_or(&0))),
+            _ => {
+                send_plain_alert(stream, ALERT_UNEXPECTED_MESSAGE);
+                return Err(TLSError::HandshakeFailure);
+            }
+        }
+    }
+}
+
+/// 读取一个以握手流量密钥加密的握手消息，返回 (类型, 包括头部的完整消息)
+fn read_encrypted_message<S: Read + Write>(
+    stream: &mut S,
+    read_keys: &mut TrafficKeys,
+    handshake_buffer: &mut HandshakeBuffer,
+) -> Result<(u8, Vec<u8>), TLSError> {
+    loop {
+        if let Some(message) = handshake_buffer.next_message() {
+            return Ok(message);
+        }
+        let (header, payload) = read_record(stream)?;
+        match header[0] {
+            CONTENT_CHANGE_CIPHER_SPEC => {}
+            CONTENT_APPLICATION_DATA => match read_keys.open(&header, &payload)? {
+                (CONTENT_HANDSHAKE, data) => handshake_buffer.push(&data),
+                (CONTENT_ALERT, data) => return Err(TLSError::Alert(*data.get(1).unwrap_or(&0))),
+                _ => return Err(TLSError::HandshakeFailure),
+            },
+            CONTENT_ALERT => return Err(TLSError::Alert(*payload.get(1).unwrap_or(&0))),
+            _ => return Err(TLSError::HandshakeFailure),
+        }
+    }
+}
+
+/// 发送一个以握手流量密钥加密的告警
+fn send_alert(stream: &mut impl Write, write_keys: &mut TrafficKeys, description: u8) {
+    let _ = stream.write_all(&write_keys.seal(CONTENT_ALERT, &[2, description]));
+}
+
+fn parse_client_hello(body: &[u8]) -> Result<ClientHello, TLSError> {
+    let mut reader = ByteReader::new(body);
+    let mut hello = ClientHello::default();
+    reader.u16()?; // legacy_version
+    reader.bytes(32)?; // random
+    hello.session_id = reader.vector(1)?.to_vec();
+    if hello.session_id.len() > 32 {
+        return Err(TLSError::BadRequest);
+    }
+    hello.supports_cipher = reader
+        .vector(2)?
+        .chunks(2)
+        .any(|a| a == CIPHER_CHACHA20_POLY1305_SHA256.to_be_bytes());
+    reader.vector(1)?; // legacy_compression_methods
+    let contains = |list: &[u8], value: u16| list.chunks(2).any(|a| a == value.to_be_bytes());
+    let extensions = reader.extensions()?;
+    for (i, (extension_type, data)) in extensions.iter().enumerate() {
+        let mut data = ByteReader::new(data);
+        match *extension_type {
+            EXTENSION_SUPPORTED_VERSIONS => {
+                hello.supports_tls13 = contains(data.vector(1)?, TLS1_3);
+            }
+            EXTENSION_SUPPORTED_GROUPS => {
+                hello.supports_x25519 = contains(data.vector(2)?, GROUP_X25519);
+            }
+            EXTENSION_SIGNATURE_ALGORITHMS => {
+                hello.signature_algorithms = data
+                    .vector(2)?
+                    .chunks_exact(2)
+                    .map(|a| u16::from_be_bytes([a[0], a[1]]))
+                    .collect();
+            }
+            EXTENSION_KEY_SHARE => {
+                let mut shares = ByteReader::new(data.vector(2)?);
+                while !shares.is_empty() {
+                    let group = shares.u16()?;
+                    let key = shares.vector(2)?;
+                    if group == GROUP_X25519 && key.len() == 32 {
+                        hello.key_share = Some(key.try_into().unwrap());
+                    }
+                }
+            }
+            EXTENSION_PSK_KEY_EXCHANGE_MODES => {
+                hello.supports_psk_dhe_ke = data.vector(1)?.contains(&PSK_DHE_KE);
+            }
+            EXTENSION_PRE_SHARED_KEY => {
+                // pre_shared_key 必须是最后一个扩展，binders 在 ClientHello 的最后
+                if i != extensions.len() - 1 {
+                    return Err(TLSError::BadRequest);
+                }
+                let mut identities = ByteReader::new(data.vector(2)?);
+                let identity = identities.vector(2)?.to_vec();
+                identities.bytes(4)?; // obfuscated_ticket_age
+                let binders = data.vector(2)?;
+                hello.psk = Some(OfferedPsk {
+                    identity,
+                    binder: ByteReader::new(binders).vector(1)?.to_vec(),
+                    binders_len: binders.len() + 2,
+                });
+            }
+            _ => {}
+        }
+    }
+    Ok(hello)
+}
+
+/// 如果客户端不支持本服务端的参数，发送告警并返回错误
+fn check_client_hello(
+    stream: &mut impl Write,
+    hello: &ClientHello,
+    config: &ServerConfig,
+) -> Result<(), TLSError> {
+    if !hello.supports_tls13 {
+        send_plain_alert(stream, ALERT_PROTOCOL_VERSION);
+        return Err(TLSError::HandshakeFailure);
+    }
+    if !hello.supports_cipher
+        || !hello.supports_x25519
+        || !hello
+            .signature_algorithms
+            .contains(&config.key.signature_scheme())
+    {
+        send_plain_alert(stream, ALERT_HANDSHAKE_FAILURE);
+        return Err(TLSError::HandshakeFailure);
+    }
+    Ok(())
+}
+
+/// 构造 ServerHello 或 HelloRetryRequest
+/// extensions: 除 supported_versions 之外的扩展，对于 HelloRetryRequest ，key_share 中只有被选择的组
+fn server_hello(random: &[u8], session_id: &[u8], extensions: &[u8]) -> Vec<u8> {
+    let mut body = vec![3, 3];
+    body.extend(random);
+    body.extend(with_length(1, session_id));
+    body.extend(CIPHER_CHACHA20_POLY1305_SHA256.to_be_bytes());
+    body.push(0);
+    let mut all = extension(EXTENSION_SUPPORTED_VERSIONS, &TLS1_3.to_be_bytes());
+    all.extend(extensions);
+    body.extend(with_length(2, &all));
+    handshake_message(HANDSHAKE_SERVER_HELLO, &body)
+}
+
+/// 验证客户端的 CertificateVerify
+fn verify_certificate_verify(
+    certificate: &x509::Certificate,
+    body: &[u8],
+    transcript_hash: &[u8],
+) -> bool {
+    let mut reader = ByteReader::new(body);
+    let (Ok(scheme), Ok(signature)) = (reader.u16(), reader.vector(2)) else {
+        return false;
+    };
+    verify_signature_scheme(
+        certificate,
+        scheme,
+        &certificate_verify_content(false, transcript_hash),
+        signature,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::client::tests::{
+        issue, issue_until, loopback, loopback_with_certificate, p256, server_config,
+    };
+    use crate::https::client::ClientCertificate;
+    use crate::https::curve25519::Ed25519KeyPair;
+    use crate::https::der;
+    use crate::https::ecdsa::{self, EcdsaKeyPair};
+
+    #[test]
+    fn test_handshake() {
+        let keys = [
+            PrivateKey::Ed25519(Ed25519KeyPair::from_seed(&[1; 32])),
+            p256(2),
+            PrivateKey::Ecdsa(EcdsaKeyPair::from_private_key(ecdsa::p384(), &[3; 48]).unwrap()),
+        ];
+        for key in &keys {
+            let certificate = issue("localhost", &["localhost"], key, "localhost", key, &[]);
+            let trusted = std::slice::from_ref(&certificate);
+            let (client, server) = loopback(
+                server_config(vec![certificate.clone()], key),
+                "localhost",
+                trusted,
+            );
+            assert!(client.is_ok());
+            assert!(server.unwrap().is_empty());
+        }
+    }
+
+    #[test]
+    fn test_client_auth() {
+        let key = p256(1);
+        let certificate = issue("localhost", &["localhost"], &key, "localhost", &key, &[]);
+        let trusted = std::slice::from_ref(&certificate);
+
+        // 本项目的客户端总是发送空的证书列表
+        let mut config = server_config(vec![certificate.clone()], &key);
+        config.client_auth = ClientAuth::Optional;
+        config.client_ca = vec![certificate.clone()];
+        let (client, server) = loopback(config, "localhost", trusted);
+        assert!(client.is_ok());
+        assert!(server.unwrap().is_empty());
+
+        let mut config = server_config(vec![certificate.clone()], &key);
+        config.client_auth = ClientAuth::Required;
+        config.client_ca = vec![certificate.clone()];
+        let (client, server) = loopback(config, "localhost", trusted);
+        // 握手已经在客户端完成，客户端在读取时收到警报
+        let expected = format!("{:?}", TLSError::Alert(ALERT_CERTIFICATE_REQUIRED));
+        assert!(matches!(client, Err(TLSError::Io(e)) if e.to_string() == expected));
+        assert!(matches!(server, Err(TLSError::BadCertificate)));
+    }
+
+    /// basicConstraints cA=true
+    fn basic_constraints_ca() -> Vec<u8> {
+        der::sequence(&[
+            der::oid(&[2, 5, 29, 19]),
+            der::octet_string(&der::sequence(&[der::encode(der::BOOLEAN, &[0xff])])),
+        ])
+    }
+
+    /// extendedKeyUsage ，其中只有 purpose
+    fn extended_key_usage(purpose: &[u64]) -> Vec<u8> {
+        der::sequence(&[
+            der::oid(&[2, 5, 29, 37]),
+            der::octet_string(&der::sequence(&[der::oid(purpose)])),
+        ])
+    }
+
+    #[test]
+    fn test_client_certificate() {
+        let server_key = p256(1);
+        let server_certificate = issue(
+            "localhost",
+            &["localhost"],
+            &server_key,
+            "localhost",
+            &server_key,
+            &[],
+        );
+        let trusted = std::slice::from_ref(&server_certificate);
+        let ca_key = p256(2);
+        let ca = issue("ca", &[], &ca_key, "ca", &ca_key, &[basic_constraints_ca()]);
+        let client_key = p256(3);
+        let client_auth = extended_key_usage(x509::OID_CLIENT_AUTH);
+        let client = |certificate: Vec<u8>| ClientCertificate {
+            certificates: vec![certificate],
+            key: p256(3),
+        };
+        let handshake = |certificate: &ClientCertificate| {
+            let mut config = server_config(vec![server_certificate.clone()], &server_key);
+            config.client_auth = ClientAuth::Required;
+            config.client_ca = vec![ca.clone()];
+            loopback_with_certificate(config, "localhost", trusted, Some(certificate))
+        };
+
+        // 由 client-ca 签发、带有 clientAuth 的证书被接受
+        let alice = client(issue(
+            "alice",
+            &[],
+            &client_key,
+            "ca",
+            &ca_key,
+            std::slice::from_ref(&client_auth),
+        ));
+        let (client_result, server) = handshake(&alice);
+        assert!(client_result.is_ok());
+        let chain = server.unwrap();
+        assert_eq!(chain, alice.certificates);
+        let leaf = x509::verify_chain(
+            &chain,
+            std::slice::from_ref(&ca),
+            crate::drop::time::time_difference::get_utc_timestamp(),
+            x509::OID_CLIENT_AUTH,
+        )
+        .unwrap();
+        assert_eq!(leaf.subject_string(), "CN=alice");
+
+        // 由其它 CA 签发、过期、没有 clientAuth 的证书被拒绝
+        let other_key = p256(4);
+        let rejected = [
+            issue(
+                "alice",
+                &[],
+                &client_key,
+                "ca",
+                &other_key,
+                std::slice::from_ref(&client_auth),
+            ),
+            issue_until(
+                "alice",
+                &[],
+                &client_key,
+                "ca",
+                &ca_key,
+                std::slice::from_ref(&client_auth),
+                der::encode(der::UTC_TIME, b"000101000000Z"),
+            ),
+            issue(
+                "alice",
+                &[],
+                &client_key,
+                "ca",
+                &ca_key,
+                &[extended_key_usage(x509::OID_SERVER_AUTH)],
+            ),
+        ];
+        for certificate in rejected {
+            let (client_result, server) = handshake(&client(certificate));
+            assert!(client_result.is_err());
+            assert!(matches!(server, Err(TLSError::BadCertificate)));
+        }
+    }
+
+    #[test]
+    fn test_client_auth_for_listener() {
+        let binds = [
+            ("0.0.0.0:8443".parse().unwrap(), ClientAuth::Required),
+            ("127.0.0.1:443".parse().unwrap(), ClientAuth::Optional),
+        ];
+        let client_auth = |a: &str| ClientAuth::for_listener(&binds, a.parse().unwrap());
+        assert_eq!(client_auth("10.0.0.1:8443"), ClientAuth::Required);
+        assert_eq!(client_auth("127.0.0.1:443"), ClientAuth::Optional);
+        assert_eq!(client_auth("10.0.0.1:443"), ClientAuth::No);
+        assert_eq!(client_auth("[::1]:80"), ClientAuth::No);
+    }
+}
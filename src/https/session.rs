@@ -0,0 +1,213 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! TLS 1.3 的会话恢复 (PSK) ，服务端在握手完成后发送 NewSessionTicket ，客户端在之后的连接中用它跳过证书的发送和验证
+//! 票据有两种：
+//! 有状态的票据是一个随机的会话 ID ，会话本身被保存在内存中的会话缓存里
+//! 无状态的票据是被服务端密钥加密的会话本身，服务端不需要保存任何东西
+//! 加密票据的密钥每隔一个会话有效期轮换一次，上一个密钥仍然可以用于解密，所以票据在有效期内总是可用的
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8446#section-4.6.1
+
+use std::collections::BTreeMap;
+use std::sync::Mutex;
+
+use super::chacha20poly1305::{self, KEY_SIZE, NONCE_SIZE};
+use super::tls13::{with_length, ByteReader};
+
+/// 会话缓存中最多保存的会话数量，超过时先清理过期的会话，再清理最早的会话
+const MAX_CACHED_SESSIONS: usize = 10000;
+const SESSION_ID_SIZE: usize = 32;
+const KEY_NAME_SIZE: usize = 16;
+
+static SESSION_CACHE: Mutex<BTreeMap<Vec<u8>, Session>> = Mutex::new(BTreeMap::new());
+static TICKET_KEYS: Mutex<Option<TicketKeys>> = Mutex::new(None);
+
+/// 一个可以被恢复的会话
+/// psk: 由 resumption_master_secret 和 ticket_nonce 派生的预共享密钥
+/// created: 会话被创建时的 UTC 时间戳（秒）
+/// age_add: 用于混淆客户端发送的票据年龄
+/// peer_certificates: 原握手中经过验证的客户端证书链，恢复后的连接沿用它
+#[derive(Clone, PartialEq, Debug)]
+pub struct Session {
+    pub psk: Vec<u8>,
+    pub created: i64,
+    pub age_add: u32,
+    pub peer_certificates: Vec<Vec<u8>>,
+}
+
+impl Session {
+    fn encode(&self) -> Vec<u8> {
+        let mut vec = with_length(1, &self.psk);
+        vec.extend(self.created.to_be_bytes());
+        vec.extend(self.age_add.to_be_bytes());
+        let mut list = vec![];
+        for certificate in &self.peer_certificates {
+            list.extend(with_length(3, certificate));
+        }
+        vec.extend(with_length(3, &list));
+        vec
+    }
+
+    fn decode(data: &[u8]) -> Option<Self> {
+        let mut reader = ByteReader::new(data);
+        let psk = reader.vector(1).ok()?.to_vec();
+        let created = i64::from_be_bytes(reader.bytes(8).ok()?.try_into().unwrap());
+        let age_add = u32::from_be_bytes(reader.bytes(4).ok()?.try_into().unwrap());
+        let mut list = ByteReader::new(reader.vector(3).ok()?);
+        let mut peer_certificates = vec![];
+        while !list.is_empty() {
+            peer_certificates.push(list.vector(3).ok()?.to_vec());
+        }
+        if !reader.is_empty() {
+            return None;
+        }
+        Some(Session {
+            psk,
+            created,
+            age_add,
+            peer_certificates,
+        })
+    }
+
+    fn is_expired(&self, lifetime: u32, now: i64) -> bool {
+        now < self.created || now - self.created >= lifetime as i64
+    }
+}
+
+/// 加密无状态票据的密钥，key_name 被放在票据的开头，用于找到解密它的密钥
+struct TicketKeys {
+    current: ([u8; KEY_NAME_SIZE], [u8; KEY_SIZE]),
+    previous: Option<([u8; KEY_NAME_SIZE], [u8; KEY_SIZE])>,
+    rotated: i64,
+}
+
+fn new_ticket_key() -> Option<([u8; KEY_NAME_SIZE], [u8; KEY_SIZE])> {
+    let random = crate::drop::random::get_random_bytes(KEY_NAME_SIZE + KEY_SIZE).ok()?;
+    Some((
+        random[..KEY_NAME_SIZE].try_into().unwrap(),
+        random[KEY_NAME_SIZE..].try_into().unwrap(),
+    ))
+}
+
+/// 保存会话并返回它的票据
+/// cache: 为 true 时使用有状态的票据，否则使用无状态的票据
+pub fn issue(session: Session, lifetime: u32, cache: bool, now: i64) -> Option<Vec<u8>> {
+    if cache {
+        let id = crate::drop::random::get_random_bytes(SESSION_ID_SIZE).ok()?;
+        let mut sessions = SESSION_CACHE.lock().unwrap();
+        if sessions.len() >= MAX_CACHED_SESSIONS {
+            sessions.retain(|_, a| !a.is_expired(lifetime, now));
+        }
+        if sessions.len() >= MAX_CACHED_SESSIONS {
+            sessions.pop_first();
+        }
+        sessions.insert(id.clone(), session);
+        return Some(id);
+    }
+
+    let mut keys = TICKET_KEYS.lock().unwrap();
+    match &mut *keys {
+        Some(a) if now - a.rotated < lifetime as i64 => {}
+        Some(a) => {
+            a.previous = Some(a.current);
+            a.current = new_ticket_key()?;
+            a.rotated = now;
+        }
+        None => {
+            *keys = Some(TicketKeys {
+                current: new_ticket_key()?,
+                previous: None,
+                rotated: now,
+            })
+        }
+    }
+    let (name, key) = keys.as_ref().unwrap().current;
+    let nonce: [u8; NONCE_SIZE] = crate::drop::random::get_random_bytes(NONCE_SIZE)
+        .ok()?
+        .try_into()
+        .unwrap();
+    let mut ticket = name.to_vec();
+    ticket.extend(nonce);
+    ticket.extend(chacha20poly1305::seal(
+        &key,
+        &nonce,
+        &name,
+        &session.encode(),
+    ));
+    Some(ticket)
+}
+
+/// 根据票据找到会话，票据无效或会话已经过期时返回 None
+pub fn resume(ticket: &[u8], lifetime: u32, now: i64) -> Option<Session> {
+    let session = if let Some(a) = SESSION_CACHE.lock().unwrap().get(ticket) {
+        a.clone()
+    } else {
+        if ticket.len() < KEY_NAME_SIZE + NONCE_SIZE {
+            return None;
+        }
+        let (name, rest) = ticket.split_at(KEY_NAME_SIZE);
+        let (nonce, ciphertext) = rest.split_at(NONCE_SIZE);
+        let key = {
+            let keys = TICKET_KEYS.lock().unwrap();
+            let keys = keys.as_ref()?;
+            [Some(keys.current), keys.previous]
+                .into_iter()
+                .flatten()
+                .find(|(a, _)| a == name)?
+                .1
+        };
+        let plain = chacha20poly1305::open(&key, nonce.try_into().unwrap(), name, ciphertext)?;
+        Session::decode(&plain)?
+    };
+    if session.is_expired(lifetime, now) {
+        return None;
+    }
+    Some(session)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn session() -> Session {
+        Session {
+            psk: vec![7; 32],
+            created: 1_700_000_000,
+            age_add: 0x01020304,
+            peer_certificates: vec![vec![1, 2, 3], vec![4; 300]],
+        }
+    }
+
+    #[test]
+    fn test_resume() {
+        let now = 1_700_000_000;
+        for cache in [true, false] {
+            let ticket = issue(session(), 3600, cache, now).unwrap();
+            assert_eq!(resume(&ticket, 3600, now + 10), Some(session()));
+            // 过期
+            assert_eq!(resume(&ticket, 3600, now + 3600), None);
+            // 被篡改
+            let mut tampered = ticket.clone();
+            *tampered.last_mut().unwrap() ^= 1;
+            assert_eq!(resume(&tampered, 3600, now + 10), None);
+        }
+        assert_eq!(Session::decode(&session().encode()), Some(session()));
+
+        // 密钥轮换后，上一个密钥加密的票据仍然可以被解密，再上一个则不能
+        let mut current = session();
+        current.created = now + 7000;
+        let ticket = issue(current.clone(), 3600, false, now + 7200).unwrap();
+        let next = issue(session(), 3600, false, now + 10800).unwrap();
+        assert_ne!(ticket[..KEY_NAME_SIZE], next[..KEY_NAME_SIZE]);
+        assert_eq!(resume(&ticket, 3600, now + 7200), Some(current));
+        issue(session(), 3600, false, now + 14400).unwrap();
+        assert_eq!(resume(&ticket, 3600, now + 7200), None);
+    }
+}
@@ -11,6 +11,9 @@
 //! 结构及其成员的命名应该遵循相关标准或论文中给定的标准论文（如果有）
 //! 本模块可能在很长一段时间内都不会被实际使用，但可靠性应该被首要考虑
 //! TODO: 在本模块的代码中插入对应标准或论文的链接的注释
+//! 目前实际使用的 TLS 1.3 实现在 tls13 、 server 和 client 模块中，本模块中 TLS 1.2 的部分暂时没有被使用
+
+#![allow(dead_code)]
 
 #[derive(Debug)]
 pub enum TLSError {
@@ -19,6 +22,12 @@ pub enum TLSError {
     HandshakeContentTypeError(u8),
     UndefinedCiperSuite,
     BadRequest,
+    Io(std::io::Error),
+    DecryptError,
+    #[allow(dead_code)]
+    Alert(u8),
+    HandshakeFailure,
+    BadCertificate,
 }
 
 #[derive(Debug)]
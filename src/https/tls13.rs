@@ -0,0 +1,620 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块提供 TLS 1.3 客户端和服务端共用的部分：记录层的读写和加密、密钥计划、握手消息的封装
+//! 目前只支持 TLS_CHACHA20_POLY1305_SHA256 密码套件和 X25519 密钥交换
+//!
+//! 密钥计划的各个阶段依次是：
+//! ```
+//! let mut schedule = KeySchedule::new(None);           // Early Secret
+//! schedule.handshake(&shared_secret);                  // Handshake Secret
+//! schedule.derive_secret(b"s hs traffic", &transcript); // 握手流量密钥
+//! schedule.master();                                   // Master Secret
+//! schedule.derive_secret(b"s ap traffic", &transcript); // 应用流量密钥
+//! ```
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc8446
+
+#![allow(dead_code)]
+
+use std::io::{Read, Write};
+
+use super::chacha20poly1305;
+use super::der;
+use super::ecdsa;
+use super::hash::{hkdf_expand_label, hkdf_extract, hmac, HashFunction, Sha256, Sha384, Sha512};
+use super::tls::TLSError;
+use super::x509::Certificate;
+
+pub const CONTENT_CHANGE_CIPHER_SPEC: u8 = 20;
+pub const CONTENT_ALERT: u8 = 21;
+pub const CONTENT_HANDSHAKE: u8 = 22;
+pub const CONTENT_APPLICATION_DATA: u8 = 23;
+
+pub const HANDSHAKE_CLIENT_HELLO: u8 = 1;
+pub const HANDSHAKE_SERVER_HELLO: u8 = 2;
+pub const HANDSHAKE_NEW_SESSION_TICKET: u8 = 4;
+pub const HANDSHAKE_ENCRYPTED_EXTENSIONS: u8 = 8;
+pub const HANDSHAKE_CERTIFICATE: u8 = 11;
+pub const HANDSHAKE_CERTIFICATE_REQUEST: u8 = 13;
+pub const HANDSHAKE_CERTIFICATE_VERIFY: u8 = 15;
+pub const HANDSHAKE_FINISHED: u8 = 20;
+pub const HANDSHAKE_KEY_UPDATE: u8 = 24;
+/// HelloRetryRequest 之后，用于在 transcript 中代替第一个 ClientHello
+pub const HANDSHAKE_MESSAGE_HASH: u8 = 254;
+
+pub const EXTENSION_SERVER_NAME: u16 = 0;
+pub const EXTENSION_SUPPORTED_GROUPS: u16 = 10;
+pub const EXTENSION_SIGNATURE_ALGORITHMS: u16 = 13;
+pub const EXTENSION_PRE_SHARED_KEY: u16 = 41;
+pub const EXTENSION_SUPPORTED_VERSIONS: u16 = 43;
+pub const EXTENSION_PSK_KEY_EXCHANGE_MODES: u16 = 45;
+pub const EXTENSION_KEY_SHARE: u16 = 51;
+
+pub const CIPHER_CHACHA20_POLY1305_SHA256: u16 = 0x1303;
+pub const GROUP_X25519: u16 = 0x001d;
+pub const SIGNATURE_ECDSA_P256_SHA256: u16 = 0x0403;
+pub const SIGNATURE_ECDSA_P384_SHA384: u16 = 0x0503;
+pub const SIGNATURE_ED25519: u16 = 0x0807;
+pub const SIGNATURE_RSA_PSS_SHA256: u16 = 0x0804;
+pub const SIGNATURE_RSA_PSS_SHA384: u16 = 0x0805;
+pub const SIGNATURE_RSA_PSS_SHA512: u16 = 0x0806;
+/// 本项目能验证的签名算法，客户端和服务端在 signature_algorithms 中声明它们
+pub const SUPPORTED_SIGNATURE_SCHEMES: &[u16] = &[
+    SIGNATURE_ED25519,
+    SIGNATURE_ECDSA_P256_SHA256,
+    SIGNATURE_ECDSA_P384_SHA384,
+    SIGNATURE_RSA_PSS_SHA256,
+    SIGNATURE_RSA_PSS_SHA384,
+    SIGNATURE_RSA_PSS_SHA512,
+];
+pub const TLS1_3: u16 = 0x0304;
+pub const PSK_DHE_KE: u8 = 1;
+
+pub const ALERT_CLOSE_NOTIFY: u8 = 0;
+pub const ALERT_UNEXPECTED_MESSAGE: u8 = 10;
+pub const ALERT_BAD_RECORD_MAC: u8 = 20;
+pub const ALERT_HANDSHAKE_FAILURE: u8 = 40;
+pub const ALERT_BAD_CERTIFICATE: u8 = 42;
+pub const ALERT_ILLEGAL_PARAMETER: u8 = 47;
+pub const ALERT_DECODE_ERROR: u8 = 50;
+pub const ALERT_DECRYPT_ERROR: u8 = 51;
+pub const ALERT_PROTOCOL_VERSION: u8 = 70;
+pub const ALERT_CERTIFICATE_REQUIRED: u8 = 116;
+
+/// 单个记录的明文最大长度
+pub const MAX_FRAGMENT: usize = 16384;
+
+/// HelloRetryRequest 的 random 是固定的
+pub const HELLO_RETRY_REQUEST_RANDOM: [u8; 32] = [
+    0xcf, 0x21, 0xad, 0x74, 0xe5, 0x9a, 0x61, 0x11, 0xbe, 0x1d, 0x8c, 0x02, 0x1e, 0x65, 0xb8, 0x91,
+    0xc2, 0xa2, 0x11, 0x16, 0x7a, 0xbb, 0x8c, 0x5e, 0x07, 0x9e, 0x09, 0xe2, 0xc8, 0xa8, 0x33, 0x9c,
+];
+
+/// 某一方向的流量密钥，每加密或解密一个记录，序号加一
+pub struct TrafficKeys {
+    key: [u8; 32],
+    iv: [u8; 12],
+    seq: u64,
+}
+impl TrafficKeys {
+    pub fn new(secret: &[u8]) -> Self {
+        TrafficKeys {
+            key: hkdf_expand_label::<Sha256>(secret, b"key", b"", 32)
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            iv: hkdf_expand_label::<Sha256>(secret, b"iv", b"", 12)
+                .unwrap()
+                .try_into()
+                .unwrap(),
+            seq: 0,
+        }
+    }
+
+    fn nonce(&mut self) -> [u8; 12] {
+        let mut nonce = self.iv;
+        for (a, b) in nonce[4..].iter_mut().zip(self.seq.to_be_bytes()) {
+            *a ^= b;
+        }
+        self.seq += 1;
+        nonce
+    }
+
+    /// 返回完整的、可以直接写入流的记录
+    pub fn seal(&mut self, content_type: u8, data: &[u8]) -> Vec<u8> {
+        let mut inner = data.to_vec();
+        inner.push(content_type);
+        let header = record_header(
+            CONTENT_APPLICATION_DATA,
+            inner.len() + chacha20poly1305::TAG_SIZE,
+        );
+        let nonce = self.nonce();
+        let mut record = header.to_vec();
+        record.extend(chacha20poly1305::seal(&self.key, &nonce, &header, &inner));
+        record
+    }
+
+    /// 返回 (真实的内容类型, 明文)
+    pub fn open(&mut self, header: &[u8; 5], payload: &[u8]) -> Result<(u8, Vec<u8>), TLSError> {
+        let nonce = self.nonce();
+        let mut inner = chacha20poly1305::open(&self.key, &nonce, header, payload)
+            .ok_or(TLSError::DecryptError)?;
+        // 去掉填充的零，最后一个非零字节是真实的内容类型
+        while let Some(0) = inner.last() {
+            inner.pop();
+        }
+        match inner.pop() {
+            Some(content_type) => Ok((content_type, inner)),
+            None => Err(TLSError::BadRequest),
+        }
+    }
+}
+
+pub fn record_header(content_type: u8, len: usize) -> [u8; 5] {
+    let len = (len as u16).to_be_bytes();
+    [content_type, 3, 3, len[0], len[1]]
+}
+
+/// 读取一个记录，返回 (记录头, 记录内容)
+pub fn read_record(stream: &mut impl Read) -> Result<([u8; 5], Vec<u8>), TLSError> {
+    let mut header = [0; 5];
+    stream.read_exact(&mut header).map_err(TLSError::Io)?;
+    let payload = read_record_payload(stream, &header)?;
+    Ok((header, payload))
+}
+
+/// 在记录头已经被读取之后，读取记录内容
+pub fn read_record_payload(stream: &mut impl Read, header: &[u8; 5]) -> Result<Vec<u8>, TLSError> {
+    let len = u16::from_be_bytes([header[3], header[4]]) as usize;
+    if len > MAX_FRAGMENT + 256 {
+        return Err(TLSError::BadRequest);
+    }
+    let mut payload = vec![0; len];
+    stream.read_exact(&mut payload).map_err(TLSError::Io)?;
+    Ok(payload)
+}
+
+/// 发送一个未加密的告警，这只应该在握手密钥建立之前使用
+pub fn send_plain_alert(stream: &mut impl Write, description: u8) {
+    let mut record = record_header(CONTENT_ALERT, 2).to_vec();
+    record.extend([2, description]);
+    let _ = stream.write_all(&record);
+}
+
+/// 为握手消息加上类型和长度
+pub fn handshake_message(handshake_type: u8, body: &[u8]) -> Vec<u8> {
+    let mut vec = vec![handshake_type];
+    vec.extend(&(body.len() as u32).to_be_bytes()[1..]);
+    vec.extend(body);
+    vec
+}
+
+/// 加上 len_size 个字节的长度前缀
+pub fn with_length(len_size: usize, body: &[u8]) -> Vec<u8> {
+    let mut vec = body.len().to_be_bytes()[8 - len_size..].to_vec();
+    vec.extend(body);
+    vec
+}
+
+pub fn extension(extension_type: u16, body: &[u8]) -> Vec<u8> {
+    let mut vec = extension_type.to_be_bytes().to_vec();
+    vec.extend(with_length(2, body));
+    vec
+}
+
+/// 握手消息的缓冲区，握手消息可能被分散在多个记录中，一个记录也可能有多个握手消息
+#[derive(Default)]
+pub struct HandshakeBuffer {
+    data: Vec<u8>,
+}
+impl HandshakeBuffer {
+    pub fn push(&mut self, data: &[u8]) {
+        self.data.extend(data);
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    /// 如果已经有了一个完整的握手消息，返回 (类型, 包括头部的完整消息)
+    pub fn next_message(&mut self) -> Option<(u8, Vec<u8>)> {
+        if self.data.len() < 4 {
+            return None;
+        }
+        let len = u32::from_be_bytes([0, self.data[1], self.data[2], self.data[3]]) as usize;
+        if self.data.len() < 4 + len {
+            return None;
+        }
+        let message: Vec<u8> = self.data.drain(..4 + len).collect();
+        Some((message[0], message))
+    }
+}
+
+/// 按顺序读取字节的游标，所有越界读取都返回 TLSError::BadRequest
+pub struct ByteReader<'a> {
+    data: &'a [u8],
+}
+impl<'a> ByteReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        ByteReader { data }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.data.is_empty()
+    }
+
+    pub fn bytes(&mut self, len: usize) -> Result<&'a [u8], TLSError> {
+        if self.data.len() < len {
+            return Err(TLSError::BadRequest);
+        }
+        let (a, b) = self.data.split_at(len);
+        self.data = b;
+        Ok(a)
+    }
+
+    pub fn u8(&mut self) -> Result<u8, TLSError> {
+        Ok(self.bytes(1)?[0])
+    }
+
+    pub fn u16(&mut self) -> Result<u16, TLSError> {
+        let a = self.bytes(2)?;
+        Ok(u16::from_be_bytes([a[0], a[1]]))
+    }
+
+    pub fn u24(&mut self) -> Result<usize, TLSError> {
+        let a = self.bytes(3)?;
+        Ok(u32::from_be_bytes([0, a[0], a[1], a[2]]) as usize)
+    }
+
+    /// 读取一个有 len_size 个字节长度前缀的字段
+    pub fn vector(&mut self, len_size: usize) -> Result<&'a [u8], TLSError> {
+        let len = match len_size {
+            1 => self.u8()? as usize,
+            2 => self.u16()? as usize,
+            _ => self.u24()?,
+        };
+        self.bytes(len)
+    }
+
+    /// 把剩余的数据解析为扩展列表，返回 (类型, 内容) 的列表
+    pub fn extensions(&mut self) -> Result<Vec<(u16, &'a [u8])>, TLSError> {
+        let mut reader = ByteReader::new(self.vector(2)?);
+        let mut vec = vec![];
+        while !reader.is_empty() {
+            vec.push((reader.u16()?, reader.vector(2)?));
+        }
+        Ok(vec)
+    }
+}
+
+/// TLS 1.3 密钥计划，使用 SHA-256
+pub struct KeySchedule {
+    secret: Vec<u8>,
+}
+impl KeySchedule {
+    /// psk: 用于会话恢复的预共享密钥，没有则为 None
+    pub fn new(psk: Option<&[u8]>) -> Self {
+        let zeros = [0; 32];
+        KeySchedule {
+            secret: hkdf_extract::<Sha256>(&zeros, psk.unwrap_or(&zeros)),
+        }
+    }
+
+    fn next_stage(&mut self, ikm: &[u8]) {
+        let derived = self.derive_secret(b"derived", &Sha256::digest(b""));
+        self.secret = hkdf_extract::<Sha256>(&derived, ikm);
+    }
+
+    /// 进入 Handshake Secret 阶段
+    pub fn handshake(&mut self, shared_secret: &[u8]) {
+        self.next_stage(shared_secret);
+    }
+
+    /// 进入 Master Secret 阶段
+    pub fn master(&mut self) {
+        self.next_stage(&[0; 32]);
+    }
+
+    /// transcript_hash: 到目前为止所有握手消息的散列
+    pub fn derive_secret(&self, label: &[u8], transcript_hash: &[u8]) -> Vec<u8> {
+        hkdf_expand_label::<Sha256>(&self.secret, label, transcript_hash, 32).unwrap()
+    }
+}
+
+/// 解析 Certificate 消息，返回 (certificate_request_context, 证书列表)
+pub fn parse_certificate(body: &[u8]) -> Result<(Vec<u8>, Vec<Vec<u8>>), TLSError> {
+    let mut reader = ByteReader::new(body);
+    let context = reader.vector(1)?.to_vec();
+    let mut list = ByteReader::new(reader.vector(3)?);
+    let mut certificates = vec![];
+    while !list.is_empty() {
+        certificates.push(list.vector(3)?.to_vec());
+        list.vector(2)?; // 每个证书的扩展
+    }
+    Ok((context, certificates))
+}
+
+/// 计算 Finished 消息的 verify_data
+pub fn finished_verify_data(traffic_secret: &[u8], transcript_hash: &[u8]) -> Vec<u8> {
+    let finished_key = hkdf_expand_label::<Sha256>(traffic_secret, b"finished", b"", 32).unwrap();
+    hmac::<Sha256>(&finished_key, transcript_hash)
+}
+
+/// KeyUpdate 之后的下一代流量密钥
+pub fn next_traffic_secret(traffic_secret: &[u8]) -> Vec<u8> {
+    hkdf_expand_label::<Sha256>(traffic_secret, b"traffic upd", b"", 32).unwrap()
+}
+
+/// CertificateVerify 签名的内容，context 区分了客户端和服务端
+pub fn certificate_verify_content(server: bool, transcript_hash: &[u8]) -> Vec<u8> {
+    let mut vec = vec![0x20; 64];
+    vec.extend(if server {
+        b"TLS 1.3, server CertificateVerify".as_slice()
+    } else {
+        b"TLS 1.3, client CertificateVerify".as_slice()
+    });
+    vec.push(0);
+    vec.extend(transcript_hash);
+    vec
+}
+
+/// 用证书的公钥验证 CertificateVerify 中的签名
+/// ECDSA 的曲线必须和签名算法相符，RSA 只支持 rsa_pss_rsae_* （TLS 1.3 不允许 PKCS#1 v1.5）
+pub fn verify_signature_scheme(
+    certificate: &Certificate,
+    scheme: u16,
+    message: &[u8],
+    signature: &[u8],
+) -> bool {
+    let curve = certificate.ecdsa_curve();
+    let rsa_public_key = || certificate.rsa_public_key();
+    match scheme {
+        SIGNATURE_ED25519 => certificate
+            .verify_signature(der::OID_ED25519, message, signature)
+            .is_ok(),
+        SIGNATURE_ECDSA_P256_SHA256 if curve.is_some_and(|a| std::ptr::eq(a, ecdsa::p256())) => {
+            certificate
+                .verify_signature(der::OID_ECDSA_SHA256, message, signature)
+                .is_ok()
+        }
+        SIGNATURE_ECDSA_P384_SHA384 if curve.is_some_and(|a| std::ptr::eq(a, ecdsa::p384())) => {
+            certificate
+                .verify_signature(der::OID_ECDSA_SHA384, message, signature)
+                .is_ok()
+        }
+        SIGNATURE_RSA_PSS_SHA256 => rsa_public_key()
+            .is_some_and(|a| a.verify_pss::<Sha256>(&Sha256::digest(message), signature)),
+        SIGNATURE_RSA_PSS_SHA384 => rsa_public_key()
+            .is_some_and(|a| a.verify_pss::<Sha384>(&Sha384::digest(message), signature)),
+        SIGNATURE_RSA_PSS_SHA512 => rsa_public_key()
+            .is_some_and(|a| a.verify_pss::<Sha512>(&Sha512::digest(message), signature)),
+        _ => false,
+    }
+}
+
+/// 握手消息的累积散列
+#[derive(Clone)]
+pub struct Transcript {
+    hash: Sha256,
+}
+impl Transcript {
+    pub fn new() -> Self {
+        Transcript {
+            hash: Sha256::new(),
+        }
+    }
+
+    pub fn update(&mut self, message: &[u8]) {
+        self.hash.update(message);
+    }
+
+    pub fn current(&self) -> Vec<u8> {
+        self.hash.clone().finalize()
+    }
+}
+
+/// 握手完成之后的连接，客户端和服务端共用，它实现了 Read 和 Write ，可以像 TcpStream 一样使用
+/// peer_certificates: 对端发送的证书链（DER 格式），第一个是对端自己的证书
+pub struct TlsStream<S: Read + Write> {
+    stream: S,
+    read_keys: TrafficKeys,
+    read_secret: Vec<u8>,
+    write_keys: TrafficKeys,
+    write_secret: Vec<u8>,
+    handshake_buffer: HandshakeBuffer,
+    plain: Vec<u8>,
+    plain_pos: usize,
+    closed: bool,
+    pub peer_certificates: Vec<Vec<u8>>,
+}
+
+impl<S: Read + Write> TlsStream<S> {
+    /// read_secret, write_secret: 双方的应用流量密钥
+    /// handshake_buffer: 握手时未被处理的数据
+    pub fn new(
+        stream: S,
+        read_secret: Vec<u8>,
+        write_secret: Vec<u8>,
+        handshake_buffer: HandshakeBuffer,
+        peer_certificates: Vec<Vec<u8>>,
+    ) -> Self {
+        TlsStream {
+            stream,
+            read_keys: TrafficKeys::new(&read_secret),
+            read_secret,
+            write_keys: TrafficKeys::new(&write_secret),
+            write_secret,
+            handshake_buffer,
+            plain: vec![],
+            plain_pos: 0,
+            closed: false,
+            peer_certificates,
+        }
+    }
+
+    /// 发送一个握手后的握手消息，例如 NewSessionTicket
+    pub fn send_handshake(&mut self, message: &[u8]) -> std::io::Result<()> {
+        let record = self.write_keys.seal(CONTENT_HANDSHAKE, message);
+        self.stream.write_all(&record)
+    }
+
+    /// 发送 close_notify 告警，之后不应再写入
+    pub fn close(&mut self) {
+        let record = self
+            .write_keys
+            .seal(CONTENT_ALERT, &[1, ALERT_CLOSE_NOTIFY]);
+        let _ = self.stream.write_all(&record);
+    }
+
+    /// 读取下一个记录，并根据其类型处理
+    fn fill(&mut self) -> std::io::Result<()> {
+        let (header, payload) = match read_record(&mut self.stream) {
+            Ok(a) => a,
+            Err(TLSError::Io(e)) if e.kind() == std::io::ErrorKind::UnexpectedEof => {
+                self.closed = true;
+                return Ok(());
+            }
+            Err(e) => return Err(to_io_error(e)),
+        };
+        match header[0] {
+            CONTENT_CHANGE_CIPHER_SPEC => Ok(()),
+            CONTENT_APPLICATION_DATA => {
+                match self
+                    .read_keys
+                    .open(&header, &payload)
+                    .map_err(to_io_error)?
+                {
+                    (CONTENT_APPLICATION_DATA, data) => {
+                        self.plain = data;
+                        self.plain_pos = 0;
+                    }
+                    (CONTENT_HANDSHAKE, data) => {
+                        self.handshake_buffer.push(&data);
+                        while let Some((handshake_type, message)) =
+                            self.handshake_buffer.next_message()
+                        {
+                            // 其它握手消息（例如 NewSessionTicket）被忽略
+                            if handshake_type == HANDSHAKE_KEY_UPDATE {
+                                self.key_update(message.get(4) == Some(&1))?;
+                            }
+                        }
+                    }
+                    (CONTENT_ALERT, data) => {
+                        if data.get(1) == Some(&ALERT_CLOSE_NOTIFY) {
+                            self.closed = true;
+                        } else {
+                            return Err(to_io_error(TLSError::Alert(*data.get(1).unwrap_or(&0))));
+                        }
+                    }
+                    _ => return Err(to_io_error(TLSError::BadRequest)),
+                }
+                Ok(())
+            }
+            CONTENT_ALERT => Err(to_io_error(TLSError::Alert(*payload.get(1).unwrap_or(&0)))),
+            _ => Err(to_io_error(TLSError::BadRequest)),
+        }
+    }
+
+    /// 对端更新了它的流量密钥，如果 update_requested ，本端也要更新
+    fn key_update(&mut self, update_requested: bool) -> std::io::Result<()> {
+        self.read_secret = next_traffic_secret(&self.read_secret);
+        self.read_keys = TrafficKeys::new(&self.read_secret);
+        if update_requested {
+            self.send_handshake(&handshake_message(HANDSHAKE_KEY_UPDATE, &[0]))?;
+            self.write_secret = next_traffic_secret(&self.write_secret);
+            self.write_keys = TrafficKeys::new(&self.write_secret);
+        }
+        Ok(())
+    }
+}
+
+impl<S: Read + Write> Read for TlsStream<S> {
+    fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+        while self.plain_pos >= self.plain.len() {
+            if self.closed {
+                return Ok(0);
+            }
+            self.fill()?;
+        }
+        let len = buf.len().min(self.plain.len() - self.plain_pos);
+        buf[..len].copy_from_slice(&self.plain[self.plain_pos..self.plain_pos + len]);
+        self.plain_pos += len;
+        Ok(len)
+    }
+}
+
+impl<S: Read + Write> Write for TlsStream<S> {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        let mut out = vec![];
+        for chunk in buf.chunks(MAX_FRAGMENT) {
+            out.extend(self.write_keys.seal(CONTENT_APPLICATION_DATA, chunk));
+        }
+        self.stream.write_all(&out)?;
+        Ok(buf.len())
+    }
+    fn flush(&mut self) -> std::io::Result<()> {
+        self.stream.flush()
+    }
+}
+
+pub fn to_io_error(e: TLSError) -> std::io::Error {
+    match e {
+        TLSError::Io(e) => e,
+        e => std::io::Error::new(std::io::ErrorKind::InvalidData, format!("{:?}", e)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::hash::to_hex;
+
+    #[test]
+    fn test_key_schedule() {
+        // RFC 8448 3. Simple 1-RTT Handshake
+        fn from_hex(str: &str) -> Vec<u8> {
+            (0..str.len())
+                .step_by(2)
+                .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+                .collect()
+        }
+        let shared = from_hex("8bd4054fb55b9d63fdfbacf9f04b9f0d35e6d63f537563efd46272900f89492d");
+        let hello_hash =
+            from_hex("860c06edc07858ee8e78f0e7428c58edd6b43f2ca3e6e95f02ed063cf0e1cad8");
+        let mut schedule = KeySchedule::new(None);
+        schedule.handshake(&shared);
+        let server_hs = schedule.derive_secret(b"s hs traffic", &hello_hash);
+        assert_eq!(
+            to_hex(&server_hs),
+            "b67b7d690cc16c4e75e54213cb2d37b4e9c912bcded9105d42befd59d391ad38"
+        );
+        let keys = TrafficKeys::new(&server_hs);
+        assert_eq!(to_hex(&keys.iv), "5d313eb2671276ee13000b30");
+    }
+
+    #[test]
+    fn test_record_roundtrip() {
+        let mut writer = TrafficKeys::new(&[7; 32]);
+        let mut reader = TrafficKeys::new(&[7; 32]);
+        for message in [b"hello".as_slice(), b"", &[0; 100]] {
+            let record = writer.seal(CONTENT_APPLICATION_DATA, message);
+            let header: [u8; 5] = record[..5].try_into().unwrap();
+            let (content_type, plain) = reader.open(&header, &record[5..]).unwrap();
+            assert_eq!(content_type, CONTENT_APPLICATION_DATA);
+            assert_eq!(plain, message);
+        }
+        // 序号不一致时无法解密
+        let record = writer.seal(CONTENT_HANDSHAKE, b"x");
+        let mut other = TrafficKeys::new(&[7; 32]);
+        assert!(other
+            .open(&record[..5].try_into().unwrap(), &record[5..])
+            .is_err());
+    }
+}
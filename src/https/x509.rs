@@ -0,0 +1,775 @@
+/* Tiny-Tiny-Web
+ * Copyright (C) 2024 Plasma (https://github.com/duoduo70/Tiny-Tiny-Web/).
+ *
+ * You should have received a copy of the GNU General Public License Version 3
+ * along with this program;
+ * if not, see <https://www.gnu.org/licenses/>.
+ */
+
+//! # 本模块的总则
+//! 本模块解析 X.509 证书，并验证证书链
+//!
+//! 支持的签名算法是 Ed25519 、ECDSA (P-256, P-384) 和 RSA PKCS#1 v1.5 (SHA-256, SHA-384, SHA-512)
+//! 这覆盖了常见的公共 CA （例如 Let's Encrypt ）和内部使用的 CA （例如给管理后台签发客户端证书）
+//!
+//! 证书链的验证是简化的：
+//! 1. 每个证书都必须在有效期内
+//! 2. 每个证书的 issuer 必须和上一级证书的 subject 完全相同（按 DER 编码比较）
+//! 3. 中间证书必须是 CA 证书 (basicConstraints) ，如果它有 keyUsage ，其中必须有 keyCertSign
+//! 4. 最后必须到达一个被信任的证书，被信任的证书本身也可以直接出现在链中
+//! 5. 终端实体证书如果有 extendedKeyUsage ，其中必须有调用者要求的用途 (serverAuth 或 clientAuth)
+//!
+//! 不支持吊销检查、名称约束和策略，所以除了 basicConstraints, keyUsage, extendedKeyUsage 和
+//! subjectAltName 之外，带有任何关键 (critical) 扩展的证书都无法被解析
+//!
+//! See: https://datatracker.ietf.org/doc/html/rfc5280
+
+#![allow(dead_code)]
+
+use super::curve25519::ed25519_verify;
+use super::der::{self, DerError, DerReader};
+use super::ecdsa::{self, Curve};
+use super::hash::{HashFunction, Sha256, Sha384, Sha512};
+use super::rsa::RsaPublicKey;
+
+/// basicConstraints 2.5.29.19
+const OID_BASIC_CONSTRAINTS: &[u64] = &[2, 5, 29, 19];
+/// keyUsage 2.5.29.15
+const OID_KEY_USAGE: &[u64] = &[2, 5, 29, 15];
+/// extendedKeyUsage 2.5.29.37
+const OID_EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37];
+/// anyExtendedKeyUsage 2.5.29.37.0
+const OID_ANY_EXTENDED_KEY_USAGE: &[u64] = &[2, 5, 29, 37, 0];
+/// id-kp-serverAuth 1.3.6.1.5.5.7.3.1
+pub const OID_SERVER_AUTH: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 3, 1];
+/// id-kp-clientAuth 1.3.6.1.5.5.7.3.2
+pub const OID_CLIENT_AUTH: &[u64] = &[1, 3, 6, 1, 5, 5, 7, 3, 2];
+/// 证书链的最大长度，不包括被信任的证书
+const MAX_CHAIN_LENGTH: usize = 8;
+
+#[derive(Debug, PartialEq)]
+pub enum X509Error {
+    Malformed,
+    Expired,
+    UnknownIssuer,
+    BadSignature,
+    UnsupportedAlgorithm,
+    UnsupportedCriticalExtension,
+    WrongPurpose,
+}
+impl From<DerError> for X509Error {
+    fn from(_: DerError) -> Self {
+        X509Error::Malformed
+    }
+}
+
+/// 一个被解析的证书
+/// raw: 完整的 DER 编码
+/// tbs: 被签名的部分 (tbsCertificate) ，包括标签和长度
+/// issuer, subject: Name 的 DER 编码，用于比较
+/// not_before, not_after: Unix 时间戳
+/// public_key_algorithm, public_key: subjectPublicKeyInfo 中的算法和公钥
+/// public_key_parameters: 算法的参数是 OID 时（即 EC 公钥的曲线），它的值
+/// is_ca: 是否有 cA 为真的 basicConstraints 扩展
+/// can_sign_certificates: 没有 keyUsage 扩展，或者其中有 keyCertSign
+/// extended_key_usage: extendedKeyUsage 扩展中的用途，没有该扩展时是 None
+/// dns_names, ip_addresses: subjectAltName 中的 dNSName 和 iPAddress
+pub struct Certificate {
+    pub raw: Vec<u8>,
+    pub tbs: Vec<u8>,
+    pub signature_algorithm: Vec<u64>,
+    pub signature: Vec<u8>,
+    pub issuer: Vec<u8>,
+    pub subject: Vec<u8>,
+    pub not_before: i64,
+    pub not_after: i64,
+    pub public_key_algorithm: Vec<u64>,
+    pub public_key: Vec<u8>,
+    pub public_key_parameters: Option<Vec<u64>>,
+    pub is_ca: bool,
+    pub can_sign_certificates: bool,
+    pub extended_key_usage: Option<Vec<Vec<u64>>>,
+    pub dns_names: Vec<String>,
+    pub ip_addresses: Vec<Vec<u8>>,
+}
+
+impl Certificate {
+    pub fn parse(data: &[u8]) -> Result<Self, X509Error> {
+        let certificate = DerReader::new(data).read_expect(der::SEQUENCE)?;
+        let mut reader = certificate.reader();
+        let tbs = reader.read_expect(der::SEQUENCE)?;
+        let signature_algorithm = reader.read_expect(der::SEQUENCE)?.reader().read()?.oid()?;
+        let signature = reader.read_expect(der::BIT_STRING)?.bit_string()?;
+
+        let mut reader = tbs.reader();
+        reader.read_optional(0xa0)?; // version
+        reader.read_expect(der::INTEGER)?; // serialNumber
+        reader.read_expect(der::SEQUENCE)?; // signature
+        let issuer = reader.read_expect(der::SEQUENCE)?;
+        let mut validity = reader.read_expect(der::SEQUENCE)?.reader();
+        let not_before = validity.read()?.time()?;
+        let not_after = validity.read()?.time()?;
+        let subject = reader.read_expect(der::SEQUENCE)?;
+        let mut public_key_info = reader.read_expect(der::SEQUENCE)?.reader();
+        let mut algorithm = public_key_info.read_expect(der::SEQUENCE)?.reader();
+        let public_key_algorithm = algorithm.read()?.oid()?;
+        let public_key_parameters = match algorithm.read_optional(der::OID)? {
+            Some(a) => Some(a.oid()?),
+            None => None,
+        };
+        let public_key = public_key_info.read_expect(der::BIT_STRING)?.bit_string()?;
+        reader.read_optional(0x81)?; // issuerUniqueID
+        reader.read_optional(0x82)?; // subjectUniqueID
+        let mut is_ca = false;
+        let mut can_sign_certificates = true;
+        let mut extended_key_usage = None;
+        let mut dns_names = vec![];
+        let mut ip_addresses = vec![];
+        if let Some(extensions) = reader.read_optional(0xa3)? {
+            let mut extensions = extensions.reader().read_expect(der::SEQUENCE)?.reader();
+            while !extensions.is_empty() {
+                let mut extension = extensions.read_expect(der::SEQUENCE)?.reader();
+                let oid = extension.read()?.oid()?;
+                let critical = extension
+                    .read_optional(der::BOOLEAN)?
+                    .is_some_and(|a| a.content.first().is_some_and(|a| *a != 0));
+                let value = extension.read_expect(der::OCTET_STRING)?;
+                let sequence = || -> Result<DerReader, X509Error> {
+                    Ok(DerReader::new(value.content)
+                        .read_expect(der::SEQUENCE)?
+                        .reader())
+                };
+                if oid == OID_BASIC_CONSTRAINTS {
+                    if let Some(ca) = sequence()?.read_optional(der::BOOLEAN)? {
+                        is_ca = ca.content.first().is_some_and(|a| *a != 0);
+                    }
+                } else if oid == OID_KEY_USAGE {
+                    // keyCertSign 是第 5 位，第一个字节是未使用的位数
+                    let key_usage = DerReader::new(value.content).read_expect(der::BIT_STRING)?;
+                    can_sign_certificates = key_usage.content.get(1).is_some_and(|a| a & 0x04 != 0);
+                } else if oid == OID_EXTENDED_KEY_USAGE {
+                    let mut value = sequence()?;
+                    let mut purposes = vec![];
+                    while !value.is_empty() {
+                        purposes.push(value.read()?.oid()?);
+                    }
+                    extended_key_usage = Some(purposes);
+                } else if oid == der::OID_SUBJECT_ALT_NAME {
+                    let mut value = sequence()?;
+                    while !value.is_empty() {
+                        let name = value.read()?;
+                        match name.tag {
+                            0x82 => dns_names.push(
+                                String::from_utf8(name.content.to_vec())
+                                    .map_err(|_| X509Error::Malformed)?,
+                            ),
+                            0x87 => ip_addresses.push(name.content.to_vec()),
+                            _ => {}
+                        }
+                    }
+                } else if critical {
+                    return Err(X509Error::UnsupportedCriticalExtension);
+                }
+            }
+        }
+
+        Ok(Certificate {
+            raw: certificate.raw.to_vec(),
+            tbs: tbs.raw.to_vec(),
+            signature_algorithm,
+            signature: signature.to_vec(),
+            issuer: issuer.raw.to_vec(),
+            subject: subject.raw.to_vec(),
+            not_before,
+            not_after,
+            public_key_algorithm,
+            public_key: public_key.to_vec(),
+            public_key_parameters,
+            is_ca,
+            can_sign_certificates,
+            extended_key_usage,
+            dns_names,
+            ip_addresses,
+        })
+    }
+
+    /// 返回 RFC 4514 格式的 subject ，例如 `CN=alice,OU=ops,O=Example`
+    pub fn subject_string(&self) -> String {
+        name_to_string(&self.subject).unwrap_or_default()
+    }
+
+    /// 如果是 Ed25519 公钥，返回它
+    pub fn ed25519_public_key(&self) -> Option<[u8; 32]> {
+        if self.public_key_algorithm != der::OID_ED25519 {
+            return None;
+        }
+        self.public_key.as_slice().try_into().ok()
+    }
+
+    /// 如果是 P-256 或 P-384 公钥，返回它的曲线
+    pub fn ecdsa_curve(&self) -> Option<&'static Curve> {
+        if self.public_key_algorithm != der::OID_EC_PUBLIC_KEY {
+            return None;
+        }
+        ecdsa::curve_from_oid(self.public_key_parameters.as_ref()?)
+    }
+
+    /// 如果是 RSA 公钥，返回它
+    pub fn rsa_public_key(&self) -> Option<RsaPublicKey> {
+        if self.public_key_algorithm != der::OID_RSA_ENCRYPTION {
+            return None;
+        }
+        RsaPublicKey::parse(&self.public_key)
+    }
+
+    /// 用本证书的公钥验证一个签名
+    /// algorithm: X.509 的签名算法，例如 ecdsa-with-SHA256 ，它必须和公钥的类型相符
+    pub fn verify_signature(
+        &self,
+        algorithm: &[u64],
+        message: &[u8],
+        signature: &[u8],
+    ) -> Result<(), X509Error> {
+        let ecdsa_algorithms = [
+            der::OID_ECDSA_SHA256,
+            der::OID_ECDSA_SHA384,
+            der::OID_ECDSA_SHA512,
+        ];
+        let rsa_algorithms = [
+            der::OID_SHA256_WITH_RSA,
+            der::OID_SHA384_WITH_RSA,
+            der::OID_SHA512_WITH_RSA,
+        ];
+        let digest = |i| match i {
+            0 => (der::OID_SHA256, Sha256::digest(message)),
+            1 => (der::OID_SHA384, Sha384::digest(message)),
+            _ => (der::OID_SHA512, Sha512::digest(message)),
+        };
+        let valid = if algorithm == der::OID_ED25519 {
+            let public_key = self
+                .ed25519_public_key()
+                .ok_or(X509Error::UnsupportedAlgorithm)?;
+            signature
+                .try_into()
+                .is_ok_and(|a| ed25519_verify(&public_key, message, a))
+        } else if let Some(i) = ecdsa_algorithms.iter().position(|a| *a == algorithm) {
+            let curve = self.ecdsa_curve().ok_or(X509Error::UnsupportedAlgorithm)?;
+            let (_, digest) = digest(i);
+            ecdsa::signature_from_der(signature, curve.bytes())
+                .is_some_and(|a| ecdsa::verify(curve, &self.public_key, &digest, &a))
+        } else if let Some(i) = rsa_algorithms.iter().position(|a| *a == algorithm) {
+            let public_key = self
+                .rsa_public_key()
+                .ok_or(X509Error::UnsupportedAlgorithm)?;
+            let (hash_oid, digest) = digest(i);
+            public_key.verify_pkcs1(hash_oid, &digest, signature)
+        } else {
+            return Err(X509Error::UnsupportedAlgorithm);
+        };
+        if valid {
+            Ok(())
+        } else {
+            Err(X509Error::BadSignature)
+        }
+    }
+
+    /// 本证书是否是被 issuer 的密钥签名的
+    pub fn is_signed_by(&self, issuer: &Certificate) -> Result<(), X509Error> {
+        issuer.verify_signature(&self.signature_algorithm, &self.tbs, &self.signature)
+    }
+
+    /// 本证书的 subjectAltName 是否包含 host ，host 是域名或 IP 地址
+    /// 通配符只能是最左边的整个标签，并且只匹配一个标签，例如 `*.example.com` 匹配 `a.example.com`
+    /// 不会退回到 commonName ，参见：https://datatracker.ietf.org/doc/html/rfc6125#section-6.4.4
+    pub fn matches_host(&self, host: &str) -> bool {
+        if let Ok(ip) = host.parse::<std::net::IpAddr>() {
+            let ip = match ip {
+                std::net::IpAddr::V4(a) => a.octets().to_vec(),
+                std::net::IpAddr::V6(a) => a.octets().to_vec(),
+            };
+            return self.ip_addresses.contains(&ip);
+        }
+        let host = host.trim_end_matches('.').to_ascii_lowercase();
+        self.dns_names.iter().any(|name| {
+            let name = name.to_ascii_lowercase();
+            match name.strip_prefix("*.") {
+                Some(suffix) => host
+                    .split_once('.')
+                    .is_some_and(|(label, rest)| !label.is_empty() && rest == suffix),
+                None => name == host,
+            }
+        })
+    }
+
+    fn check_time(&self, now: i64) -> Result<(), X509Error> {
+        if now < self.not_before || now > self.not_after {
+            return Err(X509Error::Expired);
+        }
+        Ok(())
+    }
+}
+
+/// 验证一个证书链，chain 的第一个证书是终端实体证书，之后的每个证书都签发了它前面的证书
+/// trusted: 被信任的证书 (DER 格式)
+/// now: 当前的 Unix 时间戳
+/// purpose: 终端实体证书的用途，OID_SERVER_AUTH 或 OID_CLIENT_AUTH
+/// 成功时返回被解析的终端实体证书
+pub fn verify_chain(
+    chain: &[Vec<u8>],
+    trusted: &[Vec<u8>],
+    now: i64,
+    purpose: &[u64],
+) -> Result<Certificate, X509Error> {
+    if chain.is_empty() || chain.len() > MAX_CHAIN_LENGTH {
+        return Err(X509Error::UnknownIssuer);
+    }
+    let chain = chain
+        .iter()
+        .map(|a| Certificate::parse(a))
+        .collect::<Result<Vec<_>, _>>()?;
+    if let Some(purposes) = &chain[0].extended_key_usage {
+        if !purposes
+            .iter()
+            .any(|a| a == purpose || a == OID_ANY_EXTENDED_KEY_USAGE)
+        {
+            return Err(X509Error::WrongPurpose);
+        }
+    }
+    let trusted: Vec<Certificate> = trusted
+        .iter()
+        .filter_map(|a| Certificate::parse(a).ok())
+        .collect();
+
+    for (i, certificate) in chain.iter().enumerate() {
+        certificate.check_time(now)?;
+        if trusted.iter().any(|a| a.raw == certificate.raw) {
+            break;
+        }
+        // 同一个 subject 可能有多个被信任的证书（例如更换了密钥的根证书），任意一个签发了本证书即可
+        let mut anchors = trusted
+            .iter()
+            .filter(|a| a.subject == certificate.issuer)
+            .peekable();
+        if anchors.peek().is_some() {
+            let mut result = Err(X509Error::UnknownIssuer);
+            for issuer in anchors {
+                result = issuer
+                    .check_time(now)
+                    .and_then(|_| certificate.is_signed_by(issuer));
+                if result.is_ok() {
+                    break;
+                }
+            }
+            result?;
+            break;
+        }
+        match chain.get(i + 1) {
+            Some(issuer)
+                if issuer.subject == certificate.issuer
+                    && issuer.is_ca
+                    && issuer.can_sign_certificates =>
+            {
+                certificate.is_signed_by(issuer)?;
+            }
+            _ => return Err(X509Error::UnknownIssuer),
+        }
+    }
+    Ok(chain.into_iter().next().unwrap())
+}
+
+/// 把 Name 的 DER 编码转换为 RFC 4514 格式的字符串，RDN 的顺序和编码中的顺序相反
+pub fn name_to_string(name: &[u8]) -> Result<String, X509Error> {
+    let mut rdns = vec![];
+    let mut reader = DerReader::new(name).read_expect(der::SEQUENCE)?.reader();
+    while !reader.is_empty() {
+        let mut set = reader.read_expect(der::SET)?.reader();
+        let mut attributes = vec![];
+        while !set.is_empty() {
+            let mut attribute = set.read_expect(der::SEQUENCE)?.reader();
+            let oid = attribute.read()?.oid()?;
+            let value = attribute.read()?;
+            let key = match oid.as_slice() {
+                [2, 5, 4, 3] => "CN".to_owned(),
+                [2, 5, 4, 6] => "C".to_owned(),
+                [2, 5, 4, 7] => "L".to_owned(),
+                [2, 5, 4, 8] => "ST".to_owned(),
+                [2, 5, 4, 10] => "O".to_owned(),
+                [2, 5, 4, 11] => "OU".to_owned(),
+                [0, 9, 2342, 19200300, 100, 1, 25] => "DC".to_owned(),
+                [0, 9, 2342, 19200300, 100, 1, 1] => "UID".to_owned(),
+                _ => oid
+                    .iter()
+                    .map(|a| a.to_string())
+                    .collect::<Vec<_>>()
+                    .join("."),
+            };
+            let value = match value.string() {
+                Ok(a) => escape_value(a),
+                // 无法以字符串表示的值，按照 RFC 4514 以 # 加十六进制表示
+                Err(_) => "#".to_owned() + &super::hash::to_hex(value.raw),
+            };
+            attributes.push(key + "=" + &value);
+        }
+        rdns.push(attributes.join("+"));
+    }
+    rdns.reverse();
+    Ok(rdns.join(","))
+}
+
+fn escape_value(str: &str) -> String {
+    let mut out = String::new();
+    let len = str.chars().count();
+    for (i, c) in str.chars().enumerate() {
+        if matches!(c, ',' | '+' | '"' | '\\' | '<' | '>' | ';' | '=')
+            || (i == 0 && (c == '#' || c == ' '))
+            || (i == len - 1 && c == ' ')
+        {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::https::curve25519::Ed25519KeyPair;
+    use crate::https::ecdsa::EcdsaKeyPair;
+    use crate::https::key::PrivateKey;
+
+    fn from_hex(str: &str) -> Vec<u8> {
+        (0..str.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&str[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    fn name(cn: &str) -> Vec<u8> {
+        der::sequence(&[
+            der::set(&[der::sequence(&[
+                der::oid(&[2, 5, 4, 10]),
+                der::utf8_string("Example, Inc."),
+            ])]),
+            der::set(&[der::sequence(&[
+                der::oid(der::OID_COMMON_NAME),
+                der::utf8_string(cn),
+            ])]),
+        ])
+    }
+
+    fn ed25519(seed: u8) -> PrivateKey {
+        PrivateKey::Ed25519(Ed25519KeyPair::from_seed(&[seed; 32]))
+    }
+
+    fn p256(seed: u8) -> PrivateKey {
+        PrivateKey::Ecdsa(EcdsaKeyPair::from_private_key(ecdsa::p256(), &[seed; 32]).unwrap())
+    }
+
+    fn basic_constraints_ca() -> Vec<u8> {
+        der::sequence(&[
+            der::oid(OID_BASIC_CONSTRAINTS),
+            der::octet_string(&der::sequence(&[der::encode(der::BOOLEAN, &[0xff])])),
+        ])
+    }
+
+    /// 签发一个在 [0, 1000] 之间有效的证书
+    fn issue(
+        subject: &str,
+        issuer: &str,
+        key: &PrivateKey,
+        issuer_key: &PrivateKey,
+        extensions: &[Vec<u8>],
+    ) -> Vec<u8> {
+        let time = |a: &str| der::encode(der::UTC_TIME, a.as_bytes());
+        let mut tbs = vec![
+            der::context(0, true, &der::small_integer(2)),
+            der::small_integer(1),
+            issuer_key.signature_algorithm(),
+            name(issuer),
+            der::sequence(&[time("700101000000Z"), time("700101001640Z")]),
+            name(subject),
+            key.subject_public_key_info(),
+        ];
+        if !extensions.is_empty() {
+            tbs.push(der::context(3, true, &der::sequence(extensions)));
+        }
+        let tbs = der::sequence(&tbs);
+        let signature = issuer_key.sign(&tbs);
+        der::sequence(&[
+            tbs,
+            issuer_key.signature_algorithm(),
+            der::bit_string(&signature),
+        ])
+    }
+
+    #[test]
+    fn test_verify_chain() {
+        let root_key = ed25519(1);
+        let intermediate_key = ed25519(2);
+        let leaf_key = ed25519(3);
+        let ca = [basic_constraints_ca()];
+        let root = issue("root", "root", &root_key, &root_key, &ca);
+        let intermediate = issue("ca", "root", &intermediate_key, &root_key, &ca);
+        let leaf = issue("alice", "ca", &leaf_key, &intermediate_key, &[]);
+
+        let certificate = verify_chain(
+            &[leaf.clone(), intermediate.clone()],
+            std::slice::from_ref(&root),
+            500,
+            OID_SERVER_AUTH,
+        )
+        .unwrap();
+        assert_eq!(certificate.subject_string(), "CN=alice,O=Example\\, Inc.");
+        assert_eq!(
+            certificate.ed25519_public_key(),
+            Some(Ed25519KeyPair::from_seed(&[3; 32]).public_key)
+        );
+
+        // 过期、缺少中间证书、不被信任的根、被信任的证书本身
+        assert_eq!(
+            verify_chain(
+                &[leaf.clone(), intermediate.clone()],
+                std::slice::from_ref(&root),
+                1001,
+                OID_SERVER_AUTH
+            )
+            .err(),
+            Some(X509Error::Expired)
+        );
+        assert_eq!(
+            verify_chain(
+                std::slice::from_ref(&leaf),
+                std::slice::from_ref(&root),
+                500,
+                OID_SERVER_AUTH
+            )
+            .err(),
+            Some(X509Error::UnknownIssuer)
+        );
+        assert_eq!(
+            verify_chain(
+                &[leaf.clone(), intermediate.clone()],
+                &[],
+                500,
+                OID_SERVER_AUTH
+            )
+            .err(),
+            Some(X509Error::UnknownIssuer)
+        );
+        assert!(verify_chain(
+            std::slice::from_ref(&leaf),
+            std::slice::from_ref(&leaf),
+            500,
+            OID_SERVER_AUTH
+        )
+        .is_ok());
+
+        // 中间证书不是 CA
+        let fake = issue("ca", "root", &intermediate_key, &root_key, &[]);
+        assert_eq!(
+            verify_chain(
+                &[leaf, fake],
+                std::slice::from_ref(&root),
+                500,
+                OID_SERVER_AUTH
+            )
+            .err(),
+            Some(X509Error::UnknownIssuer)
+        );
+
+        // 签名不匹配
+        let forged = issue("bob", "ca", &leaf_key, &leaf_key, &[]);
+        assert_eq!(
+            verify_chain(&[forged, intermediate], &[root], 500, OID_SERVER_AUTH).err(),
+            Some(X509Error::BadSignature)
+        );
+    }
+
+    fn extension(oid: &[u64], critical: bool, value: &[u8]) -> Vec<u8> {
+        let mut extension = vec![der::oid(oid)];
+        if critical {
+            extension.push(der::encode(der::BOOLEAN, &[0xff]));
+        }
+        extension.push(der::octet_string(value));
+        der::sequence(&extension)
+    }
+
+    #[test]
+    fn test_extensions() {
+        let root_key = ed25519(1);
+        let intermediate_key = ed25519(2);
+        let leaf_key = ed25519(3);
+        let ca = basic_constraints_ca();
+        let root = issue(
+            "root",
+            "root",
+            &root_key,
+            &root_key,
+            std::slice::from_ref(&ca),
+        );
+        let intermediate = issue(
+            "ca",
+            "root",
+            &intermediate_key,
+            &root_key,
+            std::slice::from_ref(&ca),
+        );
+        let trusted = std::slice::from_ref(&root);
+        let eku = |purposes: &[&[u64]]| {
+            let purposes: Vec<Vec<u8>> = purposes.iter().map(|a| der::oid(a)).collect();
+            extension(OID_EXTENDED_KEY_USAGE, false, &der::sequence(&purposes))
+        };
+        let chain = |extensions: &[Vec<u8>]| {
+            vec![
+                issue("alice", "ca", &leaf_key, &intermediate_key, extensions),
+                intermediate.clone(),
+            ]
+        };
+
+        // 没有 extendedKeyUsage 时，证书可以用于任何用途
+        assert!(verify_chain(&chain(&[]), trusted, 500, OID_CLIENT_AUTH).is_ok());
+        let server = chain(&[eku(&[OID_SERVER_AUTH])]);
+        assert!(verify_chain(&server, trusted, 500, OID_SERVER_AUTH).is_ok());
+        assert_eq!(
+            verify_chain(&server, trusted, 500, OID_CLIENT_AUTH).err(),
+            Some(X509Error::WrongPurpose)
+        );
+        let both = chain(&[eku(&[OID_SERVER_AUTH, OID_CLIENT_AUTH])]);
+        assert!(verify_chain(&both, trusted, 500, OID_CLIENT_AUTH).is_ok());
+        let any = chain(&[eku(&[OID_ANY_EXTENDED_KEY_USAGE])]);
+        assert!(verify_chain(&any, trusted, 500, OID_CLIENT_AUTH).is_ok());
+
+        // 不认识的扩展只有在关键时才会使证书被拒绝，这里使用 nameConstraints
+        let name_constraints = |critical| extension(&[2, 5, 29, 30], critical, &der::sequence(&[]));
+        assert!(verify_chain(
+            &chain(&[name_constraints(false)]),
+            trusted,
+            500,
+            OID_CLIENT_AUTH
+        )
+        .is_ok());
+        let critical = chain(&[name_constraints(true)]);
+        assert_eq!(
+            Certificate::parse(&critical[0]).err(),
+            Some(X509Error::UnsupportedCriticalExtension)
+        );
+        assert_eq!(
+            verify_chain(&critical, trusted, 500, OID_CLIENT_AUTH).err(),
+            Some(X509Error::UnsupportedCriticalExtension)
+        );
+
+        // 中间证书的 keyUsage 中只有 digitalSignature ，或者有 keyCertSign
+        let key_usage = |bits: u8| {
+            extension(
+                OID_KEY_USAGE,
+                true,
+                &der::encode(der::BIT_STRING, &[1, bits]),
+            )
+        };
+        for (bits, valid) in [(0x80, false), (0x86, true)] {
+            let intermediate = issue(
+                "ca",
+                "root",
+                &intermediate_key,
+                &root_key,
+                &[ca.clone(), key_usage(bits)],
+            );
+            let leaf = issue("alice", "ca", &leaf_key, &intermediate_key, &[]);
+            assert_eq!(
+                verify_chain(&[leaf, intermediate], trusted, 500, OID_CLIENT_AUTH).is_ok(),
+                valid
+            );
+        }
+    }
+
+    #[test]
+    fn test_ecdsa_chain_and_host() {
+        // 两个 subject 相同的根证书，只有第二个签发了中间证书
+        let old_root_key = p256(1);
+        let root_key = p256(2);
+        let intermediate_key = p256(3);
+        let leaf_key =
+            PrivateKey::Ecdsa(EcdsaKeyPair::from_private_key(ecdsa::p384(), &[4; 48]).unwrap());
+        let ca = [basic_constraints_ca()];
+        let old_root = issue("root", "root", &old_root_key, &old_root_key, &ca);
+        let root = issue("root", "root", &root_key, &root_key, &ca);
+        let intermediate = issue("ca", "root", &intermediate_key, &root_key, &ca);
+        let subject_alt_name = der::sequence(&[
+            der::oid(der::OID_SUBJECT_ALT_NAME),
+            der::octet_string(&der::sequence(&[
+                der::encode(0x82, b"example.com"),
+                der::encode(0x82, b"*.Example.ORG"),
+                der::encode(0x87, &[127, 0, 0, 1]),
+            ])),
+        ]);
+        let leaf = issue(
+            "alice",
+            "ca",
+            &leaf_key,
+            &intermediate_key,
+            &[subject_alt_name],
+        );
+
+        let chain = [leaf, intermediate];
+        let certificate =
+            verify_chain(&chain, &[old_root.clone(), root], 500, OID_SERVER_AUTH).unwrap();
+        assert!(std::ptr::eq(
+            certificate.ecdsa_curve().unwrap(),
+            ecdsa::p384()
+        ));
+        assert_eq!(
+            verify_chain(&chain, &[old_root], 500, OID_SERVER_AUTH).err(),
+            Some(X509Error::BadSignature)
+        );
+
+        assert!(certificate.matches_host("example.com"));
+        assert!(certificate.matches_host("EXAMPLE.com."));
+        assert!(certificate.matches_host("www.example.org"));
+        assert!(certificate.matches_host("127.0.0.1"));
+        assert!(!certificate.matches_host("www.example.com"));
+        assert!(!certificate.matches_host("example.org"));
+        assert!(!certificate.matches_host("a.b.example.org"));
+        assert!(!certificate.matches_host("alice"));
+        assert!(!certificate.matches_host("127.0.0.2"));
+        assert!(!certificate.matches_host("::1"));
+    }
+
+    #[test]
+    fn test_rsa_certificate() {
+        // 由 Python 的 cryptography 库生成的自签名证书，1024 位 RSA 密钥，sha384WithRSAEncryption
+        let raw = from_hex(
+            "308201b23082011ba003020102020101300d06092a864886f70d01010c0500300e310c300a06035504030c0372736130\
+             1e170d3730303130313030303030305a170d3730303130313030313634305a300e310c300a06035504030c0372736130\
+             819f300d06092a864886f70d010101050003818d0030818902818100cf5f47c83fda36a5c0bbf8790dc600b947a63d18\
+             d9a80de9076d3e63ed2da852bdebc2e9b4d34b93ccfcbe2c658ce60a15549119eb2761a0729ab324e516bd45746c9ebe\
+             30c518f1a6ac8295dd7d83e181e7e91e97b9cf8290cd286ae056b2a31d556e2c8a2e35b87037f22ed17316e6605d6388\
+             707dc64a97f0db9cc06e00cf0203010001a320301e301c0603551d1104153013820b7273612e6578616d706c6587047f\
+             000001300d06092a864886f70d01010c0500038181000bc92a2d275ff309ac9c6d6413ddc735ae199212b6f00447de5b\
+             3cdd3470a10ba4afdf1f71cdf8dbf198138cd77318ce9da4ca9b40b4843add01484273dee02f6318cee800d5c76fda6b\
+             a3373afd47291f85c0e387ac155fecc1fe9c32b7292e5035ac3d73b6b2fd9f3df6b387832cf2db373566d82435f619f1\
+             06927837da5c",
+        );
+        let certificate = Certificate::parse(&raw).unwrap();
+        assert!(certificate.rsa_public_key().is_some());
+        assert_eq!(certificate.is_signed_by(&certificate), Ok(()));
+        assert!(certificate.matches_host("rsa.example"));
+        assert!(certificate.matches_host("127.0.0.1"));
+
+        let mut forged = Certificate::parse(&raw).unwrap();
+        forged.tbs[20] ^= 1;
+        assert_eq!(
+            forged.is_signed_by(&certificate),
+            Err(X509Error::BadSignature)
+        );
+        // 签名算法和公钥的类型不符
+        assert_eq!(
+            certificate.verify_signature(
+                der::OID_ECDSA_SHA384,
+                &certificate.tbs,
+                &certificate.signature
+            ),
+            Err(X509Error::UnsupportedAlgorithm)
+        );
+    }
+}
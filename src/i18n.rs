@@ -43,5 +43,14 @@ crate::macros::create_static_string_list!(
     "Return code:",
     "Compile error:", // 34
     "The pipe only receive string or bool, not: ",
-    "Unsupported status code: "
+    "Unsupported status code: ",
+    "HTTPS redirect listener started: ", // 37
+    "ACME: Requesting a certificate for: ",
+    "ACME: Certificate saved: ",
+    "ACME: Can not issue certificate: ", // 40
+    "HTTPS is not configured: no usable certificate or private key",
+    "TLS handshake failed: ",
+    "Request handler error:",
+    "@handler needs a file which evaluates to a lambda and a URL pattern",
+    "Template error:" // 45
 );
@@ -7,25 +7,23 @@
  */
 
 use std::{
-    io::Read,
-    net::{TcpListener, TcpStream},
+    io::{Read, Write},
+    net::TcpListener,
     sync::{atomic::Ordering, Mutex},
 };
 
 use crate::{
     config::{
-        Config, RouterConfig, ENABLE_CODE_BAD_REQUEST, SSL_CERTIFICATE, SSL_PRAVITE_KEY,
-        XRPS_COUNTER_CACHE_SIZE,
+        Config, RedirectBind, RouterConfig, ENABLE_CODE_BAD_REQUEST, XRPS_COUNTER_CACHE_SIZE,
     },
     drop::{
         http::{HttpRequest, HttpResponse},
         log::LogLevel::*,
-        random::get_random_256,
     },
-    https::tls::*,
     i18n::LOG,
     macros::*,
-    utils::{GlobalValue, TimeErr},
+    router::ConnectionInfo,
+    utils::TimeErr,
 };
 use std::collections::VecDeque;
 
@@ -55,6 +53,8 @@ impl ReqCounter {
 }
 
 pub fn listener_init(config: Config) -> TcpListener {
+    redirect_listeners_init(&config.redirect_bind);
+
     let socket_addresses: Vec<std::net::SocketAddr> = config
         .addr_bind
         .iter()
@@ -72,11 +72,126 @@ pub fn listener_init(config: Config) -> TcpListener {
     let socket_addresses_array: &[std::net::SocketAddr] = socket_addresses.as_slice();
     let listener = TcpListener::bind(socket_addresses_array);
 
-    process_result!(
+    let listener = process_result!(
         listener,
         TcpListener,
         format!("{}{:#?}", LOG[1], config.addr_bind)
-    )
+    );
+    // 必须在监听之后启动，因为 HTTP-01 挑战需要由本服务器应答
+    crate::https::acme::start(config.acme);
+    listener
+}
+
+/// 每一个重定向监听地址都有一个独立的线程，它们不经过 Router 和 Pipe
+/// 因为重定向响应非常简单，所以不需要线程池
+fn redirect_listeners_init(binds: &[RedirectBind]) {
+    for bind in binds {
+        let listener = TcpListener::bind(&bind.addr);
+        let listener = process_result!(listener, TcpListener, format!("{}{}", LOG[1], bind.addr));
+        log!(Info, format!("{}{}", LOG[37], bind.addr));
+        let bind = bind.clone();
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                match stream {
+                    Ok(stream) => {
+                        let _ = stream.set_read_timeout(Some(std::time::Duration::from_secs(5)));
+                        handle_redirect(stream, &bind)
+                    }
+                    Err(_) => log!(Warn, LOG[4]),
+                }
+            }
+        });
+    }
+}
+
+/// 读取请求行和请求头，然后以 301 或 308 响应重定向到 HTTPS 源
+/// 如果没有配置源，则使用请求的 Host 头（去掉端口）
+fn handle_redirect<S: Read + Write>(mut stream: S, bind: &RedirectBind) {
+    let mut lines = std::io::BufRead::lines(std::io::BufReader::new(&mut stream));
+    let target = match lines.next() {
+        Some(Ok(line)) => match line.split(' ').nth(1) {
+            Some(a) if a.starts_with('/') => a.to_owned(),
+            _ => "/".to_owned(),
+        },
+        _ => return,
+    };
+    let mut host = None;
+    for line in lines {
+        match line {
+            Ok(line) if !line.is_empty() => {
+                if let Some((k, v)) = line.split_once(':') {
+                    if k.eq_ignore_ascii_case("host") {
+                        host = Some(v.trim().to_owned());
+                    }
+                }
+            }
+            _ => break,
+        }
+    }
+
+    let mut response = HttpResponse::new();
+    response.set_version("HTTP/1.1");
+    response
+        .set_default_headers("Tiny-Tiny-Web/2")
+        .result_timeerr_default();
+    response.set_header("Content-Length", "0".to_owned());
+    response.set_header("Connection", "close".to_owned());
+    let origin = match (&bind.origin, host) {
+        (Some(origin), _) => origin.clone(),
+        (None, Some(host)) if is_valid_host(&host) => {
+            "https://".to_owned() + redirect_host_without_port(&host)
+        }
+        _ => {
+            response.set_state("400 BAD REQUEST");
+            write_stream(stream, &mut response);
+            return;
+        }
+    };
+    response.set_state(if bind.code == 308 {
+        "308 PERMANENT REDIRECT"
+    } else {
+        "301 MOVED PERMANENTLY"
+    });
+    response.set_header("Location", origin + &target);
+    log!(
+        Debug,
+        format!(
+            "{}{}\n",
+            LOG[8],
+            String::from_utf8_lossy(&response.get_stream())
+        )
+    );
+    write_stream(stream, &mut response);
+}
+
+fn is_valid_host(host: &str) -> bool {
+    !host.is_empty()
+        && host
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || ".-:[]".contains(c))
+}
+
+fn redirect_host_without_port(host: &str) -> &str {
+    if host.starts_with('[') {
+        // IPv6 字面量，例如 `[::1]:80`
+        match host.find(']') {
+            Some(a) => &host[..=a],
+            None => host,
+        }
+    } else {
+        host.split(':').next().unwrap_or(host)
+    }
+}
+
+/// 为通过 TLS 发送的响应设置 `Strict-Transport-Security` 响应头
+/// 根据 RFC 6797 ，该响应头不能通过明文 HTTP 发送，所以 secure 为 false 时什么都不做
+fn set_hsts_header(response: &mut HttpResponse, config: &RouterConfig, secure: bool) {
+    if !secure {
+        return;
+    }
+    if let Some(hsts) = &config.hsts {
+        response.set_header("Strict-Transport-Security", hsts.clone());
+    }
 }
 
 #[allow(unused_mut)]
@@ -84,32 +199,93 @@ pub fn handle_connection(mut stream: std::net::TcpStream, config: &Mutex<RouterC
     #[cfg(feature = "nightly")]
     {
         let mut buf = [0; 5];
-        let _ = stream.read(&mut buf);
-        if buf[0] == 22 {
-            if unsafe { SSL_CERTIFICATE.is_none() } {
-                todo!(); // TODO: add log and return
-            }
+        if stream.read_exact(&mut buf).is_err() {
+            return;
+        }
+        if buf[0] == crate::https::tls13::CONTENT_HANDSHAKE {
             //https
-            let record = crate::https::tls::RecordMessage::new(buf.into());
-            if let Ok(a) = record {
-                result_https_request(&stream, config, a)
-            }
-        } else if buf == "GET /".as_bytes() {
-            // 因为读取 buf 时对原 Stream 进行了一次裁剪，所以在 get_request_str 函数中要把 "GET /" 加回去
+            let local_addr = stream.local_addr().ok();
+            let server_config = match tls_server_config(&config.lock().unwrap(), local_addr) {
+                Some(a) => a,
+                None => {
+                    log!(Error, LOG[41]);
+                    return;
+                }
+            };
+            handle_tls_connection(stream, buf, config, &server_config)
+        } else {
+            // 因为读取 buf 时对原 Stream 进行了一次裁剪，所以在 get_request_str 函数中要把它加回去
             //http
-            result_http_request(stream, config)
+            let consumed = String::from_utf8_lossy(&buf).into_owned();
+            result_http_request(stream, config, &consumed, &ConnectionInfo::default())
         }
     }
     #[cfg(not(feature = "nightly"))]
-    result_http_request(stream, config)
+    result_http_request(stream, config, "", &ConnectionInfo::default())
+}
+
+/// 完成 TLS 握手并处理请求，经过验证的客户端证书的 subject 被传给 Router
+/// header: 已经从流中读取的第一个记录头
+#[cfg_attr(not(feature = "nightly"), allow(dead_code))]
+fn handle_tls_connection<S: Read + Write>(
+    stream: S,
+    header: [u8; 5],
+    config: &Mutex<RouterConfig>,
+    server_config: &crate::https::server::ServerConfig,
+) {
+    match crate::https::server::accept(stream, header, server_config) {
+        Ok(stream) => {
+            let client_subject = stream
+                .peer_certificates
+                .first()
+                .and_then(|a| crate::https::x509::Certificate::parse(a).ok())
+                .map(|a| a.subject_string());
+            let connection = ConnectionInfo {
+                secure: true,
+                client_subject,
+            };
+            result_http_request(stream, config, "", &connection)
+        }
+        Err(e) => log!(Debug, format!("{}{:?}", LOG[42], e)),
+    }
 }
 
-fn result_http_request(mut stream: std::net::TcpStream, config: &Mutex<RouterConfig>) {
-    let buf_reader = std::io::BufReader::new(&mut stream);
+/// 根据全局的证书和私钥，以及 Router 的客户端证书设置，构造 TLS 服务端的配置
+/// local_addr: 连接的本地地址，它决定了是否请求客户端证书
+/// 如果没有设置证书或私钥，或者它们无法被解析，返回 None
+#[cfg(feature = "nightly")]
+fn tls_server_config(
+    config: &RouterConfig,
+    local_addr: Option<std::net::SocketAddr>,
+) -> Option<crate::https::server::ServerConfig> {
+    let mut server_config = {
+        let identity = crate::config::SSL_IDENTITY.read().unwrap();
+        crate::https::server::ServerConfig::new(
+            identity.certificate.as_ref()?,
+            identity.pravite_key.as_ref()?,
+        )?
+    };
+    if let Some(local_addr) = local_addr {
+        server_config.client_auth =
+            crate::https::server::ClientAuth::for_listener(&config.client_auth, local_addr);
+    }
+    server_config.client_ca = config.client_ca.clone();
+    server_config.session_lifetime = config.tls_session_lifetime;
+    server_config.session_cache = config.tls_session_cache;
+    Some(server_config)
+}
 
-    let mut lines = std::io::BufRead::lines(buf_reader);
+/// consumed: 在判断协议时已经从流中读取的内容，它会被加回到请求的开头
+/// connection: 请求所在的连接的信息，这决定了是否附带 HSTS 响应头，以及客户端证书的 subject
+fn result_http_request<S: Read + Write>(
+    mut stream: S,
+    config: &Mutex<RouterConfig>,
+    consumed: &str,
+    connection: &ConnectionInfo,
+) {
+    let mut reader = std::io::BufReader::new(&mut stream);
 
-    let req_str = get_request_str(&mut lines);
+    let req_str = get_request_str(&mut reader, consumed);
 
     if req_str.is_empty() {
         if ENABLE_CODE_BAD_REQUEST.load(Ordering::Relaxed) {
@@ -127,20 +303,20 @@ fn result_http_request(mut stream: std::net::TcpStream, config: &Mutex<RouterCon
     };
 
     if let Some(a) = request.get_header("Content-Length".to_owned()) {
-        request.set_content(Some(lines.take({
-            match a.parse() {
-                Ok(a) => a,
-                _ => 0,
-            }
-        })))
+        let length = a.parse().unwrap_or(0);
+        request.set_content(Some(std::io::Read::take(&mut reader, length)))
     }
 
     let response = &mut HttpResponse::new();
     response
         .set_default_headers("Tiny-Tiny-Web/2")
         .result_timeerr_default();
-    if !crate::router::router(request, response, &config.lock().unwrap()) {
-        return;
+    {
+        let config = &config.lock().unwrap();
+        if !crate::router::router(request, response, config, connection) {
+            return;
+        }
+        set_hsts_header(response, config, connection.secure);
     }
 
     let enable_pipe = crate::config::ENABLE_PIPE.load(Ordering::Relaxed);
@@ -160,7 +336,7 @@ fn result_http_request(mut stream: std::net::TcpStream, config: &Mutex<RouterCon
     if enable_pipe {
         if let Some(content) = response.content_unref() {
             if let Ok(a) = std::str::from_utf8(&content) {
-                pipe(config, a, enable_debug, response)
+                pipe(config, a, enable_debug, response, connection)
             }
         }
     }
@@ -168,155 +344,7 @@ fn result_http_request(mut stream: std::net::TcpStream, config: &Mutex<RouterCon
     write_stream(stream, response)
 }
 
-fn get_tls_keys() -> (Vec<u8>, Vec<u8>) {
-    let public_key: *mut u8 = [0; 32].as_mut_ptr();
-    let pravite_key: *mut u8 = [0; 32].as_mut_ptr();
-    let random = get_random_256().result_timeerr_default();
-    let mut random_vec = random.0.to_be_bytes().to_vec();
-    random_vec.extend(random.1.to_be_bytes());
-    unsafe {
-        crate::https::c25519::compact_x25519_keygen(
-            pravite_key,
-            public_key,
-            random_vec.as_mut_ptr(),
-        )
-    };
-    (
-        crate::https::c25519::key_to_vec(pravite_key, 32),
-        crate::https::c25519::key_to_vec(public_key, 32),
-    )
-}
-
-// TODO: add https support
-#[allow(dead_code)]
-fn result_https_request(
-    mut stream: &std::net::TcpStream,
-    _config: &Mutex<RouterConfig>,
-    record: RecordMessage,
-) {
-    let extra_length = record.length;
-    let mut buf = vec![];
-    if stream
-        .take(extra_length.into())
-        .read_to_end(&mut buf)
-        .is_err()
-    {
-        return;
-    };
-    match parse_has_record(record, buf) {
-        Ok(message) => {
-            match message.handshake_message.handshake_content {
-                crate::https::tls::HandshakeContent::HelloRequest => todo!(),
-                crate::https::tls::HandshakeContent::ClientHello(client_msg) => {
-                    println!("{:#?}", client_msg);
-                    let serverhello_random = Random::new_32bit_random(
-                        crate::drop::random::get_random_256().result_timeerr_default(),
-                    );
-                    let serverhello = HandshakeMessage {
-                        handshake_content: HandshakeContent::ServerHello(HandshakeServerHello {
-                            version: crate::https::tls::TLSVersion::TLS1_2,
-                            random: serverhello_random,
-                            session_id: client_msg.session_id,
-                            ciper_suite: CipherSuite::TLS_ECDHE_RSA_WITH_AES_128_CBC_SHA256,
-                            compression_method: CompressionMethod::Null,
-                            extenssions_length: 0,
-                        }),
-                        length: 0,
-                    }
-                    .bytes_without_length();
-                    let mut retvec =
-                        get_server_record_tls1_2_bytes(serverhello.len().try_into().unwrap());
-                    retvec.extend(serverhello);
-                    let certificate = HandshakeMessage {
-                        handshake_content: HandshakeContent::Certificate(
-                            HandshakeCertificate::new_just_one_certificate(unsafe {
-                                SSL_CERTIFICATE.get().to_vec()
-                            }),
-                        ),
-                        length: 0,
-                    }
-                    .bytes_without_length();
-                    retvec.extend(get_server_record_tls1_2_bytes(
-                        certificate.len().try_into().unwrap(),
-                    ));
-                    retvec.extend(certificate);
-                    println!("{:#?}", retvec.len());
-                    // ED25519 Signature (unstable)
-                    let mut signature = [0; 64];
-                    let sha_sign = unsafe {
-                        let mut sign_public_key = [0; 32];
-                        let mut sign_pravite_key = client_msg.random.bytes().to_vec();
-                        sign_pravite_key.extend(SSL_PRAVITE_KEY.get().to_vec());
-                        crate::https::c25519::compact_ed25519_calc_public_key(
-                            sign_public_key.as_mut_ptr(),
-                            sign_pravite_key.as_mut_ptr(),
-                        );
-                        sign_pravite_key.extend(sign_public_key);
-                        let mut sha_arg = client_msg.random.bytes().to_vec();
-                        sha_arg.extend(serverhello_random.bytes());
-                        sha_arg.extend([0x03, 0x00, 0x1d]);
-                        sha_arg.extend(sign_public_key);
-                        crate::https::c25519::compact_ed25519_sign(
-                            signature.as_mut_ptr(),
-                            sign_pravite_key.as_mut_ptr(),
-                            sha_arg.as_mut_ptr(),
-                            sha_arg.len() as u32,
-                        );
-                        signature.clone().to_vec()
-                    };
-
-                    let (_pravite_key, public_key) = get_tls_keys();
-
-                    let serverkeyexchange = HandshakeMessage {
-                        handshake_content: HandshakeContent::ServerKeyExchange(
-                            HandshakeServerKeyExchange {
-                                curve_name: crate::https::tls::CurveName::X25519,
-                                public_key,
-                                sha_sign,
-                            },
-                        ),
-                        length: 0,
-                    }
-                    .bytes_without_length();
-                    retvec.extend(get_server_record_tls1_2_bytes(
-                        serverkeyexchange.len().try_into().unwrap(),
-                    ));
-                    retvec.extend(serverkeyexchange);
-
-                    let serverdone = HandshakeMessage {
-                        handshake_content: HandshakeContent::HelloDone,
-                        length: 0,
-                    }
-                    .bytes_without_length();
-                    retvec.extend(get_server_record_tls1_2_bytes(
-                        serverdone.len().try_into().unwrap(),
-                    ));
-                    retvec.extend(serverdone);
-                    if std::io::Write::write_all(&mut stream, &retvec).is_err() {
-                        log!(Debug, LOG[6])
-                    }
-                }
-                crate::https::tls::HandshakeContent::ServerHello(_) => println!("1"),
-                crate::https::tls::HandshakeContent::Certificate(_) => println!("2"),
-                crate::https::tls::HandshakeContent::ServerKeyExchange(_) => println!("3"),
-                crate::https::tls::HandshakeContent::CertificateRequest => println!("4"),
-                crate::https::tls::HandshakeContent::HelloDone => println!("5"),
-                crate::https::tls::HandshakeContent::CertificateVerify => println!("6"),
-                crate::https::tls::HandshakeContent::ClientKeyExchange => println!("7"),
-                crate::https::tls::HandshakeContent::Finished => println!("8"),
-            }
-        }
-        Err(e) => match e {
-            crate::https::tls::TLSError::RecodeTypeError(_) => println!("1"),
-            crate::https::tls::TLSError::RecodeVersionError(_, _) => println!("2"),
-            crate::https::tls::TLSError::HandshakeContentTypeError(_) => println!("3"),
-            crate::https::tls::TLSError::UndefinedCiperSuite => println!("4"),
-            crate::https::tls::TLSError::BadRequest => println!("5"),
-        },
-    }
-}
-
-fn get_request<'a>(req_str: String) -> Result<HttpRequest<'a, TcpStream>, ()> {
+fn get_request<'a, T>(req_str: String) -> Result<HttpRequest<'a, T>, ()> {
     if crate::config::ENABLE_DEBUG.load(Ordering::Relaxed) {
         match HttpRequest::from_string(req_str.clone()) {
             Ok(req) => {
@@ -336,29 +364,31 @@ fn get_request<'a>(req_str: String) -> Result<HttpRequest<'a, TcpStream>, ()> {
     }
 }
 
-fn get_request_str(lines: &mut std::io::Lines<std::io::BufReader<&mut TcpStream>>) -> String {
-    let mut str = String::new();
-    #[allow(clippy::never_loop)]
+/// 读取请求行和全部的请求头，直到遇到空行，请求主体留在 reader 中
+fn get_request_str<R: std::io::BufRead>(reader: &mut R, consumed: &str) -> String {
+    let mut str = consumed.to_owned();
+    let mut line = String::new();
     loop {
-        let line = lines.next();
-        match line {
-            Some(Ok(a)) => {
+        line.clear();
+        match reader.read_line(&mut line) {
+            Ok(0) | Err(_) => break,
+            Ok(_) => {
+                let a = line.trim_end_matches(['\r', '\n']);
                 if a.is_empty() {
                     break;
-                };
-                str += &(a + "\r\n")
+                }
+                str += a;
+                str += "\r\n";
             }
-            _ => break,
         }
-        break;
     }
-    #[cfg(feature = "nightly")]
-    return "GET /".to_owned() + &str;
-    #[cfg(not(feature = "nightly"))]
-    return str;
+    if str == consumed {
+        return String::new();
+    }
+    str
 }
 
-fn write_stream(mut stream: TcpStream, response: &mut HttpResponse) {
+fn write_stream<S: Write>(mut stream: S, response: &mut HttpResponse) {
     if std::io::Write::write_all(&mut stream, &response.get_stream()).is_err() {
         log!(Debug, LOG[6])
     }
@@ -369,40 +399,366 @@ fn pipe(
     content: &str,
     enable_debug: bool,
     response: &mut HttpResponse,
+    connection: &ConnectionInfo,
 ) {
-    for e in &config.lock().unwrap().pipe {
-        let env = &mut crate::glisp::core::default_env();
-        env.data.insert(
-            "CONTENT".to_owned(),
-            crate::glisp::core::Expression::String(content.to_owned()),
-        );
-        match crate::glisp::core::parse_eval(e.to_string(), env, None) {
+    // 只在复制 Pipe 的列表时持有锁，因为 Pipe 是被预先解析的，复制它们的代价很小
+    let pipes = config.lock().unwrap().pipe.clone();
+    for e in &pipes {
+        let failed = match e.run(content, connection.client_subject.as_deref()) {
             Ok(crate::glisp::core::Expression::String(res)) => {
                 if enable_debug {
                     log!(Debug, format!("{}{}\n", LOG[8], res));
                 }
                 response.set_content(res.clone().into());
                 response.set_header("Content-Length", res.len().to_string());
+                false
             }
             Err(e) => {
-                match e {
-                    crate::glisp::core::GError::Reason(msg) => {
-                        log!(Info, format!("[{}] {} {}", LOG[32], LOG[34], msg))
-                    }
-                }
-                if crate::config::ENABLE_RETURN_IF_PIPE_ERR.load(Ordering::Relaxed) {
-                    return;
-                }
+                log!(Error, format!("[{}] {} {}", LOG[32], LOG[34], e.diagnostic()));
+                true
             }
             Ok(crate::glisp::core::Expression::Bool(res)) => {
-                log!(Info, format!("[{}] {} {}", LOG[32], LOG[33], res))
+                log!(Info, format!("[{}] {} {}", LOG[32], LOG[33], res));
+                false
             }
             Ok(a) => {
                 log!(Error, format!("[{}] {} {}", LOG[32], LOG[35], a));
-                if crate::config::ENABLE_RETURN_IF_PIPE_ERR.load(Ordering::Relaxed) {
-                    return;
-                }
+                true
             }
+        };
+        // 开启 return-if-pipe-err 时直接结束当前请求，不发送被处理了一半的内容
+        // 错误的细节只记录在日志中，响应中只有状态，避免泄露 Pipe 的源代码和路径
+        if failed && crate::config::ENABLE_RETURN_IF_PIPE_ERR.load(Ordering::Relaxed) {
+            let body = b"500 Internal Server Error\n".to_vec();
+            response.set_state("500 INTERNAL SERVER ERROR");
+            response.set_header("Content-Type", "text/plain; charset=utf-8".to_owned());
+            response.set_header("Content-Length", body.len().to_string());
+            response.set_content(body);
+            return;
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::atomic::AtomicBool;
+
+    /// 一个内存中的流，读取 input ，写入 output
+    struct MockStream {
+        input: std::io::Cursor<Vec<u8>>,
+        output: Vec<u8>,
+    }
+    impl MockStream {
+        fn new(input: &str) -> Self {
+            MockStream {
+                input: std::io::Cursor::new(input.as_bytes().to_vec()),
+                output: vec![],
+            }
+        }
+        fn output(&self) -> String {
+            String::from_utf8_lossy(&self.output).into_owned()
+        }
+    }
+    impl Read for MockStream {
+        fn read(&mut self, buf: &mut [u8]) -> std::io::Result<usize> {
+            self.input.read(buf)
+        }
+    }
+    impl Write for MockStream {
+        fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+            self.output.write(buf)
+        }
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    /// 保护全局的开关，修改或依赖它们的测试不能同时运行
+    static FLAGS: Mutex<()> = Mutex::new(());
+
+    /// 在被 drop 时把开关恢复为原来的值，然后才允许其它测试修改它们
+    struct FlagGuard {
+        old: Vec<(&'static AtomicBool, bool)>,
+        _lock: std::sync::MutexGuard<'static, ()>,
+    }
+    impl Drop for FlagGuard {
+        fn drop(&mut self) {
+            for (flag, value) in &self.old {
+                flag.store(*value, Ordering::Relaxed);
+            }
+        }
+    }
+
+    /// 设置全局的开关，直到返回值被 drop
+    fn set_flags(flags: &[(&'static AtomicBool, bool)]) -> FlagGuard {
+        // 其它测试失败时锁会被污染，但开关已经被恢复了
+        let lock = FLAGS.lock().unwrap_or_else(|e| e.into_inner());
+        let old = flags
+            .iter()
+            .map(|(flag, value)| (*flag, flag.swap(*value, Ordering::Relaxed)))
+            .collect();
+        FlagGuard { old, _lock: lock }
+    }
+
+    fn redirect(request: &str, origin: Option<&str>, code: u16) -> String {
+        let bind = RedirectBind {
+            addr: String::new(),
+            origin: origin.map(|a| a.to_owned()),
+            code,
+        };
+        let mut stream = MockStream::new(request);
+        handle_redirect(&mut stream, &bind);
+        stream.output()
+    }
+
+    #[test]
+    fn test_redirect_location() {
+        let response = redirect(
+            "GET /a?b=1 HTTP/1.1\r\nHost: example.com:80\r\n\r\n",
+            None,
+            301,
+        );
+        assert!(response.starts_with("HTTP/1.1 301 MOVED PERMANENTLY\r\n"));
+        assert!(response.contains("Location: https://example.com/a?b=1\r\n"));
+
+        let response = redirect("GET /a HTTP/1.1\r\nhost: [::1]:8080\r\n\r\n", None, 308);
+        assert!(response.starts_with("HTTP/1.1 308 PERMANENT REDIRECT\r\n"));
+        assert!(response.contains("Location: https://[::1]/a\r\n"));
+
+        // 配置了源时不使用 Host 头，不是路径的请求目标被替换为 `/`
+        let response = redirect(
+            "GET http://evil.com/ HTTP/1.1\r\nHost: evil.com\r\n\r\n",
+            Some("https://example.com:8443"),
+            301,
+        );
+        assert!(response.contains("Location: https://example.com:8443/\r\n"));
+    }
+
+    #[test]
+    fn test_redirect_bad_host() {
+        for request in [
+            "GET / HTTP/1.1\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: a.com/evil\r\n\r\n",
+            "GET / HTTP/1.1\r\nHost: \r\n\r\n",
+        ] {
+            let response = redirect(request, None, 301);
+            assert!(response.starts_with("HTTP/1.1 400 BAD REQUEST\r\n"));
+            assert!(!response.contains("Location"));
+        }
+        assert!(redirect("", None, 301).is_empty());
+    }
+
+    /// 所有路径都要求客户端证书，这样不需要任何文件就能得到一个 403 响应
+    fn hsts_response(secure: bool) -> String {
+        let _flags = set_flags(&[]);
+        let config = Mutex::new(RouterConfig {
+            hsts: Some("max-age=31536000".to_owned()),
+            client_cert_routes: vec!["/".to_owned()],
+            ..Default::default()
+        });
+        let connection = ConnectionInfo {
+            secure,
+            client_subject: None,
+        };
+        let mut stream = MockStream::new("GET / HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        result_http_request(&mut stream, &config, "", &connection);
+        stream.output()
+    }
+
+    #[test]
+    fn test_hsts_header() {
+        let response = hsts_response(true);
+        assert!(response.starts_with("HTTP/1.1 403 FORBIDDEN\r\n"));
+        assert!(response.contains("Strict-Transport-Security: max-age=31536000\r\n"));
+        // 不能通过明文 HTTP 发送
+        let response = hsts_response(false);
+        assert!(response.starts_with("HTTP/1.1 403 FORBIDDEN\r\n"));
+        assert!(!response.contains("Strict-Transport-Security"));
+    }
+
+    #[test]
+    fn test_acme_challenge_without_client_cert() {
+        // 即使所有路径都要求客户端证书，ACME 的挑战也能被访问
+        let _flags = set_flags(&[]);
+        crate::https::acme::add_challenge("client-cert-token", "client-cert-token.thumbprint");
+        let config = Mutex::new(RouterConfig {
+            client_cert_routes: vec!["/".to_owned()],
+            ..Default::default()
+        });
+        let mut stream = MockStream::new(
+            "GET /.well-known/acme-challenge/client-cert-token HTTP/1.1\r\nHost: example.com\r\n\r\n",
+        );
+        result_http_request(&mut stream, &config, "", &ConnectionInfo::default());
+        let response = stream.output();
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nclient-cert-token.thumbprint"));
+
+        let mut stream =
+            MockStream::new("GET /.well-known/acme-challenge/other HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        result_http_request(&mut stream, &config, "", &ConnectionInfo::default());
+        assert!(stream.output().starts_with("HTTP/1.1 403 FORBIDDEN\r\n"));
+    }
+
+    #[test]
+    fn test_missing_file() {
+        let _flags = set_flags(&[(&crate::config::ENABLE_CODE_NOT_FOUND, true)]);
+        // 被挂载的文件不存在时响应 404 ，Markdown 文件也一样
+        for markdown in [false, true] {
+            let mut config = RouterConfig::default();
+            config.serve_files_info.insert(
+                "/a".to_owned(),
+                crate::config::ServeFileData {
+                    file_path: "/no-such-file.md".to_owned(),
+                    content_type: "text/html".to_owned(),
+                    replace: None,
+                    markdown,
+                    template: None,
+                },
+            );
+            let mut stream = MockStream::new("GET /a HTTP/1.1\r\nHost: example.com\r\n\r\n");
+            result_http_request(
+                &mut stream,
+                &Mutex::new(config),
+                "",
+                &ConnectionInfo::default(),
+            );
+            assert!(stream.output().starts_with("HTTP/1.1 404 NOT FOUND\r\n"));
+        }
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-glisp"))]
+    fn test_pipe_error() {
+        use crate::glisp::core::{default_env, parse_eval, Expression};
+        let _flags = set_flags(&[
+            (&crate::config::ENABLE_PIPE, true),
+            (&crate::config::ENABLE_RETURN_IF_PIPE_ERR, true),
+        ]);
+        let Ok(Expression::Lambda(lambda)) = parse_eval(
+            "(lambda (a b c d e) \"<p>secret</p>\")".to_owned(),
+            &default_env(),
+            None,
+        ) else {
+            panic!()
+        };
+        let config = RouterConfig {
+            handlers: vec![crate::glisp::handler::Handler {
+                pattern: "/a".to_owned(),
+                lambda,
+            }],
+            // 返回的不是字符串，Pipe 出错
+            pipe: vec![crate::glisp::pipe::Pipe::compile("1").unwrap()],
+            ..Default::default()
+        };
+        let mut stream = MockStream::new("GET /a HTTP/1.1\r\nHost: example.com\r\n\r\n");
+        result_http_request(
+            &mut stream,
+            &Mutex::new(config),
+            "",
+            &ConnectionInfo::default(),
+        );
+        let response = stream.output();
+        assert!(response.starts_with("HTTP/1.1 500 INTERNAL SERVER ERROR\r\n"));
+        assert!(response.contains("Content-Type: text/plain; charset=utf-8\r\n"));
+        assert!(response.contains("Content-Length: 26\r\n"));
+        // 不发送被处理了一半的内容
+        assert!(response.ends_with("\r\n\r\n500 Internal Server Error\n"));
+    }
+
+    #[test]
+    #[cfg(not(feature = "no-glisp"))]
+    fn test_client_subject() {
+        use crate::glisp::core::{default_env, parse_eval, Expression};
+        use crate::https::client::tests::{issue, p256, server_config};
+        use crate::https::client::{connect_with_certificate, ClientCertificate};
+        let _flags = set_flags(&[(&crate::config::ENABLE_PIPE, true)]);
+
+        let server_key = p256(1);
+        let server_certificate = issue(
+            "localhost",
+            &["localhost"],
+            &server_key,
+            "localhost",
+            &server_key,
+            &[],
+        );
+        let ca_key = p256(2);
+        let ca = issue("ca", &[], &ca_key, "ca", &ca_key, &[]);
+        let mut tls_config = server_config(vec![server_certificate.clone()], &server_key);
+        tls_config.client_auth = crate::https::server::ClientAuth::Optional;
+        tls_config.client_ca = vec![ca];
+
+        let Ok(Expression::Lambda(lambda)) = parse_eval(
+            "(lambda (a b c d e subject) subject)".to_owned(),
+            &default_env(),
+            None,
+        ) else {
+            panic!()
+        };
+        let config = RouterConfig {
+            handlers: vec![crate::glisp::handler::Handler {
+                pattern: "/whoami".to_owned(),
+                lambda,
+            }],
+            pipe: vec![crate::glisp::pipe::Pipe::compile(
+                "(str.+ CONTENT (str.+ \" \" CLIENT_SUBJECT))",
+            )
+            .unwrap()],
+            client_cert_routes: vec!["/whoami".to_owned()],
+            ..Default::default()
+        };
+
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let addr = listener.local_addr().unwrap();
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut header = [0; 5];
+            stream.read_exact(&mut header).unwrap();
+            handle_tls_connection(stream, header, &Mutex::new(config), &tls_config);
+        });
+
+        let certificate = ClientCertificate {
+            certificates: vec![issue("alice", &[], &p256(3), "ca", &ca_key, &[])],
+            key: p256(3),
+        };
+        let stream = std::net::TcpStream::connect(addr).unwrap();
+        stream
+            .set_read_timeout(Some(std::time::Duration::from_secs(10)))
+            .unwrap();
+        let mut stream = connect_with_certificate(
+            stream,
+            "localhost",
+            &[server_certificate],
+            Some(&certificate),
+        )
+        .ok()
+        .unwrap();
+        stream
+            .write_all(b"GET /whoami HTTP/1.1\r\nHost: localhost\r\n\r\n")
+            .unwrap();
+        let mut response = vec![];
+        let _ = stream.read_to_end(&mut response);
+        server.join().unwrap();
+        let response = String::from_utf8_lossy(&response);
+        // 处理器和 Pipe 都得到了客户端证书的 subject
+        assert!(response.starts_with("HTTP/1.1 200 OK\r\n"));
+        assert!(response.ends_with("\r\n\r\nCN=alice CN=alice"));
+    }
+
+    #[test]
+    fn test_set_flags() {
+        let old = crate::config::ENABLE_CODE_NOT_FOUND.load(Ordering::Relaxed);
+        {
+            let _flags = set_flags(&[(&crate::config::ENABLE_CODE_NOT_FOUND, !old)]);
+            assert_eq!(
+                crate::config::ENABLE_CODE_NOT_FOUND.load(Ordering::Relaxed),
+                !old
+            );
+        }
+        assert_eq!(
+            crate::config::ENABLE_CODE_NOT_FOUND.load(Ordering::Relaxed),
+            old
+        );
+    }
+}
@@ -8,22 +8,46 @@
 use crate::{config::*, drop::http::*, drop::log::LogLevel::*, i18n::LOG, macros::*};
 use std::sync::atomic::Ordering;
 
+/// 一个请求所在的连接的信息
+/// secure: 该请求是否是通过 TLS 传输的
+/// client_subject: 可选的，经过验证的客户端证书的 subject ，例如 `CN=alice,O=Example`
+#[derive(Default)]
+pub struct ConnectionInfo {
+    pub secure: bool,
+    pub client_subject: Option<String>,
+}
+
 /// 这是一个回调函数，返回值说明了本函数是否修改了 `res`
 /// 如果请求不符合任何规则，则该函数返回 false
 ///
 /// req: 传入的请求
 /// res: 要被回调的相应
 /// config: 一些给 Router 的配置文件
+/// connection: 请求所在的连接的信息
 ///
 /// TODO: 该函数应该被 config 尽量的惰性构造来加快运行速度
 /// TODO: 需要注释或重构
-pub fn router<'a>(
-    req: HttpRequest<std::net::TcpStream>,
+pub fn router<'a, T: std::io::Read>(
+    mut req: HttpRequest<T>,
     res: &'a mut HttpResponse,
     config: &'a RouterConfig,
+    connection: &ConnectionInfo,
 ) -> bool {
+    // ACME 的挑战必须能在没有客户端证书时被访问，即使它在某个 `+client-cert-route` 之下
+    if let Some(key_authorization) = crate::https::acme::challenge_response(req.url()) {
+        return router_iftype_acme(res, key_authorization);
+    }
+
+    if connection.client_subject.is_none() && requires_client_cert(req.url(), config) {
+        return router_iftype_forbidden(res);
+    }
+
     let serve_args = &config.serve_files_info;
     if !serve_args.contains_key(&req.url().to_owned()) {
+        let path = req.url().split('?').next().unwrap_or_default().to_owned();
+        if let Some(handler) = config.handlers.iter().find(|a| a.matches(&path)) {
+            return router_iftype_handler(&mut req, res, handler, connection);
+        }
         return router_iftype_err(res, config);
     };
 
@@ -36,10 +60,22 @@ pub fn router<'a>(
             .content_type
             .clone(),
     );
-    let str = if let Some(content) = get_response_content(&req, config) {
-        content
-    } else {
-        return false;
+    if let Some(template) = &serve_args.get(&req.url().to_owned()).unwrap().template {
+        return router_iftype_template(res, template);
+    }
+
+    // 文件在挂载之后可能被删除或者变得不可读，此时响应 404 或 500 ，而不是让线程崩溃
+    let str = match get_response_content(&req, config) {
+        Ok(content) => content,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {
+            return router_iftype_err(res, config);
+        }
+        Err(_) => {
+            res.set_version("HTTP/1.1");
+            res.set_state("500 INTERNAL SERVER ERROR");
+            res.set_header("Content-Length", "0".to_owned());
+            return true;
+        }
     };
 
     if let Some(k) = serve_args.get(&req.url().to_owned()) {
@@ -71,19 +107,114 @@ pub fn router<'a>(
     return true;
 }
 
-fn get_response_content<'a>(
-    req: &'a HttpRequest<std::net::TcpStream>,
+fn get_response_content<'a, T>(
+    req: &'a HttpRequest<T>,
     config: &'a RouterConfig,
-) -> Option<Vec<u8>> {
-    let _stream = std::fs::read(
-        "export".to_owned()
-            + &config
-                .serve_files_info
-                .get(&req.url().to_owned())
-                .unwrap()
-                .file_path,
+) -> std::io::Result<Vec<u8>> {
+    let data = config.serve_files_info.get(&req.url().to_owned()).unwrap();
+    let path = "export".to_owned() + &data.file_path;
+    let _stream =
+        std::fs::read(&path).inspect_err(|_| log!(Error, format!("{}{}", LOG[22], path)))?;
+    if data.markdown {
+        let markdown = String::from_utf8_lossy(&_stream);
+        return Ok(crate::drop::markdown::to_html(&markdown).into_bytes());
+    }
+    Ok(_stream)
+}
+
+/// url 是否在某个 `+client-cert-route` 之下，`/admin` 匹配 `/admin` 和 `/admin/a` ，但不匹配 `/administrator`
+/// 以 `/` 结尾的路由匹配它下面的所有路径，`/` 匹配所有路径
+fn requires_client_cert(url: &str, config: &RouterConfig) -> bool {
+    let path = url.split('?').next().unwrap_or(url);
+    config.client_cert_routes.iter().any(|route| {
+        path.strip_prefix(route.as_str())
+            .is_some_and(|a| a.is_empty() || a.starts_with('/') || route.ends_with('/'))
+    })
+}
+
+/// 无论是否启用了 403 状态码，都会响应，因为这是一个安全相关的限制
+fn router_iftype_forbidden(res: &mut HttpResponse) -> bool {
+    res.set_version("HTTP/1.1");
+    res.set_state("403 FORBIDDEN");
+    res.set_header("Content-Length", "0".to_owned());
+    true
+}
+
+/// 应答 ACME 的 HTTP-01 挑战，它优先于所有被挂载的文件
+fn router_iftype_acme(res: &mut HttpResponse, key_authorization: String) -> bool {
+    res.set_version("HTTP/1.1");
+    res.set_state("200 OK");
+    res.set_header("Content-Type", "text/plain".to_owned());
+    res.set_header("Content-Length", key_authorization.len().to_string());
+    res.set_content(key_authorization.into());
+    true
+}
+
+/// 调用 Glisp 请求处理器，它优先级低于被挂载的文件
+/// 如果处理器出错，或者返回了不正确的值，则响应 500
+fn router_iftype_handler<T: std::io::Read>(
+    req: &mut HttpRequest<T>,
+    res: &mut HttpResponse,
+    handler: &crate::glisp::handler::Handler,
+    connection: &ConnectionInfo,
+) -> bool {
+    let body = req.read_content();
+    let result = handler.call(
+        req.request_method(),
+        req.url(),
+        req.headers(),
+        &body,
+        connection.client_subject.as_deref(),
     );
-    Some(_stream.unwrap())
+    let response = match result {
+        Ok(a) => a,
+        Err(e) => {
+            log!(Error, format!("[{}] {} {}", LOG[32], LOG[43], e.diagnostic()));
+            res.set_version("HTTP/1.1");
+            res.set_state("500 INTERNAL SERVER ERROR");
+            res.set_header("Content-Length", "0".to_owned());
+            return true;
+        }
+    };
+    res.set_version("HTTP/1.1");
+    // 原因短语可以为空，所以不认识的状态码也可以被发送
+    res.set_state(&status_text(response.status).unwrap_or(format!("{} ", response.status)));
+    if !response
+        .headers
+        .iter()
+        .any(|(k, _)| k.eq_ignore_ascii_case("Content-Type"))
+    {
+        res.set_header("Content-Type", "text/html; charset=utf-8".to_owned());
+    }
+    for (k, v) in response.headers {
+        if !k.eq_ignore_ascii_case("Content-Length") {
+            res.set_header(&k, v);
+        }
+    }
+    res.set_header("Content-Length", response.body.len().to_string());
+    res.set_content(response.body.into());
+    true
+}
+
+/// 渲染被挂载的模板，如果渲染出错，则响应 500
+fn router_iftype_template(
+    res: &mut HttpResponse,
+    template: &crate::glisp::template::Mounted,
+) -> bool {
+    res.set_version("HTTP/1.1");
+    match template.render() {
+        Ok(body) => {
+            res.set_state("200 OK");
+            res.set_header("Content-Length", body.len().to_string());
+            res.set_content(body.into());
+        }
+        Err(e) => {
+            log!(Error, format!("[{}] {} {}", LOG[32], LOG[45], e.diagnostic()));
+            res.set_state("500 INTERNAL SERVER ERROR");
+            res.set_header("Content-Length", "0".to_owned());
+        }
+    }
+    true
 }
 
 fn router_iftype_err<'a>(res: &'a mut HttpResponse, config: &'a RouterConfig) -> bool {
@@ -107,11 +238,11 @@ fn router_iftype_err<'a>(res: &'a mut HttpResponse, config: &'a RouterConfig) ->
     }
 }
 
-fn router_iftype_replace<'a>(
-    req: HttpRequest<std::net::TcpStream>,
+fn router_iftype_replace<'a, T>(
+    req: HttpRequest<T>,
     res: &'a mut HttpResponse,
     config: &'a RouterConfig,
-    replaces: &Vec<ReplaceData>,
+    replaces: &[ReplaceData],
     str: String,
 ) -> bool {
     res.set_version("HTTP/1.1");
@@ -125,9 +256,25 @@ fn router_iftype_replace<'a>(
             .content_type
             .clone(),
     );
+    // 按行号和列号找到每个 `$_gcflag` ，文件在 compile 之后被修改时，不在原来的位置的占位符不会被替换
     let mut final_str = String::new();
-    for e in replaces {
-        final_str = str.replace("$_gcflag", &e.content);
+    for (i, line) in str.split_inclusive('\n').enumerate() {
+        let mut copied = 0;
+        let mut flags: Vec<_> = replaces
+            .iter()
+            .filter(|a| a.line as usize == i + 1)
+            .collect();
+        flags.sort_by_key(|a| a.column);
+        for e in flags {
+            let column = e.column as usize;
+            let found = line.get(column..).is_some_and(|a| a.starts_with("$_gcflag"));
+            if column >= copied && found {
+                final_str.push_str(&line[copied..column]);
+                final_str.push_str(&e.content);
+                copied = column + "$_gcflag".len();
+            }
+        }
+        final_str.push_str(&line[copied..]);
     }
     res.set_header("Content-Length", final_str.len().to_string());
     res.set_content(final_str.into());